@@ -1,12 +1,12 @@
 import { invoke } from '@tauri-apps/api/core';
 
-export async function plotTissueOverlay(
-  datasetId: string,
-  imgId: string,
-  segId: string,
-  fillKey: string,
-  borderKey?: string
-): Promise<{
+export interface RenderTimings {
+  load_ms: number;
+  render_ms: number;
+  tile_ms: number;
+}
+
+export interface OverlayInfo {
   overlay_id: string;
   width: number;
   height: number;
@@ -14,29 +14,1846 @@ export async function plotTissueOverlay(
   max_zoom: number;
   fill_key: string;
   is_gene: boolean;
-}> {
+  // not reported by older python helpers
+  timings?: RenderTimings;
+  // overview tiles the bridge cached before plot returned
+  prerendered_tiles: number;
+  // false when the segments were drawn over a plain background
+  has_image: boolean;
+  // burned into the tiles, null without one
+  scalebar: ScalebarOptions | null;
+  // null when every zoom level shows individual segments
+  lod: LodOptions | null;
+  // [min, max] the colormap spans, same as the legend's. null for categories
+  value_range: [number, number] | null;
+  quality: RenderQuality;
+  // recolorOverlay repaints it without rasterizing again
+  recolorable: boolean;
+  // plot again with it to reproduce the render exactly, null if unseeded
+  seed?: number | null;
+  // how the render was turned, width/height are after it
+  orientation?: Orientation;
+  origin?: Origin;
+  // affine applied before rasterizing, null without one
+  transform?: AffineTransform | null;
+  pyramid_mode?: PyramidMode;
+  // the cap the render was sampled to, null renders every segment
+  segment_limit?: SegmentLimit | null;
+  // segments segment_limit left out of the render
+  segments_dropped?: number;
+}
+
+// render at most max_segments (at least 1) segments, to preview a huge
+// segmentation. random (default) is seeded by the plot's seed, or 0;
+// largest keeps the segments covering the most pixels; none fails the plot
+// over the limit. only the render is sampled, segment queries, statistics
+// and LOD bins still see every segment
+export interface SegmentLimit {
+  max_segments: number;
+  sampling?: Sampling;
+}
+
+export type Sampling = 'random' | 'largest' | 'none';
+
+// eager (default) cuts every tile before plot returns, emitting a
+// `pyramid_progress` event per level, and then holds only the encoded tiles.
+// lazy returns right after the render and cuts tiles on first view, keeping
+// the render (~3 bytes per pixel, plus up to a third for levels) in python
+export type PyramidMode = 'eager' | 'lazy';
+
+// payload of the `pyramid_progress` event
+export interface PyramidProgress {
+  overlay_id: string;
+  zoom: number;
+  done: number;
+  total: number;
+}
+
+// plots: fast = nearest neighbour downsampling, jagged at low zoom;
+// balanced = lanczos (default); high = lanczos with sharper jpeg, ~2x bytes.
+// tiles: fast = half resolution stand-in to stretch while panning, balanced
+// and high = the stored tile
+export type RenderQuality = 'fast' | 'balanced' | 'high';
+
+// clockwise rotations and mirrorings applied before tiling. tile, segment
+// and bbox coordinates of the overlay are all in the turned frame
+export type Orientation =
+  | 'rotate0'
+  | 'rotate90'
+  | 'rotate180'
+  | 'rotate270'
+  | 'flip_horizontal'
+  | 'flip_vertical';
+
+// which corner y counts from, match the viewer's. bottom_left overlays give
+// tile rows, segment positions and bboxes counted up from the bottom edge
+export type Origin = 'top_left' | 'bottom_left';
+
+// [a, b, c, d, e, f]: x' = a*x + b*y + c, y' = d*x + e*y + f, applied to
+// the image and segmentation before rasterizing (and before orientation) to
+// register them with another dataset. must be invertible
+export type AffineTransform = [number, number, number, number, number, number];
+
+// for genes and numeric columns. the colormap spans the data's range unless
+// valueRange is given. symmetric centers it on center (0 by default), an
+// asymmetric valueRange together with symmetric is refused
+export interface ColorScale {
+  value_range?: [number, number];
+  symmetric?: boolean;
+  center?: number;
+}
+
+// zoom levels below segmentsFromZoom show segments aggregated into square
+// bins of binSize full resolution pixels (2..=4096), the rest show segments
+export interface LodOptions {
+  bin_size: number;
+  // at least 1, so zoom 0 is always aggregated
+  segments_from_zoom: number;
+}
+
+export type LodMode = 'segments' | 'bins';
+
+export type ScalebarPosition = 'top_left' | 'top_right' | 'bottom_left' | 'bottom_right';
+
+// needs the dataset's pixel size calibration, plotting fails without it
+export interface ScalebarOptions {
+  length_um: number;
+  // bottom_right by default
+  position?: ScalebarPosition;
+  // rgba, opaque black by default
+  color?: [number, number, number, number];
+}
+
+// per-call settings accepted by the commands that talk to python
+export interface CallOptions {
+  // how long each python command of this call may take, overrides the
+  // command's own and the global timeout. the configured ones by default
+  timeoutMs?: number;
+}
+
+// how plotTissueOverlay renders, see PlotOptions in the bridge. everything
+// is optional, unset options come from setRenderDefaults where it has them
+export interface PlotOptions {
+  border_key?: string;
+  // rgba drawn under the segments when there is no image, white by default
+  background?: [number, number, number, number];
+  scalebar?: ScalebarOptions;
+  lod?: LodOptions;
+  color_scale?: ColorScale;
+  quality?: RenderQuality;
+  // keeps the full resolution render in python (~3 bytes per pixel) for
+  // recolorOverlay, only takes for continuous values
+  recolorable?: boolean;
+  // byte-identical renders across runs, see plot_tissue_overlay in the
+  // bridge. deterministic alone seeds with 0, the seed used is in the result
+  seed?: number;
+  deterministic?: boolean;
+  orientation?: Orientation;
+  origin?: Origin;
+  transform?: AffineTransform;
+  pyramid_mode?: PyramidMode;
+  segment_limit?: SegmentLimit;
+  // from openRenderContext, ids left undefined come from it
+  context_id?: string;
+  // extra kwargs for the python renderer, must not repeat the options above
+  extra?: Record<string, unknown>;
+}
+
+// pass undefined for an id to use the one from setContext. with no image
+// id at all the segments are drawn over plot.background
+export async function plotTissueOverlay(
+  datasetId: string | undefined,
+  imgId: string | undefined,
+  segId: string | undefined,
+  fillKey: string,
+  plot?: PlotOptions,
+  options?: CallOptions
+): Promise<OverlayInfo> {
   return await invoke('plot_tissue_overlay_cmd', {
     datasetId,
     imgId,
     segId,
     fillKey,
-    borderKey
+    options: plot,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// a plot's setup (image, segmentation, index) done once for these ids
+export interface RenderContext {
+  context_id: string;
+  dataset_id: string;
+  img_id: string | null;
+  seg_id: string;
+}
+
+// pass the context_id to plotTissueOverlay to skip the setup when switching
+// keys. opening the same ids again returns the open context. held until
+// closeRenderContext, unloading the dataset or a python restart
+export async function openRenderContext(
+  datasetId: string | undefined,
+  imgId: string | undefined,
+  segId: string | undefined,
+  options?: CallOptions
+): Promise<RenderContext> {
+  return await invoke('open_render_context_cmd', {
+    datasetId,
+    imgId,
+    segId,
+    timeoutMs: options?.timeoutMs
   });
 }
 
+// overlays plotted with it stay
+export async function closeRenderContext(contextId: string): Promise<void> {
+  return await invoke('close_render_context_cmd', { contextId });
+}
+
+export async function listRenderContexts(): Promise<RenderContext[]> {
+  return await invoke('list_render_contexts_cmd');
+}
+
+// another colormap (and for continuous values, range) on a plotted overlay.
+// repainted in place when recolorable, otherwise re-plotted. valueRange
+// undefined keeps the current range
+export async function recolorOverlay(
+  overlayId: string,
+  colormap: string,
+  valueRange?: [number, number],
+  options?: CallOptions
+): Promise<OverlayInfo> {
+  return await invoke('recolor_overlay_cmd', {
+    overlayId,
+    colormap,
+    valueRange,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface ValidationError {
+  field: 'dataset_id' | 'img_id' | 'seg_id' | 'fill_key' | 'border_key';
+  message: string;
+}
+
+// checks the ids/keys against what python has loaded, empty when plottable
+export async function validatePlotArgs(
+  datasetId: string | undefined,
+  imgId: string | undefined,
+  segId: string | undefined,
+  fillKey: string,
+  borderKey?: string,
+  options?: CallOptions
+): Promise<ValidationError[]> {
+  return await invoke('validate_plot_args_cmd', {
+    datasetId,
+    imgId,
+    segId,
+    fillKey,
+    borderKey,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface DownloadProgress {
+  url: string;
+  downloaded: number;
+  // null if the server didn't report a size
+  total: number | null;
+}
+
+// hashing costs a full extra read of the file. verify refuses to load a
+// file whose sha256 isn't expected ("sha256:<hex>" or bare hex), failing
+// with "Checksum of ... is ..., expected ..."
+export type ChecksumCheck =
+  | { mode: 'skip' }
+  | { mode: 'compute' }
+  | { mode: 'verify'; expected: string };
+
+// downloads (resuming if interrupted) then loads the dataset, progress
+// arrives as `download_progress` events. a download failing verification is
+// dropped from the cache so the next call fetches it again
+export async function loadDatasetFromUrl(
+  url: string,
+  datasetId?: string,
+  checksum?: ChecksumCheck,
+  options?: CallOptions
+): Promise<DatasetSummary> {
+  return await invoke('load_dataset_from_url_cmd', {
+    url,
+    datasetId,
+    checksum,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// reads a local .h5ad into python
+export async function loadDataset(
+  path: string,
+  datasetId?: string,
+  checksum?: ChecksumCheck,
+  options?: CallOptions
+): Promise<DatasetSummary> {
+  return await invoke('load_dataset_cmd', {
+    path,
+    datasetId,
+    checksum,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// "sha256:<hex>" of a file, to record before loading it elsewhere
+export async function checksumFile(path: string, options?: CallOptions): Promise<string> {
+  return await invoke('checksum_file_cmd', { path, timeoutMs: options?.timeoutMs });
+}
+
+// frees the dataset, its render contexts and every overlay plotted from it,
+// returns those overlay ids. commands still waiting on it fail with
+// "Dataset ... was unloaded"
+export async function unloadDataset(datasetId: string, options?: CallOptions): Promise<string[]> {
+  return await invoke('unload_dataset_cmd', { datasetId, timeoutMs: options?.timeoutMs });
+}
+
+// payload of the `python_log` event, emitted while the python helper runs.
+// records past 50 per second are dropped and summarized as a warning
+export interface PythonLog {
+  level: 'debug' | 'info' | 'warning' | 'error';
+  message: string;
+  // set while request tracing is on, matches RequestTrace.trace_id
+  trace_id?: number;
+}
+
+// payload of the `python_warning` event, a caveat of a command that still
+// succeeded. match on `code`, e.g. "value_range_clipped" or
+// "missing_calibration", and show `message` to the user
+export interface PythonWarning {
+  command: string;
+  code: string;
+  message: string;
+}
+
+export interface PyramidEstimate {
+  width: number;
+  height: number;
+  levels: number;
+  total_tiles: number;
+  est_bytes: number;
+}
+
+// size of the pyramid plotTissueOverlay would produce, nothing is rendered
+export async function estimateOverlay(
+  datasetId: string | undefined,
+  imgId: string | undefined,
+  segId: string | undefined,
+  tileSize?: number,
+  options?: CallOptions
+): Promise<PyramidEstimate> {
+  return await invoke('estimate_overlay_cmd', {
+    datasetId,
+    imgId,
+    segId,
+    tileSize,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface PlotParams {
+  dataset_id: string;
+  img_id: string | null;
+  seg_id: string;
+  fill_key: string;
+  border_key: string | null;
+  background: [number, number, number, number] | null;
+  scalebar: ScalebarOptions | null;
+  lod: LodOptions | null;
+  color_scale: ColorScale | null;
+  quality: RenderQuality;
+  recolorable: boolean;
+  seed: number | null;
+  orientation?: Orientation;
+  origin?: Origin;
+  transform?: AffineTransform | null;
+  pyramid_mode?: PyramidMode;
+  segment_limit?: SegmentLimit | null;
+  extra: Record<string, unknown> | null;
+}
+
+export interface Viewport {
+  zoom: number;
+  // full resolution pixels
+  center_x: number;
+  center_y: number;
+}
+
+// plain data, persist it however suits the app
+export interface ViewState {
+  overlay_id: string;
+  params: PlotParams;
+  viewport: Viewport;
+  pinned_levels: number[];
+}
+
+export interface RestoredView {
+  overlay: OverlayInfo;
+  viewport: Viewport;
+  // false when the overlay was still plotted and reused
+  replotted: boolean;
+}
+
+export async function saveViewState(overlayId: string, viewport: Viewport): Promise<ViewState> {
+  return await invoke('save_view_state_cmd', { overlayId, viewport });
+}
+
+// re-plots if needed, rejects if the dataset/image/segmentation isn't loaded
+export async function restoreViewState(
+  view: ViewState,
+  options?: CallOptions
+): Promise<RestoredView> {
+  return await invoke('restore_view_state_cmd', { view, timeoutMs: options?.timeoutMs });
+}
+
+// default ids for commands that omit them, explicit ids still win
+export async function setContext(
+  datasetId: string,
+  imgId: string,
+  segId: string
+): Promise<void> {
+  return await invoke('set_context_cmd', { datasetId, imgId, segId });
+}
+
+export async function clearContext(): Promise<void> {
+  return await invoke('clear_context_cmd');
+}
+
+// options every later plot starts from. an option passed to
+// plotTissueOverlay wins, extra is merged key by key with the call's keys
+// winning. colormap is bin2cell's cont_cmap. there is no opacity, the
+// overlay layer's opacity is the viewer's to default
+export interface RenderDefaults {
+  colormap?: string | null;
+  quality?: RenderQuality | null;
+  background?: [number, number, number, number] | null;
+  scalebar?: ScalebarOptions | null;
+  lod?: LodOptions | null;
+  orientation?: Orientation | null;
+  origin?: Origin | null;
+  pyramid_mode?: PyramidMode | null;
+  segment_limit?: SegmentLimit | null;
+  extra?: Record<string, unknown>;
+}
+
+// replaces the previous defaults entirely
+export async function setRenderDefaults(defaults: RenderDefaults): Promise<void> {
+  return await invoke('set_render_defaults_cmd', { defaults });
+}
+
+export async function getRenderDefaults(): Promise<RenderDefaults> {
+  return await invoke('get_render_defaults_cmd');
+}
+
+export async function resetRenderDefaults(): Promise<void> {
+  return await invoke('reset_render_defaults_cmd');
+}
+
+// quality 'fast' for a cheap half resolution tile while panning, re-request
+// without it once the view settles
 export async function getTissueOverlayTile(
   overlayId: string,
   zoom: number,
   x: number,
-  y: number
+  y: number,
+  quality?: RenderQuality,
+  options?: CallOptions
 ): Promise<{
   tile: string;
   format: string;
+  // e.g. "image/png", varies per tile with setTileFormat('auto'). missing
+  // from older python helpers, then `image/${format}`
+  mime?: string;
+  // what the tile shows, missing from older python helpers
+  lod?: LodMode;
+  // always false, overlay tiles are opaque
+  premultiplied?: boolean;
 }> {
   return await invoke('get_tissue_overlay_tile_cmd', {
     overlayId,
     zoom,
     x,
-    y
+    y,
+    quality,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// zoom steps a fractional zoom is rounded to, see getFractionalTile
+export const ZOOM_STEPS_PER_LEVEL = 8;
+
+// a tile between pyramid levels for continuous zoom. zoom is rounded to
+// 1 / ZOOM_STEPS_PER_LEVEL, place the tile at the rounded zoom. sharper than
+// stretching the coarser level but rendered per tile, whole levels are the
+// stored tiles
+export async function getFractionalTile(
+  overlayId: string,
+  zoom: number,
+  x: number,
+  y: number,
+  quality?: RenderQuality,
+  options?: CallOptions
+): Promise<{
+  tile: string;
+  format: string;
+  mime?: string;
+  lod?: LodMode;
+  premultiplied?: boolean;
+}> {
+  return await invoke('get_fractional_tile_cmd', {
+    overlayId,
+    zoom,
+    x,
+    y,
+    quality,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// the image alone at the same tile coordinates as its overlays, for a layer
+// under the overlay that stays when the overlay is hidden or faded. pass
+// undefined for an id to use the one from setContext. pass the overlay's
+// orientation, transform and origin so the tiles line up with it
+export async function getBaseImageTile(
+  datasetId: string | undefined,
+  imgId: string | undefined,
+  zoom: number,
+  x: number,
+  y: number,
+  quality?: RenderQuality,
+  orientation?: Orientation,
+  transform?: AffineTransform,
+  origin?: Origin,
+  options?: CallOptions
+): Promise<{ tile: string; format: string; mime?: string }> {
+  return await invoke('get_base_image_tile_cmd', {
+    datasetId,
+    imgId,
+    zoom,
+    x,
+    y,
+    quality,
+    orientation,
+    origin,
+    transform,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export type TileFormat = 'jpeg' | 'png';
+
+export interface ExportReport {
+  dzi_path: string;
+  tiles_written: number;
+  failed: { zoom: number; x: number; y: number; error: string }[];
+}
+
+// progress arrives as `export_progress` events: { overlay_id, done, total }
+export interface TileCoord {
+  zoom: number;
+  x: number;
+  y: number;
+}
+
+export interface FallbackTile {
+  tile: { tile: string; format: string };
+  // an ancestor of the requested tile when provisional
+  source: TileCoord;
+  provisional: boolean;
+}
+
+// cached tile or its closest cached ancestor. when provisional, draw the
+// matching part of source scaled up, then fetch the real tile
+export async function getTileWithFallback(
+  overlayId: string,
+  zoom: number,
+  x: number,
+  y: number,
+  options?: CallOptions
+): Promise<FallbackTile> {
+  return await invoke('get_tile_with_fallback_cmd', {
+    overlayId,
+    zoom,
+    x,
+    y,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// the whole overlay as one jpeg for the mini-map, longer side <= maxDimension.
+// rendered once per overlay and size, then served from the bridge's cache
+export async function getOverview(
+  overlayId: string,
+  maxDimension: number,
+  options?: CallOptions
+): Promise<{ tile: string; format: string }> {
+  return await invoke('get_overview_cmd', {
+    overlayId,
+    maxDimension,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// full resolution pixels of the overlay, x1/y1 exclusive
+export interface Bounds {
+  x0: number;
+  y0: number;
+  x1: number;
+  y1: number;
+}
+
+// getOverview's image with the viewport outlined in red. the overview stays
+// cached, only the box is redrawn per call. the viewport must lie inside
+// the overlay
+export async function getMinimap(
+  overlayId: string,
+  viewport: Bounds,
+  maxDimension: number,
+  options?: CallOptions
+): Promise<{ tile: string; format: string }> {
+  return await invoke('get_minimap_cmd', {
+    overlayId,
+    viewport,
+    maxDimension,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface RecentOverlay {
+  overlay_id: string;
+  // null for overlays the bridge didn't plot itself
+  params: PlotParams | null;
+  // small jpeg of the whole overlay, null if python couldn't render one
+  thumbnail: { tile: string; format: string } | null;
+  // unix millis of the last plot or recolor
+  plotted_at_ms: number;
+}
+
+// this session's plots still around, newest first, for a recents gallery.
+// the bridge remembers the last 32, a deleted overlay drops out
+export async function listRecentOverlays(
+  limit?: number,
+  options?: CallOptions
+): Promise<RecentOverlay[]> {
+  return await invoke('list_recent_overlays_cmd', { limit, timeoutMs: options?.timeoutMs });
+}
+
+// the same tile from two overlays with identical geometry
+export async function getComparisonTile(
+  overlayA: string,
+  overlayB: string,
+  zoom: number,
+  x: number,
+  y: number,
+  options?: CallOptions
+): Promise<{ a: { tile: string; format: string }; b: { tile: string; format: string } }> {
+  return await invoke('get_comparison_tile_cmd', {
+    overlayA,
+    overlayB,
+    zoom,
+    x,
+    y,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// absolute: per-channel difference, changed_mask: changed pixels over transparency
+export type DiffMode = 'absolute' | 'changed_mask';
+
+// png of where the same tile of two overlays with identical geometry differs.
+// premultiplied (changed_mask only) multiplies colors by alpha for WebGL
+// blending with ONE, ONE_MINUS_SRC_ALPHA. leave it off for <img>/canvas,
+// which expect straight alpha
+export async function diffTiles(
+  overlayA: string,
+  overlayB: string,
+  zoom: number,
+  x: number,
+  y: number,
+  mode: DiffMode = 'absolute',
+  premultiplied?: boolean,
+  options?: CallOptions
+): Promise<{ tile: string; format: string; premultiplied: boolean }> {
+  return await invoke('diff_tiles_cmd', {
+    overlayA,
+    overlayB,
+    zoom,
+    x,
+    y,
+    mode,
+    premultiplied,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface TileRequest {
+  zoom: number;
+  x: number;
+  y: number;
+  // lower is fetched sooner, overrides the distance to the viewport center
+  priority?: number;
+}
+
+// results come back in fetch order: explicit priority, then distance from
+// the center (in pixels of the tiles' zoom level). a tile python failed to
+// render carries its error next to the others, retry or placeholder it
+export async function getTilesBatch(
+  overlayId: string,
+  tiles: TileRequest[],
+  center?: { x: number; y: number },
+  options?: CallOptions
+): Promise<RegionTile[]> {
+  return await invoke('get_tiles_batch_cmd', {
+    overlayId,
+    tiles,
+    centerX: center?.x,
+    centerY: center?.y,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface SegmentInfo {
+  // a decimal string unless the bridge was built with IdEncoding::Number,
+  // labels can be larger than javascript numbers represent exactly
+  segment_id: string;
+  // [x0, y0, x1, y1] in full resolution pixels, end exclusive
+  bbox: [number, number, number, number];
+  pixel_count: number;
+  value: number | string | null;
+  // pixels to the centroid, only from nearestSegments
+  distance?: number;
+}
+
+// x/y in full resolution pixels, null over background
+export async function getSegmentAt(
+  overlayId: string,
+  x: number,
+  y: number,
+  options?: CallOptions
+): Promise<SegmentInfo | null> {
+  return await invoke('get_segment_at_cmd', { overlayId, x, y, timeoutMs: options?.timeoutMs });
+}
+
+export interface OverlayBounds {
+  // full resolution size as shown, after orientation
+  width: number;
+  height: number;
+  // size of the image and segmentation as loaded, also the canvas the
+  // transform draws onto
+  source_width: number;
+  source_height: number;
+  orientation: Orientation;
+  transform: AffineTransform | null;
+  origin: Origin;
+}
+
+export async function getOverlayBounds(overlayId: string): Promise<OverlayBounds> {
+  return await invoke('get_overlay_bounds_cmd', { overlayId });
+}
+
+// switch an overlay's origin without plotting it again. its cached tiles
+// are fetched again, at the rows the new origin gives them
+export async function setOverlayOrigin(overlayId: string, origin: Origin): Promise<OverlayInfo> {
+  return await invoke('set_overlay_origin_cmd', { overlayId, origin });
+}
+
+// the crispest pyramid level for drawing at `targetPxPerUnit` screen pixels
+// per full resolution pixel: 1 is max_zoom, 0.5 the level below, values in
+// between round up to the sharper level
+export async function zoomForResolution(
+  overlayId: string,
+  targetPxPerUnit: number
+): Promise<number> {
+  return await invoke('zoom_for_resolution_cmd', { overlayId, targetPxPerUnit });
+}
+
+// how a tile was produced, for debugging one that looks wrong
+export interface TileProvenance {
+  overlay_id: string;
+  // null for overlays the bridge didn't plot
+  overlay_params: PlotParams | null;
+  // bumped by every (re)plot, tiles of an older one are never served
+  generation: number;
+  // python's round trip the last time the bridge fetched the tile, null if
+  // it never did or too long ago
+  render_ms: number | null;
+  // segments whose bounding box overlaps the tile
+  n_segments_in_tile: number;
+  cache_status: 'pinned' | 'cached' | 'missing';
+  // [x0, y0, x1, y1] in full resolution pixels, end exclusive
+  bbox: [number, number, number, number];
+}
+
+// metadata only, nothing is rendered and the tile cache isn't touched
+export async function getTileProvenance(
+  overlayId: string,
+  zoom: number,
+  x: number,
+  y: number,
+  quality?: RenderQuality
+): Promise<TileProvenance> {
+  return await invoke('get_tile_provenance_cmd', { overlayId, zoom, x, y, quality });
+}
+
+export interface SegmentSummary {
+  segment_id: string;
+  value: number | string | null;
+}
+
+// cheap getSegmentAt for mousemove: the answer is for the 4x4 pixel square
+// around x/y and cached per square, null over background
+export async function hoverProbe(
+  overlayId: string,
+  x: number,
+  y: number,
+  options?: CallOptions
+): Promise<SegmentSummary | null> {
+  return await invoke('hover_probe_cmd', { overlayId, x, y, timeoutMs: options?.timeoutMs });
+}
+
+// hoverProbe for a stream of mouse positions: one request in flight at a
+// time, positions arriving meanwhile replace each other and only the last
+// one is probed next. onResult sees answers in order, never a stale one
+// after a newer position's
+export function createHoverProbe(
+  overlayId: string,
+  onResult: (segment: SegmentSummary | null) => void,
+  onError?: (error: unknown) => void
+): (x: number, y: number) => void {
+  let pending: [number, number] | null = null;
+  let inFlight = false;
+
+  const run = async () => {
+    inFlight = true;
+    while (pending) {
+      const [x, y] = pending;
+      pending = null;
+      try {
+        const segment = await hoverProbe(overlayId, Math.floor(x), Math.floor(y));
+        if (!pending) onResult(segment);
+      } catch (e) {
+        onError?.(e);
+      }
+    }
+    inFlight = false;
+  };
+
+  return (x, y) => {
+    // outside the image on the top left, the bridge only takes u32
+    if (x < 0 || y < 0) {
+      pending = null;
+      if (!inFlight) onResult(null);
+      return;
+    }
+    pending = [x, y];
+    if (!inFlight) void run();
+  };
+}
+
+export interface NeighborhoodSummary {
+  n_segments: number;
+  // [category, count] in legend order, empty for gene and numeric fills
+  category_composition: [string, number][];
+  // null for categorical fills
+  mean_fill: number | null;
+  // what was queried, snapped to 8 pixels and the radius capped
+  x: number;
+  y: number;
+  radius: number;
+}
+
+// segments whose centroid is within radius full resolution pixels of x/y,
+// a zeroed summary when there are none
+export async function getNeighborhoodSummary(
+  overlayId: string,
+  x: number,
+  y: number,
+  radius: number,
+  options?: CallOptions
+): Promise<NeighborhoodSummary> {
+  return await invoke('get_neighborhood_summary_cmd', {
+    overlayId,
+    x,
+    y,
+    radius,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// up to k (at most 100) segments by centroid distance, nearest first
+export async function nearestSegments(
+  overlayId: string,
+  x: number,
+  y: number,
+  k: number,
+  options?: CallOptions
+): Promise<SegmentInfo[]> {
+  return await invoke('nearest_segments_cmd', {
+    overlayId,
+    x,
+    y,
+    k,
+    timeoutMs: options?.timeoutMs
   });
 }
+
+export interface Page<T> {
+  items: T[];
+  // items over all pages
+  total: number;
+  // null on the last page
+  next_page: number | null;
+}
+
+// segments whose bbox overlaps [x0, y0, x1, y1] (full resolution pixels, end
+// exclusive), ordered by id so pages line up across calls. page is 0 based,
+// pageSize defaults to 1000 and is capped at 5000
+export async function querySegmentsInBbox(
+  overlayId: string,
+  bbox: [number, number, number, number],
+  page?: number,
+  pageSize?: number,
+  options?: CallOptions
+): Promise<Page<SegmentInfo>> {
+  return await invoke('query_segments_in_bbox_cmd', {
+    overlayId,
+    bbox,
+    page,
+    pageSize,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// every segment overlapping bbox, a page at a time so each can be drawn
+// before the next arrives
+export async function* segmentsInBbox(
+  overlayId: string,
+  bbox: [number, number, number, number],
+  pageSize?: number,
+  options?: CallOptions
+): AsyncGenerator<SegmentInfo[]> {
+  let page: number | null = 0;
+  while (page !== null) {
+    const result: Page<SegmentInfo> = await querySegmentsInBbox(
+      overlayId,
+      bbox,
+      page,
+      pageSize,
+      options
+    );
+    yield result.items;
+    page = result.next_page;
+  }
+}
+
+export interface PrefetchReport {
+  // ring tiles after the 256 tile cap
+  scheduled: number;
+  // of those, already in the tile cache
+  cached: number;
+  fetched: number;
+  failed: number;
+  // left out by the cap, the outermost rings
+  skipped: number;
+  // stopped by cancelPrefetch, the counts cover the tiles handled until then
+  cancelled: boolean;
+}
+
+// warms `radius` tiles around a full resolution point at each zoom level,
+// innermost rings first, e.g. right after plotting. prefetchId is chosen by
+// the caller for cancelPrefetch
+export async function prefetchLevels(
+  prefetchId: string,
+  overlayId: string,
+  center: { x: number; y: number },
+  zoomLevels: number[],
+  radius: number,
+  options?: CallOptions
+): Promise<PrefetchReport> {
+  return await invoke('prefetch_levels_cmd', {
+    prefetchId,
+    overlayId,
+    centerX: center.x,
+    centerY: center.y,
+    zoomLevels,
+    radius,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// resolves false if the prefetch already finished
+export async function cancelPrefetch(prefetchId: string): Promise<boolean> {
+  return await invoke('cancel_prefetch_cmd', { prefetchId });
+}
+
+export interface RegionTile {
+  coord: TileCoord;
+  // exactly one of tile / error is set
+  tile: { tile: string; format: string } | null;
+  error: string | null;
+}
+
+// tiles covering [x0, x1) x [y0, y1) in pixels of the given zoom level
+export async function getTilesForRegion(
+  overlayId: string,
+  zoom: number,
+  x0: number,
+  y0: number,
+  x1: number,
+  y1: number,
+  options?: CallOptions
+): Promise<RegionTile[]> {
+  return await invoke('get_tiles_for_region_cmd', {
+    overlayId,
+    zoom,
+    x0,
+    y0,
+    x1,
+    y1,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// payload of the `viewport_tile` events of subscribeViewport
+export type ViewportTileEvent = {
+  subscription_id: string;
+  // the updateViewport call it belongs to
+  update: number;
+} & (
+  | { event: 'tile_arrived'; coord: TileCoord; tile: { tile: string; format: string } }
+  | {
+      event: 'tile_dropped';
+      coord: TileCoord;
+      reason: 'superseded' | 'failed' | 'ended';
+      error: string | null;
+    }
+  | { event: 'idle' }
+  | { event: 'ended'; reason: 'unsubscribed' | 'overlay_gone' | 'shutting_down' }
+);
+
+// streams the overlay's tiles as `viewport_tile` events, each updateViewport
+// queues the missing tiles of the new viewport and drops the queued ones it
+// moved off. resolves to the subscription id
+export async function subscribeViewport(
+  overlayId: string,
+  quality?: RenderQuality
+): Promise<string> {
+  return await invoke('subscribe_viewport_cmd', { overlayId, quality });
+}
+
+// [x0, x1) x [y0, y1) in pixels of the given zoom level, resolves to the
+// update number the events carry
+export async function updateViewport(
+  subscriptionId: string,
+  zoom: number,
+  x0: number,
+  y0: number,
+  x1: number,
+  y1: number
+): Promise<number> {
+  return await invoke('update_viewport_cmd', { subscriptionId, zoom, x0, y0, x1, y1 });
+}
+
+// cancels the tile being fetched too, it arrives as dropped with the queued
+// ones. resolves false if the subscription already ended
+export async function unsubscribeViewport(subscriptionId: string): Promise<boolean> {
+  return await invoke('unsubscribe_viewport_cmd', { subscriptionId });
+}
+
+// one operation of a preprocessDataset pipeline, leiden, gene_score and
+// qc_metrics add obs columns usable as a fill key
+export type PreprocStep =
+  | { op: 'normalize'; target_sum?: number | null }
+  | { op: 'pca'; n_comps: number }
+  | { op: 'neighbors'; n_neighbors: number }
+  | { op: 'leiden'; resolution: number; key: string }
+  | { op: 'gene_score'; genes: string[]; key: string }
+  | { op: 'qc_metrics'; prefix?: string };
+
+// payload of the `preprocess_progress` events
+export interface PreprocessStatus {
+  preprocessing_id: string;
+  dataset_id: string;
+  done: number;
+  total: number;
+  // obs columns added so far
+  keys: string[];
+  state: 'running' | 'completed' | 'cancelled' | 'failed';
+  error: string | null;
+}
+
+// rejects a pipeline with an invalid step before running any of it,
+// resolves to the preprocessing id its `preprocess_progress` events carry
+export async function preprocessDataset(datasetId: string, steps: PreprocStep[]): Promise<string> {
+  return await invoke('preprocess_dataset_cmd', { datasetId, steps });
+}
+
+// null once the pipeline finished, its last event has the outcome
+export async function getPreprocessing(preprocessingId: string): Promise<PreprocessStatus | null> {
+  return await invoke('get_preprocessing_cmd', { preprocessingId });
+}
+
+// stops after the step in progress, resolves false if it isn't running
+export async function cancelPreprocessing(preprocessingId: string): Promise<boolean> {
+  return await invoke('cancel_preprocessing_cmd', { preprocessingId });
+}
+
+export type ExportOutcome =
+  | ({ status: 'completed' } & ExportReport)
+  | { status: 'aborted'; done: number; total: number };
+
+// progress arrives as `export_progress` events carrying the same exportId
+export async function exportTilePyramid(
+  exportId: string,
+  overlayId: string,
+  outDir: string,
+  format: TileFormat,
+  options?: CallOptions
+): Promise<ExportOutcome> {
+  return await invoke('export_tile_pyramid_cmd', {
+    exportId,
+    overlayId,
+    outDir,
+    format,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface OmeTiffReport {
+  path: string;
+  // full resolution
+  width: number;
+  height: number;
+  // in file order: 'overlay red', 'overlay green', 'overlay blue', then the
+  // base image's when included
+  channels: string[];
+  // each half the size of the one before
+  levels: number;
+  // microns per pixel, null for an uncalibrated dataset
+  pixel_size: number | null;
+}
+
+export type OmeTiffOutcome =
+  | ({ status: 'completed' } & OmeTiffReport)
+  | { status: 'aborted'; done: number; total: number };
+
+// a pyramidal OME-TIFF for QuPath or napari. progress arrives as
+// `export_progress` events, one per level, and cancelExport stops it
+export async function exportOmeTiff(
+  exportId: string,
+  overlayId: string,
+  path: string,
+  includeImage?: boolean,
+  options?: CallOptions
+): Promise<OmeTiffOutcome> {
+  return await invoke('export_ome_tiff_cmd', {
+    exportId,
+    overlayId,
+    path,
+    includeImage,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export type ComparisonLayout = { kind: 'side_by_side' } | { kind: 'swipe'; split: number };
+
+// width/height are the figure's, max_dimension and clamped each panel's
+export type ComparisonOutcome =
+  | ({ status: 'completed' } & RegionExport)
+  | { status: 'aborted'; done: number; total: number };
+
+// the same region ([x0, y0, x1, y1], as for exportRegionPng) of two overlays
+// with the same geometry in one png figure, labeled with their fill keys.
+// maxDimension applies to each panel. progress arrives as `export_progress`
+// events, three steps, and cancelExport stops it
+export async function exportComparison(
+  exportId: string,
+  overlayA: string,
+  overlayB: string,
+  region: [number, number, number, number],
+  path: string,
+  layout: ComparisonLayout,
+  maxDimension?: number,
+  options?: CallOptions
+): Promise<ComparisonOutcome> {
+  return await invoke('export_comparison_cmd', {
+    exportId,
+    overlayA,
+    overlayB,
+    region,
+    path,
+    layout,
+    maxDimension,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// resolves false if the export already finished
+export async function cancelExport(exportId: string): Promise<boolean> {
+  return await invoke('cancel_export_cmd', { exportId });
+}
+
+export interface Progress {
+  // 0 to 1
+  fraction: number;
+  // e.g. 'waiting' for the bridge or 'tiles'
+  stage: string;
+}
+
+// latest progress of a running command by the id it was started with (an
+// exportId for now), null once it finished or if the id is unknown. for a
+// window that missed the progress events
+export async function getCommandProgress(id: string): Promise<Progress | null> {
+  return await invoke('get_command_progress_cmd', { id });
+}
+
+export interface RegionExport {
+  path: string;
+  // of the written png, smaller than the region if downscaled
+  width: number;
+  height: number;
+  // what it was exported with, lower than asked for when clamped
+  max_dimension?: number | null;
+  // downscaled further than asked to stay within the export memory budget
+  clamped?: boolean;
+}
+
+export interface ExportEstimate {
+  // of the png exportRegionPng would write
+  width: number;
+  height: number;
+  // zoom level the region is stitched from
+  zoom: number;
+  // peak image memory in python
+  est_bytes: number;
+}
+
+export async function estimateRegionExport(
+  overlayId: string,
+  x0: number,
+  y0: number,
+  x1: number,
+  y1: number,
+  maxDimension?: number
+): Promise<ExportEstimate> {
+  return await invoke('estimate_region_export_cmd', {
+    overlayId,
+    x0,
+    y0,
+    x1,
+    y1,
+    maxDimension
+  });
+}
+
+// full resolution pixels, corners in any order, clamped to the overlay.
+// exports over the bridge's memory budget are downscaled to fit unless
+// forceFullSize
+export async function exportRegionPng(
+  overlayId: string,
+  x0: number,
+  y0: number,
+  x1: number,
+  y1: number,
+  path: string,
+  maxDimension?: number,
+  // drawn after downscaling
+  scalebar?: ScalebarOptions,
+  forceFullSize?: boolean,
+  options?: CallOptions
+): Promise<RegionExport> {
+  return await invoke('export_region_png_cmd', {
+    overlayId,
+    x0,
+    y0,
+    x1,
+    y1,
+    path,
+    maxDimension,
+    scalebar,
+    forceFullSize,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export type LegendOrientation = 'vertical' | 'horizontal';
+
+// the overlay's legend as a png: a gradient bar in its colormap and range, or
+// category swatches. size (64..4096, 400 by default) is the long side before
+// trimming, lay out with the returned width/height
+export async function exportLegend(
+  overlayId: string,
+  path: string,
+  orientation?: LegendOrientation,
+  size?: number,
+  options?: CallOptions
+): Promise<RegionExport> {
+  return await invoke('export_legend_cmd', {
+    overlayId,
+    path,
+    orientation,
+    size,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// parquet needs pyarrow in the python environment
+export type TableFormat = 'csv' | 'parquet';
+
+export interface SegmentsExport {
+  path: string;
+  rows: number;
+}
+
+// one row per segment: id, centroid, bbox, pixel count, fill value, obs columns
+export async function exportSegments(
+  overlayId: string,
+  path: string,
+  format: TableFormat,
+  options?: CallOptions
+): Promise<SegmentsExport> {
+  return await invoke('export_segments_cmd', {
+    overlayId,
+    path,
+    format,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface OverlayStats {
+  overlay_id: string;
+  levels: { zoom: number; width: number; height: number; tiles: number; bytes: number }[];
+  total_tiles: number;
+  total_bytes: number;
+}
+
+export type OverlayLegend =
+  | { kind: 'categorical'; categories: { name: string; color: string | null }[] }
+  | { kind: 'continuous'; min: number; max: number };
+
+export interface EnvironmentInfo {
+  python_version: string;
+  platform: string;
+  pid: number;
+  packages: Record<string, string | null>;
+  memory_rss_bytes: number | null;
+  // limits in effect inside python, null where it can't tell (windows)
+  resource_limits: ResourceLimits | null;
+  // null from older python helpers
+  render_backend: RenderBackend | null;
+}
+
+// a GUI backend while headless can't plot, prewarm fails with a hint
+export interface RenderBackend {
+  name: string;
+  interactive: boolean;
+  headless: boolean;
+}
+
+// set through the bridge builder, null fields are unlimited
+export interface ResourceLimits {
+  memory_bytes: number | null;
+  cpu_seconds: number | null;
+}
+
+// stats, legend and environment info are cached on the rust side,
+// pass forceRefresh to bypass the cache
+export async function getOverlayStats(
+  overlayId: string,
+  forceRefresh?: boolean,
+  options?: CallOptions
+): Promise<OverlayStats> {
+  return await invoke('get_overlay_stats_cmd', {
+    overlayId,
+    forceRefresh,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export async function getOverlayLegend(
+  overlayId: string,
+  forceRefresh?: boolean,
+  options?: CallOptions
+): Promise<OverlayLegend> {
+  return await invoke('get_overlay_legend_cmd', {
+    overlayId,
+    forceRefresh,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// [category, segment count] pairs in legend order, categorical fills only
+export async function getCategoryCounts(
+  overlayId: string,
+  forceRefresh?: boolean,
+  options?: CallOptions
+): Promise<[string, number][]> {
+  return await invoke('get_category_counts_cmd', {
+    overlayId,
+    forceRefresh,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export async function getEnvironmentInfo(
+  forceRefresh?: boolean,
+  options?: CallOptions
+): Promise<EnvironmentInfo> {
+  return await invoke('get_environment_info_cmd', { forceRefresh, timeoutMs: options?.timeoutMs });
+}
+
+export interface ColormapInfo {
+  name: string;
+  kind: 'sequential' | 'diverging' | 'qualitative';
+  // rgba samples from one end to the other
+  preview_stops: [number, number, number, number][];
+}
+
+export interface Histogram {
+  // counts.length + 1 boundaries, ascending
+  edges: number[];
+  counts: number[];
+  min: number;
+  max: number;
+}
+
+// cells per value bin of a gene or numeric column, for a value range slider.
+// bins 1..=1024, categorical keys are refused. cached until the dataset is
+// reloaded or unloaded
+export async function getValueHistogram(
+  datasetId: string | undefined,
+  fillKey: string,
+  bins: number,
+  options?: CallOptions
+): Promise<Histogram> {
+  return await invoke('get_value_histogram_cmd', {
+    datasetId,
+    fillKey,
+    bins,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface ImageInfo {
+  img_id: string;
+  // the TIFF's id, or the registered image's key, e.g. "hires"
+  name: string;
+  // [width, height] in pixels
+  dimensions: [number, number];
+  channels: number;
+}
+
+// images the dataset's overlays can be drawn over: loaded TIFFs first, then
+// the dataset's registered images ("<library>/<name>"). cached until the
+// dataset is reloaded or unloaded, forceRefresh picks up a newly loaded TIFF
+export async function listImages(
+  datasetId?: string,
+  forceRefresh?: boolean,
+  options?: CallOptions
+): Promise<ImageInfo[]> {
+  return await invoke('list_images_cmd', {
+    datasetId,
+    forceRefresh,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+// reversed "_r" variants are accepted but not listed
+export async function listColormaps(options?: CallOptions): Promise<ColormapInfo[]> {
+  return await invoke('list_colormaps_cmd', { timeoutMs: options?.timeoutMs });
+}
+
+// resolves once every command issued before it has completed
+export async function barrier(options?: CallOptions): Promise<void> {
+  return await invoke('barrier_cmd', { timeoutMs: options?.timeoutMs });
+}
+
+export async function clearCaches(): Promise<void> {
+  return await invoke('clear_caches_cmd');
+}
+
+export async function deleteOverlay(overlayId: string, options?: CallOptions): Promise<void> {
+  return await invoke('delete_overlay_cmd', { overlayId, timeoutMs: options?.timeoutMs });
+}
+
+// pinned levels are never evicted from the tile cache, the overview levels
+// are pinned when an overlay is plotted
+export async function pinTiles(overlayId: string, zoomLevels: number[]): Promise<void> {
+  return await invoke('pin_tiles_cmd', { overlayId, zoomLevels });
+}
+
+export async function unpinTiles(overlayId: string, zoomLevels: number[]): Promise<void> {
+  return await invoke('unpin_tiles_cmd', { overlayId, zoomLevels });
+}
+
+export interface TileCacheStats {
+  // pinned tiles included
+  entries: number;
+  bytes: number;
+  capacity_bytes: number;
+  pinned_bytes: number;
+  hits: number;
+  misses: number;
+  // tiles dropped to make room, many of them with a low hit_rate while
+  // panning the same area mean the cache is too small
+  evictions: number;
+  // null before the first lookup
+  hit_rate: number | null;
+  compact: boolean;
+}
+
+// the overlay tile cache's numbers, the base image tile cache's with baseImage
+export async function getTileCacheStats(baseImage?: boolean): Promise<TileCacheStats> {
+  return await invoke('get_tile_cache_stats_cmd', { baseImage });
+}
+
+// zero hits, misses and evictions of both caches, the tiles stay cached
+export async function resetTileCacheStats(): Promise<void> {
+  return await invoke('reset_tile_cache_stats_cmd');
+}
+
+export interface SnapshotCounts {
+  // in the file
+  tiles: number;
+  // written, or put back into the cache on load
+  restored: number;
+  // tiles of overlays since removed or re-plotted, always 0 on save
+  discarded: number;
+}
+
+// write the overlay tile cache to one file, to re-prime it later
+export async function saveCacheSnapshot(path: string): Promise<SnapshotCounts> {
+  return await invoke('save_cache_snapshot_cmd', { path });
+}
+
+// restore a saved snapshot, keeping only tiles whose overlay is still at
+// the generation and plot params it was saved with
+export async function loadCacheSnapshot(path: string): Promise<SnapshotCounts> {
+  return await invoke('load_cache_snapshot_cmd', { path });
+}
+
+export type BridgeStatus =
+  | { state: 'not_started' }
+  | { state: 'starting' }
+  | { state: 'ready' }
+  | { state: 'failed'; error: string }
+  | { state: 'dead' }
+  // stopped after an idle period, restarts on the next command
+  | { state: 'idle' };
+
+export async function getBridgeStatus(): Promise<BridgeStatus> {
+  return await invoke('get_bridge_status_cmd');
+}
+
+export interface CommandSpec {
+  name: string;
+  params: { name: string; required: boolean; type: string | null }[];
+}
+
+export async function listSupportedCommands(options?: CallOptions): Promise<CommandSpec[]> {
+  return await invoke('list_supported_commands_cmd', { timeoutMs: options?.timeoutMs });
+}
+
+export interface CommandMetrics {
+  command: string;
+  in_flight: number;
+  // requests waiting for a slot
+  queued: number;
+  // null when the command is unlimited
+  limit: number | null;
+}
+
+export async function getCommandMetrics(): Promise<CommandMetrics[]> {
+  return await invoke('get_command_metrics_cmd');
+}
+
+// plot and tile commands run on separate python workers, control ones on
+// its main thread
+export interface LaneMetrics {
+  lane: 'plot' | 'tile' | 'control';
+  in_flight: number;
+  queued: number;
+  limit: number | null;
+  // only the tile lane, see pauseTiles
+  paused: boolean;
+}
+
+export async function getLaneMetrics(): Promise<LaneMetrics[]> {
+  return await invoke('get_lane_metrics_cmd');
+}
+
+// where one command's response time goes, means per response in ms
+export interface WireMetrics {
+  command: string;
+  responses: number;
+  mean_bytes: number;
+  max_bytes: number;
+  serialize_ms: number;
+  // python running the handler, 0 from older scripts
+  compute_ms: number;
+  // the rest of the round trip, pipe and python's json encoding
+  ipc_ms: number;
+  // for a direct decode only the envelope, the data is parsed in decode_ms
+  parse_ms: number;
+  decode_ms: number;
+}
+
+// commands with no response yet are left out. answers while busy
+export async function getWireMetrics(): Promise<WireMetrics[]> {
+  return await invoke('get_wire_metrics_cmd');
+}
+
+// the latest command's lines exactly as they crossed the pipe, base64 tile
+// data summarized as "<N bytes of base64>"
+export interface WireFrames {
+  command: string;
+  request: number[];
+  // empty if no response arrived
+  response: number[];
+  // sizes on the wire, before redaction and the cut
+  request_bytes: number;
+  response_bytes: number;
+  redacted: string[];
+  truncated: boolean;
+}
+
+// null unless the bridge was built with capture_wire_frames
+export async function getLastWireFrames(): Promise<WireFrames | null> {
+  return await invoke('get_last_wire_frames_cmd');
+}
+
+// a stage python timed in its handler: "load", "render" or "encode"
+export interface PythonSpan {
+  name: string;
+  // from the start of the handler
+  start_ms: number;
+  duration_ms: number;
+}
+
+// where one traced request spent its time on both sides of the pipe
+export interface RequestTrace {
+  trace_id: number;
+  command: string;
+  // unix time in ms
+  at_ms: number;
+  total_ms: number;
+  serialize_ms: number;
+  python_ms: number;
+  ipc_ms: number;
+  parse_ms: number;
+  spans: PythonSpan[];
+}
+
+// off by default, while on every request is traced (the last 200 kept)
+export async function setRequestTracing(enabled: boolean): Promise<void> {
+  return await invoke('set_request_tracing_cmd', { enabled });
+}
+
+// oldest first, answers while busy
+export async function getRequestTraces(): Promise<RequestTrace[]> {
+  return await invoke('get_request_traces_cmd');
+}
+
+// serve tiles from the cache only, e.g. during a big plot. uncached tiles
+// wait until resumeTiles, getLaneMetrics counts them as queued. works while
+// a plot is running
+export async function pauseTiles(): Promise<void> {
+  return await invoke('pause_tiles_cmd');
+}
+
+export async function resumeTiles(): Promise<void> {
+  return await invoke('resume_tiles_cmd');
+}
+
+// effective builder settings and defaults, durations in ms (null = off)
+export interface BridgeConfig {
+  python_path: string;
+  script_path: string;
+  embedded_script: boolean;
+  namespace: string;
+  startup_timeout_ms: number;
+  response_timeout_ms: number | null;
+  // command name -> its own timeout, overriding response_timeout_ms
+  command_timeouts_ms: Record<string, number>;
+  kill_on_timeout: boolean;
+  // commands decoded without an intermediate json value
+  direct_decode: string[];
+  idle_timeout_ms: number | null;
+  tile_cache_bytes: number;
+  pinned_tile_bytes: number;
+  compact_tile_cache: boolean;
+  tile_processing_stage: 'after_cache' | 'before_cache';
+  export_memory_budget: number | null;
+  warmup_levels: number;
+  // unlimited lanes and commands are left out
+  lane_limits: Record<string, number>;
+  concurrency_limits: Record<string, number>;
+  id_encoding: 'string' | 'number';
+  resource_limits: ResourceLimits;
+  respawn_on_desync: boolean;
+  capture_wire_frames: boolean;
+  strict_commands: boolean;
+  // commands the python helper didn't list at startup, they fail when
+  // called. always empty when strict_commands refused such a helper
+  missing_commands: string[];
+  protocol_version: number | null;
+  python_pid: number | null;
+  // set when python_path is a launcher that handed over to another process
+  launcher_pid: number | null;
+  transport: 'stdio';
+  // PYTHON*, CONDA_*, VIRTUAL_ENV, OMP_* variables, secrets "<redacted>"
+  python_env: Record<string, string>;
+  // set through the builder's env, redacted the same way
+  env: Record<string, string>;
+}
+
+export async function getBridgeConfig(): Promise<BridgeConfig> {
+  return await invoke('get_bridge_config_cmd');
+}
+
+// only stdio is built in, asking for the others fails with a clear error
+export type TransportConfig =
+  | { kind: 'stdio' }
+  | { kind: 'unix_socket'; path: string }
+  | { kind: 'tcp'; host: string; port: number }
+  | { kind: 'shared_memory'; name: string };
+
+export interface TransportInfo {
+  transport: TransportConfig;
+  // 'json_lines'
+  framing: string;
+  supported: string[];
+  status: BridgeStatus;
+  python_pid: number | null;
+  launcher_pid: number | null;
+  // connections set up again since startup, crash and idle respawns included
+  reconnects: number;
+}
+
+export async function getTransportInfo(): Promise<TransportInfo> {
+  return await invoke('get_transport_info_cmd');
+}
+
+// reconnect over `config`. python restarts, so overlays and datasets have
+// to be loaded again afterwards
+export async function reconfigureTransport(config: TransportConfig): Promise<TransportInfo> {
+  return await invoke('reconfigure_transport_cmd', { config });
+}
+
+export interface DiagnosticsReport {
+  commands: { command: string; params: string; duration_ms: number; ok: boolean; at_ms: number }[];
+  errors: { command: string; error: string; at_ms: number }[];
+  stderr_tail: string[];
+}
+
+// null until the bridge has been started
+export async function getDiagnostics(): Promise<DiagnosticsReport | null> {
+  return await invoke('get_diagnostics_cmd');
+}
+
+export interface DatasetSummary {
+  dataset_id: string;
+  n_obs: number;
+  n_vars: number;
+  // "sha256:<hex>" when loaded with a checksum check, null otherwise
+  checksum?: string | null;
+}
+
+// missing sections are null or empty, failures says why. home dir shown as ~
+export interface CrashReport {
+  captured_at_ms: number;
+  status: BridgeStatus;
+  config: BridgeConfig | null;
+  command_metrics: CommandMetrics[];
+  lane_metrics: LaneMetrics[];
+  diagnostics: DiagnosticsReport | null;
+  environment: EnvironmentInfo | null;
+  datasets: DatasetSummary[] | null;
+  overlays: OverlayInfo[];
+  failures: string[];
+}
+
+// always resolves, even with python dead or busy, to save and attach as JSON
+export async function captureCrashReport(): Promise<CrashReport> {
+  return await invoke('capture_crash_report_cmd');
+}
+
+// applied in rust to overlay and base image tiles as they are served,
+// python's renders and the cache stay unadjusted unless the bridge was
+// built with process_tiles_before_cache
+export interface TileAdjustment {
+  // -1 (black) to 1 (white), 0 leaves it
+  brightness: number;
+  // 0 flattens to gray, 1 leaves it
+  contrast: number;
+  // above 1 brightens midtones, 1 leaves it
+  gamma: number;
+}
+
+// null serves tiles as python rendered them
+export async function setTileAdjustment(adjustment: TileAdjustment | null): Promise<void> {
+  return await invoke('set_tile_adjustment_cmd', { adjustment });
+}
+
+export async function getTileAdjustment(): Promise<TileAdjustment | null> {
+  return await invoke('get_tile_adjustment_cmd');
+}
+
+// the format overlay and base image tiles are served in. 'auto' picks per
+// tile: png for transparent or flat tiles (segment fills, background), jpeg
+// for photographic ones, read each tile's mime. force 'jpeg' or 'png' for
+// output that doesn't depend on content. defaults to 'jpeg'
+export type TileFormatMode = 'jpeg' | 'png' | 'auto';
+
+export async function setTileFormat(mode: TileFormatMode): Promise<void> {
+  return await invoke('set_tile_format_cmd', { mode });
+}
+
+export async function getTileFormat(): Promise<TileFormatMode> {
+  return await invoke('get_tile_format_cmd');
+}
+
+export interface BenchmarkResult {
+  // after capping to 1..1000 round trips and 16 MiB payloads
+  iterations: number;
+  payload_size: number;
+  rtt_p50_ms: number;
+  rtt_p95_ms: number;
+  // payload megabits per second, both directions counted
+  throughput_mbps: number;
+}
+
+// time echo round trips through the bridge, a number to attach to
+// performance complaints. python does no work, so slow results point at
+// the pipe or the machine rather than rendering
+export async function benchmark(
+  iterations: number,
+  payloadSize: number,
+  options?: CallOptions
+): Promise<BenchmarkResult> {
+  return await invoke('benchmark_cmd', {
+    iterations,
+    payloadSize,
+    timeoutMs: options?.timeoutMs
+  });
+}
+
+export interface DecodeBenchmark {
+  // after capping to 1..1,000,000
+  rows: number;
+  bytes: number;
+  // median ms through a json value and decoded directly
+  value_ms: number;
+  direct_ms: number;
+  speedup: number;
+}
+
+// time decoding a segment table of `rows` both ways, in rust alone
+export async function benchmarkDecode(rows: number): Promise<DecodeBenchmark> {
+  return await invoke('benchmark_decode_cmd', { rows });
+}
@@ -0,0 +1,5 @@
+//! the python helper stand-in as a program, see `rust_bridge::fake_python`
+
+fn main() {
+    rust_bridge::fake_python::run();
+}
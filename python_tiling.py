@@ -1,25 +1,75 @@
 import io
+import sys
+import codecs
+import random
+import contextlib
+import json
 import base64
 import tempfile
 import os
+import logging
+import queue
+import select
+import threading
 from pathlib import Path
-from typing import Dict, Any
+from typing import Dict, Any, List, Optional
 import numpy as np
 import tifffile as tiff
-from PIL import Image
+from PIL import Image, ImageDraw, ImageFont
 import bin2cell as b2c
 
 # global in-memory state
 # stores generated tiles: TILES[overlay_id] = {zoom_level: {(x, y): tile_bytes}}
+# (a LazyPyramid for overlays plotted with pyramid_mode "lazy")
 TILES = {}
 DATASETS = {}  
+# dataset_id -> "sha256:<hex>" of the file, for datasets loaded with one
+DATASET_CHECKSUMS = {}
 IMAGE = {}     
 SEGMENTATION = {}
+# per-segmentation lookup tables built on first use, see segment_index.
+# keyed by (seg_id, orientation, transform, origin)
+SEGMENT_INDEX = {}
+# context_id -> a plot's inputs set up once, see open_render_context
+RENDER_CONTEXTS = {}
+
+# how a plot can be turned, see orient_array. rotations are clockwise
+ORIENTATIONS = ('rotate0', 'rotate90', 'rotate180', 'rotate270',
+                'flip_horizontal', 'flip_vertical')
+
+# where an overlay's y coordinates count from, see segment_index. tiles are
+# stored top_left, the rust side flips tile rows for bottom_left overlays
+ORIGINS = ('top_left', 'bottom_left')
+
+# quality -> (downsampling filter, jpeg quality, chroma subsampling) of a
+# plot's pyramid, see RenderQuality on the rust side. subsampling 0 is 4:4:4
+RENDER_QUALITY = {
+    'fast': (Image.NEAREST, 75, 2),
+    'balanced': (Image.LANCZOS, 85, 2),
+    'high': (Image.LANCZOS, 95, 0)
+}
+
+# jpeg quality of the half resolution tiles served for quality "fast"
+FAST_TILE_QUALITY = 60
+
+# formats a tile can be asked for in, "auto" picks one per tile, see
+# pick_tile_format
+TILE_FORMATS = ('jpeg', 'png', 'auto')
+TILE_MIME = {'jpeg': 'image/jpeg', 'png': 'image/png'}
+
+# luminance entropy in bits (0 for a single color, 8 at most) below which
+# an "auto" tile is served as png
+AUTO_PNG_MAX_ENTROPY = 4.0
+
+# serializes protocol writes to stdout, see write_message
+STDOUT_LOCK = threading.Lock()
 
 
 def generate_tiles_from_image(pil_img: Image.Image, 
                               tile_size: int = 256, 
-                              max_zoom: int = 4) -> Dict[int, Dict[tuple, bytes]]:
+                              max_zoom: int = 4,
+                              level_hook=None,
+                              quality: str = 'balanced') -> Dict[int, Dict[tuple, bytes]]:
     """
     generate image tiles at multiple zoom levels (e.g. google maps)
 
@@ -27,47 +77,34 @@ def generate_tiles_from_image(pil_img: Image.Image,
         pil_img: PIL Image to tile
         tile_size: size of each tile (default 256x256)
         max_zoom: maximum zoom level (0 = most zoomed out)
+        level_hook: optional callable(zoom, scale, scaled_img) returning the
+            image to tile for that level, see lod_level_hook
+        quality: key of RENDER_QUALITY, how levels are downsampled and encoded
 
     returns:
         dict mapping zoom_level -> {(x, y): tile_jpeg_bytes}
     """
     import sys
     tiles = {}
+    if quality not in RENDER_QUALITY:
+        raise ValueError(f'Unknown render quality {quality}')
+    resample, jpeg_quality, subsampling = RENDER_QUALITY[quality]
 
     print(f"Generating tiles from image {pil_img.size}", file=sys.stderr)
 
     # generate tiles for each zoom level
     for zoom in range(max_zoom + 1):
-        scale = 2 ** (max_zoom - zoom)  # zoom 0 = smallest, max_zoom = full size
-        scaled_width = pil_img.width // scale
-        scaled_height = pil_img.height // scale
-
-        # resize image for this zoom level
-        scaled_img = pil_img.resize((scaled_width, scaled_height), Image.LANCZOS)
+        scaled_img = pyramid_level_image(pil_img, zoom, max_zoom, resample, level_hook)
+        scaled_width, scaled_height = scaled_img.size
 
         tiles[zoom] = {}
         tile_count = 0
 
         # split into tiles
-        for y in range(0, scaled_height, tile_size):
-            for x in range(0, scaled_width, tile_size):
-                # crop tile
-                tile = scaled_img.crop((
-                    x,
-                    y,
-                    min(x + tile_size, scaled_width),
-                    min(y + tile_size, scaled_height)
-                ))
-
-                # save tile as JPEG
-                buf = io.BytesIO()
-                tile.save(buf, format='JPEG', quality=85, optimize=True)
-                buf.seek(0)
-
-                # store tile
-                tile_x = x // tile_size
-                tile_y = y // tile_size
-                tiles[zoom][(tile_x, tile_y)] = buf.read()
+        for tile_y in range(-(-scaled_height // tile_size)):
+            for tile_x in range(-(-scaled_width // tile_size)):
+                tiles[zoom][(tile_x, tile_y)] = encode_tile(scaled_img, tile_x, tile_y, tile_size,
+                                                            jpeg_quality, subsampling)
                 tile_count += 1
 
         print(f"  Zoom {zoom}: {scaled_width}x{scaled_height}, {tile_count} tiles", file=sys.stderr)
@@ -75,20 +112,322 @@ def generate_tiles_from_image(pil_img: Image.Image,
     return tiles
 
 
+def pyramid_level_image(pil_img: Image.Image, zoom: int, max_zoom: int, resample,
+                        level_hook=None) -> Image.Image:
+    """
+    the image one zoom level is cut from, see generate_tiles_from_image
+    """
+    scale = 2 ** (max_zoom - zoom)  # zoom 0 = smallest, max_zoom = full size
+    if scale == 1:
+        # no copy, a lazy pyramid would otherwise hold the render twice
+        scaled_img = pil_img
+    else:
+        scaled_img = pil_img.resize((pil_img.width // scale, pil_img.height // scale), resample)
+    if level_hook is not None:
+        scaled_img = level_hook(zoom, scale, scaled_img)
+    return scaled_img
+
+
+def encode_tile(scaled_img: Image.Image, tile_x: int, tile_y: int, tile_size: int,
+                jpeg_quality: int, subsampling: int) -> bytes:
+    """
+    one tile of a level image as JPEG, edge tiles cut short
+    """
+    x, y = tile_x * tile_size, tile_y * tile_size
+    tile = scaled_img.crop((x, y, min(x + tile_size, scaled_img.width),
+                            min(y + tile_size, scaled_img.height)))
+    buf = io.BytesIO()
+    tile.save(buf, format='JPEG', quality=jpeg_quality, subsampling=subsampling, optimize=True)
+    return buf.getvalue()
+
+
+class LazyLevel(dict):
+    """
+    one zoom level of a LazyPyramid: the tiles cut so far, the others are
+    cut on first access. `in` answers for the level's whole grid, len() and
+    values() only cover the tiles cut so far
+    """
+
+    def __init__(self, pyramid, zoom: int, cols: int, rows: int):
+        super().__init__()
+        self.pyramid = pyramid
+        self.zoom = zoom
+        self.cols = cols
+        self.rows = rows
+
+    def __contains__(self, key):
+        x, y = key
+        return 0 <= x < self.cols and 0 <= y < self.rows
+
+    def __missing__(self, key):
+        if key not in self:
+            raise KeyError(key)
+        tile = self.pyramid.cut(self.zoom, *key)
+        self[key] = tile
+        return tile
+
+    def get(self, key, default=None):
+        return self[key] if key in self else default
+
+
+class LazyPyramid(dict):
+    """
+    zoom -> LazyLevel, a generate_tiles_from_image result that cuts its
+    tiles on demand, see plot_tissue_overlay's pyramid_mode
+
+    holds on to the full resolution render and each level image it has
+    cut from until build_level has cut every tile of every level
+    """
+
+    def __init__(self, pil_img: Image.Image, tile_size: int = 256, max_zoom: int = 4,
+                 level_hook=None, quality: str = 'balanced'):
+        super().__init__()
+        if quality not in RENDER_QUALITY:
+            raise ValueError(f'Unknown render quality {quality}')
+        self.source = pil_img
+        self.tile_size = tile_size
+        self.max_zoom = max_zoom
+        self.level_hook = level_hook
+        self.resample, self.jpeg_quality, self.subsampling = RENDER_QUALITY[quality]
+        self.images = {}
+        self.built = set()
+        self.lock = threading.Lock()
+        for zoom in range(max_zoom + 1):
+            scale = 2 ** (max_zoom - zoom)
+            cols = -(-(pil_img.width // scale) // tile_size)
+            rows = -(-(pil_img.height // scale) // tile_size)
+            self[zoom] = LazyLevel(self, zoom, cols, rows)
+
+    def level_image(self, zoom: int) -> Image.Image:
+        # tile requests and build_level may ask from different lanes
+        with self.lock:
+            if zoom not in self.images:
+                self.images[zoom] = pyramid_level_image(self.source, zoom, self.max_zoom,
+                                                        self.resample, self.level_hook)
+            return self.images[zoom]
+
+    def cut(self, zoom: int, x: int, y: int) -> bytes:
+        return encode_tile(self.level_image(zoom), x, y, self.tile_size,
+                           self.jpeg_quality, self.subsampling)
+
+    def build_level(self, zoom: int) -> LazyLevel:
+        """
+        cut every tile of a level not cut yet
+        """
+        level = self[zoom]
+        for y in range(level.rows):
+            for x in range(level.cols):
+                level.get((x, y))
+        with self.lock:
+            self.images.pop(zoom, None)
+            self.built.add(zoom)
+            if self.complete():
+                self.source = None
+        return level
+
+    def complete(self) -> bool:
+        return len(self.built) == len(self)
+
+
+# see plot_tissue_overlay
+PYRAMID_MODES = ('eager', 'lazy')
+
+
+def cut_pyramid(pil_img: Image.Image, mode: str, **kwargs):
+    """
+    generate_tiles_from_image for an eager pyramid, a LazyPyramid for a
+    lazy one
+    """
+    if mode not in PYRAMID_MODES:
+        raise ValueError(f'Unknown pyramid mode {mode}')
+    if mode == 'lazy':
+        return LazyPyramid(pil_img, **kwargs)
+    return generate_tiles_from_image(pil_img, **kwargs)
+
+
+def build_legend(adata, fill_key: str, is_gene: bool) -> Dict[str, Any]:
+    """
+    describe how fill_key values map to colors
+
+    returns:
+        {"kind": "continuous", "min": ..., "max": ...} for genes and numeric
+        columns, {"kind": "categorical", "categories": [{"name", "color"}]}
+        otherwise. colors come from adata.uns[f"{fill_key}_colors"] if set
+    """
+    import pandas as pd
+
+    if is_gene:
+        values = adata[:, fill_key].X
+        values = values.toarray() if hasattr(values, 'toarray') else np.asarray(values)
+        values = values.ravel()
+        return {'kind': 'continuous', 'min': float(values.min()), 'max': float(values.max())}
+
+    column = adata.obs[fill_key]
+    if pd.api.types.is_numeric_dtype(column) and not isinstance(column.dtype, pd.CategoricalDtype):
+        return {'kind': 'continuous', 'min': float(column.min()), 'max': float(column.max())}
+
+    categories = list(column.astype('category').cat.categories)
+    colors = adata.uns.get(f'{fill_key}_colors')
+    return {
+        'kind': 'categorical',
+        'categories': [
+            {
+                'name': str(category),
+                'color': str(colors[i]) if colors is not None and i < len(colors) else None
+            }
+            for i, category in enumerate(categories)
+        ]
+    }
+
+
+def open_render_context(dataset_id: str,
+                        img_id: Optional[str],
+                        seg_id: str) -> Dict[str, Any]:
+    """
+    do a plot's setup once for plots of several keys on the same inputs
+
+    checks the ids, builds the segmentation's index and writes the image
+    and segmentation where bin2cell reads them, into a directory kept until
+    close_render_context. plot_tissue_overlay with the context_id skips all
+    of that, bin2cell still reads the two files per plot. without an image
+    only the segmentation is written, the background is the plot's. there
+    is one context per set of ids, opening it again returns the open one
+
+    args:
+        dataset_id, img_id, seg_id: as for plot_tissue_overlay
+
+    returns:
+        {"context_id", "dataset_id", "img_id", "seg_id"}
+    """
+    if dataset_id not in DATASETS:
+        raise ValueError(f'Dataset {dataset_id} not found')
+    if img_id is not None and find_image(dataset_id, img_id) is None:
+        raise ValueError(f'Image {img_id} not found. Please load TIFF file first.')
+    if seg_id is None:
+        raise ValueError('A segmentation is required to plot an overlay')
+    if seg_id not in SEGMENTATION:
+        raise ValueError(f'Segmentation {seg_id} not found. Please load NPZ file first.')
+
+    context_id = f"context:{dataset_id}:{img_id or 'background'}:{seg_id}"
+    if context_id not in RENDER_CONTEXTS:
+        # removes itself on cleanup() or when python exits
+        directory = tempfile.TemporaryDirectory(prefix='render_context_')
+        try:
+            seg_data = SEGMENTATION[seg_id]
+            seg_path = os.path.join(directory.name, 'seg.npz')
+            np.savez(seg_path, **{k: seg_data[k] for k in seg_data.files})
+            image_path = None
+            if img_id is not None:
+                image_path = os.path.join(directory.name, 'image.tif')
+                tiff.imwrite(image_path, find_image(dataset_id, img_id))
+            segment_index(seg_id)
+        except Exception:
+            directory.cleanup()
+            raise
+        RENDER_CONTEXTS[context_id] = {
+            'dataset_id': dataset_id,
+            'img_id': img_id,
+            'seg_id': seg_id,
+            'directory': directory,
+            'image_path': image_path,
+            'seg_path': seg_path
+        }
+        print(f"Opened render context {context_id}", file=sys.stderr)
+    return {'context_id': context_id, 'dataset_id': dataset_id, 'img_id': img_id,
+            'seg_id': seg_id}
+
+
+def close_render_context(context_id: str) -> Dict[str, Any]:
+    """
+    free a render context and its files, closing an unknown one does nothing
+
+    returns:
+        dict with whether the context was open
+    """
+    context = RENDER_CONTEXTS.pop(context_id, None)
+    if context is not None:
+        context['directory'].cleanup()
+    return {'closed': context is not None}
+
+
+def render_context(context_id: str, dataset_id: str, img_id: Optional[str],
+                   seg_id: str) -> Dict[str, Any]:
+    """
+    the open render context a plot names, which must be for the plot's ids
+    """
+    context = RENDER_CONTEXTS.get(context_id)
+    if context is None:
+        raise ValueError(f'Render context {context_id} not found')
+    for name, value in (('dataset_id', dataset_id), ('img_id', img_id), ('seg_id', seg_id)):
+        if context[name] != value:
+            raise ValueError(
+                f'Render context {context_id} is for {name} {context[name]}, not {value}')
+    return context
+
+
 def plot_tissue_overlay(dataset_id: str,
-                        img_id: str,
+                        img_id: Optional[str],
                         seg_id: str,
                         fill_key: str,
-                        border_key: str = None) -> Dict[str, Any]:
+                        border_key: str = None,
+                        background: Optional[List[int]] = None,
+                        scalebar: Optional[Dict[str, Any]] = None,
+                        lod: Optional[Dict[str, Any]] = None,
+                        color_scale: Optional[Dict[str, Any]] = None,
+                        quality: str = 'balanced',
+                        recolorable: bool = False,
+                        seed: Optional[int] = None,
+                        orientation: str = 'rotate0',
+                        origin: str = 'top_left',
+                        transform: Optional[List[float]] = None,
+                        pyramid_mode: str = 'eager',
+                        segment_limit: Optional[Dict[str, Any]] = None,
+                        context_id: Optional[str] = None,
+                        **extra) -> Dict[str, Any]:
     """
     plot gene expression or cluster labels on tissue with segmentation overlay using bin2cell
 
     args:
         dataset_id: ID of the loaded dataset
-        img_id: ID of the loaded TIFF image
+        img_id: ID of the loaded TIFF image, None to draw the segments over
+            a plain background
         seg_id: ID of the loaded segmentation NPZ
         fill_key: column name in adata.obs (cluster) or gene name
         border_key: optional column for cell borders
+        background: [r, g, b, a] behind the segments when there is no image,
+            white by default. tiles are JPEG, so alpha is blended onto white
+        scalebar: {"length_um", "position", "color"} to burn a scalebar
+            into the overlay, needs the dataset's pixel size calibration
+        lod: {"bin_size", "segments_from_zoom"} to draw zoom levels below
+            segments_from_zoom as bins of bin_size full resolution pixels,
+            see lod_bins. zoom segments_from_zoom..max_zoom show segments
+        color_scale: {"value_range", "symmetric", "center"} for the range
+            a continuous fill_key is colored over, see resolve_value_range
+        quality: "fast", "balanced" or "high", see RENDER_QUALITY
+        recolorable: keep the full resolution render for recolor_overlay,
+            only honoured for continuous values, see recolor_state
+        seed: seeds the random generators for the render, see seeded_rng
+        orientation: one of ORIENTATIONS, turns the render before it is
+            tiled. tile, segment and bbox coordinates are all in the turned
+            frame afterwards
+        origin: one of ORIGINS. with "bottom_left" y counts up from the
+            bottom edge in segment and bbox coordinates, the render itself
+            is the same
+        transform: [a, b, c, d, e, f] affine (x' = a*x + b*y + c,
+            y' = d*x + e*y + f) applied to the image and segmentation before
+            rasterizing, to register them with another dataset. the canvas
+            keeps the segmentation's size, see transform_image. orientation
+            turns the result afterwards
+        pyramid_mode: "eager" cuts every tile before returning, "lazy" keeps
+            the render and cuts each tile on its first request, see
+            LazyPyramid and build_pyramid_level
+        segment_limit: {"max_segments", "sampling"} to render at most that
+            many segments, see sample_segments. everything but the render
+            still sees every segment
+        context_id: a render context for these ids, see
+            open_render_context. its files are used unless transform (both)
+            or segment_limit (the segmentation) changes them
+        **extra: passed through to bin2cell.view_cell_labels unchanged
 
     returns:
         dict containing metadata:
@@ -98,20 +437,85 @@ def plot_tissue_overlay(dataset_id: str,
         - max_zoom: 4
         - fill_key: what was visualized
         - is_gene: whether fill_key is a gene or cluster column
+        - has_image: False when rendered without a base image
+        - scalebar: the scalebar options drawn, None without one
+        - lod: the LOD options used, None if every level shows segments
+        - value_range: [min, max] the colormap spans, None for categories
+        - quality: the render quality used
+        - recolorable: whether recolor_overlay can repaint it in place
+        - seed: the seed rendered with, None if unseeded
+        - orientation: how the render was turned, width/height are after it
+        - origin: where y coordinates count from
+        - transform: the affine applied before rasterizing, None without one
+        - pyramid_mode: how the tiles are cut
+        - segment_limit: the cap the render was sampled to, None without one
+        - segments_dropped: how many segments segment_limit left out
+        - timings: load_ms (writing inputs for bin2cell), render_ms
+          (bin2cell rasterization) and tile_ms (cutting the pyramid)
     """
+    import time
+
     try:
         if dataset_id not in DATASETS:
             raise ValueError(f'Dataset {dataset_id} not found')
 
-        if img_id not in IMAGE:
+        if img_id is not None and find_image(dataset_id, img_id) is None:
             raise ValueError(f'Image {img_id} not found. Please load TIFF file first.')
 
+        if seg_id is None:
+            raise ValueError('A segmentation is required to plot an overlay')
+
         if seg_id not in SEGMENTATION:
             raise ValueError(f'Segmentation {seg_id} not found. Please load NPZ file first.')
 
+        if orientation not in ORIENTATIONS:
+            raise ValueError(f'Unknown orientation {orientation}')
+        if origin not in ORIGINS:
+            raise ValueError(f'Unknown origin {origin}')
+        if pyramid_mode not in PYRAMID_MODES:
+            raise ValueError(f'Unknown pyramid mode {pyramid_mode}')
+        if transform is not None:
+            transform = check_transform(transform)
+        context = None
+        if context_id is not None:
+            context = render_context(context_id, dataset_id, img_id, seg_id)
+
+        # checked before rendering, a missing calibration shouldn't cost a plot
+        pixel_size = microns_per_pixel(dataset_id) if scalebar else None
+
+        if lod:
+            shape = tuple(SEGMENTATION[seg_id]['shape'])
+            if lod['bin_size'] >= min(shape):
+                raise ValueError(
+                    f'LOD bin size {lod["bin_size"]} is not below image size {shape[1]}x{shape[0]}')
+            if lod['segments_from_zoom'] > 4:
+                raise ValueError(
+                    f'LOD segments_from_zoom {lod["segments_from_zoom"]} is past max zoom 4')
+
         adata = DATASETS[dataset_id]
-        image_data = IMAGE[img_id]
         seg_data = SEGMENTATION[seg_id]
+        # the render context's files, None where this plot's inputs differ
+        context_image = context_seg = None
+        if context is not None and transform is None:
+            context_image = context['image_path']
+            context_seg = None if segment_limit else context['seg_path']
+        image_data = seg_arrays = None
+        if context_image is None:
+            if img_id is not None:
+                image_data = find_image(dataset_id, img_id)
+            else:
+                image_data = background_image(tuple(seg_data['shape']), background)
+            if transform is not None:
+                image_data = transform_image(image_data, transform)
+        if context_seg is None:
+            seg_arrays = {k: seg_data[k] for k in seg_data.files}
+            if transform is not None:
+                # bin2cell rasterizes the index's labels, so get_segment_at
+                # and the render agree pixel for pixel
+                labels = segment_index(seg_id, transform=transform)['labels']
+                seg_arrays.update(data=labels.data, indices=labels.indices, indptr=labels.indptr,
+                                  shape=np.array(labels.shape))
+        segments_dropped = sample_segments(seg_arrays, segment_limit, seed) if segment_limit else 0
 
         # check if fill_key is a gene or a column in obs
         is_gene = fill_key in adata.var_names
@@ -120,6 +524,16 @@ def plot_tissue_overlay(dataset_id: str,
         if not is_gene and not is_obs_col:
             raise ValueError(f'{fill_key} not found in genes or observation columns')
 
+        # resolved before rendering so bin2cell and the legend agree
+        legend = build_legend(adata, fill_key, is_gene)
+        cmap = extra.get('cont_cmap', 'viridis')
+        if color_scale:
+            if legend['kind'] != 'continuous':
+                raise ValueError(f'{fill_key} is categorical, a color scale needs numbers')
+            vmin, vmax = resolve_value_range(legend['min'], legend['max'], color_scale)
+            extra['cont_cmap'] = rescaled_colormap(cmap, legend['min'], legend['max'], vmin, vmax)
+            legend = {'kind': 'continuous', 'min': vmin, 'max': vmax}
+
         # create temporary directory for bin2cell outputs
         with tempfile.TemporaryDirectory() as tmpdir:
             # debug: check input data
@@ -130,29 +544,44 @@ def plot_tissue_overlay(dataset_id: str,
             print(f"Seg ID: {seg_id}", file=sys.stderr)
             print(f"Fill key: {fill_key}", file=sys.stderr)
             print(f"Is gene: {is_gene}, Is obs col: {is_obs_col}", file=sys.stderr)
-            print(f"Image data shape: {image_data.shape}, dtype: {image_data.dtype}", file=sys.stderr)
+            if image_data is not None:
+                print(f"Image data shape: {image_data.shape}, dtype: {image_data.dtype}",
+                      file=sys.stderr)
+            print(f"Render context: {context_id}", file=sys.stderr)
             print(f"Segmentation files: {seg_data.files}", file=sys.stderr)
             print(f"AnnData shape: {adata.shape}", file=sys.stderr)
 
-            # save image temporarily
-            temp_img_path = os.path.join(tmpdir, 'temp_image.tif')
-            tiff.imwrite(temp_img_path, image_data)
-            print(f"Saved temp TIFF to: {temp_img_path}", file=sys.stderr)
+            load_start = time.perf_counter()
+
+            # save image temporarily, unless the render context has it
+            if context_image is not None:
+                temp_img_path = context_image
+            else:
+                temp_img_path = os.path.join(tmpdir, 'temp_image.tif')
+                tiff.imwrite(temp_img_path, image_data)
+                print(f"Saved temp TIFF to: {temp_img_path}", file=sys.stderr)
+
+            # save segmentation temporarily, same
+            if context_seg is not None:
+                temp_seg_path = context_seg
+            else:
+                temp_seg_path = os.path.join(tmpdir, 'temp_seg.npz')
+                np.savez(temp_seg_path, **seg_arrays)
+                print(f"Saved temp NPZ to: {temp_seg_path}", file=sys.stderr)
 
-            # save segmentation temporarily
-            temp_seg_path = os.path.join(tmpdir, 'temp_seg.npz')
-            np.savez(temp_seg_path, **{k: seg_data[k] for k in seg_data.files})
-            print(f"Saved temp NPZ to: {temp_seg_path}", file=sys.stderr)
+            render_start = time.perf_counter()
 
             # use bin2cell to generate the visualization
             print(f"Calling bin2cell.view_cell_labels...", file=sys.stderr)
-            img, legends = b2c.view_cell_labels(
-                image_path=temp_img_path,
-                labels_npz_path=temp_seg_path,
-                cdata=adata,
-                fill_key=fill_key,
-                border_key=border_key
-            )
+            with seeded_rng(seed):
+                img, legends = b2c.view_cell_labels(
+                    image_path=temp_img_path,
+                    labels_npz_path=temp_seg_path,
+                    cdata=adata,
+                    fill_key=fill_key,
+                    border_key=border_key,
+                    **extra
+                )
             print(f"bin2cell returned successfully", file=sys.stderr)
 
             # close any matplotlib figures in legends to prevent serialization issues
@@ -181,15 +610,35 @@ def plot_tissue_overlay(dataset_id: str,
                 print(f"Image already uint8", file=sys.stderr)
 
             buf = io.BytesIO()
-            pil_img = Image.fromarray(img)
+            # turned before the scalebar, it stays upright in its corner
+            pil_img = Image.fromarray(np.ascontiguousarray(orient_array(img, orientation)))
             print(f"PIL Image mode: {pil_img.mode}, size: {pil_img.size}", file=sys.stderr)
+            recolor = None
+            if recolorable and legend['kind'] == 'continuous':
+                recolor = recolor_state(pil_img, adata, seg_id, (orientation, transform),
+                                        fill_key, is_gene, cmap, (legend['min'], legend['max']))
+            if scalebar:
+                pil_img = draw_scalebar(pil_img, scalebar, pixel_size)
 
             # Generate tiles from the image
+            tile_start = time.perf_counter()
             print(f"Generating tiles for tissue overlay...", file=sys.stderr)
-            tiles = generate_tiles_from_image(pil_img, tile_size=256, max_zoom=4)
+            level_hook = None
+            if lod:
+                bins = lod_bins(adata, seg_id, fill_key, is_gene, legend, lod['bin_size'], cmap,
+                                orientation, transform)
+                level_hook = lod_level_hook(bins, lod)
+            tiles = cut_pyramid(pil_img, pyramid_mode, tile_size=256, max_zoom=4,
+                                level_hook=level_hook, quality=quality)
+            tile_end = time.perf_counter()
+            record_span('load', load_start, render_start)
+            record_span('render', render_start, tile_start)
+            record_span('encode', tile_start, tile_end)
+
+            value_range = [legend['min'], legend['max']] if legend['kind'] == 'continuous' else None
 
             # Create unique overlay ID
-            overlay_id = f"{dataset_id}:{img_id}:{seg_id}:{fill_key}"
+            overlay_id = f"{dataset_id}:{img_id or 'background'}:{seg_id}:{fill_key}"
 
             # Store tiles in memory
             TILES[overlay_id] = {
@@ -199,7 +648,26 @@ def plot_tissue_overlay(dataset_id: str,
                 'tile_size': 256,
                 'max_zoom': 4,
                 'fill_key': fill_key,
-                'is_gene': is_gene
+                'is_gene': is_gene,
+                'legend': legend,
+                'dataset_id': dataset_id,
+                'seg_id': seg_id,
+                'has_image': img_id is not None,
+                # base image, see begin_ome_tiff
+                'img_id': img_id,
+                'scalebar': scalebar,
+                'lod': lod,
+                'quality': quality,
+                'recolor': recolor,
+                # colormap name over the legend's range, see export_legend
+                'cmap': cmap,
+                'seed': seed,
+                'orientation': orientation,
+                'origin': origin,
+                'transform': transform,
+                'pyramid_mode': pyramid_mode,
+                'segment_limit': segment_limit,
+                'segments_dropped': segments_dropped
             }
 
             print(f"Stored tiles for overlay_id: {overlay_id}", file=sys.stderr)
@@ -212,7 +680,25 @@ def plot_tissue_overlay(dataset_id: str,
                 'tile_size': 256,
                 'max_zoom': 4,
                 'fill_key': fill_key,
-                'is_gene': is_gene
+                'is_gene': is_gene,
+                'has_image': img_id is not None,
+                'scalebar': scalebar,
+                'lod': lod,
+                'value_range': value_range,
+                'quality': quality,
+                'recolorable': recolor is not None,
+                'seed': seed,
+                'orientation': orientation,
+                'origin': origin,
+                'transform': transform,
+                'pyramid_mode': pyramid_mode,
+                'segment_limit': segment_limit,
+                'segments_dropped': segments_dropped,
+                'timings': {
+                    'load_ms': (render_start - load_start) * 1000,
+                    'render_ms': (tile_start - render_start) * 1000,
+                    'tile_ms': (tile_end - tile_start) * 1000
+                }
             }
 
             return result
@@ -223,10 +709,695 @@ def plot_tissue_overlay(dataset_id: str,
         raise RuntimeError(f'Failed to generate tissue overlay: {str(e)}')
 
 
+SAMPLINGS = ('random', 'largest', 'none')
+
+
+def sample_segments(seg_arrays: Dict[str, Any], segment_limit: Dict[str, Any],
+                    seed: Optional[int]) -> int:
+    """
+    cut the label matrix in seg_arrays down to segment_limit["max_segments"]
+    segments, in place. returns how many were dropped
+
+    "random" keeps a subset drawn from seed (0 when the plot is unseeded, so
+    a preview comes back the same every time), "largest" the segments with
+    the most pixels, ties going to the lower id. "none" doesn't sample and
+    refuses a segmentation over the limit. the dropped segments' pixels are
+    removed, so bin2cell draws them as background
+    """
+    import scipy.sparse as sp
+
+    max_segments = segment_limit.get('max_segments')
+    sampling = segment_limit.get('sampling', 'random')
+    if sampling not in SAMPLINGS:
+        raise ValueError(f'Unknown sampling {sampling}')
+    if not isinstance(max_segments, int) or max_segments < 1:
+        raise ValueError(f'max_segments must be at least 1, got {max_segments}')
+
+    data = np.asarray(seg_arrays['data'])
+    ids, counts = np.unique(data[data != 0], return_counts=True)
+    dropped = len(ids) - max_segments
+    if dropped <= 0:
+        return 0
+    if sampling == 'none':
+        raise ValueError(f'Segmentation has {len(ids)} segments, more than max_segments '
+                         f'{max_segments}')
+    if sampling == 'largest':
+        kept = ids[np.argsort(-counts, kind='stable')[:max_segments]]
+    else:
+        kept = np.random.default_rng(0 if seed is None else seed).choice(
+            ids, size=max_segments, replace=False)
+
+    labels = sp.csr_matrix((np.where(np.isin(data, kept), data, 0), seg_arrays['indices'],
+                            seg_arrays['indptr']), shape=tuple(seg_arrays['shape']))
+    labels.eliminate_zeros()
+    seg_arrays.update(data=labels.data, indices=labels.indices, indptr=labels.indptr)
+    warn('segments_sampled',
+         f'Rendered {max_segments} of {len(ids)} segments ({sampling} sample), '
+         'queries still see all of them')
+    return dropped
+
+
+@contextlib.contextmanager
+def seeded_rng(seed: Optional[int]):
+    """
+    seed python's and numpy's global random generators, e.g. the colors
+    bin2cell picks for categories without stored ones, and put their state
+    back afterwards so other commands aren't affected. a no-op for None
+
+    everything else a render does (downsampling, jpeg encoding, lod bins)
+    has no randomness, so a seeded render is byte-identical across runs
+    with the same library versions
+    """
+    if seed is None:
+        yield
+        return
+    py_state, np_state = random.getstate(), np.random.get_state()
+    random.seed(seed)
+    # numpy's legacy seeding only takes 32 bits
+    np.random.seed(seed % 2 ** 32)
+    try:
+        yield
+    finally:
+        random.setstate(py_state)
+        np.random.set_state(np_state)
+
+
+def resolve_value_range(data_min: float, data_max: float, color_scale: Dict[str, Any]) -> tuple:
+    """
+    the (min, max) a continuous colormap spans, see ColorScale on the rust side
+
+    an explicit value_range wins over the data's range. symmetric widens to
+    [center - m, center + m], m the largest distance from the center, and
+    refuses a value_range that isn't symmetric around it already
+    """
+    value_range = color_scale.get('value_range')
+    vmin, vmax = value_range if value_range else (data_min, data_max)
+    if value_range and (data_min < vmin or data_max > vmax):
+        warn('value_range_clipped',
+             f'Color value range {list(value_range)} clips the data range '
+             f'[{data_min:g}, {data_max:g}], values outside it share the end colors')
+    if not color_scale.get('symmetric'):
+        return float(vmin), float(vmax)
+
+    center = color_scale.get('center') or 0.0
+    if value_range and not np.isclose(center - vmin, vmax - center, rtol=1e-9, atol=0):
+        raise ValueError(f'Color value range {value_range} is not symmetric around {center}')
+    half = max(abs(center - vmin), abs(vmax - center)) or 1.0
+    return float(center - half), float(center + half)
+
+
+def rescaled_colormap(name: str, data_min: float, data_max: float, vmin: float, vmax: float):
+    """
+    a colormap that, stretched over [data_min, data_max] the way bin2cell
+    normalizes values, colors each value as name would over [vmin, vmax]
+    """
+    from matplotlib import colormaps
+    from matplotlib.colors import ListedColormap
+
+    values = np.linspace(data_min, data_max, 256)
+    positions = np.clip((values - vmin) / (vmax - vmin), 0, 1)
+    return ListedColormap(colormaps[name](positions), name=f'{name}_rescaled')
+
+
+# largest per-channel difference between a rendered fill pixel and the color
+# its value maps to, covers the 256 entry colormap lookup of the renderer
+RECOLOR_TOLERANCE = 6
+
+# share of segment pixels that must show their value's color for an overlay
+# to be recolorable. fills blended into the image (fill_label_weight < 1)
+# don't, and neither do renderers that color differently than assumed
+RECOLOR_MIN_MATCH = 0.5
+
+
+def value_colors(values: np.ndarray, cmap: str, value_range: tuple) -> np.ndarray:
+    """
+    rgb uint8 color of each value through cmap over value_range
+    """
+    from matplotlib import colormaps
+
+    vmin, vmax = value_range
+    normalized = np.clip((values - vmin) / ((vmax - vmin) or 1.0), 0, 1)
+    return np.round(colormaps[cmap](np.nan_to_num(normalized))[:, :3] * 255).astype(np.uint8)
+
+
+def fill_pixels(image: np.ndarray, index: Dict[str, Any], colors: np.ndarray, present: np.ndarray):
+    """
+    segment pixels of a rendered image that show their segment's color
+
+    returns:
+        (rows, cols, segment) arrays of the matching pixels, segment being
+        positions in index['ids'], and how many segment pixels were checked
+    """
+    coo = index['labels'].tocoo()
+    segment = np.searchsorted(index['ids'], coo.data)
+    inside = (coo.row < image.shape[0]) & (coo.col < image.shape[1])
+    keep = inside & present[segment]
+    rows, cols, segment = coo.row[keep], coo.col[keep], segment[keep]
+
+    difference = np.abs(image[rows, cols].astype(np.int16) - colors[segment])
+    match = difference.max(axis=1) <= RECOLOR_TOLERANCE
+    return rows[match], cols[match], segment[match], len(segment)
+
+
+def recolor_state(pil_img: Image.Image,
+                  adata,
+                  seg_id: str,
+                  frame: tuple,
+                  fill_key: str,
+                  is_gene: bool,
+                  cmap: str,
+                  value_range: tuple) -> Optional[Dict[str, Any]]:
+    """
+    what recolor_overlay needs to repaint a render, None if it can't
+
+    keeps the full resolution render (before the scalebar) and the colormap
+    and range it was colored with. the fill pixels are found again on every
+    recolor by matching them against those colors. frame is the plot's
+    (orientation, transform)
+    """
+    image = np.array(pil_img.convert('RGB'))
+    index = segment_index(seg_id, *frame)
+    values = segment_column(adata, fill_key, is_gene, index).to_numpy(dtype=np.float64)
+    present = ~np.isnan(values)
+    _, _, matched, checked = fill_pixels(image, index, value_colors(values, cmap, value_range),
+                                         present)
+    if checked == 0 or len(matched) < RECOLOR_MIN_MATCH * checked:
+        print(f"Overlay fills not recognizable ({len(matched)}/{checked} pixels), "
+              f"not recolorable", file=sys.stderr)
+        return None
+    return {'image': image, 'cmap': cmap, 'value_range': tuple(value_range)}
+
+
+def recolor_overlay(overlay_id: str,
+                    colormap: str,
+                    value_range: Optional[List[float]] = None) -> Dict[str, Any]:
+    """
+    repaint a recolorable overlay with another colormap and/or value range
+
+    the segments aren't rasterized again: the fill pixels of the kept render
+    are swapped for their value's new color, then the pyramid is cut again.
+    borders and the base image stay as they were
+
+    args:
+        overlay_id: ID of an overlay plotted with recolorable
+        colormap: matplotlib colormap name
+        value_range: [min, max] to color over, None keeps the current one
+
+    returns:
+        dict shaped like plot_tissue_overlay's result
+    """
+    import time
+    from matplotlib import colormaps
+
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+    overlay = TILES[overlay_id]
+    state = overlay.get('recolor')
+    if state is None:
+        raise ValueError(f'Overlay {overlay_id} was not plotted recolorable')
+    if colormap not in colormaps:
+        raise ValueError(f'Unknown colormap {colormap}')
+
+    render_start = time.perf_counter()
+    adata = DATASETS[overlay['dataset_id']]
+    # the render's rows, whatever the overlay's origin
+    index = segment_index(overlay['seg_id'], overlay.get('orientation', 'rotate0'),
+                          overlay.get('transform'))
+    values = segment_column(adata, overlay['fill_key'], overlay['is_gene'], index)
+    values = values.to_numpy(dtype=np.float64)
+    present = ~np.isnan(values)
+    new_range = tuple(value_range) if value_range else state['value_range']
+
+    image = state['image']
+    old_colors = value_colors(values, state['cmap'], state['value_range'])
+    rows, cols, segment, _ = fill_pixels(image, index, old_colors, present)
+    image[rows, cols] = value_colors(values, colormap, new_range)[segment]
+    state.update(cmap=colormap, value_range=new_range)
+
+    legend = {'kind': 'continuous', 'min': float(new_range[0]), 'max': float(new_range[1])}
+    pil_img = Image.fromarray(image)
+    if overlay['scalebar']:
+        pixel_size = microns_per_pixel(overlay['dataset_id'])
+        pil_img = draw_scalebar(pil_img, overlay['scalebar'], pixel_size)
+    level_hook = None
+    if overlay['lod']:
+        bins = lod_bins(adata, overlay['seg_id'], overlay['fill_key'], overlay['is_gene'],
+                        legend, overlay['lod']['bin_size'], colormap,
+                        overlay.get('orientation', 'rotate0'), overlay.get('transform'))
+        level_hook = lod_level_hook(bins, overlay['lod'])
+
+    tile_start = time.perf_counter()
+    overlay['tiles'] = cut_pyramid(pil_img, overlay.get('pyramid_mode', 'eager'),
+                                   tile_size=overlay['tile_size'], max_zoom=overlay['max_zoom'],
+                                   level_hook=level_hook, quality=overlay['quality'])
+    tile_end = time.perf_counter()
+    record_span('render', render_start, tile_start)
+    record_span('encode', tile_start, tile_end)
+    overlay['legend'] = legend
+    overlay['cmap'] = colormap
+
+    return {
+        'overlay_id': overlay_id,
+        'width': overlay['width'],
+        'height': overlay['height'],
+        'tile_size': overlay['tile_size'],
+        'max_zoom': overlay['max_zoom'],
+        'fill_key': overlay['fill_key'],
+        'is_gene': overlay['is_gene'],
+        'has_image': overlay['has_image'],
+        'scalebar': overlay['scalebar'],
+        'lod': overlay['lod'],
+        'value_range': [legend['min'], legend['max']],
+        'quality': overlay['quality'],
+        'recolorable': True,
+        'seed': overlay['seed'],
+        'orientation': overlay.get('orientation', 'rotate0'),
+        'origin': overlay.get('origin', 'top_left'),
+        'transform': overlay.get('transform'),
+        'pyramid_mode': overlay.get('pyramid_mode', 'eager'),
+        'segment_limit': overlay.get('segment_limit'),
+        'segments_dropped': overlay.get('segments_dropped', 0),
+        'timings': {
+            'load_ms': 0.0,
+            'render_ms': (tile_start - render_start) * 1000,
+            'tile_ms': (tile_end - tile_start) * 1000
+        }
+    }
+
+
+def lod_bins(adata,
+             seg_id: str,
+             fill_key: str,
+             is_gene: bool,
+             legend: Dict[str, Any],
+             bin_size: int,
+             cmap: str = 'viridis',
+             orientation: str = 'rotate0',
+             transform: Optional[List[float]] = None) -> np.ndarray:
+    """
+    segments aggregated into square bins, as an rgba image with one pixel per bin
+
+    each segment counts towards the bin its centroid falls into. continuous
+    values are averaged and colored through cmap over the legend's range,
+    categories take the most common one in the bin and its legend color
+    (tab20 when the dataset has none). bins without segments are transparent.
+    bins are laid out over the segmentation as plotted, see segment_index
+    """
+    import pandas as pd
+    from matplotlib import colormaps
+    from matplotlib.colors import to_rgba
+
+    index = segment_index(seg_id, orientation, transform)
+    height, width = index['labels'].shape
+    nbx, nby = -(-width // bin_size), -(-height // bin_size)
+    bin_of = ((index['cy'] // bin_size).astype(np.int64) * nbx
+              + (index['cx'] // bin_size).astype(np.int64))
+
+    column = segment_column(adata, fill_key, is_gene, index)
+
+    rgba = np.zeros((nby * nbx, 4), dtype=np.uint8)
+    if legend['kind'] == 'continuous':
+        values = column.to_numpy(dtype=np.float64)
+        present = ~np.isnan(values)
+        sums = np.bincount(bin_of[present], weights=values[present], minlength=nby * nbx)
+        counts = np.bincount(bin_of[present], minlength=nby * nbx)
+        filled = counts > 0
+        span = (legend['max'] - legend['min']) or 1.0
+        normalized = (sums[filled] / counts[filled] - legend['min']) / span
+        rgba[filled] = (colormaps[cmap](np.clip(normalized, 0, 1)) * 255).astype(np.uint8)
+    else:
+        names = [category['name'] for category in legend['categories']]
+        codes = pd.Categorical(column.astype(str), categories=names).codes
+        present = codes >= 0
+        keys, counts = np.unique(bin_of[present] * len(names) + codes[present], return_counts=True)
+        # most common category first within each bin, then one row per bin
+        order = np.lexsort((-counts, keys // len(names)))
+        bins, first = np.unique(keys[order] // len(names), return_index=True)
+        winners = keys[order][first] % len(names)
+        fallback = colormaps['tab20']
+        palette = np.array([
+            to_rgba(category['color'] or fallback(i % 20))
+            for i, category in enumerate(legend['categories'])
+        ])
+        rgba[bins] = (palette[winners] * 255).astype(np.uint8)
+
+    return rgba.reshape(nby, nbx, 4)
+
+
+def segment_column(adata, fill_key: str, is_gene: bool, index: Dict[str, Any]):
+    """
+    fill_key's value for every segment of a segment_index, as a pandas
+    Series in index['ids'] order. NaN for segments missing from the dataset
+    """
+    import pandas as pd
+
+    if is_gene:
+        values = adata[:, fill_key].X
+        values = values.toarray() if hasattr(values, 'toarray') else np.asarray(values)
+        column = pd.Series(values.ravel(), index=adata.obs_names)
+    else:
+        column = adata.obs[fill_key]
+    return column.reindex(index['ids'].astype(str))
+
+
+def lod_level_hook(bins: np.ndarray, lod: Dict[str, Any]):
+    """
+    a generate_tiles_from_image level_hook drawing bins (see lod_bins) over
+    the zoom levels below lod["segments_from_zoom"]
+    """
+    bins_img = Image.fromarray(bins, 'RGBA')
+
+    def hook(zoom, scale, scaled_img):
+        if zoom >= lod['segments_from_zoom']:
+            return scaled_img
+        # nearest keeps bin edges sharp, the last row/column of bins may
+        # hang over the image edge
+        size = (round(bins.shape[1] * lod['bin_size'] / scale),
+                round(bins.shape[0] * lod['bin_size'] / scale))
+        layer = bins_img.resize(size, Image.NEAREST).crop((0, 0) + scaled_img.size)
+        return Image.alpha_composite(scaled_img.convert('RGBA'), layer).convert('RGB')
+
+    return hook
+
+
+def lod_mode(overlay: Dict[str, Any], zoom: int) -> str:
+    """
+    "bins" or "segments", what an overlay's tiles at zoom show
+    """
+    lod = overlay.get('lod')
+    return 'bins' if lod and zoom < lod['segments_from_zoom'] else 'segments'
+
+
+def background_image(shape, background: Optional[List[int]] = None) -> np.ndarray:
+    """
+    solid RGB image of a segmentation's (rows, cols) shape to render onto
+
+    background is [r, g, b, a], a translucent color is blended onto white
+    since the tiles are JPEG
+    """
+    r, g, b, a = background if background is not None else (255, 255, 255, 255)
+    alpha = a / 255
+    color = [round(c * alpha + 255 * (1 - alpha)) for c in (r, g, b)]
+    height, width = int(shape[0]), int(shape[1])
+    return np.full((height, width, 3), color, dtype=np.uint8)
+
+
+def microns_per_pixel(dataset_id: str) -> float:
+    """
+    full resolution pixel size of a dataset, from the scalefactors
+    spaceranger writes and bin2cell keeps in adata.uns['spatial']
+    """
+    if dataset_id not in DATASETS:
+        raise ValueError(f'Dataset {dataset_id} not found')
+
+    for library in DATASETS[dataset_id].uns.get('spatial', {}).values():
+        value = library.get('scalefactors', {}).get('microns_per_pixel')
+        if value:
+            return float(value)
+    raise ValueError(f'Dataset {dataset_id} has no pixel size calibration, cannot draw a scalebar')
+
+
+def dataset_images(dataset_id: Optional[str]) -> Dict[str, Any]:
+    """
+    every image an overlay of the dataset can be drawn over, img_id -> array
+
+    the loaded TIFFs in IMAGE plus the images registered in the dataset's
+    adata.uns['spatial'], those under "<library>/<name>", e.g.
+    "sample1/hires". a TIFF wins over a registered image of the same id
+    """
+    images = {}
+    adata = DATASETS.get(dataset_id) if dataset_id is not None else None
+    if adata is not None:
+        for library_id, library in adata.uns.get('spatial', {}).items():
+            for name, image in library.get('images', {}).items():
+                images[f'{library_id}/{name}'] = np.asarray(image)
+    images.update(IMAGE)
+    return images
+
+
+def find_image(dataset_id: Optional[str], img_id: str):
+    """the image img_id refers to for this dataset, None if there's none"""
+    if img_id in IMAGE:
+        return IMAGE[img_id]
+    return dataset_images(dataset_id).get(img_id)
+
+
+def list_images(dataset_id: str) -> List[Dict[str, Any]]:
+    """
+    images a dataset's overlays can be drawn over, for an image switcher
+
+    returns:
+        list of {"img_id", "name", "dimensions": [width, height], "channels"},
+        loaded TIFFs first, then the dataset's registered images
+    """
+    if dataset_id not in DATASETS:
+        raise ValueError(f'Dataset {dataset_id} not found')
+
+    images = dataset_images(dataset_id)
+    order = list(IMAGE) + [img_id for img_id in images if img_id not in IMAGE]
+    result = []
+    for img_id in order:
+        image = images[img_id]
+        height, width = image.shape[:2]
+        result.append({
+            'img_id': img_id,
+            # a registered image is named by its key, e.g. "hires"
+            'name': img_id if img_id in IMAGE else img_id.split('/', 1)[-1],
+            'dimensions': [int(width), int(height)],
+            'channels': int(image.shape[2]) if image.ndim == 3 else 1
+        })
+    return result
+
+
+def default_font(size: int):
+    """
+    pillow's built-in font at size pixels, or at its one bitmap size
+    """
+    try:
+        return ImageFont.load_default(size=size)
+    except TypeError:
+        # pillow before 10.1 has a single bitmap size
+        return ImageFont.load_default()
+
+
+def draw_scalebar(image: Image.Image, scalebar: Dict[str, Any], pixel_size: float) -> Image.Image:
+    """
+    a copy of image with a labelled scalebar in one corner
+
+    args:
+        image: the rendered image
+        scalebar: {"length_um", "position", "color"} as sent by the bridge
+        pixel_size: microns per pixel of image (not of the full resolution
+            image if it was downscaled)
+    """
+    image = image.copy()
+    length_um = float(scalebar['length_um'])
+    position = scalebar.get('position', 'bottom_right')
+    color = tuple(scalebar.get('color', (0, 0, 0, 255)))
+
+    margin = max(4, min(image.size) // 40)
+    thickness = max(2, min(image.size) // 150)
+    length = max(1, round(length_um / pixel_size))
+    if length > image.width - 2 * margin:
+        raise ValueError(f'A {length_um:g} um scalebar does not fit into the image')
+
+    font = default_font(max(10, thickness * 4))
+    label = f'{length_um:g} \u00b5m'
+    draw = ImageDraw.Draw(image, 'RGBA')
+    left, top, right, bottom = draw.textbbox((0, 0), label, font=font)
+    label_height = bottom - top
+    block_height = label_height + margin // 2 + thickness
+
+    x0 = margin if position.endswith('left') else image.width - margin - length
+    y0 = margin if position.startswith('top') else image.height - margin - block_height
+    label_x = x0 + (length - (right - left)) // 2 - left
+    draw.text((label_x, y0 - top), label, fill=color, font=font)
+    bar_y = y0 + label_height + margin // 2
+    draw.rectangle([x0, bar_y, x0 + length - 1, bar_y + thickness - 1], fill=color)
+    return image
+
+
+def pick_tile_format(tile: Image.Image) -> str:
+    """
+    the format a tile asked for as "auto" is served in
+
+    png if any pixel is transparent, jpeg would lose the alpha, or if the
+    tile is flat: segment fills and empty background have a luminance
+    entropy below AUTO_PNG_MAX_ENTROPY, png is smaller there and exact.
+    jpeg for the rest, photographic tissue compresses several times better
+    lossy. depends on the pixels only, the same tile always gets the same
+    format
+    """
+    if 'A' in tile.getbands() and tile.getchannel('A').getextrema()[0] < 255:
+        return 'png'
+    if tile.convert('L').entropy() < AUTO_PNG_MAX_ENTROPY:
+        return 'png'
+    return 'jpeg'
+
+
+def get_base_image_tile(dataset_id: str, img_id: str, zoom: int, x: int, y: int,
+                        quality: str = 'balanced',
+                        orientation: str = 'rotate0',
+                        transform: Optional[List[float]] = None,
+                        origin: str = 'top_left',
+                        format: str = 'jpeg') -> Dict[str, Any]:
+    """
+    a tile of the image alone, without the overlay, for toggling it off
+
+    cut from the full resolution image on request with the overlay pyramid's
+    geometry (256 pixel tiles, zoom 0..4 with 4 at full size), so tile x, y
+    covers the same pixels as the overlay's. the image is brought to uint8
+    the way plot_tissue_overlay does, put through transform and turned by
+    orientation. pass the overlay's so the tiles line up. with a
+    bottom_left origin y counts tile rows up from the bottom, as the rust
+    side does for overlay tiles. only the tile's own pixels are resampled.
+    format is one of TILE_FORMATS
+
+    returns:
+        dict with the base64 encoded tile, its format and mime type
+    """
+    image = find_image(dataset_id, img_id)
+    if image is None:
+        raise ValueError(f'Image {img_id} not found. Please load TIFF file first.')
+    if quality not in RENDER_QUALITY and quality != 'fast':
+        raise ValueError(f'Unknown render quality {quality}')
+    if orientation not in ORIENTATIONS:
+        raise ValueError(f'Unknown orientation {orientation}')
+    if origin not in ORIGINS:
+        raise ValueError(f'Unknown origin {origin}')
+    if format not in TILE_FORMATS:
+        raise ValueError(f'Unsupported tile format {format}')
+    if transform is not None:
+        transform = check_transform(transform)
+
+    tile_size, max_zoom = 256, 4
+    if not 0 <= zoom <= max_zoom:
+        raise ValueError(f'Zoom level {zoom} not found')
+    scale = 2 ** (max_zoom - zoom)
+    height, width = image.shape[:2]
+    if orientation in ('rotate90', 'rotate270'):
+        width, height = height, width
+    level_width, level_height = width // scale, height // scale
+    if origin == 'bottom_left':
+        y = -(-level_height // tile_size) - 1 - y
+    if not (0 <= x * tile_size < level_width and 0 <= y * tile_size < level_height):
+        raise ValueError(f'Tile ({x}, {y}) not found at zoom {zoom}')
+
+    tile_width = min(tile_size, level_width - x * tile_size)
+    tile_height = min(tile_size, level_height - y * tile_size)
+    left, top = x * tile_size * scale, y * tile_size * scale
+    box = (left, top, min(left + tile_width * scale, width), min(top + tile_height * scale, height))
+    # cut before turning, a turned crop is the crop of the turned image
+    x0, y0, x1, y1 = source_box(orientation, box, width, height)
+    if transform is not None:
+        region = transform_image(image, transform, (x0, y0, x1, y1))
+    else:
+        region = np.asarray(image[y0:y1, x0:x1])
+    region = orient_array(region, orientation)
+    if region.dtype != np.uint8:
+        region = (region * 255 if region.max() <= 1.0 else region).astype(np.uint8)
+
+    resample, jpeg_quality, subsampling = RENDER_QUALITY.get(quality, RENDER_QUALITY['balanced'])
+    tile = Image.fromarray(region).convert('RGB').resize((tile_width, tile_height), resample)
+    if quality == 'fast':
+        tile = tile.reduce(2) if min(tile.size) >= 2 else tile
+        jpeg_quality = FAST_TILE_QUALITY
+    if format == 'auto':
+        format = pick_tile_format(tile)
+    buf = io.BytesIO()
+    if format == 'png':
+        tile.save(buf, format='PNG')
+    else:
+        tile.save(buf, format='JPEG', quality=jpeg_quality, subsampling=subsampling)
+    return {'tile': base64.b64encode(buf.getvalue()).decode('utf-8'), 'format': format,
+            'mime': TILE_MIME[format]}
+
+
+def fractional_tile(overlay: Dict[str, Any], zoom: int, fraction: float, x: int, y: int) -> bytes:
+    """
+    jpeg tile x, y of an overlay at zoom + fraction, between two levels
+
+    at zoom z the image is width * 2 ** (z - max_zoom) pixels wide and cut
+    into tile_size tiles like a stored level. the pixels come from the next
+    finer level's stored tiles, stitched and downsampled by
+    2 ** (1 - fraction) with the overlay's resampling filter, so the tile
+    is as sharp as that level. nothing is stored, every call renders
+    """
+    tile_size, max_zoom = overlay['tile_size'], overlay['max_zoom']
+    finer = zoom + 1
+    if not 0 <= zoom < max_zoom or not 0 < fraction < 1:
+        raise ValueError(f'Zoom level {zoom + fraction} not found')
+
+    level_scale = 2.0 ** (zoom + fraction - max_zoom)
+    level_width = int(overlay['width'] * level_scale)
+    level_height = int(overlay['height'] * level_scale)
+    if not (0 <= x * tile_size < level_width and 0 <= y * tile_size < level_height):
+        raise ValueError(f'Tile ({x}, {y}) not found at zoom {zoom + fraction}')
+    tile_width = min(tile_size, level_width - x * tile_size)
+    tile_height = min(tile_size, level_height - y * tile_size)
+
+    # the tile's box in pixels of the finer level
+    scale = 2.0 ** (1 - fraction)
+    left, top = x * tile_size * scale, y * tile_size * scale
+    right, bottom = left + tile_width * scale, top + tile_height * scale
+    col0, row0 = int(left // tile_size), int(top // tile_size)
+    col1, row1 = int(np.ceil(right / tile_size)), int(np.ceil(bottom / tile_size))
+
+    stored = overlay['tiles'][finer]
+    canvas = Image.new('RGB', ((col1 - col0) * tile_size, (row1 - row0) * tile_size), 'white')
+    for row in range(row0, row1):
+        for col in range(col0, col1):
+            if (col, row) in stored:
+                tile = Image.open(io.BytesIO(stored[(col, row)]))
+                canvas.paste(tile, ((col - col0) * tile_size, (row - row0) * tile_size))
+
+    origin_x, origin_y = col0 * tile_size, row0 * tile_size
+    box = (left - origin_x, top - origin_y, right - origin_x, bottom - origin_y)
+    resample, jpeg_quality, subsampling = RENDER_QUALITY.get(
+        overlay.get('quality', 'balanced'), RENDER_QUALITY['balanced'])
+    tile = canvas.resize((tile_width, tile_height), resample, box=box)
+
+    buf = io.BytesIO()
+    tile.save(buf, format='JPEG', quality=jpeg_quality, subsampling=subsampling)
+    return buf.getvalue()
+
+
+def build_pyramid_level(overlay_id: str, zoom: int) -> Dict[str, Any]:
+    """
+    cut every tile of one zoom level of a lazily plotted overlay, the
+    bridge's eager pyramid build calls this level by level. once every
+    level is built the overlay counts as eager
+
+    args:
+        overlay_id: ID of the overlay
+        zoom: the level to build
+
+    returns:
+        {"zoom", "tiles": tiles in the level}
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay = TILES[overlay_id]
+    tiles = overlay['tiles']
+    if zoom not in tiles:
+        raise ValueError(f'Zoom level {zoom} not found')
+    if not isinstance(tiles, LazyPyramid):
+        return {'zoom': zoom, 'tiles': len(tiles[zoom])}
+
+    level = tiles.build_level(zoom)
+    if tiles.complete():
+        overlay['pyramid_mode'] = 'eager'
+    return {'zoom': zoom, 'tiles': len(level)}
+
+
 def get_tissue_overlay_tile(overlay_id: str,
                             zoom: int,
                             x: int,
-                            y: int) -> Dict[str, Any]:
+                            y: int,
+                            format: str = 'jpeg',
+                            quality: str = 'balanced',
+                            fraction: float = 0.0) -> Dict[str, Any]:
     """
     get a specific tile for a tissue overlay
 
@@ -238,10 +1409,20 @@ def get_tissue_overlay_tile(overlay_id: str,
         zoom: zoom level
         x: tile x coordinate
         y: tile y coordinate
+        format: 'jpeg' (as stored), 'png' (re-encoded, e.g. for exports) or
+            'auto' to pick one by the tile's content, see pick_tile_format
+        quality: "fast" for a half resolution tile to stretch while panning,
+            "balanced" or "high" for the stored tile
+        fraction: 0 for a stored tile, otherwise how far towards zoom + 1
+            to render it, see fractional_tile
 
     returns:
-        dict containing base64 encoded JPEG tile
+        dict containing base64 encoded tile, its format and lod, "bins" or
+        "segments" depending on what the overlay shows at this zoom, and
+        the mime type of the format served
     """
+    import time
+
     try:
         if overlay_id not in TILES:
             raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
@@ -249,19 +1430,2172 @@ def get_tissue_overlay_tile(overlay_id: str,
         overlay_data = TILES[overlay_id]
         tiles = overlay_data['tiles']
 
-        if zoom not in tiles:
-            raise ValueError(f'Zoom level {zoom} not found')
+        if fraction:
+            with trace_span('render'):
+                tile_bytes = fractional_tile(overlay_data, zoom, fraction, x, y)
+            # the pixels are the finer level's
+            lod_zoom = zoom + 1
+        else:
+            if zoom not in tiles:
+                raise ValueError(f'Zoom level {zoom} not found')
 
-        if (x, y) not in tiles[zoom]:
-            raise ValueError(f'Tile ({x}, {y}) not found at zoom {zoom}')
+            # a lazy pyramid cuts the tile here
+            with trace_span('load'):
+                if (x, y) not in tiles[zoom]:
+                    raise ValueError(f'Tile ({x}, {y}) not found at zoom {zoom}')
+                tile_bytes = tiles[zoom][(x, y)]
+            lod_zoom = zoom
+
+        encode_start = time.perf_counter()
+        if quality == 'fast':
+            tile = Image.open(io.BytesIO(tile_bytes))
+            tile = tile.reduce(2) if min(tile.size) >= 2 else tile
+            buf = io.BytesIO()
+            tile.save(buf, format='JPEG', quality=FAST_TILE_QUALITY)
+            tile_bytes = buf.getvalue()
+        elif quality not in RENDER_QUALITY:
+            raise ValueError(f'Unknown render quality {quality}')
+
+        if format == 'auto':
+            format = pick_tile_format(Image.open(io.BytesIO(tile_bytes)))
+        if format == 'png':
+            buf = io.BytesIO()
+            Image.open(io.BytesIO(tile_bytes)).save(buf, format='PNG')
+            tile_bytes = buf.getvalue()
+        elif format != 'jpeg':
+            raise ValueError(f'Unsupported tile format {format}')
 
-        tile_bytes = tiles[zoom][(x, y)]
         tile_base64 = base64.b64encode(tile_bytes).decode('utf-8')
+        record_span('encode', encode_start, time.perf_counter())
 
         return {
             'tile': tile_base64,
-            'format': 'jpeg'
+            'format': format,
+            'mime': TILE_MIME[format],
+            'lod': lod_mode(overlay_data, lod_zoom),
+            # stored tiles are opaque, straight and premultiplied are the same
+            'premultiplied': False
         }
 
     except Exception as e:
         raise RuntimeError(f'Failed to get tile: {str(e)}')
+
+
+def get_tiles_batch(overlay_id: str,
+                    tiles: List[Dict[str, Any]],
+                    format: str = 'jpeg',
+                    quality: str = 'balanced') -> Dict[str, Any]:
+    """
+    several tiles of an overlay in one round trip, each with its own outcome
+
+    a tile that can't be served (e.g. a corrupt region of a lazy pyramid)
+    carries its error and the rest of the batch still renders. only an
+    unknown overlay fails the whole batch
+
+    args:
+        overlay_id: ID of the overlay
+        tiles: [{"zoom", "x", "y"}, ...] in the order to render them
+        format, quality: as for get_tissue_overlay_tile, for every tile
+
+    returns:
+        dict with "tiles", one entry per requested tile in order: the
+        get_tissue_overlay_tile result or {"error": message}
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    results = []
+    for tile in tiles:
+        try:
+            results.append(get_tissue_overlay_tile(overlay_id, tile['zoom'], tile['x'], tile['y'],
+                                                   format, quality))
+        except Exception as e:
+            results.append({'error': str(e)})
+    return {'tiles': results}
+
+
+def wire_path(path) -> str:
+    """
+    a path param as the bridge sends it, see wire_path on the rust side
+
+    a plain string, or for a path that isn't valid UTF-8 {"bytes_b64": ...}
+    with its raw bytes (unix) or {"wide_b64": ...} with its UTF-16LE units
+    (windows). the result may hold surrogate escapes for those bytes, os
+    and open() turn them back into the original path
+    """
+    if isinstance(path, str):
+        return path
+    if isinstance(path, dict) and 'bytes_b64' in path:
+        return os.fsdecode(base64.b64decode(path['bytes_b64']))
+    if isinstance(path, dict) and 'wide_b64' in path:
+        return base64.b64decode(path['wide_b64']).decode('utf-16-le', 'surrogatepass')
+    raise ValueError(f'Invalid path {path!r}')
+
+
+def path_to_wire(path: str):
+    """a path for a response, in the form wire_path reads"""
+    try:
+        path.encode('utf-8')
+        return path
+    except UnicodeEncodeError:
+        pass
+    if os.name == 'nt':
+        raw = path.encode('utf-16-le', 'surrogatepass')
+        return {'wide_b64': base64.b64encode(raw).decode('ascii')}
+    return {'bytes_b64': base64.b64encode(os.fsencode(path)).decode('ascii')}
+
+
+def load_dataset(path: str, dataset_id: str = None,
+                 checksum: Optional[str] = None) -> Dict[str, Any]:
+    """
+    read an .h5ad file into DATASETS
+
+    args:
+        path: local path of the file, see wire_path
+        dataset_id: ID to store it under, defaults to the file name stem
+        checksum: the file's checksum_file result, which the bridge hashed
+            (and verified) first. kept for list_datasets
+
+    returns:
+        dict with dataset_id, n_obs, n_vars and checksum
+    """
+    import anndata
+
+    path = wire_path(path)
+    dataset_id = dataset_id or Path(path).stem
+    adata = anndata.read_h5ad(path)
+    DATASETS[dataset_id] = adata
+    DATASET_CHECKSUMS.pop(dataset_id, None)
+    if checksum is not None:
+        DATASET_CHECKSUMS[dataset_id] = checksum
+    print(f"Loaded dataset {dataset_id} from {path}: {adata.shape}", file=sys.stderr)
+
+    return dataset_summary(dataset_id)
+
+
+def dataset_summary(dataset_id: str) -> Dict[str, Any]:
+    """
+    {"dataset_id", "n_obs", "n_vars", "checksum"} of a loaded dataset
+    """
+    adata = DATASETS[dataset_id]
+    return {'dataset_id': dataset_id, 'n_obs': int(adata.n_obs), 'n_vars': int(adata.n_vars),
+            'checksum': DATASET_CHECKSUMS.get(dataset_id)}
+
+
+def checksum_file(path: str) -> Dict[str, Any]:
+    """
+    sha256 of a file, read in full
+
+    returns:
+        {"checksum": "sha256:<hex>", "bytes": size read}
+    """
+    import hashlib
+
+    digest = hashlib.sha256()
+    size = 0
+    with open(wire_path(path), 'rb') as f:
+        for chunk in iter(lambda: f.read(1024 * 1024), b''):
+            digest.update(chunk)
+            size += len(chunk)
+    return {'checksum': f'sha256:{digest.hexdigest()}', 'bytes': size}
+
+
+def unload_dataset(dataset_id: str) -> Dict[str, Any]:
+    """
+    free a dataset, every overlay plotted from it and its render contexts
+
+    main() drains every lane before running it, like barrier, so no render
+    or tile still in progress can see the data disappear. unloading a
+    dataset that isn't loaded does nothing
+
+    returns:
+        {"dataset_id": ..., "overlays": [ids of the deleted overlays]}
+    """
+    DATASETS.pop(dataset_id, None)
+    DATASET_CHECKSUMS.pop(dataset_id, None)
+    for preprocessing_id, job in list(PREPROC_JOBS.items()):
+        if job['dataset_id'] == dataset_id:
+            del PREPROC_JOBS[preprocessing_id]
+    overlays = [overlay_id for overlay_id, overlay in TILES.items()
+                if overlay.get('dataset_id') == dataset_id]
+    for overlay_id in overlays:
+        del TILES[overlay_id]
+    for context_id, context in list(RENDER_CONTEXTS.items()):
+        if context['dataset_id'] == dataset_id:
+            close_render_context(context_id)
+    print(f"Unloaded dataset {dataset_id} and {len(overlays)} overlays", file=sys.stderr)
+    return {'dataset_id': dataset_id, 'overlays': overlays}
+
+
+def list_datasets() -> List[Dict[str, Any]]:
+    """
+    the datasets currently loaded
+
+    returns:
+        list of dataset_summary, sorted by id
+    """
+    return [dataset_summary(dataset_id) for dataset_id in sorted(DATASETS)]
+
+
+def get_input_dimensions(dataset_id: str, img_id: str, seg_id: str) -> Dict[str, Any]:
+    """
+    size of the image an overlay of these inputs would be rendered at
+
+    cheap, nothing is rendered. lets the bridge estimate the pyramid before
+    committing to plot_tissue_overlay
+
+    returns:
+        {"width": ..., "height": ...} in pixels
+    """
+    if dataset_id not in DATASETS:
+        raise ValueError(f'Dataset {dataset_id} not found')
+    image = find_image(dataset_id, img_id)
+    if image is None:
+        raise ValueError(f'Image {img_id} not found. Please load TIFF file first.')
+    if seg_id not in SEGMENTATION:
+        raise ValueError(f'Segmentation {seg_id} not found. Please load NPZ file first.')
+
+    height, width = image.shape[:2]
+    return {'width': int(width), 'height': int(height)}
+
+
+def missing_inputs(dataset_id: str, img_id: Optional[str] = None, seg_id: Optional[str] = None) -> List[str]:
+    """
+    which of these inputs aren't loaded, e.g. ["dataset sample1"]
+
+    lets the bridge explain why a saved view can't be restored instead of
+    failing somewhere inside plot_tissue_overlay
+    """
+    missing = []
+    if dataset_id not in DATASETS:
+        missing.append(f'dataset {dataset_id}')
+    if img_id is not None and find_image(dataset_id, img_id) is None:
+        missing.append(f'image {img_id}')
+    if seg_id is not None and seg_id not in SEGMENTATION:
+        missing.append(f'segmentation {seg_id}')
+    return missing
+
+
+def validate_plot_args(dataset_id: Optional[str],
+                       img_id: Optional[str],
+                       seg_id: Optional[str],
+                       fill_key: str,
+                       border_key: Optional[str] = None) -> List[Dict[str, str]]:
+    """
+    every reason plot_tissue_overlay would reject these arguments
+
+    cheap, only looks ids and keys up, nothing is rendered
+
+    returns:
+        list of {"field", "message"}, empty if the plot can go ahead
+    """
+    errors = []
+
+    def error(field, message):
+        errors.append({'field': field, 'message': message})
+
+    adata = DATASETS.get(dataset_id) if dataset_id is not None else None
+    if dataset_id is None:
+        error('dataset_id', 'No dataset selected')
+    elif adata is None:
+        error('dataset_id', f'Dataset {dataset_id} is not loaded')
+
+    image = find_image(dataset_id, img_id) if img_id is not None else None
+    if img_id is not None and image is None:
+        error('img_id', f'Image {img_id} is not loaded')
+
+    if seg_id is None:
+        error('seg_id', 'A segmentation is required to plot an overlay')
+    elif seg_id not in SEGMENTATION:
+        error('seg_id', f'Segmentation {seg_id} is not loaded')
+    elif image is not None:
+        image_shape = tuple(image.shape[:2])
+        seg_shape = tuple(int(n) for n in SEGMENTATION[seg_id]['shape'])
+        if image_shape != seg_shape:
+            error('seg_id', f'Segmentation is {seg_shape[1]}x{seg_shape[0]} but the image is '
+                            f'{image_shape[1]}x{image_shape[0]}')
+
+    if adata is not None:
+        if not fill_key:
+            error('fill_key', 'Pick a gene or an observation column to color by')
+        elif fill_key not in adata.var_names and fill_key not in adata.obs.columns:
+            error('fill_key', f'{fill_key} is neither a gene nor an observation column')
+        if border_key is not None and border_key not in adata.obs.columns:
+            error('border_key', f'{border_key} is not an observation column')
+
+    return errors
+
+
+def export_region_png(overlay_id: str,
+                      x0: int,
+                      y0: int,
+                      x1: int,
+                      y1: int,
+                      path: str,
+                      max_dimension: Optional[int] = None,
+                      scalebar: Optional[Dict[str, Any]] = None) -> Dict[str, Any]:
+    """
+    write a full resolution rectangle of an overlay to a PNG
+
+    stitched from the tiles, nothing is re-rendered, so a figure panel
+    costs a few tile decodes rather than a whole export. with max_dimension
+    the region is stitched from the coarsest level still that large, a big
+    region scaled far down is never held at full resolution
+
+    args:
+        overlay_id: ID of the overlay
+        x0, y0, x1, y1: the region in full resolution pixels, end exclusive
+        path: where to write the PNG, parent folders are created
+        max_dimension: downscale so the longer side is at most this
+        scalebar: {"length_um", "position", "color"}, drawn after
+            downscaling. needs the dataset's pixel size calibration
+
+    returns:
+        {"path": ..., "width": ..., "height": ...} of the written image
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+    path = wire_path(path)
+
+    overlay = TILES[overlay_id]
+    x0, y0 = max(0, int(x0)), max(0, int(y0))
+    x1, y1 = min(int(x1), overlay['width']), min(int(y1), overlay['height'])
+    if x1 <= x0 or y1 <= y0:
+        raise ValueError('Region is empty')
+    pixel_size = microns_per_pixel(overlay['dataset_id']) if scalebar else None
+
+    max_zoom = overlay['max_zoom']
+    zoom = max_zoom
+    if max_dimension:
+        long_side = max(x1 - x0, y1 - y0)
+        while zoom > 0 and long_side // 2 ** (max_zoom - zoom + 1) >= max_dimension:
+            zoom -= 1
+    scale = 2 ** (max_zoom - zoom)
+    level_width, level_height = overlay['width'] // scale, overlay['height'] // scale
+    lx0, ly0 = min(x0 // scale, level_width - 1), min(y0 // scale, level_height - 1)
+    lx1 = max(lx0 + 1, min(-(-x1 // scale), level_width))
+    ly1 = max(ly0 + 1, min(-(-y1 // scale), level_height))
+    region = stitch_tiles(overlay, zoom, lx0, ly0, lx1, ly1)
+
+    if max_dimension and max(region.size) > max_dimension:
+        scale = max_dimension / max(region.size)
+        size = (max(1, round(region.width * scale)), max(1, round(region.height * scale)))
+        region = region.resize(size, Image.LANCZOS)
+    if scalebar:
+        region = draw_scalebar(region, scalebar, pixel_size * (x1 - x0) / region.width)
+
+    directory = os.path.dirname(path)
+    if directory:
+        os.makedirs(directory, exist_ok=True)
+    region.save(path, format='PNG')
+    return {'path': path_to_wire(path), 'width': region.width, 'height': region.height}
+
+
+# pixels between the panels of a side by side comparison
+COMPARISON_GAP = 16
+
+
+def compose_comparison(panel_a: str,
+                       panel_b: str,
+                       path: str,
+                       layout: Dict[str, Any],
+                       labels: List[Optional[str]]) -> Dict[str, Any]:
+    """
+    put two exported panels of the same region into one comparison figure
+
+    layout "side_by_side" puts a left of b on white, each under its label.
+    "swipe" shows a left of split (a fraction of the width) and b right of
+    it, with a white divider and the labels in the top corners. b is
+    resized to a's size should they differ by rounding
+
+    args:
+        panel_a, panel_b: PNGs written by export_region_png, see wire_path
+        path: where to write the PNG, parent folders are created
+        layout: {"kind": "side_by_side"} or {"kind": "swipe", "split": ...}
+        labels: [label of a, label of b], None leaves a panel unlabeled
+
+    returns:
+        {"path": ..., "width": ..., "height": ...} of the written image
+    """
+    a = Image.open(wire_path(panel_a)).convert('RGB')
+    b = Image.open(wire_path(panel_b)).convert('RGB')
+    if b.size != a.size:
+        b = b.resize(a.size, Image.LANCZOS)
+    width, height = a.size
+    font = default_font(max(12, min(width, height) // 30))
+    margin = max(4, min(width, height) // 60)
+
+    kind = layout.get('kind')
+    if kind == 'side_by_side':
+        band = font.getbbox('Ag')[3] + 2 * margin
+        figure = Image.new('RGB', (2 * width + COMPARISON_GAP, height + band), 'white')
+        figure.paste(a, (0, band))
+        figure.paste(b, (width + COMPARISON_GAP, band))
+        draw = ImageDraw.Draw(figure)
+        for left, label in zip((0, width + COMPARISON_GAP), labels):
+            if label:
+                text_width = draw.textlength(label, font=font)
+                draw.text((left + (width - text_width) / 2, margin), label, fill='black',
+                          font=font)
+    elif kind == 'swipe':
+        split = float(layout.get('split', 0.5))
+        if not 0 < split < 1:
+            raise ValueError(f'Swipe split must be between 0 and 1, got {split}')
+        at = min(width - 1, max(1, round(width * split)))
+        figure = a.copy()
+        figure.paste(b.crop((at, 0, width, height)), (at, 0))
+        draw = ImageDraw.Draw(figure, 'RGBA')
+        divider = max(2, width // 300)
+        draw.rectangle([at - divider // 2, 0, at - divider // 2 + divider - 1, height - 1],
+                       fill='white')
+        for corner, label in zip(('left', 'right'), labels):
+            if not label:
+                continue
+            left, top, right, bottom = draw.textbbox((0, 0), label, font=font)
+            x = margin if corner == 'left' else width - margin - (right - left)
+            draw.rectangle([x - margin // 2, margin // 2, x + (right - left) + margin // 2,
+                            margin + (bottom - top) + margin // 2], fill=(0, 0, 0, 160))
+            draw.text((x - left, margin - top), label, fill='white', font=font)
+    else:
+        raise ValueError(f'Unknown comparison layout {kind}')
+
+    path = wire_path(path)
+    directory = os.path.dirname(path)
+    if directory:
+        os.makedirs(directory, exist_ok=True)
+    figure.save(path, format='PNG')
+    return {'path': path_to_wire(path), 'width': figure.width, 'height': figure.height}
+
+
+def stitch_tiles(overlay: Dict[str, Any], zoom: int, x0: int, y0: int, x1: int, y1: int) -> Image.Image:
+    """
+    the rectangle x0..x1, y0..y1 (end exclusive, in that level's pixels) of
+    one zoom level, pasted together from its tiles
+    """
+    tile_size = overlay['tile_size']
+    tiles = overlay['tiles'][zoom]
+    region = Image.new('RGB', (x1 - x0, y1 - y0))
+    for ty in range(y0 // tile_size, (y1 - 1) // tile_size + 1):
+        for tx in range(x0 // tile_size, (x1 - 1) // tile_size + 1):
+            tile = Image.open(io.BytesIO(tiles[(tx, ty)]))
+            region.paste(tile, (tx * tile_size - x0, ty * tile_size - y0))
+    return region
+
+
+def get_overview(overlay_id: str, max_dimension: int) -> Dict[str, Any]:
+    """
+    the whole overlay as one image, for the mini-map
+
+    stitched from the smallest zoom level at least max_dimension across (or
+    the full resolution level if none is), then downscaled to fit
+
+    args:
+        overlay_id: ID of the overlay
+        max_dimension: longest side of the result in pixels
+
+    returns:
+        dict shaped like get_tissue_overlay_tile's result, format "jpeg"
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+    max_dimension = int(max_dimension)
+    if max_dimension <= 0:
+        raise ValueError('max_dimension must be positive')
+
+    overlay = TILES[overlay_id]
+    max_zoom = overlay['max_zoom']
+    for zoom in range(max_zoom + 1):
+        scale = 2 ** (max_zoom - zoom)
+        width, height = overlay['width'] // scale, overlay['height'] // scale
+        if max(width, height) >= max_dimension or zoom == max_zoom:
+            break
+
+    overview = stitch_tiles(overlay, zoom, 0, 0, width, height)
+    if max(overview.size) > max_dimension:
+        overview.thumbnail((max_dimension, max_dimension), Image.LANCZOS)
+
+    buf = io.BytesIO()
+    overview.save(buf, format='JPEG', quality=85)
+    return {'tile': base64.b64encode(buf.getvalue()).decode('utf-8'), 'format': 'jpeg'}
+
+
+def get_comparison_tile(overlay_a: str,
+                        overlay_b: str,
+                        zoom: int,
+                        x: int,
+                        y: int) -> Dict[str, Any]:
+    """
+    the same tile from two overlays in one call, for comparison views
+
+    args:
+        overlay_a: ID of the first overlay
+        overlay_b: ID of the second overlay
+        zoom/x/y: tile coordinates, shared by both
+
+    returns:
+        {"a": tile, "b": tile}, each shaped like get_tissue_overlay_tile's result
+    """
+    check_same_pyramid(overlay_a, overlay_b)
+    return {
+        'a': get_tissue_overlay_tile(overlay_a, zoom, x, y),
+        'b': get_tissue_overlay_tile(overlay_b, zoom, x, y)
+    }
+
+
+def check_same_pyramid(overlay_a: str, overlay_b: str) -> None:
+    """
+    raise unless both overlays exist and tile coordinates mean the same in both
+    """
+    for overlay_id in (overlay_a, overlay_b):
+        if overlay_id not in TILES:
+            raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    geometry = lambda o: (o['width'], o['height'], o['tile_size'], o['max_zoom'],
+                          o.get('orientation', 'rotate0'), o.get('transform'))
+    if geometry(TILES[overlay_a]) != geometry(TILES[overlay_b]):
+        raise ValueError(f'Overlays {overlay_a} and {overlay_b} have different tile pyramids')
+
+
+# summed channel difference below which a pixel counts as unchanged, JPEG
+# noise alone moves identical renderings by a few levels
+DIFF_THRESHOLD = 24
+
+# rgba of changed pixels in a changed_mask diff
+DIFF_HIGHLIGHT = (255, 0, 255, 200)
+
+
+def diff_tiles(overlay_a: str,
+               overlay_b: str,
+               zoom: int,
+               x: int,
+               y: int,
+               mode: str = 'absolute',
+               premultiplied: bool = False) -> Dict[str, Any]:
+    """
+    a PNG of where the same tile of two overlays differs
+
+    args:
+        overlay_a, overlay_b: IDs of overlays sharing their tile pyramid
+        zoom/x/y: tile coordinates, shared by both
+        mode: "absolute" for the per-channel absolute difference, or
+            "changed_mask" for changed pixels in DIFF_HIGHLIGHT over
+            transparency. both ignore differences under DIFF_THRESHOLD
+        premultiplied: multiply a changed_mask's colors by its alpha, for
+            compositors that blend premultiplied. absolute diffs are opaque
+
+    returns:
+        dict shaped like get_tissue_overlay_tile's result, format "png" and
+        premultiplied telling whether the colors were premultiplied
+    """
+    check_same_pyramid(overlay_a, overlay_b)
+    if mode not in ('absolute', 'changed_mask'):
+        raise ValueError(f'Unknown diff mode {mode}')
+
+    def pixels(overlay_id):
+        tiles = TILES[overlay_id]['tiles']
+        if zoom not in tiles or (x, y) not in tiles[zoom]:
+            raise ValueError(f'Tile ({x}, {y}) not found at zoom {zoom}')
+        tile = Image.open(io.BytesIO(tiles[zoom][(x, y)])).convert('RGB')
+        return np.asarray(tile, dtype=np.int16)
+
+    a, b = pixels(overlay_a), pixels(overlay_b)
+    difference = np.abs(a - b)
+    changed = difference.sum(axis=2) >= DIFF_THRESHOLD
+
+    if mode == 'absolute':
+        difference[~changed] = 0
+        image = Image.fromarray(difference.astype(np.uint8), 'RGB')
+    else:
+        mask = np.zeros(changed.shape + (4,), dtype=np.uint8)
+        mask[changed] = DIFF_HIGHLIGHT
+        premultiplied = bool(premultiplied)
+        if premultiplied:
+            mask = premultiply_alpha(mask)
+        image = Image.fromarray(mask, 'RGBA')
+
+    buf = io.BytesIO()
+    image.save(buf, format='PNG')
+    return {
+        'tile': base64.b64encode(buf.getvalue()).decode('utf-8'),
+        'format': 'png',
+        'premultiplied': premultiplied and mode == 'changed_mask'
+    }
+
+
+def premultiply_alpha(rgba: np.ndarray) -> np.ndarray:
+    """
+    rgba uint8 pixels with color channels scaled by alpha, rounded
+    """
+    alpha = rgba[..., 3:].astype(np.uint16)
+    out = rgba.copy()
+    out[..., :3] = (rgba[..., :3].astype(np.uint16) * alpha + 127) // 255
+    return out
+
+
+def get_overlay_stats(overlay_id: str) -> Dict[str, Any]:
+    """
+    tile counts and encoded sizes per zoom level
+
+    args:
+        overlay_id: ID of the overlay
+
+    returns:
+        dict with per-level stats and totals
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay_data = TILES[overlay_id]
+    levels = []
+    for zoom, level_tiles in sorted(overlay_data['tiles'].items()):
+        scale = 2 ** (overlay_data['max_zoom'] - zoom)
+        levels.append({
+            'zoom': zoom,
+            'width': overlay_data['width'] // scale,
+            'height': overlay_data['height'] // scale,
+            'tiles': len(level_tiles),
+            'bytes': sum(len(tile) for tile in level_tiles.values())
+        })
+
+    return {
+        'overlay_id': overlay_id,
+        'levels': levels,
+        'total_tiles': sum(level['tiles'] for level in levels),
+        'total_bytes': sum(level['bytes'] for level in levels)
+    }
+
+
+def get_overlay_legend(overlay_id: str) -> Dict[str, Any]:
+    """
+    legend of an overlay, computed when it was plotted
+
+    args:
+        overlay_id: ID of the overlay
+
+    returns:
+        legend dict, see build_legend
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    return TILES[overlay_id]['legend']
+
+
+def get_category_counts(overlay_id: str) -> List[List[Any]]:
+    """
+    how many of an overlay's segments fall in each category of its fill
+
+    args:
+        overlay_id: ID of a categorical overlay
+
+    returns:
+        [[category, count]] in legend order, zero for categories no segment
+        has. segments missing from the dataset are left out
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay = TILES[overlay_id]
+    if overlay['legend']['kind'] != 'categorical':
+        raise ValueError(f"{overlay['fill_key']} is continuous, it has no category counts")
+    if overlay['dataset_id'] not in DATASETS:
+        raise ValueError(f"Dataset {overlay['dataset_id']} not found")
+
+    adata = DATASETS[overlay['dataset_id']]
+    index = overlay_index(overlay)
+    column = segment_column(adata, overlay['fill_key'], overlay['is_gene'], index).dropna()
+    counts = column.astype(str).value_counts()
+    return [
+        [category['name'], int(counts.get(category['name'], 0))]
+        for category in overlay['legend']['categories']
+    ]
+
+
+def export_legend(overlay_id: str, path: str, orientation: str = 'vertical',
+                  size: int = 400) -> Dict[str, Any]:
+    """
+    render an overlay's legend to a PNG, to place beside an exported figure
+
+    a gradient bar over the legend's range in the overlay's colormap for
+    continuous values, a swatch list for categories. drawn with
+    matplotlib's Figure directly, so no pyplot backend is involved
+
+    args:
+        overlay_id: ID of the overlay
+        path: where to write the PNG, parent folders are created
+        orientation: "vertical" or "horizontal"
+        size: length in pixels of the legend's long side before it is
+            trimmed to its contents
+
+    returns:
+        {"path": ..., "width": ..., "height": ...} of the written image
+    """
+    from matplotlib.cm import ScalarMappable
+    from matplotlib.colors import Normalize
+    from matplotlib.figure import Figure
+    from matplotlib.patches import Patch
+
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+    if orientation not in ('vertical', 'horizontal'):
+        raise ValueError(f'Unknown legend orientation {orientation}')
+    path = wire_path(path)
+
+    overlay = TILES[overlay_id]
+    legend = overlay['legend']
+    dpi = 100
+    long_side, short_side = size / dpi, max(size / 4, 120) / dpi
+    figsize = (short_side, long_side) if orientation == 'vertical' else (long_side, short_side)
+    fig = Figure(figsize=figsize, dpi=dpi)
+
+    if legend['kind'] == 'continuous':
+        mappable = ScalarMappable(norm=Normalize(legend['min'], legend['max']),
+                                  cmap=overlay.get('cmap', 'viridis'))
+        if orientation == 'vertical':
+            cax = fig.add_axes([0.1, 0.05, 0.2, 0.9])
+        else:
+            cax = fig.add_axes([0.05, 0.6, 0.9, 0.2])
+        fig.colorbar(mappable, cax=cax, orientation=orientation, label=overlay['fill_key'])
+    else:
+        missing = [c['name'] for c in legend['categories'] if c['color'] is None]
+        if missing or not legend['categories']:
+            raise ValueError(f'Overlay {overlay_id} has no stored colors for '
+                             f'{overlay["fill_key"]}, there is no legend to draw')
+        handles = [Patch(facecolor=c['color'], label=c['name']) for c in legend['categories']]
+        columns = 1 if orientation == 'vertical' else len(handles)
+        fig.legend(handles=handles, loc='center', ncols=columns, frameon=False,
+                   title=overlay['fill_key'])
+
+    directory = os.path.dirname(path)
+    if directory:
+        os.makedirs(directory, exist_ok=True)
+    fig.savefig(path, format='png', bbox_inches='tight', pad_inches=0.1)
+    with Image.open(path) as written:
+        width, height = written.size
+    return {'path': path_to_wire(path), 'width': width, 'height': height}
+
+
+def segment_index(seg_id: str, orientation: str = 'rotate0',
+                  transform: Optional[List[float]] = None,
+                  origin: str = 'top_left') -> Dict[str, Any]:
+    """
+    label matrix and per-segment bounding boxes/pixel counts of a segmentation
+
+    the NPZ is bin2cell's sparse label matrix (scipy save_npz layout), built
+    once per segmentation, orientation, transform and origin since scanning
+    it is the expensive part. with either everything is in the plotted
+    frame: the labels put through transform (nearest neighbour), then
+    turned. a bottom_left origin flips the rows last, so row 0 is the
+    render's bottom row. anything matched against render pixels wants the
+    top_left index
+    """
+    key = (seg_id, orientation, tuple(transform) if transform is not None else None, origin)
+    if key in SEGMENT_INDEX:
+        return SEGMENT_INDEX[key]
+
+    import scipy.sparse as sp
+
+    seg_data = SEGMENTATION[seg_id]
+    labels = sp.csr_matrix(
+        (seg_data['data'], seg_data['indices'], seg_data['indptr']),
+        shape=tuple(seg_data['shape'])
+    )
+    if transform is not None:
+        labels = sp.csr_matrix(transform_image(labels.toarray(), transform, order=0))
+    if orientation != 'rotate0':
+        coo = labels.tocoo()
+        height, width = labels.shape
+        cols, rows = orient_pixels(orientation, coo.col, coo.row, width, height)
+        shape = (width, height) if orientation in ('rotate90', 'rotate270') else labels.shape
+        labels = sp.csr_matrix((coo.data, (rows, cols)), shape=shape)
+    if origin == 'bottom_left':
+        coo = labels.tocoo()
+        height, width = labels.shape
+        cols, rows = orient_pixels('flip_vertical', coo.col, coo.row, width, height)
+        labels = sp.csr_matrix((coo.data, (rows, cols)), shape=labels.shape)
+
+    coo = labels.tocoo()
+    order = np.argsort(coo.data, kind='stable')
+    ids, starts, counts = np.unique(coo.data[order], return_index=True, return_counts=True)
+    rows, cols = coo.row[order], coo.col[order]
+
+    index = {
+        'labels': labels,
+        'ids': ids,
+        # summed as floats, large segments overflow int32 coordinates. the
+        # half pixel puts the centroid on pixel centers
+        'cx': np.add.reduceat(cols.astype(np.float64), starts) / counts + 0.5,
+        'cy': np.add.reduceat(rows.astype(np.float64), starts) / counts + 0.5,
+        'x0': np.minimum.reduceat(cols, starts),
+        'y0': np.minimum.reduceat(rows, starts),
+        'x1': np.maximum.reduceat(cols, starts) + 1,
+        'y1': np.maximum.reduceat(rows, starts) + 1,
+        'pixel_count': counts
+    }
+    SEGMENT_INDEX[key] = index
+    return index
+
+
+def overlay_index(overlay: Dict[str, Any]) -> Dict[str, Any]:
+    """
+    the segment_index in an overlay's frame, what its coordinates refer to
+    """
+    return segment_index(overlay['seg_id'], overlay.get('orientation', 'rotate0'),
+                         overlay.get('transform'), overlay.get('origin', 'top_left'))
+
+
+def orient_array(array, orientation: str):
+    """
+    an image array (rows, cols[, channels]) turned by one of ORIENTATIONS,
+    a view where numpy can make one
+    """
+    if orientation == 'rotate90':
+        return np.rot90(array, k=-1)
+    if orientation == 'rotate180':
+        return np.rot90(array, k=2)
+    if orientation == 'rotate270':
+        return np.rot90(array, k=1)
+    if orientation == 'flip_horizontal':
+        return array[:, ::-1]
+    if orientation == 'flip_vertical':
+        return array[::-1]
+    return array
+
+
+def source_box(orientation: str, box: tuple, width: int, height: int) -> tuple:
+    """
+    box (x0, y0, x1, y1, end exclusive) of a width x height image turned by
+    orientation, in the image before it was turned
+    """
+    inverse = {'rotate90': 'rotate270', 'rotate270': 'rotate90'}.get(orientation, orientation)
+    x0, y0, x1, y1 = box
+    cols, rows = orient_pixels(inverse, np.array([x0, x1 - 1]), np.array([y0, y1 - 1]),
+                               width, height)
+    return int(cols.min()), int(rows.min()), int(cols.max()) + 1, int(rows.max()) + 1
+
+
+def check_transform(transform) -> List[float]:
+    """
+    a plot transform as six floats, raises unless it is finite and invertible
+    """
+    transform = [float(v) for v in transform]
+    if len(transform) != 6 or not np.all(np.isfinite(transform)):
+        raise ValueError(f'Transform must be six finite numbers, got {transform}')
+    a, b, _, d, e, _ = transform
+    if abs(a * e - b * d) < 1e-9:
+        raise ValueError(f'Transform {transform} is not invertible')
+    return transform
+
+
+def transform_image(image, transform: List[float], box: Optional[tuple] = None, order: int = 1):
+    """
+    an image (rows, cols[, channels]) put through a plot transform onto a
+    canvas of its own size, or only box (x0, y0, x1, y1) of that canvas.
+    order 1 interpolates linearly, 0 takes the nearest pixel (for labels).
+    what maps from outside the image is 0
+
+    the transform is in continuous coordinates, pixel (x, y) covering
+    x..x + 1, the same as OverlayBounds on the rust side
+    """
+    from scipy import ndimage
+
+    a, b, c, d, e, f = transform
+    det = a * e - b * d
+    # scipy maps output indices back to input indices, pixel centers sit at
+    # index + 0.5 in continuous coordinates
+    c, f = c + 0.5 * (a + b - 1), f + 0.5 * (d + e - 1)
+    ia, ib, id_, ie = e / det, -b / det, -d / det, a / det
+    # (row, col) order: [y, x] = matrix @ [y', x'] + offset
+    matrix = np.array([[ie, id_], [ib, ia]])
+    offset = np.array([-(id_ * c + ie * f), -(ia * c + ib * f)])
+
+    image = np.asarray(image)
+    height, width = image.shape[:2]
+    x0, y0, x1, y1 = box if box is not None else (0, 0, width, height)
+    offset = offset + matrix @ np.array([y0, x0])
+    shape = (y1 - y0, x1 - x0)
+
+    def resample(channel):
+        return ndimage.affine_transform(channel, matrix, offset, output_shape=shape, order=order)
+
+    if image.ndim == 2:
+        return resample(image)
+    return np.stack([resample(image[..., i]) for i in range(image.shape[2])], axis=-1)
+
+
+def orient_pixels(orientation: str, cols, rows, width: int, height: int):
+    """
+    where pixels of a width x height image land once it is turned by
+    orientation, the same as orient_array moves them. returns (cols, rows)
+    """
+    if orientation == 'rotate90':
+        return height - 1 - rows, cols
+    if orientation == 'rotate180':
+        return width - 1 - cols, height - 1 - rows
+    if orientation == 'rotate270':
+        return rows, width - 1 - cols
+    if orientation == 'flip_horizontal':
+        return width - 1 - cols, rows
+    if orientation == 'flip_vertical':
+        return cols, height - 1 - rows
+    return cols, rows
+
+
+def get_segment_at(overlay_id: str, x: int, y: int) -> Any:
+    """
+    the segment under a full resolution pixel of an overlay
+
+    args:
+        overlay_id: ID of the overlay
+        x, y: pixel coordinates in the full resolution image
+
+    returns:
+        None over background, otherwise dict with segment_id (as a string,
+        labels can exceed what JSON numbers survive in javascript), bbox
+        [x0, y0, x1, y1] (end exclusive), pixel_count and the overlay's
+        fill_key value for that segment (None if the cell isn't in the dataset)
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay = TILES[overlay_id]
+    index = overlay_index(overlay)
+    labels = index['labels']
+    if not (0 <= y < labels.shape[0] and 0 <= x < labels.shape[1]):
+        return None
+
+    label = int(labels[y, x])
+    if label == 0:
+        return None
+
+    i = int(np.searchsorted(index['ids'], label))
+    return segment_record(overlay, index, i)
+
+
+def hover_probe(overlay_id: str, x: int, y: int) -> Any:
+    """
+    get_segment_at cut down to the id and fill value, called on mousemove
+
+    only reads the label under the pixel, not its bbox or pixel count.
+    the segment index is shared with get_segment_at, built once
+
+    returns:
+        None over background, otherwise {"segment_id", "value"}
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay = TILES[overlay_id]
+    labels = overlay_index(overlay)['labels']
+    if not (0 <= y < labels.shape[0] and 0 <= x < labels.shape[1]):
+        return None
+
+    label = int(labels[y, x])
+    if label == 0:
+        return None
+    return {'segment_id': str(label), 'value': segment_value(overlay, label)}
+
+
+def segment_value(overlay: Dict[str, Any], label: int) -> Any:
+    """
+    the overlay's fill_key value for a segment, None if the cell isn't in
+    the dataset
+    """
+    adata = DATASETS[overlay['dataset_id']]
+    fill_key = overlay['fill_key']
+    if str(label) not in adata.obs_names:
+        return None
+    if overlay['is_gene']:
+        cell = adata[str(label), fill_key].X
+        cell = cell.toarray() if hasattr(cell, 'toarray') else np.asarray(cell)
+        return float(cell.ravel()[0])
+    raw = adata.obs.at[str(label), fill_key]
+    return raw.item() if hasattr(raw, 'item') else str(raw)
+
+
+def segment_record(overlay: Dict[str, Any], index: Dict[str, Any], i: int) -> Dict[str, Any]:
+    """
+    the get_segment_at dict for the i-th segment of a segment_index
+    """
+    label = int(index['ids'][i])
+    return {
+        'segment_id': str(label),
+        'bbox': [int(index['x0'][i]), int(index['y0'][i]), int(index['x1'][i]), int(index['y1'][i])],
+        'pixel_count': int(index['pixel_count'][i]),
+        'value': segment_value(overlay, label)
+    }
+
+
+def nearest_segments(overlay_id: str, x: float, y: float, k: int) -> List[Dict[str, Any]]:
+    """
+    the k segments whose centroids are closest to a full resolution point
+
+    args:
+        overlay_id: ID of the overlay
+        x, y: point in full resolution pixels, need not be on a segment
+        k: how many to return at most
+
+    returns:
+        list of get_segment_at dicts plus "distance" (pixels to the
+        centroid), nearest first. empty if the segmentation has no segments
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay = TILES[overlay_id]
+    index = overlay_index(overlay)
+    k = min(int(k), len(index['ids']))
+    if k <= 0:
+        return []
+
+    distances = np.hypot(index['cx'] - x, index['cy'] - y)
+    closest = np.argpartition(distances, k - 1)[:k]
+    closest = closest[np.argsort(distances[closest], kind='stable')]
+
+    segments = []
+    for i in closest:
+        segment = segment_record(overlay, index, int(i))
+        segment['distance'] = float(distances[i])
+        segments.append(segment)
+    return segments
+
+
+def get_neighborhood_summary(overlay_id: str, x: float, y: float,
+                             radius: float) -> Dict[str, Any]:
+    """
+    the segments whose centroids lie within radius of a full resolution point
+
+    args:
+        overlay_id: ID of the overlay
+        x, y: point in full resolution pixels, need not be on a segment
+        radius: in full resolution pixels
+
+    returns:
+        {"n_segments", "category_composition": [[category, count]] in legend
+        order without empty categories (empty for continuous fills),
+        "mean_fill": mean value of those in the dataset, None for categorical
+        fills or if none is}
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay = TILES[overlay_id]
+    index = overlay_index(overlay)
+    rows = np.flatnonzero(np.hypot(index['cx'] - x, index['cy'] - y) <= radius)
+    summary = {'n_segments': int(len(rows)), 'category_composition': [], 'mean_fill': None}
+    if len(rows) == 0:
+        return summary
+
+    adata = DATASETS[overlay['dataset_id']]
+    column = segment_column(adata, overlay['fill_key'], overlay['is_gene'], index)
+    column = column.iloc[rows].dropna()
+    if overlay['legend']['kind'] == 'categorical':
+        counts = column.astype(str).value_counts()
+        summary['category_composition'] = [
+            [category['name'], int(counts[category['name']])]
+            for category in overlay['legend']['categories']
+            if category['name'] in counts
+        ]
+    elif len(column):
+        summary['mean_fill'] = float(column.mean())
+    return summary
+
+
+def query_segments_in_bbox(overlay_id: str, x0: int, y0: int, x1: int, y1: int,
+                           page: int, page_size: int) -> Dict[str, Any]:
+    """
+    one page of the segments whose bbox overlaps a full resolution box
+
+    ordered by segment id, so pages of the same query line up across calls
+    as long as the segmentation isn't reloaded
+
+    args:
+        overlay_id: ID of the overlay
+        x0, y0, x1, y1: the box in full resolution pixels, end exclusive
+        page: 0 based page number
+        page_size: segments per page
+
+    returns:
+        {"items": [get_segment_at dicts], "total": segments in the box,
+        "next_page": the following page number, None on the last page}
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+
+    overlay = TILES[overlay_id]
+    index = overlay_index(overlay)
+    hits = np.flatnonzero((index['x0'] < x1) & (index['x1'] > x0)
+                          & (index['y0'] < y1) & (index['y1'] > y0))
+
+    start = page * page_size
+    end = min(start + page_size, len(hits))
+    return {
+        'items': [segment_record(overlay, index, int(i)) for i in hits[start:end]],
+        'total': int(len(hits)),
+        'next_page': page + 1 if end < len(hits) else None
+    }
+
+
+# rows per chunk of export_segments, bounds the memory a huge table needs
+EXPORT_CHUNK_ROWS = 100_000
+
+
+def segment_table(overlay: Dict[str, Any], index: Dict[str, Any], rows: slice) -> Any:
+    """
+    a slice of export_segments' table as a pandas DataFrame
+    """
+    import pandas as pd
+
+    adata = DATASETS[overlay['dataset_id']]
+    fill_key = overlay['fill_key']
+    ids = pd.Index(index['ids'][rows].astype(str))
+    table = pd.DataFrame({
+        'segment_id': ids,
+        'centroid_x': index['cx'][rows],
+        'centroid_y': index['cy'][rows],
+        'x0': index['x0'][rows],
+        'y0': index['y0'][rows],
+        'x1': index['x1'][rows],
+        'y1': index['y1'][rows],
+        'pixel_count': index['pixel_count'][rows],
+    })
+
+    # segments missing from the dataset get empty values, not dropped rows
+    obs = adata.obs.reindex(ids)
+    if overlay['is_gene']:
+        present = ids.isin(adata.obs_names)
+        values = np.full(len(ids), np.nan)
+        if present.any():
+            cells = adata[ids[present], fill_key].X
+            cells = cells.toarray() if hasattr(cells, 'toarray') else np.asarray(cells)
+            values[present] = cells.ravel()
+        table['value'] = values
+    else:
+        table['value'] = obs[fill_key].to_numpy()
+
+    attributes = obs.loc[:, ~obs.columns.isin(table.columns)].reset_index(drop=True)
+    return pd.concat([table, attributes], axis=1)
+
+
+def export_segments(overlay_id: str, path: str, format: str = 'csv') -> Dict[str, Any]:
+    """
+    write the per-segment table behind an overlay to CSV or Parquet
+
+    written EXPORT_CHUNK_ROWS segments at a time to a .partial file next to
+    path, renamed into place once complete so a failed export never leaves
+    a truncated table behind
+
+    args:
+        overlay_id: ID of the overlay
+        path: where to write the table, parent folders are created
+        format: "csv" or "parquet" (needs pyarrow)
+
+    returns:
+        {"path": ..., "rows": ...}
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+    if format not in ('csv', 'parquet'):
+        raise ValueError(f'Unknown table format {format}')
+    path = wire_path(path)
+    if format == 'parquet':
+        try:
+            import pyarrow as pa
+            import pyarrow.parquet as pq
+        except ImportError:
+            raise ValueError('Parquet export needs pyarrow installed')
+
+    overlay = TILES[overlay_id]
+    index = overlay_index(overlay)
+    total = len(index['ids'])
+
+    directory = os.path.dirname(path)
+    if directory:
+        os.makedirs(directory, exist_ok=True)
+    partial = f'{path}.partial'
+
+    writer = None
+    rows = 0
+    try:
+        # at least one chunk, an empty table still gets its header
+        for start in range(0, max(total, 1), EXPORT_CHUNK_ROWS):
+            chunk = segment_table(overlay, index, slice(start, start + EXPORT_CHUNK_ROWS))
+            if format == 'csv':
+                chunk.to_csv(partial, mode='a' if start else 'w', header=not start, index=False)
+            else:
+                table = pa.Table.from_pandas(chunk, preserve_index=False)
+                if writer is None:
+                    writer = pq.ParquetWriter(partial, table.schema)
+                else:
+                    table = table.cast(writer.schema)
+                writer.write_table(table)
+            rows += len(chunk)
+        if writer is not None:
+            writer.close()
+            writer = None
+        os.replace(partial, path)
+    finally:
+        if writer is not None:
+            writer.close()
+        if os.path.exists(partial):
+            os.remove(partial)
+
+    return {'path': path_to_wire(path), 'rows': rows}
+
+
+# OME-TIFF exports being written, output path -> state, see begin_ome_tiff
+OME_EXPORTS = {}
+
+# tile edge inside the OME-TIFFs written by write_ome_tiff_level
+OME_TILE_SIZE = 256
+
+
+def begin_ome_tiff(overlay_id: str, path: str, include_image: bool = False) -> Dict[str, Any]:
+    """
+    start writing an overlay as a pyramidal OME-TIFF, for QuPath, napari
+    and other bioimage tools
+
+    nothing is written yet. the bridge calls write_ome_tiff_level once per
+    level, full resolution first, checking for cancellation in between,
+    then finish_ome_tiff, or abort_ome_tiff to give up. until finished the
+    file is a .partial next to path
+
+    the overlay's rendered pixels become the channels "overlay red",
+    "overlay green" and "overlay blue". include_image adds the image the
+    overlay was drawn over, transformed and turned like the overlay, as
+    "image" channels. the dataset's pixel size goes into the OME metadata
+    when it is calibrated
+
+    args:
+        overlay_id: ID of the overlay
+        path: where to write the file, parent folders are created
+        include_image: also write the base image
+
+    returns:
+        {"levels": ..., "channels": [...]}
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+    path = wire_path(path)
+    if path in OME_EXPORTS:
+        raise ValueError(f'An OME-TIFF export to {path} is already running')
+
+    overlay = TILES[overlay_id]
+    image = None
+    if include_image:
+        if overlay.get('img_id') is None:
+            raise ValueError(f'Overlay {overlay_id} was not drawn over an image')
+        image = find_image(overlay['dataset_id'], overlay['img_id'])
+        if image is None:
+            raise ValueError(f"Image {overlay['img_id']} not found. Please load TIFF file first.")
+
+    channels = ['overlay red', 'overlay green', 'overlay blue']
+    if image is not None:
+        channels += ['image'] if image.ndim == 2 else ['image red', 'image green', 'image blue']
+    try:
+        pixel_size = microns_per_pixel(overlay['dataset_id'])
+    except ValueError:
+        pixel_size = None
+        warn('missing_calibration',
+             f"Dataset {overlay['dataset_id']} has no pixel size calibration, "
+             'the OME-TIFF is written without a physical pixel size')
+
+    directory = os.path.dirname(path)
+    if directory:
+        os.makedirs(directory, exist_ok=True)
+    partial = f'{path}.partial'
+    levels = overlay['max_zoom'] + 1
+    OME_EXPORTS[path] = {
+        'overlay': overlay,
+        'image': image,
+        # the base image in the overlay's frame, made on the first level
+        'base': None,
+        'channels': channels,
+        'pixel_size': pixel_size,
+        'levels': levels,
+        'written': 0,
+        'partial': partial,
+        'writer': tiff.TiffWriter(partial, bigtiff=True, ome=True)
+    }
+    return {'levels': levels, 'channels': channels}
+
+
+def ome_base_image(image, overlay: Dict[str, Any]) -> Image.Image:
+    """
+    the full base image in an overlay's frame, as get_base_image_tile cuts
+    its tiles, and of the overlay's size
+    """
+    if overlay['transform'] is not None:
+        image = transform_image(image, overlay['transform'])
+    image = orient_array(np.asarray(image), overlay['orientation'])
+    if image.dtype != np.uint8:
+        image = (image * 255 if image.max() <= 1.0 else image).astype(np.uint8)
+    base = Image.fromarray(np.ascontiguousarray(image))
+    base = base.convert('L' if image.ndim == 2 else 'RGB')
+    if base.size != (overlay['width'], overlay['height']):
+        base = base.resize((overlay['width'], overlay['height']), Image.LANCZOS)
+    return base
+
+
+def write_ome_tiff_level(path: str, level: int) -> None:
+    """
+    write the next resolution level of an export begun with begin_ome_tiff
+
+    level 0 is full resolution and each one after it half the size, the
+    overlay's zoom levels stitched from its tiles. levels must come in order
+    """
+    path = wire_path(path)
+    export = OME_EXPORTS.get(path)
+    if export is None:
+        raise ValueError(f'No OME-TIFF export to {path} is running')
+    level = int(level)
+    if level != export['written']:
+        raise ValueError(f"Expected OME-TIFF level {export['written']}, got {level}")
+
+    overlay = export['overlay']
+    scale = 2 ** level
+    width, height = overlay['width'] // scale, overlay['height'] // scale
+    region = stitch_tiles(overlay, overlay['max_zoom'] - level, 0, 0, width, height)
+    planes = np.asarray(region).transpose(2, 0, 1)
+    if export['image'] is not None:
+        if export['base'] is None:
+            export['base'] = ome_base_image(export['image'], overlay)
+        base = np.asarray(export['base'].resize((width, height), Image.LANCZOS))
+        base = base[np.newaxis] if base.ndim == 2 else base.transpose(2, 0, 1)
+        planes = np.concatenate([planes, base])
+
+    options = {'tile': (OME_TILE_SIZE, OME_TILE_SIZE), 'photometric': 'minisblack',
+               'compression': 'zlib'}
+    pixel_size = export['pixel_size']
+    if pixel_size:
+        # pixels per centimeter, what the TIFF resolution tags hold
+        options['resolution'] = (1e4 / (pixel_size * scale),) * 2
+        options['resolutionunit'] = 'CENTIMETER'
+
+    if level == 0:
+        metadata = {'axes': 'CYX', 'Channel': {'Name': export['channels']}}
+        if pixel_size:
+            metadata.update({'PhysicalSizeX': pixel_size, 'PhysicalSizeXUnit': 'µm',
+                             'PhysicalSizeY': pixel_size, 'PhysicalSizeYUnit': 'µm'})
+        export['writer'].write(planes, subifds=export['levels'] - 1, metadata=metadata, **options)
+    else:
+        export['writer'].write(planes, subfiletype=1, **options)
+    export['written'] += 1
+
+
+def finish_ome_tiff(path: str) -> Dict[str, Any]:
+    """
+    close an export once every level is written and move it into place
+
+    returns:
+        {"path", "width", "height", "channels", "levels", "pixel_size"},
+        pixel_size in microns per full resolution pixel or None
+    """
+    path = wire_path(path)
+    export = OME_EXPORTS.pop(path, None)
+    if export is None:
+        raise ValueError(f'No OME-TIFF export to {path} is running')
+    try:
+        if export['written'] != export['levels']:
+            written, levels = export['written'], export['levels']
+            raise ValueError(f'Only {written} of {levels} OME-TIFF levels written')
+        export['writer'].close()
+        os.replace(export['partial'], path)
+    except Exception:
+        discard_ome_export(export)
+        raise
+
+    overlay = export['overlay']
+    return {
+        'path': path_to_wire(path),
+        'width': overlay['width'],
+        'height': overlay['height'],
+        'channels': export['channels'],
+        'levels': export['levels'],
+        'pixel_size': export['pixel_size']
+    }
+
+
+def abort_ome_tiff(path: str) -> None:
+    """
+    give up on an export and delete its partial file, nothing to do if it
+    isn't running
+    """
+    export = OME_EXPORTS.pop(wire_path(path), None)
+    if export is not None:
+        discard_ome_export(export)
+
+
+def discard_ome_export(export: Dict[str, Any]):
+    # the writer may already be closed, or broken by the error being handled
+    with contextlib.suppress(Exception):
+        export['writer'].close()
+    if os.path.exists(export['partial']):
+        os.remove(export['partial'])
+
+
+# matplotlib doesn't record what a colormap is for, these are its diverging
+# ones. listed colormaps with few colors count as qualitative, the rest as
+# sequential
+DIVERGING_COLORMAPS = {
+    'PiYG', 'PRGn', 'BrBG', 'PuOr', 'RdGy', 'RdBu', 'RdYlBu', 'RdYlGn',
+    'Spectral', 'coolwarm', 'bwr', 'seismic', 'berlin', 'managua', 'vanimo'
+}
+
+# samples per colormap in list_colormaps
+COLORMAP_PREVIEW_STOPS = 8
+
+
+def list_colormaps() -> List[Dict[str, Any]]:
+    """
+    matplotlib's registered colormaps with a few preview colors each
+
+    reversed "_r" variants are left out, they're accepted wherever a name is
+
+    returns:
+        list of {"name", "kind", "preview_stops"}, kind is "sequential",
+        "diverging" or "qualitative", preview_stops are [r, g, b, a] samples
+        from one end of the colormap to the other
+    """
+    import matplotlib
+    from matplotlib.colors import ListedColormap
+
+    colormaps = []
+    for name in sorted(matplotlib.colormaps, key=str.lower):
+        if name.endswith('_r'):
+            continue
+        cmap = matplotlib.colormaps[name]
+        if name in DIVERGING_COLORMAPS:
+            kind = 'diverging'
+        elif isinstance(cmap, ListedColormap) and cmap.N <= 20:
+            kind = 'qualitative'
+        else:
+            kind = 'sequential'
+
+        samples = cmap(np.linspace(0, 1, COLORMAP_PREVIEW_STOPS))
+        stops = np.round(samples * 255).astype(np.uint8)
+        colormaps.append({'name': name, 'kind': kind, 'preview_stops': stops.tolist()})
+    return colormaps
+
+
+def get_value_histogram(dataset_id: str, fill_key: str, bins: int) -> Dict[str, Any]:
+    """
+    distribution of a gene's or numeric obs column's values over all cells
+
+    args:
+        dataset_id: ID of the loaded dataset
+        fill_key: gene name or numeric column in adata.obs
+        bins: number of equal width bins between the smallest and largest value
+
+    returns:
+        {"edges": bins + 1 boundaries, "counts": cells per bin, "min", "max"},
+        cells without a value (NaN) are left out
+    """
+    import pandas as pd
+
+    if dataset_id not in DATASETS:
+        raise ValueError(f'Dataset {dataset_id} not found')
+    adata = DATASETS[dataset_id]
+
+    if fill_key in adata.var_names:
+        values = adata[:, fill_key].X
+        values = values.toarray() if hasattr(values, 'toarray') else np.asarray(values)
+        values = values.ravel().astype(np.float64)
+    elif fill_key in adata.obs.columns:
+        column = adata.obs[fill_key]
+        categorical = isinstance(column.dtype, pd.CategoricalDtype)
+        if categorical or not pd.api.types.is_numeric_dtype(column):
+            raise ValueError(f'{fill_key} is categorical, it has no value histogram')
+        values = column.to_numpy(dtype=np.float64)
+    else:
+        raise ValueError(f'{fill_key} not found in genes or observation columns')
+
+    values = values[~np.isnan(values)]
+    if len(values) == 0:
+        raise ValueError(f'{fill_key} has no values in dataset {dataset_id}')
+
+    counts, edges = np.histogram(values, bins=int(bins))
+    return {
+        'edges': edges.tolist(),
+        'counts': counts.tolist(),
+        'min': float(values.min()),
+        'max': float(values.max())
+    }
+
+
+# working copies of running preprocessing pipelines by preprocessing_id:
+# {"dataset_id", "work": AnnData the steps transform, "done": ops run so far}
+PREPROC_JOBS = {}
+
+# step op -> the steps it needs before it in the same pipeline
+PREPROC_OPS = {
+    'normalize': (),
+    'pca': (),
+    'neighbors': ('pca',),
+    'leiden': ('neighbors',),
+    'gene_score': (),
+    'qc_metrics': (),
+}
+
+
+def preprocess_step(preprocessing_id: str, dataset_id: str, step: Dict[str, Any]) -> Dict[str, Any]:
+    """
+    run one step of a preprocessing pipeline
+
+    the first step of a pipeline copies the dataset, every step after works
+    on that copy. the dataset itself only gains the obs columns leiden,
+    gene_score and qc_metrics add, so they can be plotted as a fill_key.
+    end_preprocessing frees the copy
+
+    args:
+        preprocessing_id: pipeline the step belongs to
+        dataset_id: ID of the loaded dataset
+        step: {"op": one of PREPROC_OPS, ...its parameters}
+
+    returns:
+        {"keys": obs columns the step added}
+    """
+    import scanpy as sc
+
+    if dataset_id not in DATASETS:
+        raise ValueError(f'Dataset {dataset_id} not found')
+    adata = DATASETS[dataset_id]
+    op = step.get('op')
+    if op not in PREPROC_OPS:
+        raise ValueError(f'Unknown preprocessing step {op}, expected one of {sorted(PREPROC_OPS)}')
+
+    job = PREPROC_JOBS.get(preprocessing_id)
+    if job is None:
+        job = {'dataset_id': dataset_id, 'work': adata.copy(), 'done': set()}
+        PREPROC_JOBS[preprocessing_id] = job
+    elif job['dataset_id'] != dataset_id:
+        raise ValueError(f'Preprocessing {preprocessing_id} runs on dataset {job["dataset_id"]}')
+    missing = [needed for needed in PREPROC_OPS[op] if needed not in job['done']]
+    if missing:
+        raise ValueError(f'Preprocessing step {op} needs {missing[0]} before it')
+    work = job['work']
+
+    if op == 'qc_metrics':
+        prefix = step.get('prefix', '')
+        keys = [f'{prefix}total_counts', f'{prefix}n_genes']
+    elif op in ('leiden', 'gene_score'):
+        keys = [step['key']]
+    else:
+        keys = []
+    for key in keys:
+        if key in adata.obs.columns or key in adata.var_names:
+            raise ValueError(f'{key} already exists in dataset {dataset_id}')
+
+    if op == 'normalize':
+        sc.pp.normalize_total(work, target_sum=step.get('target_sum'))
+        sc.pp.log1p(work)
+    elif op == 'pca':
+        n_comps = min(int(step['n_comps']), work.n_obs - 1, work.n_vars - 1)
+        sc.pp.pca(work, n_comps=n_comps)
+    elif op == 'neighbors':
+        sc.pp.neighbors(work, n_neighbors=int(step['n_neighbors']))
+    elif op == 'leiden':
+        sc.tl.leiden(work, resolution=float(step['resolution']), key_added=keys[0])
+        adata.obs[keys[0]] = work.obs[keys[0]].values
+    elif op == 'gene_score':
+        genes = [gene for gene in step['genes'] if gene in work.var_names]
+        if not genes:
+            raise ValueError(f'None of {step["genes"]} are genes of dataset {dataset_id}')
+        values = work[:, genes].X
+        values = values.toarray() if hasattr(values, 'toarray') else np.asarray(values)
+        adata.obs[keys[0]] = values.mean(axis=1)
+    else:
+        # from the dataset's own counts, not the normalized copy
+        counts = adata.X
+        adata.obs[keys[0]] = np.asarray(counts.sum(axis=1)).ravel()
+        adata.obs[keys[1]] = np.asarray((counts > 0).sum(axis=1)).ravel()
+
+    job['done'].add(op)
+    return {'keys': keys}
+
+
+def end_preprocessing(preprocessing_id: str) -> Dict[str, Any]:
+    """
+    free a pipeline's working copy, nothing to do if it has none
+    """
+    PREPROC_JOBS.pop(preprocessing_id, None)
+    return {'preprocessing_id': preprocessing_id}
+
+
+def render_backend() -> Dict[str, Any]:
+    """
+    the matplotlib backend plots go through and whether it can work here
+
+    a GUI backend without a display hangs or fails on the first plot, the
+    bridge turns that combination into BadRenderBackend. only matplotlib
+    itself is imported, not pyplot, so this can't trip over the backend
+
+    returns:
+        {"name": ..., "interactive": GUI backend, "headless": no display}
+    """
+    import matplotlib
+    from matplotlib import rcsetup
+
+    name = matplotlib.get_backend()
+    non_interactive = {backend.lower() for backend in rcsetup.non_interactive_bk}
+    interactive = not name.startswith('module://') and name.lower() not in non_interactive
+    # windows and macOS always have a window server for a logged in user
+    headless = sys.platform.startswith(('linux', 'freebsd', 'openbsd')) and not (
+        os.environ.get('DISPLAY') or os.environ.get('WAYLAND_DISPLAY'))
+    return {'name': name, 'interactive': interactive, 'headless': headless}
+
+
+def get_environment_info() -> Dict[str, Any]:
+    """
+    interpreter, package versions and current memory usage of this process
+
+    returns:
+        dict with python_version, platform, pid, packages, memory_rss_bytes,
+        resource_limits, {"memory_bytes", "cpu_seconds"} (None where
+        unlimited) or None without the resource module (windows), and
+        render_backend, see render_backend
+    """
+    import platform
+    from importlib import metadata
+
+    packages = {}
+    for name in ['numpy', 'pillow', 'tifffile', 'bin2cell', 'scanpy', 'anndata', 'matplotlib']:
+        try:
+            packages[name] = metadata.version(name)
+        except metadata.PackageNotFoundError:
+            packages[name] = None
+
+    memory_rss_bytes = None
+    resource_limits = None
+    try:
+        import resource
+        # ru_maxrss is KiB on linux and bytes on macOS
+        rss = resource.getrusage(resource.RUSAGE_SELF).ru_maxrss
+        memory_rss_bytes = rss if sys.platform == 'darwin' else rss * 1024
+
+        def soft_limit(which):
+            limit = resource.getrlimit(which)[0]
+            return None if limit == resource.RLIM_INFINITY else limit
+
+        resource_limits = {
+            'memory_bytes': soft_limit(resource.RLIMIT_AS),
+            'cpu_seconds': soft_limit(resource.RLIMIT_CPU)
+        }
+    except ImportError:
+        pass
+
+    return {
+        'python_version': platform.python_version(),
+        'platform': platform.platform(),
+        'pid': os.getpid(),
+        'packages': packages,
+        'memory_rss_bytes': memory_rss_bytes,
+        'resource_limits': resource_limits,
+        'render_backend': render_backend()
+    }
+
+
+def delete_overlay(overlay_id: str) -> Dict[str, Any]:
+    """
+    free the tiles of an overlay that is no longer needed
+
+    args:
+        overlay_id: ID of the overlay
+
+    returns:
+        dict with whether the overlay existed
+    """
+    existed = TILES.pop(overlay_id, None) is not None
+    return {'deleted': existed}
+
+
+def set_overlay_origin(overlay_id: str, origin: str) -> Dict[str, Any]:
+    """
+    switch where an overlay's y coordinates count from, see ORIGINS
+
+    the tiles are kept, only segment and bbox coordinates change
+
+    args:
+        overlay_id: ID of the overlay
+        origin: "top_left" or "bottom_left"
+
+    returns:
+        dict with the origin now in effect
+    """
+    if overlay_id not in TILES:
+        raise ValueError(f'Overlay {overlay_id} not found. Please generate overlay first.')
+    if origin not in ORIGINS:
+        raise ValueError(f'Unknown origin {origin}')
+    TILES[overlay_id]['origin'] = origin
+    return {'origin': origin}
+
+
+# tags cancel_tagged was called with, oldest first. a bounded dict as an
+# ordered set, the bridge only cancels what it sent moments ago
+CANCELLED_TAGS = {}
+
+MAX_CANCELLED_TAGS = 256
+
+
+def cancel_tagged(tag: str) -> Dict[str, Any]:
+    """
+    skip the requests tagged tag that haven't started yet
+
+    sent by the bridge as a notification when the work behind the tag (a
+    viewport subscription) is torn down. it runs on the main thread, so it
+    lands before the lanes get to the requests queued ahead of it. a request
+    already running finishes, the bridge drops its response
+    """
+    CANCELLED_TAGS[tag] = True
+    while len(CANCELLED_TAGS) > MAX_CANCELLED_TAGS:
+        del CANCELLED_TAGS[next(iter(CANCELLED_TAGS))]
+    return {'tag': tag}
+
+
+def barrier() -> Dict[str, Any]:
+    """
+    no-op the bridge uses as an ordering sentinel
+
+    main() drains every lane before running it, so by the time this runs
+    everything sent before it has finished
+    """
+    return {}
+
+
+def echo(payload: str = '') -> Dict[str, Any]:
+    """
+    send payload straight back, what the bridge's benchmark times
+
+    does no work of its own, so the round trip is the pipe, the json
+    encoding on both sides and the dispatch
+    """
+    return {'payload': payload}
+
+
+def prewarm() -> Dict[str, Any]:
+    """
+    import the heavy libraries up front
+
+    the bridge calls this in the background right after the handshake so the
+    first real command doesn't pay for the imports
+
+    returns:
+        dict with the imported module names, how long it took and the
+        render_backend. pyplot is left out when the backend can't work
+        here, importing it is what hangs
+    """
+    import time
+    import importlib
+
+    start = time.perf_counter()
+    modules = ['scanpy', 'matplotlib']
+    for name in modules:
+        importlib.import_module(name)
+    backend = render_backend()
+    if not (backend['interactive'] and backend['headless']):
+        importlib.import_module('matplotlib.pyplot')
+        modules.append('matplotlib.pyplot')
+
+    elapsed_ms = (time.perf_counter() - start) * 1000
+    print(f"Prewarm imported {modules} in {elapsed_ms:.0f} ms", file=sys.stderr)
+
+    return {
+        'modules': modules,
+        'elapsed_ms': elapsed_ms,
+        'render_backend': backend
+    }
+
+
+def list_supported_commands():
+    """
+    describe every command in COMMANDS and its parameters
+
+    lets the bridge check at startup that this script supports everything
+    it is going to call
+
+    returns:
+        list of {"name": ..., "params": [{"name", "required", "type"}]}
+    """
+    import inspect
+
+    specs = []
+    for name, handler in COMMANDS.items():
+        params = []
+        for param in inspect.signature(handler).parameters.values():
+            # **extra pass-through kwargs aren't a named param
+            if param.kind in (param.VAR_POSITIONAL, param.VAR_KEYWORD):
+                continue
+            annotation = param.annotation
+            if annotation is inspect.Parameter.empty:
+                type_name = None
+            else:
+                type_name = getattr(annotation, '__name__', str(annotation))
+
+            params.append({
+                'name': param.name,
+                'required': param.default is inspect.Parameter.empty,
+                'type': type_name
+            })
+
+        specs.append({'name': name, 'params': params})
+
+    return specs
+
+
+# bumped whenever the request/response format changes
+PROTOCOL_VERSION = 1
+
+# command name -> handler, params are passed as keyword arguments
+COMMANDS = {
+    'list_supported_commands': list_supported_commands,
+    'prewarm': prewarm,
+    'plot_tissue_overlay': plot_tissue_overlay,
+    'open_render_context': open_render_context,
+    'close_render_context': close_render_context,
+    'recolor_overlay': recolor_overlay,
+    'get_tissue_overlay_tile': get_tissue_overlay_tile,
+    'get_tiles_batch': get_tiles_batch,
+    'build_pyramid_level': build_pyramid_level,
+    'get_base_image_tile': get_base_image_tile,
+    'delete_overlay': delete_overlay,
+    'set_overlay_origin': set_overlay_origin,
+    'get_overlay_stats': get_overlay_stats,
+    'get_overlay_legend': get_overlay_legend,
+    'get_category_counts': get_category_counts,
+    'get_environment_info': get_environment_info,
+    'list_colormaps': list_colormaps,
+    'get_value_histogram': get_value_histogram,
+    'preprocess_step': preprocess_step,
+    'end_preprocessing': end_preprocessing,
+    'list_images': list_images,
+    'get_comparison_tile': get_comparison_tile,
+    'get_overview': get_overview,
+    'diff_tiles': diff_tiles,
+    'barrier': barrier,
+    'cancel_tagged': cancel_tagged,
+    'echo': echo,
+    'get_segment_at': get_segment_at,
+    'hover_probe': hover_probe,
+    'nearest_segments': nearest_segments,
+    'get_neighborhood_summary': get_neighborhood_summary,
+    'query_segments_in_bbox': query_segments_in_bbox,
+    'get_input_dimensions': get_input_dimensions,
+    'missing_inputs': missing_inputs,
+    'validate_plot_args': validate_plot_args,
+    'export_region_png': export_region_png,
+    'compose_comparison': compose_comparison,
+    'export_legend': export_legend,
+    'export_segments': export_segments,
+    'begin_ome_tiff': begin_ome_tiff,
+    'write_ome_tiff_level': write_ome_tiff_level,
+    'finish_ome_tiff': finish_ome_tiff,
+    'abort_ome_tiff': abort_ome_tiff,
+    'load_dataset': load_dataset,
+    'checksum_file': checksum_file,
+    'unload_dataset': unload_dataset,
+    'list_datasets': list_datasets,
+}
+
+
+# namespace -> command table
+#
+# a bridge configured with a namespace prefixes every command, e.g. "tissue."
+# sends "tissue.plot_tissue_overlay". the command is split on its last "." and
+# the remainder is looked up in NAMESPACES["tissue"]; commands without a dot
+# use the default "" namespace. other subsystems register their own table here
+NAMESPACES = {
+    '': COMMANDS,
+}
+
+# the namespace of the bridge that spawned this script (see
+# PythonBridgeBuilder::namespace), it sends this script's commands there
+BRIDGE_NAMESPACE = os.environ.get('SPATIALVIEWER_NAMESPACE', '')
+NAMESPACES.setdefault(BRIDGE_NAMESPACE, COMMANDS)
+
+
+def resolve_command(command: str):
+    """
+    find the handler for a possibly namespaced command string
+
+    returns:
+        the handler, or None if the namespace or command is unknown
+    """
+    namespace, _, name = command.rpartition('.')
+    table = NAMESPACES.get(namespace)
+    if table is None:
+        return None
+    return table.get(name)
+
+
+def escape_surrogates(error: UnicodeEncodeError):
+    """
+    codec error handler for write_message: a message mentioning a path that
+    isn't UTF-8 (see wire_path) carries surrogate escapes, which aren't
+    valid UTF-8. each is sent as the text \\xNN of the byte it stands for
+    (\\uNNNN for a lone UTF-16 surrogate), so nothing of the path is lost.
+    surrogates only occur inside JSON strings, where the backslash is
+    escaped. paths the bridge reads back are sent by path_to_wire
+    """
+    escaped = []
+    for char in error.object[error.start:error.end]:
+        code = ord(char)
+        if 0xdc80 <= code <= 0xdcff:
+            escaped.append(f'\\\\x{code - 0xdc00:02x}')
+        else:
+            escaped.append(f'\\\\u{code:04x}')
+    return ''.join(escaped), error.end
+
+
+codecs.register_error('wire_escape', escape_surrogates)
+
+
+def write_message(message):
+    """
+    write one JSON message and its newline to stdout in full
+
+    with -u stdout is unbuffered and a write to the pipe can be partial, so
+    the remainder of a large response is written until nothing is left. a
+    pipe someone made non-blocking answers None while it is full, the write
+    then waits for the bridge to read. a write taking nothing means the pipe
+    is gone
+    """
+    text = json.dumps(message, ensure_ascii=False) + '\n'
+    view = memoryview(text.encode('utf-8', 'wire_escape'))
+    out = sys.stdout.buffer
+    # log records can come from other threads, lines must not interleave
+    with STDOUT_LOCK:
+        while view:
+            written = out.write(view)
+            if written is None:
+                select.select([], [out], [])
+                continue
+            if written == 0:
+                raise BrokenPipeError('stdout took no data')
+            view = view[written:]
+        out.flush()
+
+
+def log(level: str, message: str):
+    """
+    send a log record to the app, shown live while a command runs
+
+    written as {"type": "log", "level": ..., "message": ...} between
+    responses, level is one of debug, info, warning, error. the bridge drops
+    records past a rate limit, so this is for progress, not bulk output.
+    records of a traced request carry its "trace_id", see trace_span
+    """
+    record = {'type': 'log', 'level': level, 'message': message}
+    trace_id = getattr(REQUEST_TRACE, 'trace_id', None)
+    if trace_id is not None:
+        record['trace_id'] = trace_id
+    write_message(record)
+
+
+# warnings of the request running on this thread, see warn
+REQUEST_WARNINGS = threading.local()
+
+
+def warn(code: str, message: str):
+    """
+    attach a caveat to the response of the request being handled, which
+    still succeeds
+
+    sent as {"code": ..., "message": ...} in the response's "warnings" list,
+    the bridge passes them on to the app. outside a request (or when the
+    request fails) it only goes to stderr
+    """
+    print(f'Warning {code}: {message}', file=sys.stderr)
+    pending = getattr(REQUEST_WARNINGS, 'items', None)
+    if pending is not None:
+        pending.append({'code': code, 'message': message})
+
+
+# trace id, spans and handler start of the request running on this thread,
+# see trace_span
+REQUEST_TRACE = threading.local()
+
+
+def record_span(name: str, started: float, ended: float):
+    """
+    add a stage timed with time.perf_counter() to the request's trace
+
+    the bridge sends a "trace_id" with each request while it traces them,
+    the response then carries the stages as "spans" ({"name", "start_ms",
+    "duration_ms"}, start_ms from the handler's start). name them "load",
+    "render" or "encode". untraced requests and code outside a request
+    record nothing
+    """
+    spans = getattr(REQUEST_TRACE, 'spans', None)
+    if spans is None:
+        return
+    spans.append({'name': name,
+                  'start_ms': (started - REQUEST_TRACE.started) * 1000,
+                  'duration_ms': (ended - started) * 1000})
+
+
+@contextlib.contextmanager
+def trace_span(name: str):
+    """
+    record_span around a block
+    """
+    import time
+
+    started = time.perf_counter()
+    try:
+        yield
+    finally:
+        record_span(name, started, time.perf_counter())
+
+
+class ProtocolLogHandler(logging.Handler):
+    """
+    routes the logging module to the app via log()
+    """
+    LEVELS = {logging.DEBUG: 'debug', logging.INFO: 'info', logging.WARNING: 'warning'}
+
+    def emit(self, record):
+        try:
+            log(self.LEVELS.get(record.levelno, 'error'), self.format(record))
+        except Exception:
+            self.handleError(record)
+
+
+# lanes with a worker thread each, so a long render never holds up tile
+# requests (numpy and PIL drop the GIL for the heavy parts). requests in
+# other lanes ("control") run on the main thread in arrival order
+WORKER_LANES = ('plot', 'tile')
+
+
+def handle_request(request):
+    """
+    run one parsed request and write its response, none for notifications
+
+    the response carries elapsed_ms, how long the handler ran, so the bridge
+    can tell python's work apart from json and pipe time. a request with a
+    "trace_id" gets the handler's stages back as "spans", see record_span
+    """
+    import time
+
+    # echoed back so the bridge can match responses to requests
+    request_id = request.get('id')
+    notification = bool(request.get('notification'))
+    REQUEST_WARNINGS.items = []
+    trace_id = request.get('trace_id')
+    REQUEST_TRACE.trace_id = trace_id
+    REQUEST_TRACE.spans = [] if trace_id is not None else None
+    try:
+        command = request.get('command')
+        handler = resolve_command(command) if isinstance(command, str) else None
+        if handler is None:
+            raise ValueError(f'Unknown command: {command}')
+        tag = request.get('tag')
+        if tag is not None and tag in CANCELLED_TAGS:
+            raise RuntimeError(f'Request cancelled, {tag} ended')
+
+        started = time.perf_counter()
+        REQUEST_TRACE.started = started
+        data = handler(**(request.get('params') or {}))
+        elapsed_ms = (time.perf_counter() - started) * 1000
+        response = {'success': True, 'data': data, 'error': None, 'elapsed_ms': elapsed_ms}
+    except Exception as e:
+        response = {'success': False, 'data': None, 'error': str(e)}
+    finally:
+        warnings, REQUEST_WARNINGS.items = REQUEST_WARNINGS.items, None
+        spans, REQUEST_TRACE.spans = REQUEST_TRACE.spans, None
+        REQUEST_TRACE.trace_id = None
+    # left out when empty, older bridges don't know the key
+    if warnings and response['success']:
+        response['warnings'] = warnings
+    if spans and response['success']:
+        response['spans'] = spans
+
+    if notification:
+        if not response['success']:
+            print(f"Notification {request.get('command')} failed: {response['error']}", file=sys.stderr)
+        return
+
+    response['id'] = request_id
+    write_message(response)
+
+
+class LaneWorker:
+    """
+    runs one lane's requests on its own thread, in arrival order
+    """
+    def __init__(self, lane: str):
+        self.queue = queue.Queue()
+        threading.Thread(target=self.run, name=f'lane-{lane}', daemon=True).start()
+
+    def run(self):
+        while True:
+            request = self.queue.get()
+            try:
+                handle_request(request)
+            finally:
+                self.queue.task_done()
+
+
+def main():
+    """
+    command loop run when the bridge spawns this script
+
+    reads one JSON request per line from stdin ({"command": ..., "params": {...}})
+    and writes exactly one JSON response per line to stdout
+    ({"success": ..., "data": ..., "error": ...})
+
+    stdout is reserved for responses and log records (see log()), print()
+    and anything else must go to stderr
+
+    before reading any request a handshake response is written so the bridge
+    knows the loop is up: {"success": true, "data": {"ready": true, ...}}
+
+    requests with "notification": true get no response at all, a failure is
+    only logged to stderr
+
+    a request's "lane" picks where it runs, see WORKER_LANES. commands are
+    ordered within a lane only, except barrier and unload_dataset, which
+    wait for every lane to drain before they run
+
+    an optional "routing_key" names the dataset a request touches. it's
+    meant for a scheduler spreading requests over several processes, this
+    single process ignores it. an optional "tag" names the work a request
+    belongs to, see cancel_tagged. an optional "trace_id" asks for the
+    handler's timings, see record_span
+    """
+    handshake = {'ready': True, 'pid': os.getpid(), 'protocol_version': PROTOCOL_VERSION}
+    write_message({'success': True, 'data': handshake, 'error': None})
+
+    # only after the handshake, the bridge expects that to be the first line
+    root = logging.getLogger()
+    root.addHandler(ProtocolLogHandler())
+    root.setLevel(logging.INFO)
+
+    workers = {lane: LaneWorker(lane) for lane in WORKER_LANES}
+
+    for line in sys.stdin:
+        line = line.strip()
+        if not line:
+            continue
+
+        try:
+            request = json.loads(line)
+            if not isinstance(request, dict):
+                raise ValueError('Request must be a JSON object')
+        except Exception as e:
+            write_message({'id': None, 'success': False, 'data': None, 'error': str(e)})
+            continue
+
+        worker = workers.get(request.get('lane'))
+        if worker is not None:
+            worker.queue.put(request)
+            continue
+
+        command = request.get('command')
+        if isinstance(command, str) and resolve_command(command) in (barrier, unload_dataset):
+            for lane_worker in workers.values():
+                lane_worker.queue.join()
+        handle_request(request)
+
+
+if __name__ == '__main__':
+    main()
@@ -72,7 +72,8 @@ export function TiledImageViewer({
             promises.push(
               api.getTissueOverlayTile(overlayId, zoom, x, y)
                 .then((result) => {
-                  newTiles.set(key, `data:image/jpeg;base64,${result.tile}`);
+                  const mime = result.mime ?? `image/${result.format}`;
+                  newTiles.set(key, `data:${mime};base64,${result.tile}`);
                 })
                 .catch((err) => {
                   console.error(`Failed to load tile ${x},${y} at zoom ${zoom}:`, err);
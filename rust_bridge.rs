@@ -1,187 +1,14139 @@
+use base64::Engine;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::io::{BufRead, BufReader, Write};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
-use std::sync::{Arc, Mutex};
-use tauri::State;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, RwLock, Weak};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager, State};
+
+// ===== Logging =====
+
+/// debug logging for the bridge
+///
+/// compiled out unless the `verbose-logging` cargo feature is enabled (off
+/// by default) so release builds stay quiet. always writes to stderr:
+/// stdout must never carry anything but protocol traffic
+#[cfg(feature = "verbose-logging")]
+macro_rules! bridge_debug {
+    ($($arg:tt)*) => {
+        eprintln!($($arg)*)
+    };
+}
+
+/// with logging disabled the arguments are still type-checked (and count as
+/// used) but nothing is formatted or written
+#[cfg(not(feature = "verbose-logging"))]
+macro_rules! bridge_debug {
+    ($($arg:tt)*) => {
+        {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+// ===== Errors =====
+
+/// everything that can go wrong talking to the python helper
+#[derive(Debug, Clone, PartialEq)]
+pub enum BridgeError {
+    /// the python process could not be started or failed its handshake
+    Spawn(String),
+    /// python exited or closed its pipes while a command was in flight
+    ProcessExited,
+    /// reading or writing the pipes failed for some other reason
+    Io(String),
+    /// python wrote something that isn't a valid response
+    Protocol(String),
+    /// python answered a request that wasn't outstanding, the stream can't
+    /// be trusted to pair responses with requests anymore
+    ProtocolDesync(String),
+    /// the command reached python and raised there
+    Python(String),
+    /// the overlay was never plotted through this bridge (or was deleted)
+    OverlayNotFound(String),
+    /// the image isn't among the dataset's, see `list_images`
+    ImageNotFound(String),
+    /// the app is closing, the command was abandoned
+    ShuttingDown,
+    /// no response to the named command before its deadline
+    Timeout(String),
+    /// python didn't complete the startup handshake in time
+    StartupTimeout(Duration),
+    /// fetching a remote dataset failed
+    Download(String),
+    /// inputs a saved view refers to aren't loaded in python (anymore),
+    /// e.g. "dataset sample1"
+    InputsMissing(Vec<String>),
+    /// the dataset the command was working on has been unloaded
+    DatasetUnloaded(String),
+    /// the work the request was tagged with was torn down, see
+    /// `RequestCanceller`
+    Cancelled(String),
+    /// `reconfigure_transport` can't switch to this transport
+    UnsupportedTransport {
+        requested: String,
+        supported: Vec<String>,
+    },
+    /// the helper doesn't list these commands of `PythonCommand`, see
+    /// `PythonBridgeBuilder::strict_commands`
+    MissingCommands { missing: Vec<String> },
+    /// the file's checksum isn't the expected one, see `ChecksumCheck`
+    IntegrityMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    /// `validate_plot_args` found problems with these plot arguments
+    InvalidPlotArgs(Vec<ValidationError>),
+    /// the tile wasn't cached and tile fetches are paused, see `pause_tiles`
+    TilesPaused,
+    /// matplotlib is set to this GUI backend but there is no display, plots
+    /// would hang or fail. see `PythonBridgeBuilder::force_agg_backend`
+    BadRenderBackend(String),
+    /// python answered, but not in the shape the rust type expects
+    Deserialize {
+        command: String,
+        /// serde path of the failing field, e.g. `levels[2].bytes`
+        path: String,
+        message: String,
+        /// the response as received, capped at `RAW_PREVIEW_BYTES`
+        raw: String,
+    },
+}
+
+impl std::fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BridgeError::Spawn(e) => write!(f, "Failed to start Python: {}", e),
+            BridgeError::ProcessExited => write!(f, "Python process exited unexpectedly"),
+            BridgeError::Io(e) => write!(f, "Python I/O error: {}", e),
+            BridgeError::Protocol(e) => write!(f, "Python protocol error: {}", e),
+            BridgeError::ProtocolDesync(e) => write!(f, "Python protocol out of sync: {}", e),
+            BridgeError::Python(e) => write!(f, "{}", e),
+            BridgeError::OverlayNotFound(id) => write!(f, "Overlay {} not found", id),
+            BridgeError::ImageNotFound(id) => write!(f, "Image {} not found", id),
+            BridgeError::ShuttingDown => write!(f, "Python bridge is shutting down"),
+            BridgeError::Timeout(command) => write!(f, "Python command {} timed out", command),
+            BridgeError::Download(msg) => write!(f, "Download failed: {}", msg),
+            BridgeError::InputsMissing(inputs) => {
+                write!(f, "No longer loaded, load them again first: {}", inputs.join(", "))
+            }
+            BridgeError::DatasetUnloaded(id) => write!(f, "Dataset {} was unloaded", id),
+            BridgeError::Cancelled(tag) => write!(f, "Request cancelled, {} ended", tag),
+            BridgeError::UnsupportedTransport {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "Can't switch to the {} transport, this build supports: {}",
+                requested,
+                supported.join(", ")
+            ),
+            BridgeError::MissingCommands { missing } => write!(
+                f,
+                "Python helper does not support commands required by this version of the app: {}",
+                missing.join(", ")
+            ),
+            BridgeError::IntegrityMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(f, "Checksum of {} is {}, expected {}", path.display(), actual, expected),
+            BridgeError::InvalidPlotArgs(errors) => {
+                let errors: Vec<String> = errors
+                    .iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect();
+                write!(f, "Can't plot with these arguments: {}", errors.join("; "))
+            }
+            BridgeError::TilesPaused => write!(f, "Tile not cached and tile fetches are paused"),
+            BridgeError::BadRenderBackend(backend) => write!(
+                f,
+                "Matplotlib backend {} needs a display and there is none, set MPLBACKEND=Agg",
+                backend
+            ),
+            BridgeError::StartupTimeout(after) => {
+                write!(f, "Python helper did not start within {:?}", after)
+            }
+            BridgeError::Deserialize {
+                command,
+                path,
+                message,
+                raw,
+            } => write!(
+                f,
+                "Unexpected response to {} at `{}`: {}\n  response: {}",
+                command, path, message, raw
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BridgeError {}
+
+impl BridgeError {
+    /// classify a pipe error, a broken pipe means the child has gone away
+    fn from_io(e: std::io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::UnexpectedEof => {
+                BridgeError::ProcessExited
+            }
+            _ => BridgeError::Io(e.to_string()),
+        }
+    }
+}
 
 // ===== python Bridge Core =====
 
 #[derive(Debug, Serialize, Deserialize)]
 struct PythonRequest {
+    /// echoed back in the response so it can be matched to its waiter,
+    /// absent on notifications
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
     command: String,
     params: serde_json::Value,
+    /// python must not answer, see `send_notification`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    notification: bool,
+    /// where python runs it, see `Lane`
+    #[serde(default)]
+    lane: Lane,
+    /// the dataset a command touches, for a scheduler that keeps each
+    /// dataset's commands on the process that has it loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    routing_key: Option<String>,
+    /// the caller's work the request belongs to (a viewport subscription
+    /// id), python skips requests of a cancelled tag still queued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<String>,
+    /// python times its handler's stages under it and tags its log records
+    /// with it, see `PythonBridge::set_request_tracing`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trace_id: Option<u64>,
 }
 
+/// a response line. `D` is `IgnoredAny` when `data` is left for the caller
+/// to decode, see `Body::Line`
 #[derive(Debug, Serialize, Deserialize)]
-struct PythonResponse {
+struct PythonResponse<D = serde_json::Value> {
+    /// id of the request this answers, absent on the startup handshake
+    #[serde(default)]
+    id: Option<u64>,
     success: bool,
-    data: Option<serde_json::Value>,
+    data: Option<D>,
     error: Option<String>,
+    /// caveats of a successful command, absent from older scripts
+    #[serde(default)]
+    warnings: Vec<PythonWarning>,
+    /// how long python's handler ran, absent from older scripts
+    #[serde(default)]
+    elapsed_ms: Option<f64>,
+    /// stages of the handler, only for requests with a `trace_id`
+    #[serde(default)]
+    spans: Vec<PythonSpan>,
 }
 
 pub struct PythonBridge {
-    /// python child process
-    process: Child,
-    /// sending commands to python, wrapped in arc for thread-safe sharing
-    stdin: Arc<Mutex<ChildStdin>>,
-    /// reading responses from python
-    stdout: Arc<Mutex<BufReader<ChildStdout>>>,
+    /// interpreter and script the child was spawned from, kept for respawns
+    python_path: PathBuf,
+    script_path: PathBuf,
+    /// prefix for every command string, "" or e.g. "tissue."
+    namespace: String,
+    /// pipes to the current python child, replaced on respawn. wrapped in
+    /// arc so background threads (prewarm, the stdout reader) can share it
+    connection: Arc<Connection>,
+    /// temp file holding an embedded script, removed on drop
+    embedded_script_file: Option<EmbeddedScript>,
+    /// lifecycle state, shared with the prewarm and reader threads
+    status: Arc<Mutex<BridgeStatus>>,
+    /// cancelled when the app shuts down, fails every in-flight command
+    shutdown: CancellationToken,
+    /// overlays generated through this bridge and their generations
+    overlays: OverlayRegistry,
+    /// plot setups python holds, see `open_render_context`
+    render_contexts: RenderContextRegistry,
+    /// overlay tiles: their cache, processors and format. shared with
+    /// `AppState` to serve them while a command holds the bridge
+    tiles: Arc<TileService>,
+    /// tiles of images without an overlay, see `get_base_image_tile`
+    base_tiles: TileCache,
+    /// last mini-map overview of each overlay, see `get_overview`
+    overviews: OverviewCache,
+    /// answers of `hover_probe` by hover cell
+    hover_cache: HoverCache,
+    /// responses of read-only commands, see `ResultCache`
+    result_cache: ResultCache,
+    /// zoom levels fetched into the tile cache right after a plot
+    warmup_levels: u32,
+    /// per-command concurrency limits, shared with the prewarm thread
+    gates: Arc<CommandGates>,
+    /// deadline, log handler and desync policy, applied again on respawn
+    connection_options: ConnectionOptions,
+    /// response deadlines overriding the global one, see `command_timeout`
+    command_timeouts: HashMap<PythonCommand, Duration>,
+    /// commands whose `data` skips `serde_json::Value`, see
+    /// `PythonBridgeBuilder::direct_decode`
+    direct_decode: HashSet<PythonCommand>,
+    /// deadline of the call in progress, see `with_timeout`
+    call_timeout: Option<Duration>,
+    /// tag of the call in progress, see `with_request_tag`
+    request_tag: Option<String>,
+    /// calls tagged requests off without the bridge, follows respawns
+    canceller: Arc<RequestCanceller>,
+    /// ids filled in when a command omits them, see `set_context`
+    context: DatasetContext,
+    /// plot options filled in when a plot omits them, see
+    /// `set_render_defaults`
+    render_defaults: RenderDefaults,
+    /// recent commands, errors and python stderr for bug reports
+    diagnostics: Arc<Diagnostics>,
+    /// how segment ids are serialized for the frontend
+    id_encoding: IdEncoding,
+    /// handshake deadline, applied again on respawn
+    startup_timeout: Duration,
+    /// rlimits of the child, applied again on respawn
+    resource_limits: ResourceLimits,
+    /// variables set for the child on top of ours, applied again on respawn
+    env: BTreeMap<String, OsString>,
+    /// resolved builder settings, see `get_bridge_config`
+    config: BridgeConfig,
+    /// stops the child after a quiet period, `None` keeps it alive
+    idle: Option<Arc<IdleWatch>>,
+    /// told about each level of an eager pyramid build
+    on_pyramid_progress: Option<PyramidProgressHandler>,
+    /// told about each warning python attaches to a response
+    on_python_warning: Option<PythonWarningHandler>,
+    /// warnings of the `with_warnings` call in progress
+    warnings: Option<Vec<PythonWarning>>,
+    /// connections set up again since the bridge was built, see
+    /// `TransportInfo::reconnects`
+    reconnects: u64,
+    /// preprocessing pipelines started, numbers their ids
+    preprocessings: u64,
+}
+
+/// lifecycle of the python helper as seen by the UI
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum BridgeStatus {
+    /// no bridge has been spawned yet
+    NotStarted,
+    /// spawned, heavy imports still running
+    Starting,
+    /// the next command will run without import overhead
+    Ready,
+    /// prewarm failed, commands may still work but will be slow or fail
+    Failed { error: String },
+    /// the child exited, it is respawned on the next command
+    Dead,
+    /// stopped after sitting unused, respawned and prewarmed on the next
+    /// command. see `PythonBridgeBuilder::idle_timeout`
+    Idle,
 }
 
 impl PythonBridge {
+    /// spawn the python helper with default settings
+    pub fn new() -> Result<Self, BridgeError> {
+        PythonBridgeBuilder::new().build()
+    }
+
+    pub fn builder() -> PythonBridgeBuilder {
+        PythonBridgeBuilder::new()
+    }
+
     /// core communication method
     ///
     /// sends JSON request to Python, waits for JSON response, parses result
     /// this is synchronous and blocking, the calling thread will wait until
     /// Python processes the command and returns
+    ///
+    /// if python has died (before or during the command) the bridge is
+    /// marked `Dead` and a fresh child is spawned. the command fails with
+    /// `ProcessExited` unless it is idempotent, in which case it is retried
+    /// once on the new child
     fn send_command(
-        &self,
-        command: &str,
+        &mut self,
+        command: PythonCommand,
         params: serde_json::Value,
-    ) -> Result<serde_json::Value, String> {
-        let request = PythonRequest {
-            command: command.to_string(),
-            params,
-        };
+    ) -> Result<serde_json::Value, BridgeError> {
+        self.send_command_body(command, params)?.into_value(command)
+    }
 
-        let request_json = serde_json::to_string(&request).map_err(|e| e.to_string())?;
-        println!("PythonBridge: Sending JSON: {}", request_json);
+    /// `send_command`, leaving the `data` of a direct decode unparsed
+    fn send_command_body(
+        &mut self,
+        command: PythonCommand,
+        params: serde_json::Value,
+    ) -> Result<Body, BridgeError> {
+        let started = Instant::now();
+        let params_preview = preview_json(&params, DIAGNOSTICS_PARAMS_BYTES);
+        let _active = self.idle.as_ref().map(IdleWatch::begin);
+        let result = self.execute_command(command, params);
+        self.diagnostics
+            .record_command(command, params_preview, started.elapsed(), &result);
+        result
+    }
 
-        // send command - write JSON to python via stdin
-        {
-            let mut stdin = self.stdin.lock().unwrap();
-            writeln!(stdin, "{}", request_json).map_err(|e| {
-                println!("PythonBridge: Error writing to stdin: {}", e);
-                e.to_string()
-            })?;
-            stdin.flush().map_err(|e| {
-                println!("PythonBridge: Error flushing stdin: {}", e);
-                e.to_string()
-            })?;
+    fn execute_command(
+        &mut self,
+        command: PythonCommand,
+        params: serde_json::Value,
+    ) -> Result<Body, BridgeError> {
+        if self.shutdown.is_cancelled() {
+            return Err(BridgeError::ShuttingDown);
         }
-        println!("PythonBridge: Sent command, waiting for response...");
+        let (started, at_ms) = (Instant::now(), unix_millis());
+        self.revive(command.name())?;
 
-        // read response from python via stdout
-        let mut stdout = self.stdout.lock().unwrap();
-        let mut response_line = String::new();
-        stdout
-            .read_line(&mut response_line)
-            .map_err(|e| {
-                println!("PythonBridge: Error reading from stdout: {}", e);
-                e.to_string()
-            })?;
+        let wire_name = command.wire_name(&self.namespace);
+        // the permit outlives a respawn, which needs `&mut self`
+        let gates = Arc::clone(&self.gates);
+        let _permit = gates.acquire(command)?;
 
-        println!("PythonBridge: Got response line: {}", response_line);
+        let dispatch = self.dispatch(command, &params);
+        let reply = match self.connection.request(&wire_name, params.clone(), &dispatch) {
+            Outcome::Redispatch => {
+                bridge_debug!("PythonBridge: Python exited during {}, redispatching", wire_name);
+                *self.status.lock().unwrap() = BridgeStatus::Dead;
+                self.respawn()?;
+                // only once, a request that crashes python twice may well
+                // be the reason it crashes
+                let retry = Dispatch {
+                    idempotent: false,
+                    ..dispatch.clone()
+                };
+                self.connection.request(&wire_name, params, &retry).into_result()
+            }
+            Outcome::Answered(Err(BridgeError::ProcessExited)) => {
+                bridge_debug!("PythonBridge: Python exited during {}", wire_name);
+                *self.status.lock().unwrap() = BridgeStatus::Dead;
+                self.respawn()?;
+                Err(BridgeError::ProcessExited)
+            }
+            outcome => outcome.into_result(),
+        }?;
+        self.diagnostics.record_reply(command, &dispatch, at_ms, started, &reply);
+        self.forward_warnings(command, reply.warnings);
+        Ok(reply.body)
+    }
 
-        // parse the JSON response to PythonResponse
-        let response: PythonResponse =
-            serde_json::from_str(&response_line).map_err(|e| {
-                println!("PythonBridge: Error parsing JSON: {}", e);
-                println!("PythonBridge: Raw response was: '{}'", response_line);
-                format!("Failed to parse Python response: {}. Raw output: '{}'", e, response_line)
-            })?;
+    /// spawn a new child if the last one died or was stopped while idle,
+    /// before sending it `purpose`
+    fn revive(&mut self, _purpose: &str) -> Result<(), BridgeError> {
+        match self.status() {
+            BridgeStatus::Dead => self.respawn()?,
+            BridgeStatus::Idle => {
+                bridge_debug!("PythonBridge: Waking from idle for {}", _purpose);
+                self.respawn()?;
+                self.prewarm();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 
-        if response.success {
-            Ok(response.data.unwrap_or(serde_json::Value::Null))
-        } else {
-            Err(response.error.unwrap_or_else(|| "Unknown error".to_string()))
+    /// lane, idempotency and routing key of a command about to be sent
+    ///
+    /// the routing key is the dataset the command touches: its
+    /// `dataset_id` param, or the dataset an `overlay_id` param was
+    /// plotted from. python has a single process and ignores it for now
+    fn dispatch(&self, command: PythonCommand, params: &serde_json::Value) -> Dispatch {
+        let param = |name: &str| params.get(name).and_then(|value| value.as_str());
+        let routing_key = param("dataset_id")
+            .map(str::to_string)
+            .or_else(|| {
+                let overlay_id = param("overlay_id")?;
+                Some(self.overlays.params(overlay_id)?.dataset_id.clone())
+            });
+        Dispatch {
+            lane: command.lane(),
+            idempotent: command.is_idempotent(),
+            direct: self.direct_decode.contains(&command),
+            routing_key,
+            tag: self.request_tag.clone(),
+            trace_id: self.diagnostics.next_trace_id(),
+            timeout: self.timeout_for(command),
+        }
+    }
+
+    /// response deadline of a command about to be sent: the call's
+    /// `with_timeout` override, else the command's default from
+    /// `PythonBridgeBuilder::command_timeout`, else the global
+    /// `response_timeout`. `None` waits indefinitely
+    fn timeout_for(&self, command: PythonCommand) -> Option<Duration> {
+        self.call_timeout
+            .or_else(|| self.command_timeouts.get(&command).copied())
+            .or_else(|| self.connection_options.deadline.map(|d| d.after))
+    }
+
+    /// run `f` with every python command it sends given `timeout` to
+    /// answer, instead of the command's usual deadline (see `timeout_for`)
+    ///
+    /// for calls whose cost depends on their arguments, e.g. a tiny region
+    /// export vs a full slide one. `None` keeps the usual deadlines. each
+    /// command `f` sends gets the full `timeout`, it isn't a budget for the
+    /// whole call. `kill_on_timeout` still comes from `response_timeout`
+    pub fn with_timeout<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let outer = self.call_timeout;
+        if timeout.is_some() {
+            self.call_timeout = timeout;
+        }
+        let result = f(self);
+        self.call_timeout = outer;
+        result
+    }
+
+    /// run `f` with every python command it sends tagged `tag`, so
+    /// `RequestCanceller::cancel_tagged` can call them off
+    pub fn with_request_tag<T>(&mut self, tag: &str, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = self.request_tag.replace(tag.to_string());
+        let result = f(self);
+        self.request_tag = outer;
+        result
+    }
+
+    /// replace a dead child with a freshly spawned one
+    ///
+    /// python's in-memory state (datasets, overlays, render contexts) died
+    /// with the old process, so their registries are cleared as well
+    fn respawn(&mut self) -> Result<(), BridgeError> {
+        bridge_debug!("PythonBridge: Respawning Python");
+        self.connection.close(BridgeError::ProcessExited);
+
+        let (process, stdin, stdout, handshake) = spawn_python(
+            &self.python_path,
+            &self.script_path,
+            &self.diagnostics,
+            self.startup_timeout,
+            &self.resource_limits,
+            &self.env,
+        )?;
+        self.connection = Connection::start(
+            process,
+            handshake.reexec_pid(),
+            stdin,
+            stdout,
+            Arc::clone(&self.status),
+            &self.shutdown,
+            self.connection_options.clone(),
+        );
+        self.config.protocol_version = handshake.protocol_version;
+        self.config.python_pid = handshake.pid;
+        self.config.launcher_pid = handshake.launcher_pid;
+        self.reconnects += 1;
+        self.overlays.clear();
+        self.render_contexts.clear();
+        self.overviews.clear();
+        // results and overlay tiles of the old child's datasets
+        self.result_cache.clear();
+        self.tiles.cache().clear();
+        if let Some(idle) = &self.idle {
+            idle.watch(&self.connection);
+        }
+        self.canceller.watch(&self.connection);
+        // before the status, a prewarm of the old child checks it
+        self.tiles.watch(&self.connection);
+        *self.status.lock().unwrap() = BridgeStatus::Ready;
+
+        Ok(())
+    }
+
+    /// current lifecycle state, `Starting` until prewarm has finished
+    pub fn status(&self) -> BridgeStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// import python's heavy libraries in the background
+    ///
+    /// scanpy/matplotlib are imported lazily on the python side, which makes
+    /// the first real command slow. this runs the imports right away on a
+    /// background thread and keeps the status at `Starting` until they're
+    /// done. it runs in the plot lane, commands sent meanwhile that need
+    /// those libraries wait on python's import lock
+    pub fn prewarm(&self) {
+        *self.status.lock().unwrap() = BridgeStatus::Starting;
+
+        let connection = Arc::clone(&self.connection);
+        let status = Arc::clone(&self.status);
+        let gates = Arc::clone(&self.gates);
+        let tiles = Arc::clone(&self.tiles);
+        let wire_name = PythonCommand::Prewarm.wire_name(&self.namespace);
+        let on_warning = self.on_python_warning.clone();
+
+        std::thread::spawn(move || {
+            let result = gates
+                .acquire(PythonCommand::Prewarm)
+                // heavy imports can take far longer than a normal command,
+                // so prewarm waits without a deadline (`timeout: None`). it
+                // isn't redispatched either: this thread only knows the old
+                // child, the next command respawns it without a prewarm
+                .and_then(|_permit| {
+                    let dispatch = Dispatch {
+                        lane: PythonCommand::Prewarm.lane(),
+                        idempotent: false,
+                        direct: false,
+                        routing_key: None,
+                        tag: None,
+                        trace_id: None,
+                        timeout: None,
+                    };
+                    connection
+                        .request(&wire_name, serde_json::json!({}), &dispatch)
+                        .into_result()
+                })
+                .and_then(|reply| {
+                    // no `with_warnings` call can be waiting on a prewarm
+                    if let Some(handler) = &on_warning {
+                        for warning in reply.warnings {
+                            handler(warning.for_command(PythonCommand::Prewarm));
+                        }
+                    }
+                    reply.body.into_value(PythonCommand::Prewarm)
+                });
+
+            // helpers without the report are assumed to render fine
+            let result = result.and_then(|info| {
+                let backend = info.get("render_backend").cloned().unwrap_or_default();
+                match serde_json::from_value::<RenderBackend>(backend) {
+                    Ok(backend) => backend.check().map(|_| info),
+                    Err(_) => Ok(info),
+                }
+            });
+
+            // a command redispatched after the crash may have respawned
+            // python already, the new child isn't dead. checked under the
+            // status lock, the respawn writes `Ready` after switching over
+            let mut status = status.lock().unwrap();
+            if matches!(result, Err(BridgeError::ProcessExited)) && !tiles.is_current(&connection) {
+                return;
+            }
+
+            *status = match result {
+                Ok(_info) => {
+                    bridge_debug!("PythonBridge: Prewarm finished: {:?}", _info);
+                    BridgeStatus::Ready
+                }
+                // the app shut down or the bridge was dropped mid-import,
+                // the child has been killed and nothing is left to report
+                Err(BridgeError::ShuttingDown) => {
+                    bridge_debug!("PythonBridge: Prewarm cancelled by shutdown");
+                    return;
+                }
+                Err(BridgeError::ProcessExited) => BridgeStatus::Dead,
+                Err(e) => BridgeStatus::Failed {
+                    error: e.to_string(),
+                },
+            };
+        });
+    }
+
+    /// commands the python helper supports along with their parameters
+    pub fn list_supported_commands(&mut self) -> Result<Vec<CommandSpec>, BridgeError> {
+        self.send_command_as(PythonCommand::ListSupportedCommands, serde_json::json!({}))
+    }
+
+    /// the commands the bridge can call that the helper doesn't list
+    ///
+    /// the rust and python halves ship separately, so a stale script would
+    /// otherwise only show up as an "Unknown command" the first time a
+    /// feature is used. see `PythonBridgeBuilder::strict_commands`
+    fn missing_commands(&mut self) -> Result<Vec<String>, BridgeError> {
+        let supported = self.list_supported_commands()?;
+
+        // the helper reports names without our namespace prefix
+        Ok(PythonCommand::ALL
+            .iter()
+            .map(|command| command.name())
+            .filter(|name| !supported.iter().any(|spec| spec.name == *name))
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// fail the build over missing commands when `strict`, otherwise only
+    /// note them in the config
+    fn check_supported_commands(&mut self, strict: bool) -> Result<(), BridgeError> {
+        if strict {
+            let missing = self.missing_commands()?;
+            if !missing.is_empty() {
+                return Err(BridgeError::MissingCommands { missing });
+            }
+            return Ok(());
+        }
+
+        // a helper too partial to list its commands is fine as well. the
+        // gaps are reported through `get_bridge_config`
+        match self.missing_commands() {
+            Ok(missing) => {
+                if !missing.is_empty() {
+                    bridge_debug!(
+                        "PythonBridge: Python helper lacks commands, they will fail: {}",
+                        missing.join(", ")
+                    );
+                }
+                self.config.missing_commands = missing;
+            }
+            Err(_e) => {
+                bridge_debug!("PythonBridge: Could not list the helper's commands: {}", _e);
+            }
+        }
+        Ok(())
+    }
+
+    /// send a command and deserialize its `data` into `T`
+    fn send_command_as<T: DeserializeOwned>(
+        &mut self,
+        command: PythonCommand,
+        params: serde_json::Value,
+    ) -> Result<T, BridgeError> {
+        let body = self.send_command_body(command, params)?;
+        let started = Instant::now();
+        let decoded = body.decode(command);
+        self.diagnostics.record_decode(command, started.elapsed());
+        decoded
+    }
+
+    /// fire-and-forget: write the command and return without a response
+    ///
+    /// only for commands where `is_notification_safe` holds, python runs
+    /// them in order with the rest of their lane but never answers, so errors are
+    /// only logged on its side. a dead child is respawned first but a
+    /// notification lost to a crash isn't resent
+    pub fn send_notification(
+        &mut self,
+        command: PythonCommand,
+        params: serde_json::Value,
+    ) -> Result<(), BridgeError> {
+        if !command.is_notification_safe() {
+            return Err(BridgeError::Protocol(format!(
+                "{} can't be sent as a notification",
+                command.name()
+            )));
+        }
+        if self.shutdown.is_cancelled() {
+            return Err(BridgeError::ShuttingDown);
+        }
+        if self.status() == BridgeStatus::Dead {
+            self.respawn()?;
         }
+
+        let dispatch = self.dispatch(command, &params);
+        let result = self
+            .connection
+            .notify(&command.wire_name(&self.namespace), params, &dispatch);
+        if result == Err(BridgeError::ProcessExited) {
+            *self.status.lock().unwrap() = BridgeStatus::Dead;
+        }
+        result
+    }
+
+    /// wait until python has finished everything sent before this call
+    ///
+    /// python drains every lane before running the barrier, so it can only
+    /// be answered once every earlier command, notifications and the
+    /// background prewarm included, is done.
+    /// fails with `ProcessExited` rather than retrying if python died
+    /// meanwhile, since that earlier work may never have run
+    pub fn barrier(&mut self) -> Result<(), BridgeError> {
+        self.send_command(PythonCommand::Barrier, serde_json::json!({}))?;
+        Ok(())
     }
 
+    /// ids left as `None` are taken from the context, see `set_context`.
+    /// with `options.context_id` they come from that render context
+    /// instead, see `open_render_context`
+    ///
+    /// how the overlay is rendered is up to `options`, see `PlotOptions`.
+    /// options left unset there come from the render defaults, see
+    /// `set_render_defaults`
     pub fn plot_tissue_overlay(
         &mut self,
-        dataset_id: &str,
-        img_id: &str,
-        seg_id: &str,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        seg_id: Option<&str>,
         fill_key: &str,
-        border_key: Option<&str>,
-    ) -> Result<serde_json::Value, String> {
-        println!("PythonBridge: plot_tissue_overlay called with dataset_id: {}, fill_key: {}", dataset_id, fill_key);
-        let params = serde_json::json!({
+        options: &PlotOptions,
+    ) -> Result<OverlayInfo, BridgeError> {
+        let seed = options
+            .seed
+            .or(options.deterministic.then_some(DETERMINISTIC_SEED));
+        let defaults = &self.render_defaults;
+        let border_key = options.border_key.clone();
+        let background = options.background.or(defaults.background);
+        let scalebar = options.scalebar.or(defaults.scalebar);
+        let lod = options.lod.or(defaults.lod);
+        let color_scale = options.color_scale;
+        let quality = options.quality.or(defaults.quality).unwrap_or_default();
+        let recolorable = options.recolorable;
+        let orientation = options.orientation.or(defaults.orientation).unwrap_or_default();
+        let origin = options.origin.or(defaults.origin).unwrap_or_default();
+        let transform = options.transform;
+        let pyramid_mode = options.pyramid_mode.or(defaults.pyramid_mode).unwrap_or_default();
+        let segment_limit = options.segment_limit.or(defaults.segment_limit);
+        let context_id = options.context_id.as_deref();
+        let extra = defaults.extra_for(options.extra.as_ref());
+        if let Some(ref transform) = transform {
+            transform.validate()?;
+        }
+        if let Some(ref scalebar) = scalebar {
+            scalebar.validate()?;
+        }
+        if let Some(ref lod) = lod {
+            lod.validate()?;
+        }
+        if let Some(ref color_scale) = color_scale {
+            color_scale.validate()?;
+        }
+        if let Some(ref segment_limit) = segment_limit {
+            segment_limit.validate()?;
+        }
+        let (dataset_id, img_id, seg_id) = match context_id {
+            Some(context_id) => self
+                .render_contexts
+                .get(context_id)?
+                .resolve(dataset_id, img_id, seg_id)?,
+            None => self.context.resolve_layers(dataset_id, img_id, seg_id)?,
+        };
+        if let Some(ref img_id) = img_id {
+            self.check_image(&dataset_id, img_id)?;
+        }
+        bridge_debug!("PythonBridge: plot_tissue_overlay called with dataset_id: {}, fill_key: {}", dataset_id, fill_key);
+        let mut params = serde_json::json!({
             "dataset_id": dataset_id,
             "img_id": img_id,
             "seg_id": seg_id,
             "fill_key": fill_key,
-            "border_key": border_key
+            "border_key": border_key,
+            "background": background,
+            "scalebar": scalebar,
+            "lod": lod,
+            "color_scale": color_scale,
+            "quality": quality,
+            "recolorable": recolorable,
+            "seed": seed,
+            "orientation": orientation,
+            "origin": origin,
+            "transform": transform,
+            // eager pyramids are built below, level by level
+            "pyramid_mode": PyramidMode::Lazy,
+            "segment_limit": segment_limit,
+            "context_id": context_id
         });
-        println!("PythonBridge: Sending command to Python...");
-        let result = self.send_command("plot_tissue_overlay", params)?;
-        println!("PythonBridge: Got response from Python: {:?}", result);
-        Ok(result)
+        if let Some(ref extra) = extra {
+            merge_extra_params(&mut params, extra)?;
+        }
+        bridge_debug!("PythonBridge: Sending command to Python...");
+        let mut info: OverlayInfo =
+            self.send_command_as(PythonCommand::PlotTissueOverlay, params)?;
+        bridge_debug!("PythonBridge: Got response from Python: {:?}", info);
+        info.pyramid_mode = pyramid_mode;
+        if let Some(ref _timings) = info.timings {
+            bridge_debug!(
+                "PythonBridge: {} rendered in load {:.0} ms, render {:.0} ms, tile {:.0} ms",
+                info.overlay_id,
+                _timings.load_ms,
+                _timings.render_ms,
+                _timings.tile_ms
+            );
+        }
+
+        // re-plotting an existing id replaces its tiles on the python side,
+        // so anything cached under the old generation is now stale
+        let plot_params = PlotParams {
+            dataset_id,
+            img_id,
+            seg_id,
+            fill_key: fill_key.to_string(),
+            border_key,
+            background,
+            scalebar,
+            lod,
+            color_scale,
+            quality,
+            recolorable,
+            seed,
+            orientation,
+            origin,
+            transform,
+            pyramid_mode,
+            segment_limit,
+            extra,
+        };
+        self.overlays.register(info.clone(), Some(plot_params));
+        self.overviews.remove(&info.overlay_id);
+
+        if pyramid_mode == PyramidMode::Eager {
+            self.build_pyramid(&info)?;
+        }
+        info.prerendered_tiles = self.warmup_tiles(&info);
+        Ok(info)
+    }
+
+    /// fetch the lowest `warmup_levels` zoom levels into the tile cache so
+    /// the first view paints without waiting on python. those levels are
+    /// pinned, see `pin_tiles`
+    ///
+    /// a failed tile is left for the viewer to request again, the overlay
+    /// itself plotted fine
+    fn warmup_tiles(&mut self, info: &OverlayInfo) -> u32 {
+        let levels = i32::try_from(self.warmup_levels)
+            .unwrap_or(i32::MAX)
+            .min(info.max_zoom + 1);
+        let mut prerendered = 0;
+
+        // the overview is what the viewer keeps coming back to
+        let overview: Vec<i32> = (0..levels).collect();
+        self.tiles.cache().pin(&info.overlay_id, &overview);
+
+        let format = self.tiles.format();
+        for zoom in 0..levels {
+            let (cols, rows) = info.tile_grid(zoom);
+            for y in 0..rows {
+                for x in 0..cols {
+                    match self.fetch_tile(&info.overlay_id, zoom, x, y, format) {
+                        Ok(_) => prerendered += 1,
+                        Err(_e) => {
+                            bridge_debug!(
+                                "PythonBridge: Warmup of {} tile {}/{}/{} failed: {}",
+                                info.overlay_id,
+                                zoom,
+                                x,
+                                y,
+                                _e
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        bridge_debug!("PythonBridge: Prerendered {} tiles for {}", prerendered, info.overlay_id);
+        prerendered
     }
 
+    /// `quality` trades sharpness for speed, see `RenderQuality`. e.g. ask
+    /// for `Fast` tiles while the user pans and `Balanced` once the view
+    /// settles, the two are cached separately
     pub fn get_tissue_overlay_tile(
         &mut self,
         overlay_id: &str,
         zoom: i32,
         x: i32,
         y: i32,
-    ) -> Result<serde_json::Value, String> {
+        quality: RenderQuality,
+    ) -> Result<TileResponse, BridgeError> {
+        self.serve_tiles(|tiles, call| {
+            tiles.get_tissue_overlay_tile(overlay_id, zoom, x, y, quality, call)
+        })
+    }
+
+    /// a tile at a zoom between two pyramid levels, for viewers with
+    /// continuous zoom (e.g. deck.gl)
+    ///
+    /// at zoom `z` the image is `width * 2^(z - max_zoom)` pixels wide and
+    /// cut into `tile_size` tiles, the same way as at a stored level. `z` is
+    /// rounded to the nearest of `ZOOM_STEPS_PER_LEVEL` steps per level so
+    /// smooth zooming reuses a few cached renders per level instead of one
+    /// per frame, place the tile with the rounded zoom to line it up.
+    /// whole levels are the stored tiles, shared with
+    /// `get_tissue_overlay_tile`.
+    ///
+    /// in between, python downsamples the next finer level's tiles, so the
+    /// tile is as sharp as that level but costs a render from up to four
+    /// stored tiles, where stretching the coarser level's tiles in the
+    /// viewer is free but blurry. only fast tiles are halved afterwards
+    pub fn get_fractional_tile(
+        &mut self,
+        overlay_id: &str,
+        zoom: f64,
+        x: i32,
+        y: i32,
+        quality: RenderQuality,
+    ) -> Result<TileResponse, BridgeError> {
+        self.serve_tiles(|tiles, call| {
+            tiles.get_fractional_tile(overlay_id, zoom, x, y, quality, call)
+        })
+    }
+
+    /// serve a stored tile from the cache or ask python for it
+    fn fetch_tile(
+        &mut self,
+        overlay_id: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        format: TileFormatMode,
+    ) -> Result<TileResponse, BridgeError> {
+        self.serve_tiles(|tiles, call| {
+            tiles.fetch_tile_at(overlay_id, zoom, x, y, format, RenderQuality::Balanced, call)
+        })
+    }
+
+    /// run `f` on the tile service with this call's timeout and tag, see
+    /// `with_timeout` and `with_request_tag`
+    ///
+    /// a child that is gone is respawned first. tile requests are safe to
+    /// repeat, so one that loses the child on the way runs once more on a
+    /// new one
+    fn serve_tiles<T>(
+        &mut self,
+        f: impl Fn(&TileService, &mut TileCall) -> Result<T, BridgeError>,
+    ) -> Result<T, BridgeError> {
+        if self.shutdown.is_cancelled() {
+            return Err(BridgeError::ShuttingDown);
+        }
+        self.revive("tiles")?;
+        let mut call = TileCall {
+            timeout: self.call_timeout,
+            tag: self.request_tag.clone(),
+            warnings: Vec::new(),
+            fail_when_paused: true,
+        };
+        let result = match f(&self.tiles, &mut call) {
+            Err(BridgeError::ProcessExited) => {
+                self.revive("tiles")?;
+                f(&self.tiles, &mut call)
+            }
+            result => result,
+        };
+        if let Some(collected) = &mut self.warnings {
+            collected.append(&mut call.warnings);
+        }
+        result
+    }
+
+    /// a tile of the image alone, without any segment coloring
+    ///
+    /// same pyramid geometry as the overlays, so it lines up with their
+    /// tile at `zoom`, `x`, `y` and the viewer can stack the two as
+    /// independent layers, e.g. to fade the overlay out. ids left as `None`
+    /// come from the context. `img_id` is checked against `list_images`.
+    /// cached apart from overlay tiles, in a quarter of the tile cache's
+    /// size, until a dataset is loaded or unloaded. pass the overlay's
+    /// `origin` as well for `y` to count the same way
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_base_image_tile(
+        &mut self,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        quality: RenderQuality,
+        orientation: Orientation,
+        origin: Origin,
+        transform: Option<AffineTransform>,
+    ) -> Result<TileResponse, BridgeError> {
+        let dataset_id = self.context.resolve_dataset(dataset_id)?;
+        let img_id = img_id
+            .map(str::to_string)
+            .or_else(|| self.context.img_id.clone())
+            .ok_or_else(|| {
+                BridgeError::Protocol("No img_id given and no context set".to_string())
+            })?;
+        if let Some(ref transform) = transform {
+            transform.validate()?;
+        }
+        let quality = quality.for_tiles();
+        let frame = format!("{}\0{}", orientation.name(), origin.name());
+        let frame = match transform {
+            Some(transform) => format!("{}\0{:?}", frame, transform.0),
+            None => frame,
+        };
+        let key = TileKey {
+            // no overlay, the layer is the image as plotted
+            overlay_id: format!("{}\0{}\0{}", dataset_id, img_id, frame),
+            generation: 0,
+            zoom,
+            x,
+            y,
+            format: self.tiles.format(),
+            quality,
+        };
+        if let Some(tile) = self.base_tiles.get(&key) {
+            return Ok(self.tiles.after_cache(tile));
+        }
+
+        self.check_image(&dataset_id, &img_id)?;
         let params = serde_json::json!({
-            "overlay_id": overlay_id,
+            "dataset_id": dataset_id,
+            "img_id": img_id,
             "zoom": zoom,
             "x": x,
-            "y": y
+            "y": y,
+            "quality": quality,
+            "format": key.format,
+            "orientation": orientation,
+            "origin": origin,
+            "transform": transform
         });
-        self.send_command("get_tissue_overlay_tile", params)
+        let mut tile: TileResponse = self.send_command_as(PythonCommand::GetBaseImageTile, params)?;
+        self.tiles.before_cache(&mut tile);
+        self.base_tiles.insert(key, tile.clone());
+        Ok(self.tiles.after_cache(tile))
+    }
+
+    /// free an overlay's tiles on the python side
+    ///
+    /// its generation is bumped so cached tiles become unreachable even if
+    /// the same id is plotted again later
+    pub fn delete_overlay(&mut self, overlay_id: &str) -> Result<(), BridgeError> {
+        self.overlays.remove(overlay_id);
+        self.tiles.cache().unpin(overlay_id, None);
+        self.overviews.remove(overlay_id);
+        self.send_command(
+            PythonCommand::DeleteOverlay,
+            serde_json::json!({ "overlay_id": overlay_id }),
+        )?;
+        Ok(())
     }
 }
 
-// ===== Tauri Commands =====
+/// one command the python helper accepts, as reported by the helper itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandSpec {
+    pub name: String,
+    pub params: Vec<ParamSpec>,
+}
 
-// global state for Python bridge
-pub struct AppState {
-    pub python: Mutex<Option<PythonBridge>>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamSpec {
+    pub name: String,
+    pub required: bool,
+    /// python type annotation, if the handler has one
+    #[serde(rename = "type")]
+    pub type_name: Option<String>,
 }
 
-/// tauri command to generate tissue overlay and tiles
-#[tauri::command]
-pub async fn plot_tissue_overlay_cmd(
-    dataset_id: String,
-    img_id: String,
-    seg_id: String,
-    fill_key: String,
-    border_key: Option<String>,
-    state: State<'_, AppState>,
-) -> Result<serde_json::Value, String> {
-    let mut python = state.python.lock().unwrap();
+/// every command the bridge sends to python
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PythonCommand {
+    ListSupportedCommands,
+    Prewarm,
+    PlotTissueOverlay,
+    GetTissueOverlayTile,
+    DeleteOverlay,
+    GetOverlayStats,
+    GetOverlayLegend,
+    GetEnvironmentInfo,
+    GetComparisonTile,
+    Barrier,
+    GetSegmentAt,
+    GetInputDimensions,
+    LoadDataset,
+    NearestSegments,
+    MissingInputs,
+    ExportRegionPng,
+    ExportSegments,
+    DiffTiles,
+    ListColormaps,
+    ValidatePlotArgs,
+    GetOverview,
+    UnloadDataset,
+    RecolorOverlay,
+    GetValueHistogram,
+    ListImages,
+    QuerySegmentsInBbox,
+    GetBaseImageTile,
+    ExportLegend,
+    GetCategoryCounts,
+    ListDatasets,
+    HoverProbe,
+    Echo,
+    BeginOmeTiff,
+    WriteOmeTiffLevel,
+    FinishOmeTiff,
+    AbortOmeTiff,
+    GetNeighborhoodSummary,
+    BuildPyramidLevel,
+    ChecksumFile,
+    ComposeComparison,
+    PreprocessStep,
+    EndPreprocessing,
+    CancelTagged,
+    SetOverlayOrigin,
+    GetTilesBatch,
+    OpenRenderContext,
+    CloseRenderContext,
+}
+
+impl PythonCommand {
+    /// all variants, checked against the helper at startup
+    pub const ALL: &'static [PythonCommand] = &[
+        PythonCommand::ListSupportedCommands,
+        PythonCommand::Prewarm,
+        PythonCommand::PlotTissueOverlay,
+        PythonCommand::GetTissueOverlayTile,
+        PythonCommand::DeleteOverlay,
+        PythonCommand::GetOverlayStats,
+        PythonCommand::GetOverlayLegend,
+        PythonCommand::GetEnvironmentInfo,
+        PythonCommand::GetComparisonTile,
+        PythonCommand::Barrier,
+        PythonCommand::GetSegmentAt,
+        PythonCommand::GetInputDimensions,
+        PythonCommand::LoadDataset,
+        PythonCommand::NearestSegments,
+        PythonCommand::MissingInputs,
+        PythonCommand::ExportRegionPng,
+        PythonCommand::ExportSegments,
+        PythonCommand::DiffTiles,
+        PythonCommand::ListColormaps,
+        PythonCommand::ValidatePlotArgs,
+        PythonCommand::GetOverview,
+        PythonCommand::UnloadDataset,
+        PythonCommand::RecolorOverlay,
+        PythonCommand::GetValueHistogram,
+        PythonCommand::ListImages,
+        PythonCommand::QuerySegmentsInBbox,
+        PythonCommand::GetBaseImageTile,
+        PythonCommand::ExportLegend,
+        PythonCommand::GetCategoryCounts,
+        PythonCommand::ListDatasets,
+        PythonCommand::HoverProbe,
+        PythonCommand::Echo,
+        PythonCommand::BeginOmeTiff,
+        PythonCommand::WriteOmeTiffLevel,
+        PythonCommand::FinishOmeTiff,
+        PythonCommand::AbortOmeTiff,
+        PythonCommand::GetNeighborhoodSummary,
+        PythonCommand::BuildPyramidLevel,
+        PythonCommand::ChecksumFile,
+        PythonCommand::ComposeComparison,
+        PythonCommand::PreprocessStep,
+        PythonCommand::EndPreprocessing,
+        PythonCommand::CancelTagged,
+        PythonCommand::SetOverlayOrigin,
+        PythonCommand::GetTilesBatch,
+        PythonCommand::OpenRenderContext,
+        PythonCommand::CloseRenderContext,
+    ];
 
-    if python.is_none() {
-        *python = Some(PythonBridge::new().map_err(|e| e.to_string())?);
+    /// name of the handler in the helper's COMMANDS table
+    pub fn name(self) -> &'static str {
+        match self {
+            PythonCommand::ListSupportedCommands => "list_supported_commands",
+            PythonCommand::Prewarm => "prewarm",
+            PythonCommand::PlotTissueOverlay => "plot_tissue_overlay",
+            PythonCommand::GetTissueOverlayTile => "get_tissue_overlay_tile",
+            PythonCommand::DeleteOverlay => "delete_overlay",
+            PythonCommand::GetOverlayStats => "get_overlay_stats",
+            PythonCommand::GetOverlayLegend => "get_overlay_legend",
+            PythonCommand::GetEnvironmentInfo => "get_environment_info",
+            PythonCommand::GetComparisonTile => "get_comparison_tile",
+            PythonCommand::Barrier => "barrier",
+            PythonCommand::GetSegmentAt => "get_segment_at",
+            PythonCommand::GetInputDimensions => "get_input_dimensions",
+            PythonCommand::LoadDataset => "load_dataset",
+            PythonCommand::NearestSegments => "nearest_segments",
+            PythonCommand::MissingInputs => "missing_inputs",
+            PythonCommand::ExportRegionPng => "export_region_png",
+            PythonCommand::ExportSegments => "export_segments",
+            PythonCommand::DiffTiles => "diff_tiles",
+            PythonCommand::ListColormaps => "list_colormaps",
+            PythonCommand::ValidatePlotArgs => "validate_plot_args",
+            PythonCommand::GetOverview => "get_overview",
+            PythonCommand::UnloadDataset => "unload_dataset",
+            PythonCommand::RecolorOverlay => "recolor_overlay",
+            PythonCommand::GetValueHistogram => "get_value_histogram",
+            PythonCommand::ListImages => "list_images",
+            PythonCommand::QuerySegmentsInBbox => "query_segments_in_bbox",
+            PythonCommand::GetBaseImageTile => "get_base_image_tile",
+            PythonCommand::ExportLegend => "export_legend",
+            PythonCommand::GetCategoryCounts => "get_category_counts",
+            PythonCommand::ListDatasets => "list_datasets",
+            PythonCommand::HoverProbe => "hover_probe",
+            PythonCommand::Echo => "echo",
+            PythonCommand::BeginOmeTiff => "begin_ome_tiff",
+            PythonCommand::WriteOmeTiffLevel => "write_ome_tiff_level",
+            PythonCommand::FinishOmeTiff => "finish_ome_tiff",
+            PythonCommand::AbortOmeTiff => "abort_ome_tiff",
+            PythonCommand::GetNeighborhoodSummary => "get_neighborhood_summary",
+            PythonCommand::BuildPyramidLevel => "build_pyramid_level",
+            PythonCommand::ChecksumFile => "checksum_file",
+            PythonCommand::ComposeComparison => "compose_comparison",
+            PythonCommand::PreprocessStep => "preprocess_step",
+            PythonCommand::EndPreprocessing => "end_preprocessing",
+            PythonCommand::CancelTagged => "cancel_tagged",
+            PythonCommand::SetOverlayOrigin => "set_overlay_origin",
+            PythonCommand::GetTilesBatch => "get_tiles_batch",
+            PythonCommand::OpenRenderContext => "open_render_context",
+            PythonCommand::CloseRenderContext => "close_render_context",
+        }
     }
 
-    if let Some(ref mut bridge) = *python {
-        bridge.plot_tissue_overlay(
-            &dataset_id,
-            &img_id,
-            &seg_id,
-            &fill_key,
-            border_key.as_deref(),
-        )
-    } else {
-        Err("Failed to initialize Python bridge".to_string())
+    /// command string as sent on the wire, `namespace` is "" or ends in "."
+    fn wire_name(self, namespace: &str) -> String {
+        format!("{}{}", namespace, self.name())
+    }
+
+    /// safe to send again after python was respawned mid-request, it
+    /// doesn't change state or changing it twice is harmless
+    fn is_idempotent(self) -> bool {
+        match self {
+            PythonCommand::ListSupportedCommands
+            | PythonCommand::Prewarm
+            | PythonCommand::GetTissueOverlayTile
+            | PythonCommand::DeleteOverlay
+            | PythonCommand::GetOverlayStats
+            | PythonCommand::GetOverlayLegend
+            | PythonCommand::GetEnvironmentInfo
+            | PythonCommand::GetComparisonTile
+            | PythonCommand::GetSegmentAt
+            | PythonCommand::GetInputDimensions
+            | PythonCommand::LoadDataset
+            | PythonCommand::NearestSegments
+            | PythonCommand::MissingInputs
+            | PythonCommand::ExportRegionPng
+            | PythonCommand::ExportSegments
+            | PythonCommand::DiffTiles
+            | PythonCommand::ListColormaps
+            | PythonCommand::ValidatePlotArgs
+            | PythonCommand::GetOverview
+            | PythonCommand::UnloadDataset
+            | PythonCommand::GetValueHistogram
+            | PythonCommand::ListImages
+            | PythonCommand::QuerySegmentsInBbox
+            | PythonCommand::GetBaseImageTile
+            | PythonCommand::ExportLegend
+            | PythonCommand::GetCategoryCounts
+            | PythonCommand::ListDatasets
+            | PythonCommand::HoverProbe
+            | PythonCommand::Echo
+            | PythonCommand::AbortOmeTiff
+            | PythonCommand::GetNeighborhoodSummary
+            | PythonCommand::BuildPyramidLevel
+            | PythonCommand::ChecksumFile
+            | PythonCommand::ComposeComparison
+            | PythonCommand::EndPreprocessing
+            | PythonCommand::CancelTagged
+            | PythonCommand::SetOverlayOrigin
+            | PythonCommand::GetTilesBatch
+            | PythonCommand::OpenRenderContext
+            | PythonCommand::CloseRenderContext => true,
+            PythonCommand::PlotTissueOverlay
+            | PythonCommand::RecolorOverlay
+            | PythonCommand::BeginOmeTiff
+            | PythonCommand::WriteOmeTiffLevel
+            | PythonCommand::FinishOmeTiff
+            | PythonCommand::PreprocessStep => false,
+            // harmless to resend, but answering on a fresh child would claim
+            // work finished that died with the old one
+            PythonCommand::Barrier => false,
+        }
+    }
+
+    /// fine to send without waiting for the result: the caller doesn't
+    /// need any data back and a failure leaves nothing to clean up
+    ///
+    /// deleting an overlay that is already gone and warming imports that
+    /// are already loaded are both no-ops, everything else either returns
+    /// data or has an outcome the caller has to see
+    pub fn is_notification_safe(self) -> bool {
+        matches!(self, PythonCommand::DeleteOverlay | PythonCommand::Prewarm)
     }
 }
 
-/// tauri command to get a specific tile
-/// this is called frequently as the user pans/zooms
-#[tauri::command]
-pub async fn get_tissue_overlay_tile_cmd(
-    overlay_id: String,
-    zoom: i32,
-    x: i32,
-    y: i32,
-    state: State<'_, AppState>,
-) -> Result<serde_json::Value, String> {
-    let mut python = state.python.lock().unwrap();
+impl Drop for PythonBridge {
+    fn drop(&mut self) {
+        // kills the child, a prewarm stuck mid-import resolves as cancelled
+        self.connection.close(BridgeError::ShuttingDown);
+        // only once the child is gone, respawns run the same file
+        self.embedded_script_file.take();
+    }
+}
 
-    if python.is_none() {
-        *python = Some(PythonBridge::new().map_err(|e| e.to_string())?);
+// ===== Connection =====
+
+/// cooperative cancellation flag shared between threads
+///
+/// cancelling runs every registered hook once, which is how blocked waiters
+/// get woken instead of polling the flag
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenInner>,
+}
+
+#[derive(Default)]
+struct TokenInner {
+    cancelled: AtomicBool,
+    hooks: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    if let Some(ref mut bridge) = *python {
-        bridge.get_tissue_overlay_tile(&overlay_id, zoom, x, y)
-    } else {
-        Err("Failed to initialize Python bridge".to_string())
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let hooks = std::mem::take(&mut *self.inner.hooks.lock().unwrap());
+        for hook in hooks {
+            hook();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// run `hook` on cancellation, or right away if already cancelled
+    fn on_cancel(&self, hook: impl FnOnce() + Send + 'static) {
+        let mut hooks = self.inner.hooks.lock().unwrap();
+        if self.is_cancelled() {
+            drop(hooks);
+            hook();
+        } else {
+            hooks.push(Box::new(hook));
+        }
+    }
+}
+
+type Waiter = mpsc::Sender<Delivery>;
+
+/// what wakes a waiter
+enum Delivery {
+    /// the response line, parsed by the waiter so a huge one doesn't hold
+    /// up the reader thread and the responses behind it
+    Response(String),
+    Failed(BridgeError),
+    /// the child exited before answering a request that is safe to repeat
+    Redispatch,
+}
+
+/// what python answered a request with
+struct Reply {
+    body: Body,
+    warnings: Vec<PythonWarning>,
+    timing: WireTiming,
+    spans: Vec<PythonSpan>,
+}
+
+/// commands `PythonBridgeBuilder::direct_decode` is on for by default,
+/// those whose responses run to thousands of records
+pub const DIRECT_DECODE_COMMANDS: &[PythonCommand] = &[PythonCommand::QuerySegmentsInBbox];
+
+/// environment variable naming the bridge's namespace to the python child,
+/// see `PythonBridgeBuilder::namespace`
+const NAMESPACE_ENV: &str = "SPATIALVIEWER_NAMESPACE";
+
+/// the `data` of a successful response
+enum Body {
+    Value(serde_json::Value),
+    /// the whole response line with `data` not parsed yet, for commands
+    /// decoded straight into their type, see
+    /// `PythonBridgeBuilder::direct_decode`
+    Line(String),
+}
+
+impl Body {
+    fn into_value(self, command: PythonCommand) -> Result<serde_json::Value, BridgeError> {
+        match self {
+            Body::Value(data) => Ok(data),
+            Body::Line(line) => decode_line(command, &line),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(self, command: PythonCommand) -> Result<T, BridgeError> {
+        match self {
+            Body::Value(data) => decode_response(command, &data),
+            Body::Line(line) => decode_line(command, &line),
+        }
     }
 }
+
+impl<D> PythonResponse<D> {
+    /// python's error, or the `data` handed to `body`
+    fn into_reply(self, body: impl FnOnce(Option<D>) -> Body) -> Result<Reply, BridgeError> {
+        if !self.success {
+            return Err(BridgeError::Python(
+                self.error.unwrap_or_else(|| "Unknown error".to_string()),
+            ));
+        }
+        let compute = self
+            .elapsed_ms
+            .and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok())
+            .unwrap_or_default();
+        Ok(Reply {
+            body: body(self.data),
+            warnings: self.warnings,
+            timing: WireTiming {
+                compute,
+                ..WireTiming::default()
+            },
+            spans: self.spans,
+        })
+    }
+}
+
+/// parse the response line a request was answered with
+///
+/// for a direct decode only the envelope is parsed here, `data` is skipped
+/// over and read into the caller's type by `decode_line`
+fn parse_reply(line: String, direct: bool) -> Result<Reply, BridgeError> {
+    let started = Instant::now();
+    let bytes = line.len();
+    let reply = if direct {
+        let response = parse_response::<serde::de::IgnoredAny>(&line)?;
+        response.into_reply(|_| Body::Line(line))
+    } else {
+        let response = parse_response::<serde_json::Value>(&line)?;
+        response.into_reply(|data| Body::Value(data.unwrap_or(serde_json::Value::Null)))
+    };
+    reply.map(|mut reply| {
+        reply.timing.parse = started.elapsed();
+        reply.timing.bytes = bytes;
+        reply
+    })
+}
+
+/// how a request ended, see `Connection::request`
+enum Outcome {
+    Answered(Result<Reply, BridgeError>),
+    /// python never answered, the caller should send it again to a
+    /// respawned child
+    Redispatch,
+}
+
+impl Outcome {
+    /// for callers that can't redispatch
+    fn into_result(self) -> Result<Reply, BridgeError> {
+        match self {
+            Outcome::Answered(result) => result,
+            Outcome::Redispatch => Err(BridgeError::ProcessExited),
+        }
+    }
+}
+
+/// how a request is sent, besides its command and params
+#[derive(Debug, Clone)]
+struct Dispatch {
+    lane: Lane,
+    /// see `Outcome::Redispatch`
+    idempotent: bool,
+    /// leave `data` unparsed, see `Body::Line`
+    direct: bool,
+    routing_key: Option<String>,
+    /// see `PythonBridge::with_request_tag`
+    tag: Option<String>,
+    /// see `PythonBridge::set_request_tracing`
+    trace_id: Option<u64>,
+    /// how long to wait for the response, `None` waits indefinitely
+    timeout: Option<Duration>,
+}
+
+/// a request written to python, waiting for its response
+struct Outstanding {
+    waiter: Waiter,
+    /// redispatched rather than failed if the child exits, see `close`
+    idempotent: bool,
+    /// dataset it touches, see `cancel_routed`
+    routing_key: Option<String>,
+    /// see `cancel_tagged`
+    tag: Option<String>,
+}
+
+/// requests written to python that haven't been answered yet
+#[derive(Default)]
+struct Pending {
+    waiters: HashMap<u64, Outstanding>,
+    /// requests given up on (timed out, or failed by a garbled line or a
+    /// desync) whose response may still arrive and is dropped
+    abandoned: HashSet<u64>,
+    /// set once the connection is unusable, new requests fail with it
+    closed: Option<BridgeError>,
+    /// tags cancelled lately, newest last. new requests with one fail
+    /// right away, see `cancel_tagged`
+    cancelled_tags: VecDeque<String>,
+}
+
+/// one python child: its stdin, the requests waiting on its stdout, and
+/// the thread reading stdout
+///
+/// every request gets an id that python echoes back. the reader thread
+/// routes each response line to the waiter with that id, so callers never
+/// touch stdout themselves and can be woken when the child dies or the app
+/// shuts down
+struct Connection {
+    process: Mutex<Child>,
+    /// python's own pid when `process` is a launcher that handed over to
+    /// it (a re-exec that forked, a venv redirector), killed along with it
+    reexec_pid: Option<u32>,
+    /// python closed stdout, after which `reexec_pid` may name someone else
+    stdout_closed: AtomicBool,
+    stdin: Mutex<ChildStdin>,
+    pending: Mutex<Pending>,
+    next_id: AtomicU64,
+    deadline: Option<ResponseDeadline>,
+    respawn_on_desync: bool,
+    watchdog: Mutex<Watchdog>,
+    /// signalled when a deadline is armed or the connection closes
+    watchdog_wake: Condvar,
+    /// keep `last_frames`, see `PythonBridgeBuilder::capture_wire_frames`
+    capture_frames: bool,
+    last_frames: Mutex<Option<WireFrames>>,
+}
+
+/// how a connection handles deadlines, log records, desyncs and frame
+/// captures
+#[derive(Clone, Default)]
+struct ConnectionOptions {
+    deadline: Option<ResponseDeadline>,
+    on_log: Option<PythonLogHandler>,
+    respawn_on_desync: bool,
+    capture_frames: bool,
+}
+
+/// how long a command may wait for its response
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseDeadline {
+    pub after: Duration,
+    /// also kill the child on timeout. a python stuck in a C extension
+    /// never answers again, killing it makes the next command respawn
+    pub kill_on_timeout: bool,
+}
+
+/// armed deadlines, earliest first
+#[derive(Default)]
+struct Watchdog {
+    deadlines: BTreeMap<(Instant, u64), String>,
+    stopped: bool,
+}
+
+impl Connection {
+    /// take over a freshly spawned (and handshaken) child
+    fn start(
+        process: Child,
+        reexec_pid: Option<u32>,
+        stdin: ChildStdin,
+        stdout: BufReader<ChildStdout>,
+        status: Arc<Mutex<BridgeStatus>>,
+        shutdown: &CancellationToken,
+        options: ConnectionOptions,
+    ) -> Arc<Self> {
+        let connection = Arc::new(Connection {
+            process: Mutex::new(process),
+            reexec_pid,
+            stdout_closed: AtomicBool::new(false),
+            stdin: Mutex::new(stdin),
+            pending: Mutex::new(Pending::default()),
+            next_id: AtomicU64::new(1),
+            deadline: options.deadline,
+            respawn_on_desync: options.respawn_on_desync,
+            watchdog: Mutex::new(Watchdog::default()),
+            watchdog_wake: Condvar::new(),
+            capture_frames: options.capture_frames,
+            last_frames: Mutex::new(None),
+        });
+
+        let weak = Arc::downgrade(&connection);
+        shutdown.on_cancel(move || {
+            if let Some(connection) = weak.upgrade() {
+                connection.close(BridgeError::ShuttingDown);
+            }
+        });
+
+        let reader = Arc::clone(&connection);
+        let logs = options.on_log.map(LogForwarder::new);
+        let reader_status = Arc::clone(&status);
+        std::thread::spawn(move || reader.read_loop(stdout, reader_status, logs));
+
+        let watchdog = Arc::clone(&connection);
+        std::thread::spawn(move || watchdog.watchdog_loop(status));
+
+        connection
+    }
+
+    /// write one request and block until its response arrives or the
+    /// dispatch's timeout passes
+    ///
+    /// an idempotent request the child exits on (or that never made it
+    /// into the pipe) ends in `Outcome::Redispatch`, anything else that
+    /// isn't answered fails with `ProcessExited`
+    fn request(&self, command: &str, params: serde_json::Value, dispatch: &Dispatch) -> Outcome {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let idempotent = dispatch.idempotent;
+        let request = PythonRequest {
+            id: Some(id),
+            command: command.to_string(),
+            params,
+            notification: false,
+            lane: dispatch.lane,
+            routing_key: dispatch.routing_key.clone(),
+            tag: dispatch.tag.clone(),
+            trace_id: dispatch.trace_id,
+        };
+
+        let serializing = Instant::now();
+        let request_json = match serde_json::to_string(&request) {
+            Ok(json) => json,
+            Err(e) => return Outcome::Answered(Err(BridgeError::Protocol(e.to_string()))),
+        };
+        let serialize = serializing.elapsed();
+        bridge_debug!("PythonBridge: Sending JSON: {}", request_json);
+
+        // register before writing so a fast response can't beat us to it
+        let (tx, rx) = mpsc::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if let Some(ref error) = pending.closed {
+                return match error {
+                    BridgeError::ProcessExited if idempotent => Outcome::Redispatch,
+                    error => Outcome::Answered(Err(error.clone())),
+                };
+            }
+            let cancelled = dispatch.tag.as_ref().filter(|t| pending.cancelled_tags.contains(t));
+            if let Some(tag) = cancelled {
+                return Outcome::Answered(Err(BridgeError::Cancelled(tag.clone())));
+            }
+            pending.waiters.insert(
+                id,
+                Outstanding {
+                    waiter: tx,
+                    idempotent,
+                    routing_key: dispatch.routing_key.clone(),
+                    tag: dispatch.tag.clone(),
+                },
+            );
+        }
+
+        // a failed write closes the connection, which resolves our waiter
+        // along with every other outstanding one
+        let sent = Instant::now();
+        let written = self.write_line(&request_json).is_ok();
+        bridge_debug!("PythonBridge: Sent command, waiting for response...");
+
+        let armed = dispatch
+            .timeout
+            .filter(|_| written)
+            .map(|after| self.arm_deadline(id, command, after));
+
+        // the sender is only dropped without a message if the reader thread
+        // died, which means the pipe is gone
+        let delivery = rx.recv().unwrap_or(Delivery::Failed(BridgeError::ProcessExited));
+        let round_trip = sent.elapsed();
+
+        if let Some(deadline) = armed {
+            self.watchdog.lock().unwrap().deadlines.remove(&(deadline, id));
+        }
+        if self.capture_frames {
+            let response = match &delivery {
+                Delivery::Response(line) => line.as_str(),
+                _ => "",
+            };
+            let frames = WireFrames::capture(command, &request_json, response);
+            *self.last_frames.lock().unwrap() = Some(frames);
+        }
+
+        Outcome::Answered(match delivery {
+            Delivery::Redispatch => return Outcome::Redispatch,
+            Delivery::Failed(error) => Err(error),
+            Delivery::Response(line) => parse_reply(line, dispatch.direct).map(|mut reply| {
+                reply.timing.serialize = serialize;
+                reply.timing.ipc = round_trip.saturating_sub(reply.timing.compute);
+                reply
+            }),
+        })
+    }
+
+    /// write a request python won't answer
+    fn notify(
+        &self,
+        command: &str,
+        params: serde_json::Value,
+        dispatch: &Dispatch,
+    ) -> Result<(), BridgeError> {
+        let request = PythonRequest {
+            id: None,
+            command: command.to_string(),
+            params,
+            notification: true,
+            lane: dispatch.lane,
+            routing_key: dispatch.routing_key.clone(),
+            tag: dispatch.tag.clone(),
+            trace_id: dispatch.trace_id,
+        };
+        let request_json =
+            serde_json::to_string(&request).map_err(|e| BridgeError::Protocol(e.to_string()))?;
+        bridge_debug!("PythonBridge: Sending notification: {}", request_json);
+
+        if let Some(ref error) = self.pending.lock().unwrap().closed {
+            return Err(error.clone());
+        }
+        self.write_line(&request_json)
+    }
+
+    /// send command - write JSON to python via stdin
+    ///
+    /// the line and its newline go out in one `write_all` so a large request
+    /// is never interleaved with another writer's. python can't stall us by
+    /// writing back meanwhile, the reader thread keeps draining its stdout.
+    /// a failed write may have left half a line in the pipe that the next
+    /// request would be glued onto, so the child is stopped and the caller
+    /// sees `ProcessExited` to recover through the usual respawn
+    fn write_line(&self, line: &str) -> Result<(), BridgeError> {
+        let mut frame = Vec::with_capacity(line.len() + 1);
+        frame.extend_from_slice(line.as_bytes());
+        frame.push(b'\n');
+
+        let written = {
+            let mut stdin = self.stdin.lock().unwrap();
+            stdin.write_all(&frame).and_then(|_| stdin.flush())
+        };
+        written.map_err(|e| {
+            bridge_debug!("PythonBridge: Error writing {} bytes to stdin: {}", frame.len(), e);
+            self.close(BridgeError::ProcessExited);
+            BridgeError::ProcessExited
+        })
+    }
+
+    fn arm_deadline(&self, id: u64, command: &str, after: Duration) -> Instant {
+        let deadline = Instant::now() + after;
+        self.watchdog
+            .lock()
+            .unwrap()
+            .deadlines
+            .insert((deadline, id), command.to_string());
+        self.watchdog_wake.notify_one();
+        deadline
+    }
+
+    /// runs on its own thread, fails requests whose deadline passed
+    ///
+    /// the waiter is moved to `abandoned` so a late response is dropped by
+    /// the reader instead of being taken for a desync
+    fn watchdog_loop(&self, status: Arc<Mutex<BridgeStatus>>) {
+        let mut watchdog = self.watchdog.lock().unwrap();
+        loop {
+            if watchdog.stopped {
+                return;
+            }
+
+            let now = Instant::now();
+            let Some((&(deadline, _), _)) = watchdog.deadlines.first_key_value() else {
+                watchdog = self.watchdog_wake.wait(watchdog).unwrap();
+                continue;
+            };
+            if deadline > now {
+                watchdog = self.watchdog_wake.wait_timeout(watchdog, deadline - now).unwrap().0;
+                continue;
+            }
+
+            let ((_, id), command) = watchdog.deadlines.pop_first().unwrap();
+            drop(watchdog);
+
+            let waiter = {
+                let mut pending = self.pending.lock().unwrap();
+                let waiter = pending.waiters.remove(&id);
+                if waiter.is_some() {
+                    pending.abandoned.insert(id);
+                }
+                waiter
+            };
+            if let Some(outstanding) = waiter {
+                bridge_debug!("PythonBridge: {} (request {}) timed out", command, id);
+                if self.deadline.is_some_and(|d| d.kill_on_timeout) {
+                    bridge_debug!("PythonBridge: Killing unresponsive Python");
+                    // under the status lock and before the caller hears of
+                    // the timeout, nothing sees the killed child as `Ready`
+                    let mut status = status.lock().unwrap();
+                    self.close(BridgeError::ProcessExited);
+                    *status = BridgeStatus::Dead;
+                }
+                let _ = outstanding
+                    .waiter
+                    .send(Delivery::Failed(BridgeError::Timeout(command)));
+            }
+
+            watchdog = self.watchdog.lock().unwrap();
+        }
+    }
+
+    /// kill the child and fail every outstanding and future request with
+    /// `error`. only the first close wins
+    ///
+    /// this is the drain step of a crash: with `ProcessExited` the
+    /// outstanding requests are split by idempotency, idempotent ones are
+    /// handed back for redispatch to the respawned child and the rest fail.
+    /// every waiter is resolved here, none is left for the new child
+    fn close(&self, error: BridgeError) {
+        let waiters = {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed.is_some() {
+                return;
+            }
+            pending.closed = Some(error.clone());
+            std::mem::take(&mut pending.waiters)
+        };
+
+        self.watchdog.lock().unwrap().stopped = true;
+        self.watchdog_wake.notify_all();
+
+        {
+            let mut process = self.process.lock().unwrap();
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+        // killing the launcher leaves the interpreter it handed over to
+        // running. once stdout closed that one has exited too
+        if let Some(pid) = self.reexec_pid {
+            if !self.stdout_closed.load(Ordering::Acquire) {
+                kill_pid(pid);
+            }
+        }
+
+        let _redispatched = waiters
+            .values()
+            .filter(|o| o.idempotent && error == BridgeError::ProcessExited)
+            .count();
+        bridge_debug!(
+            "PythonBridge: Closing with {} requests outstanding, {} redispatched",
+            waiters.len(),
+            _redispatched
+        );
+        for (_, outstanding) in waiters {
+            let delivery = match error {
+                BridgeError::ProcessExited if outstanding.idempotent => Delivery::Redispatch,
+                _ => Delivery::Failed(error.clone()),
+            };
+            let _ = outstanding.waiter.send(delivery);
+        }
+    }
+
+    /// runs on its own thread for the lifetime of the child
+    fn read_loop(
+        &self,
+        mut stdout: BufReader<ChildStdout>,
+        status: Arc<Mutex<BridgeStatus>>,
+        mut logs: Option<LogForwarder>,
+    ) {
+        loop {
+            match read_message(&mut stdout) {
+                Ok(Incoming::Log(log)) => {
+                    if let Some(ref mut logs) = logs {
+                        logs.forward(log);
+                    }
+                }
+                Ok(Incoming::Response { id, line }) => {
+                    if let Err(desync) = self.route(id, line) {
+                        // the waiters get it as their error, which is how it
+                        // reaches the callers and the diagnostics
+                        bridge_debug!("PythonBridge: {}", desync);
+                        self.abandon_all(&desync);
+                        if self.respawn_on_desync {
+                            *status.lock().unwrap() = BridgeStatus::Dead;
+                            self.close(BridgeError::ProcessExited);
+                            return;
+                        }
+                    }
+                }
+                // a garbled line can't be matched to its request, fail all
+                // waiters rather than leave one hanging forever
+                Err(BridgeError::Protocol(e)) => {
+                    self.abandon_all(&BridgeError::Protocol(e));
+                }
+                Err(_) => {
+                    // EOF or a broken pipe: unless we closed it ourselves the
+                    // child died, the bridge respawns on its next command.
+                    // this is python's stdout, a launcher that exits after
+                    // handing over to it doesn't end up here
+                    self.stdout_closed.store(true, Ordering::Release);
+                    let closed_by_us = self.pending.lock().unwrap().closed.is_some();
+                    if !closed_by_us {
+                        *status.lock().unwrap() = BridgeStatus::Dead;
+                    }
+                    self.close(BridgeError::ProcessExited);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// hand a response line to the request with its id
+    ///
+    /// a late answer to an abandoned request is dropped. anything else
+    /// without a waiter (no id, an id that was never sent or was already
+    /// answered) means a line went missing or was repeated on the way, and
+    /// the requests in flight can no longer be trusted to get their own
+    /// answers
+    fn route(&self, id: Option<u64>, line: String) -> Result<(), BridgeError> {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(id) = id else {
+            return Err(BridgeError::ProtocolDesync(
+                "response without a request id".to_string(),
+            ));
+        };
+        if let Some(outstanding) = pending.waiters.remove(&id) {
+            drop(pending);
+            let _ = outstanding.waiter.send(Delivery::Response(line));
+            return Ok(());
+        }
+        if pending.abandoned.remove(&id) {
+            bridge_debug!("PythonBridge: Dropping late response to request {}", id);
+            return Ok(());
+        }
+        Err(BridgeError::ProtocolDesync(format!(
+            "response to request {} that isn't outstanding",
+            id
+        )))
+    }
+
+    /// fail the requests in flight that touch dataset `key` with `error`,
+    /// returns how many there were
+    ///
+    /// like `abandon_all` their responses are dropped if python still
+    /// sends them
+    fn cancel_routed(&self, key: &str, error: &BridgeError) -> usize {
+        self.cancel_where(|o| o.routing_key.as_deref() == Some(key), error)
+    }
+
+    /// fail the requests in flight tagged `tag` with `Cancelled`, the same
+    /// way as `cancel_routed`, and those sent with it from now on
+    fn cancel_tagged(&self, tag: &str) -> usize {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            pending.cancelled_tags.push_back(tag.to_string());
+            if pending.cancelled_tags.len() > MAX_CANCELLED_TAGS {
+                pending.cancelled_tags.pop_front();
+            }
+        }
+        self.cancel_where(
+            |o| o.tag.as_deref() == Some(tag),
+            &BridgeError::Cancelled(tag.to_string()),
+        )
+    }
+
+    fn cancel_where(&self, matches: impl Fn(&Outstanding) -> bool, error: &BridgeError) -> usize {
+        let cancelled: Vec<Outstanding> = {
+            let mut pending = self.pending.lock().unwrap();
+            let ids: Vec<u64> = pending
+                .waiters
+                .iter()
+                .filter(|(_, o)| matches(o))
+                .map(|(&id, _)| id)
+                .collect();
+            pending.abandoned.extend(ids.iter().copied());
+            ids.iter().filter_map(|id| pending.waiters.remove(id)).collect()
+        };
+        for outstanding in &cancelled {
+            let _ = outstanding.waiter.send(Delivery::Failed(error.clone()));
+        }
+        cancelled.len()
+    }
+
+    /// fail every request in flight with `error`
+    ///
+    /// their responses may still be on the way, they are remembered as
+    /// abandoned so they're dropped on arrival and the stream is back in
+    /// step once python has answered them
+    fn abandon_all(&self, error: &BridgeError) {
+        let waiters = {
+            let mut pending = self.pending.lock().unwrap();
+            let waiters = std::mem::take(&mut pending.waiters);
+            pending.abandoned.extend(waiters.keys().copied());
+            waiters
+        };
+        for (_, outstanding) in waiters {
+            let _ = outstanding.waiter.send(Delivery::Failed(error.clone()));
+        }
+    }
+}
+
+/// tags a connection remembers as cancelled, see `Pending::cancelled_tags`
+const MAX_CANCELLED_TAGS: usize = 256;
+
+/// cancels tagged requests while a command holds the bridge, e.g. the
+/// tile a viewport subscription's worker is waiting on when it's torn down
+///
+/// see `PythonBridge::with_request_tag`. shared with `AppState` like the
+/// gates, and pointed at the new child on every respawn
+pub struct RequestCanceller {
+    connection: Mutex<Weak<Connection>>,
+    /// of `PythonCommand::CancelTagged`, in the bridge's namespace
+    wire_name: String,
+}
+
+impl RequestCanceller {
+    fn new(connection: &Arc<Connection>, wire_name: String) -> Arc<Self> {
+        Arc::new(RequestCanceller {
+            connection: Mutex::new(Arc::downgrade(connection)),
+            wire_name,
+        })
+    }
+
+    fn watch(&self, connection: &Arc<Connection>) {
+        *self.connection.lock().unwrap() = Arc::downgrade(connection);
+    }
+
+    /// fail the requests in flight tagged `tag` with `Cancelled` and tell
+    /// python to skip the ones it hasn't started, returns how many were
+    /// waiting
+    ///
+    /// a request python already started runs to its end, its response is
+    /// dropped. requests tagged `tag` after this fail without being sent
+    pub fn cancel_tagged(&self, tag: &str) -> usize {
+        let Some(connection) = self.connection.lock().unwrap().upgrade() else {
+            return 0;
+        };
+        let cancelled = connection.cancel_tagged(tag);
+        let dispatch = Dispatch {
+            lane: PythonCommand::CancelTagged.lane(),
+            idempotent: true,
+            direct: false,
+            routing_key: None,
+            tag: None,
+            trace_id: None,
+            timeout: None,
+        };
+        let params = serde_json::json!({ "tag": tag });
+        if let Err(_e) = connection.notify(&self.wire_name, params, &dispatch) {
+            bridge_debug!("PythonBridge: Couldn't cancel {} in python: {}", tag, _e);
+        }
+        cancelled
+    }
+}
+
+/// longest raw response kept in a `BridgeError::Deserialize`
+const RAW_PREVIEW_BYTES: usize = 2048;
+
+/// parse a command's response data into its rust type
+///
+/// on failure the error names the field that didn't match and keeps a
+/// preview of what python actually sent, so schema drift between the two
+/// halves is obvious from the message alone
+fn decode_response<T: DeserializeOwned>(
+    command: PythonCommand,
+    data: &serde_json::Value,
+) -> Result<T, BridgeError> {
+    serde_path_to_error::deserialize(data).map_err(|e| BridgeError::Deserialize {
+        command: command.name().to_string(),
+        path: e.path().to_string(),
+        message: e.inner().to_string(),
+        raw: preview_json(data, RAW_PREVIEW_BYTES),
+    })
+}
+
+/// deserialize the `data` of a whole response line into `T` in one pass,
+/// see `Body::Line`
+fn decode_line<T: DeserializeOwned>(command: PythonCommand, line: &str) -> Result<T, BridgeError> {
+    #[derive(Deserialize)]
+    struct Data<T> {
+        data: T,
+    }
+    let mut deserializer = serde_json::Deserializer::from_str(line);
+    serde_path_to_error::deserialize(&mut deserializer)
+        .map(|response: Data<T>| response.data)
+        .map_err(|e| BridgeError::Deserialize {
+            command: command.name().to_string(),
+            path: e.path().to_string(),
+            message: e.inner().to_string(),
+            raw: truncate_preview(line.to_string(), RAW_PREVIEW_BYTES),
+        })
+}
+
+#[cfg(test)]
+mod decode_tests {
+    use super::*;
+
+    #[test]
+    fn names_a_missing_field() {
+        let data = serde_json::json!([
+            { "name": "prewarm", "params": [] },
+            { "name": "delete_overlay", "params": [{ "name": "overlay_id", "type": "str" }] },
+        ]);
+        let command = PythonCommand::ListSupportedCommands;
+        let err = decode_response::<Vec<CommandSpec>>(command, &data).unwrap_err();
+
+        let BridgeError::Deserialize { ref path, ref message, ref raw, .. } = err else {
+            panic!("{:?}", err);
+        };
+        assert_eq!(path, "[1].params[0]");
+        assert!(message.contains("missing field `required`"), "{}", message);
+        assert!(raw.contains("delete_overlay"));
+        let expected = "list_supported_commands at `[1].params[0]`";
+        assert!(err.to_string().contains(expected), "{}", err);
+    }
+
+    #[test]
+    fn names_the_field_in_a_whole_line() {
+        let line = r#"{"id":1,"success":true,"data":[{"img_id":1}],"error":null}"#;
+        let err = decode_line::<Vec<ImageInfo>>(PythonCommand::ListImages, line).unwrap_err();
+        assert!(matches!(err, BridgeError::Deserialize { ref path, .. } if path == "data[0].img_id"));
+    }
+}
+
+/// merge pass-through kwargs into a typed command's params
+///
+/// lets the frontend reach python options the rust side doesn't know about
+/// yet. a key the typed signature already sends is rejected rather than
+/// silently overwritten
+fn merge_extra_params(
+    params: &mut serde_json::Value,
+    extra: &serde_json::Map<String, serde_json::Value>,
+) -> Result<(), BridgeError> {
+    let params = params
+        .as_object_mut()
+        .ok_or_else(|| BridgeError::Protocol("params must be a JSON object".to_string()))?;
+
+    let mut collisions: Vec<&str> = extra
+        .keys()
+        .filter(|key| params.contains_key(key.as_str()))
+        .map(String::as_str)
+        .collect();
+    if !collisions.is_empty() {
+        collisions.sort_unstable();
+        return Err(BridgeError::Protocol(format!(
+            "extra params would overwrite core params: {}",
+            collisions.join(", ")
+        )));
+    }
+
+    for (key, value) in extra {
+        params.insert(key.clone(), value.clone());
+    }
+    Ok(())
+}
+
+/// read one response line from python via stdout and parse it
+fn read_response(stdout: &mut BufReader<ChildStdout>) -> Result<PythonResponse, BridgeError> {
+    let response_line = read_line(stdout)?;
+    parse_response(&response_line)
+}
+
+/// the next line python wrote, a reply or a log record
+fn read_message(stdout: &mut BufReader<ChildStdout>) -> Result<Incoming, BridgeError> {
+    let line = read_line(stdout)?;
+
+    // only the tag and id are looked at here, the rest of the line is
+    // skipped. a response is parsed by the request waiting for it
+    #[derive(Deserialize)]
+    struct Envelope {
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        id: Option<u64>,
+    }
+    let envelope = parse_first::<Envelope>(&line)
+        .map(|(envelope, _)| envelope)
+        .map_err(|e| invalid_response(&line, e))?;
+    if envelope.kind.as_deref() == Some("log") {
+        let (log, rest) = parse_first(&line).map_err(|e| {
+            BridgeError::Protocol(format!("Invalid log message from Python: {}", e))
+        })?;
+        warn_trailing(rest);
+        return Ok(Incoming::Log(log));
+    }
+
+    Ok(Incoming::Response {
+        id: envelope.id,
+        line,
+    })
+}
+
+fn read_line(stdout: &mut BufReader<ChildStdout>) -> Result<String, BridgeError> {
+    let mut response_line = String::new();
+    let bytes_read = stdout.read_line(&mut response_line).map_err(|e| {
+        bridge_debug!("PythonBridge: Error reading from stdout: {}", e);
+        BridgeError::from_io(e)
+    })?;
+
+    // EOF: python closed stdout, typically because the process exited
+    if bytes_read == 0 {
+        return Err(BridgeError::ProcessExited);
+    }
+
+    bridge_debug!("PythonBridge: Got response line: {}", response_line);
+    Ok(response_line)
+}
+
+fn parse_response<D: DeserializeOwned>(
+    response_line: &str,
+) -> Result<PythonResponse<D>, BridgeError> {
+    // parse the JSON response to PythonResponse
+    let (response, rest) =
+        parse_first(response_line).map_err(|e| invalid_response(response_line, e))?;
+    warn_trailing(rest);
+    Ok(response)
+}
+
+fn invalid_response(response_line: &str, e: String) -> BridgeError {
+    bridge_debug!("PythonBridge: Error parsing JSON: {}", e);
+    bridge_debug!("PythonBridge: Raw response was: '{}'", response_line);
+    BridgeError::Protocol(format!(
+        "Failed to parse Python response: {}. Raw output: '{}'",
+        e, response_line
+    ))
+}
+
+/// the first JSON value on a line and whatever non-whitespace follows it
+///
+/// python occasionally leaves stray bytes after a message (a second value,
+/// a `print` that slipped onto stdout). the message itself is intact, so
+/// it is used rather than failing the request. a line that doesn't start
+/// with a valid value is still an error
+fn parse_first<T: DeserializeOwned>(line: &str) -> Result<(T, &str), String> {
+    let mut values = serde_json::Deserializer::from_str(line).into_iter::<T>();
+    match values.next() {
+        Some(Ok(value)) => Ok((value, line[values.byte_offset()..].trim())),
+        Some(Err(e)) => Err(e.to_string()),
+        None => Err("empty line".to_string()),
+    }
+}
+
+fn warn_trailing(rest: &str) {
+    if !rest.is_empty() {
+        bridge_debug!(
+            "PythonBridge: Ignoring trailing data after a Python message: {}",
+            truncate_preview(rest.to_string(), RAW_PREVIEW_BYTES)
+        );
+    }
+}
+
+#[cfg(test)]
+mod parse_tests {
+    use super::*;
+
+    #[test]
+    fn ignores_trailing_whitespace() {
+        let response: PythonResponse =
+            parse_response("{\"id\":1,\"success\":true,\"data\":2,\"error\":null}  \r\n").unwrap();
+        assert_eq!(response.data, Some(serde_json::json!(2)));
+    }
+
+    #[test]
+    fn returns_the_first_value_before_a_stray_token() {
+        let line = "{\"id\":1,\"success\":true,\"data\":2,\"error\":null} {\"id\":2}\n";
+        let (response, rest) = parse_first::<PythonResponse>(line).unwrap();
+        assert_eq!(response.id, Some(1));
+        assert_eq!(rest, "{\"id\":2}");
+    }
+
+    #[test]
+    fn still_fails_a_malformed_line() {
+        let result = parse_response::<serde_json::Value>("{\"id\":1,\"success\": tru}\n");
+        assert!(matches!(result, Err(BridgeError::Protocol(_))));
+    }
+}
+
+/// first message python writes once its command loop is up
+#[derive(Debug, Deserialize)]
+struct Handshake {
+    ready: bool,
+    protocol_version: Option<u32>,
+    /// `os.getpid()` in python
+    pid: Option<u32>,
+    /// pid of the process we spawned when python reports a different one,
+    /// set by `spawn_python`
+    #[serde(skip)]
+    launcher_pid: Option<u32>,
+}
+
+impl Handshake {
+    /// python's pid when it isn't the process we spawned
+    fn reexec_pid(&self) -> Option<u32> {
+        self.launcher_pid.and(self.pid)
+    }
+}
+
+/// block until python reports it is ready to accept commands
+fn read_handshake(stdout: &mut BufReader<ChildStdout>) -> Result<Handshake, BridgeError> {
+    let response = read_response(stdout).map_err(|e| {
+        BridgeError::Spawn(format!(
+            "Python helper did not complete the startup handshake: {}",
+            e
+        ))
+    })?;
+
+    if !response.success {
+        return Err(BridgeError::Spawn(format!(
+            "Python helper failed to start: {}",
+            response.error.unwrap_or_else(|| "Unknown error".to_string())
+        )));
+    }
+
+    let handshake: Handshake =
+        serde_json::from_value(response.data.unwrap_or(serde_json::Value::Null))
+            .map_err(|e| BridgeError::Spawn(format!("Invalid handshake from Python helper: {}", e)))?;
+
+    if !handshake.ready {
+        return Err(BridgeError::Spawn(
+            "Python helper reported it is not ready".to_string(),
+        ));
+    }
+
+    Ok(handshake)
+}
+
+// ===== Python Logs =====
+
+/// most log records forwarded per second, the rest are counted and
+/// reported as dropped so a chatty loop can't flood the event channel
+const LOG_RECORDS_PER_SECOND: u32 = 50;
+
+/// a log record python wrote between replies
+///
+/// on the wire: `{"type": "log", "level": ..., "message": ...}`, at any
+/// time and without an id. `level` is one of "debug", "info", "warning"
+/// or "error"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PythonLog {
+    pub level: String,
+    pub message: String,
+    /// the traced request python was handling, see
+    /// `PythonBridge::set_request_tracing`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<u64>,
+}
+
+/// receives python's log records on the connection's reader thread, keep
+/// it quick
+pub type PythonLogHandler = Arc<dyn Fn(PythonLog) + Send + Sync>;
+
+/// one line from python's stdout
+enum Incoming {
+    /// left unparsed but for its id, see `Delivery::Response`
+    Response { id: Option<u64>, line: String },
+    Log(PythonLog),
+}
+
+/// hands log records to the handler, at most `LOG_RECORDS_PER_SECOND`
+struct LogForwarder {
+    handler: PythonLogHandler,
+    window_start: Instant,
+    forwarded: u32,
+    dropped: u64,
+}
+
+impl LogForwarder {
+    fn new(handler: PythonLogHandler) -> Self {
+        Self {
+            handler,
+            window_start: Instant::now(),
+            forwarded: 0,
+            dropped: 0,
+        }
+    }
+
+    fn forward(&mut self, log: PythonLog) {
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            if self.dropped > 0 {
+                (self.handler)(PythonLog {
+                    level: "warning".to_string(),
+                    message: format!("{} log messages dropped", self.dropped),
+                    trace_id: None,
+                });
+            }
+            self.window_start = Instant::now();
+            self.forwarded = 0;
+            self.dropped = 0;
+        }
+
+        if self.forwarded < LOG_RECORDS_PER_SECOND {
+            self.forwarded += 1;
+            (self.handler)(log);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+// ===== Python Warnings =====
+
+/// a caveat python attached to a command that still succeeded, e.g. a
+/// colormap range that clips the data
+///
+/// on the wire: `"warnings": [{"code": ..., "message": ...}]` next to
+/// `data`, left out when there are none
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PythonWarning {
+    /// name of the command that warned, filled in by the bridge
+    #[serde(default)]
+    pub command: String,
+    /// stable identifier to match on, e.g. "value_range_clipped"
+    pub code: String,
+    /// readable explanation for the user
+    pub message: String,
+}
+
+impl PythonWarning {
+    fn for_command(mut self, command: PythonCommand) -> Self {
+        self.command = command.name().to_string();
+        self
+    }
+}
+
+/// receives each `PythonWarning` on the thread that sent the command
+pub type PythonWarningHandler = Arc<dyn Fn(PythonWarning) + Send + Sync>;
+
+/// the result of a `PythonBridge::with_warnings` call and what python
+/// warned about while producing it
+#[derive(Debug, Clone, Serialize)]
+pub struct WithWarnings<T> {
+    pub data: T,
+    pub warnings: Vec<PythonWarning>,
+}
+
+impl PythonBridge {
+    /// run `f` and collect the warnings of every command it sends
+    ///
+    /// they reach `PythonBridgeBuilder::on_python_warning` as well. a
+    /// nested call collects its own, which the outer one gets too. a result
+    /// served from a cache comes back without the warnings it was first
+    /// fetched with
+    pub fn with_warnings<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, BridgeError>,
+    ) -> Result<WithWarnings<T>, BridgeError> {
+        let outer = self.warnings.replace(Vec::new());
+        let result = f(self);
+        let warnings = std::mem::replace(&mut self.warnings, outer).unwrap_or_default();
+        if let Some(outer) = &mut self.warnings {
+            outer.extend(warnings.iter().cloned());
+        }
+        result.map(|data| WithWarnings { data, warnings })
+    }
+
+    fn forward_warnings(&mut self, command: PythonCommand, warnings: Vec<PythonWarning>) {
+        for warning in warnings {
+            let warning = warning.for_command(command);
+            bridge_debug!("PythonBridge: {} warned: {:?}", command.name(), warning);
+            if let Some(handler) = &self.on_python_warning {
+                handler(warning.clone());
+            }
+            if let Some(collected) = &mut self.warnings {
+                collected.push(warning);
+            }
+        }
+    }
+}
+
+// ===== Wire Frames =====
+
+/// bytes kept of each captured line, after redaction
+pub const MAX_WIRE_FRAME_BYTES: usize = 64 * 1024;
+
+/// string fields holding base64 image data, captured as their length only
+const BINARY_FIELDS: &[&str] = &["tile"];
+
+/// the raw request and response lines of one command, see
+/// `PythonBridgeBuilder::capture_wire_frames`
+///
+/// byte for byte what crossed the pipe, newline framing included, except
+/// that `BINARY_FIELDS` values are replaced by a `<N bytes of base64>`
+/// summary and each side is cut at `MAX_WIRE_FRAME_BYTES`
+#[derive(Debug, Clone, Serialize)]
+pub struct WireFrames {
+    /// wire name, namespace included
+    pub command: String,
+    pub request: Vec<u8>,
+    /// empty if no response arrived (timed out, cancelled, python exited)
+    pub response: Vec<u8>,
+    /// sizes on the wire, before redaction and the cut
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+    /// fields replaced by their length, e.g. `["tile"]`
+    pub redacted: Vec<String>,
+    pub truncated: bool,
+}
+
+impl WireFrames {
+    fn capture(command: &str, request_json: &str, response_line: &str) -> Self {
+        let request = format!("{}\n", request_json);
+        let mut redacted = Vec::new();
+        let mut truncated = false;
+        let mut frame = |line: &str| {
+            let mut line = line.to_string();
+            for field in BINARY_FIELDS {
+                if let Some(summarized) = redact_field(&line, field) {
+                    line = summarized;
+                    if !redacted.iter().any(|f| f == field) {
+                        redacted.push(field.to_string());
+                    }
+                }
+            }
+            let mut bytes = line.into_bytes();
+            if bytes.len() > MAX_WIRE_FRAME_BYTES {
+                bytes.truncate(MAX_WIRE_FRAME_BYTES);
+                truncated = true;
+            }
+            bytes
+        };
+        let (request_frame, response_frame) = (frame(&request), frame(response_line));
+        WireFrames {
+            command: command.to_string(),
+            request: request_frame,
+            response: response_frame,
+            request_bytes: request.len(),
+            response_bytes: response_line.len(),
+            redacted,
+            truncated,
+        }
+    }
+}
+
+/// `line` with the string values of every `"field":` replaced by their
+/// length, `None` if it has none
+///
+/// a plain scan rather than a json round trip, so the rest of the line
+/// keeps its exact bytes. only for fields whose values never contain a
+/// quote or an escape, like base64
+fn redact_field(line: &str, field: &str) -> Option<String> {
+    let key = format!("\"{}\"", field);
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    let mut found = false;
+    while let Some(at) = rest.find(&key) {
+        let after_key = &rest[at + key.len()..];
+        let value = after_key
+            .trim_start()
+            .strip_prefix(':')
+            .map(str::trim_start)
+            .and_then(|value| value.strip_prefix('"'));
+        // the name as a value, e.g. `"stage": "tile"`
+        let Some(value) = value else {
+            out.push_str(&rest[..at + key.len()]);
+            rest = after_key;
+            continue;
+        };
+        let Some(end) = value.find('"') else { break };
+        out.push_str(&rest[..rest.len() - value.len()]);
+        out.push_str(&format!("<{} bytes of base64>", end));
+        rest = &value[end..];
+        found = true;
+    }
+    out.push_str(rest);
+    found.then_some(out)
+}
+
+impl PythonBridge {
+    /// the latest command's request and response as they crossed the
+    /// pipe, `None` unless built with `capture_wire_frames` or before the
+    /// first command. the bridge's own commands (prewarm, warmup) count
+    ///
+    /// unlike the diagnostics log, which keeps a readable summary of many
+    /// commands, this is the exact bytes of one. lost on respawn
+    pub fn get_last_wire_frames(&self) -> Option<WireFrames> {
+        self.connection.last_frames.lock().unwrap().clone()
+    }
+}
+
+// ===== Concurrency Limits =====
+
+/// a class of commands with its own concurrency limit and its own worker
+/// in python
+///
+/// python runs each lane on a separate thread, so a long render never
+/// holds up tile requests and a burst of tiles never delays a plot.
+/// commands are ordered within a lane only, `barrier` waits for all of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lane {
+    /// renders and other heavy work
+    Plot,
+    /// serving already rendered tiles
+    Tile,
+    /// everything else, cheap queries and bookkeeping. runs on python's
+    /// main thread
+    #[default]
+    Control,
+}
+
+impl Lane {
+    pub const ALL: [Lane; 3] = [Lane::Plot, Lane::Tile, Lane::Control];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lane::Plot => "plot",
+            Lane::Tile => "tile",
+            Lane::Control => "control",
+        }
+    }
+
+    /// default cap on concurrent requests in the lane, `None` for unlimited
+    fn default_limit(self) -> Option<usize> {
+        match self {
+            // python has one plot worker, two renders only slow each other
+            Lane::Plot => Some(1),
+            // fast zooms fire dozens at once
+            Lane::Tile => Some(8),
+            Lane::Control => None,
+        }
+    }
+}
+
+impl PythonCommand {
+    /// the lane python runs this command in
+    pub fn lane(self) -> Lane {
+        match self {
+            PythonCommand::PlotTissueOverlay
+            | PythonCommand::Prewarm
+            | PythonCommand::LoadDataset
+            | PythonCommand::ChecksumFile
+            | PythonCommand::ExportRegionPng
+            | PythonCommand::ExportSegments
+            | PythonCommand::ExportLegend
+            | PythonCommand::ComposeComparison
+            | PythonCommand::PreprocessStep
+            | PythonCommand::EndPreprocessing
+            | PythonCommand::RecolorOverlay
+            | PythonCommand::BeginOmeTiff
+            | PythonCommand::WriteOmeTiffLevel
+            | PythonCommand::FinishOmeTiff
+            | PythonCommand::AbortOmeTiff
+            | PythonCommand::BuildPyramidLevel
+            // behind any plot still reading the context's inputs
+            | PythonCommand::OpenRenderContext
+            | PythonCommand::CloseRenderContext => Lane::Plot,
+            PythonCommand::GetTissueOverlayTile
+            | PythonCommand::GetComparisonTile
+            | PythonCommand::DiffTiles
+            | PythonCommand::GetOverview
+            | PythonCommand::GetBaseImageTile
+            | PythonCommand::GetTilesBatch => Lane::Tile,
+            _ => Lane::Control,
+        }
+    }
+}
+
+/// in-flight numbers for one lane, see `PythonBridge::lane_metrics`
+#[derive(Debug, Clone, Serialize)]
+pub struct LaneMetrics {
+    pub lane: Lane,
+    pub in_flight: usize,
+    /// callers waiting for a slot
+    pub queued: usize,
+    pub limit: Option<usize>,
+    /// only the tile lane can be paused, see `PythonBridge::pause_tiles`
+    pub paused: bool,
+}
+
+/// in-flight numbers for one command, see `PythonBridge::command_metrics`
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandMetrics {
+    pub command: String,
+    pub in_flight: usize,
+    /// callers waiting for a slot
+    pub queued: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Default)]
+struct GateState {
+    in_flight: usize,
+    queued: usize,
+    closed: bool,
+    /// see `CommandGates::pause_tiles`
+    paused: bool,
+}
+
+/// counting semaphore for one command or lane
+struct Gate {
+    limit: Option<usize>,
+    state: Mutex<GateState>,
+    available: Condvar,
+}
+
+impl Gate {
+    fn new(limit: Option<usize>) -> Self {
+        Gate {
+            limit,
+            state: Mutex::new(GateState::default()),
+            available: Condvar::new(),
+        }
+    }
+
+    /// `wait_if_paused` queues while the gate is paused, otherwise that
+    /// fails with `TilesPaused`
+    fn acquire(&self, wait_if_paused: bool) -> Result<GatePermit<'_>, BridgeError> {
+        let mut state = self.state.lock().unwrap();
+        if state.paused && !wait_if_paused && !state.closed {
+            return Err(BridgeError::TilesPaused);
+        }
+
+        state.queued += 1;
+        while !state.closed
+            && (state.paused || self.limit.is_some_and(|limit| state.in_flight >= limit))
+        {
+            state = self.available.wait(state).unwrap();
+        }
+        state.queued -= 1;
+
+        if state.closed {
+            return Err(BridgeError::ShuttingDown);
+        }
+        state.in_flight += 1;
+        Ok(GatePermit { gate: self })
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.available.notify_all();
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.state.lock().unwrap().paused = paused;
+        self.available.notify_all();
+    }
+
+    fn paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+}
+
+/// per-lane and per-command semaphores so the bridge throttles itself
+/// instead of flooding python
+///
+/// a command needs a slot in its lane and then one of its own. excess
+/// callers queue until a slot frees up and are woken with `ShuttingDown`
+/// if the app exits meanwhile
+pub struct CommandGates {
+    lanes: HashMap<Lane, Gate>,
+    gates: HashMap<PythonCommand, Gate>,
+}
+
+/// a held slot, released on drop
+struct GatePermit<'a> {
+    gate: &'a Gate,
+}
+
+/// a command's lane and command slots
+struct Permit<'a> {
+    // dropped in declaration order, the command slot first
+    _command: GatePermit<'a>,
+    _lane: GatePermit<'a>,
+}
+
+impl CommandGates {
+    fn new(
+        limits: &HashMap<PythonCommand, usize>,
+        lane_limits: &HashMap<Lane, usize>,
+        shutdown: &CancellationToken,
+    ) -> Arc<Self> {
+        let lanes = Lane::ALL
+            .iter()
+            .map(|&lane| {
+                let limit = lane_limits.get(&lane).copied().or_else(|| lane.default_limit());
+                (lane, Gate::new(limit))
+            })
+            .collect();
+        let gates = PythonCommand::ALL
+            .iter()
+            .map(|&command| {
+                (command, Gate::new(limits.get(&command).copied()))
+            })
+            .collect();
+        let gates = Arc::new(CommandGates { lanes, gates });
+
+        let weak = Arc::downgrade(&gates);
+        shutdown.on_cancel(move || {
+            if let Some(gates) = weak.upgrade() {
+                gates.close();
+            }
+        });
+
+        gates
+    }
+
+    /// wait for a free slot for `command`
+    ///
+    /// the lane is always taken before the command, so two callers can't
+    /// each hold the slot the other is waiting for
+    ///
+    /// a paused tile lane fails with `TilesPaused` right away: the caller
+    /// holds the bridge, waiting would keep the plot it makes way for from
+    /// running
+    fn acquire(&self, command: PythonCommand) -> Result<Permit<'_>, BridgeError> {
+        self.acquire_slots(command, false)
+    }
+
+    /// `acquire` that also waits out a paused tile lane, for callers that
+    /// don't hold the bridge
+    fn acquire_queued(&self, command: PythonCommand) -> Result<Permit<'_>, BridgeError> {
+        self.acquire_slots(command, true)
+    }
+
+    fn acquire_slots(
+        &self,
+        command: PythonCommand,
+        wait_if_paused: bool,
+    ) -> Result<Permit<'_>, BridgeError> {
+        let lane = self.lanes[&command.lane()].acquire(wait_if_paused)?;
+        let command = self.gates[&command].acquire(wait_if_paused)?;
+        Ok(Permit {
+            _command: command,
+            _lane: lane,
+        })
+    }
+
+    /// see `PythonBridge::pause_tiles`, callable while the bridge is busy
+    pub fn pause_tiles(&self) {
+        self.lanes[&Lane::Tile].set_paused(true);
+    }
+
+    /// queued misses go ahead, up to the lane's limit
+    pub fn resume_tiles(&self) {
+        self.lanes[&Lane::Tile].set_paused(false);
+    }
+
+    pub fn tiles_paused(&self) -> bool {
+        self.lanes[&Lane::Tile].paused()
+    }
+
+    /// wake every queued caller, they fail with `ShuttingDown`
+    fn close(&self) {
+        for gate in self.lanes.values().chain(self.gates.values()) {
+            gate.close();
+        }
+    }
+
+    pub fn lane_metrics(&self) -> Vec<LaneMetrics> {
+        Lane::ALL
+            .iter()
+            .map(|&lane| {
+                let gate = &self.lanes[&lane];
+                let state = gate.state.lock().unwrap();
+                LaneMetrics {
+                    lane,
+                    in_flight: state.in_flight,
+                    queued: state.queued,
+                    limit: gate.limit,
+                    paused: state.paused,
+                }
+            })
+            .collect()
+    }
+
+    pub fn metrics(&self) -> Vec<CommandMetrics> {
+        PythonCommand::ALL
+            .iter()
+            .map(|command| {
+                let gate = &self.gates[command];
+                let state = gate.state.lock().unwrap();
+                CommandMetrics {
+                    command: command.name().to_string(),
+                    in_flight: state.in_flight,
+                    queued: state.queued,
+                    limit: gate.limit,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Drop for GatePermit<'_> {
+    fn drop(&mut self) {
+        self.gate.state.lock().unwrap().in_flight -= 1;
+        self.gate.available.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod gate_tests {
+    use super::*;
+
+    #[test]
+    fn holds_concurrent_requests_to_the_limit() {
+        let limits = HashMap::from([(PythonCommand::GetTissueOverlayTile, 2)]);
+        let gates = CommandGates::new(&limits, &HashMap::new(), &CancellationToken::new());
+        let tile_metrics = || {
+            gates
+                .metrics()
+                .into_iter()
+                .find(|m| m.command == PythonCommand::GetTissueOverlayTile.name())
+                .unwrap()
+        };
+
+        let (mut most_in_flight, mut most_queued) = (0, 0);
+        std::thread::scope(|scope| {
+            let requests: Vec<_> = (0..5)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let _permit = gates.acquire(PythonCommand::GetTissueOverlayTile).unwrap();
+                        std::thread::sleep(Duration::from_millis(50));
+                    })
+                })
+                .collect();
+            while !requests.iter().all(|request| request.is_finished()) {
+                let metrics = tile_metrics();
+                most_in_flight = most_in_flight.max(metrics.in_flight);
+                most_queued = most_queued.max(metrics.queued);
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        });
+        assert_eq!(most_in_flight, 2);
+        assert!(most_queued > 0);
+        assert_eq!(tile_metrics().in_flight, 0);
+    }
+}
+
+impl PythonBridge {
+    /// current in-flight and queued requests per command
+    pub fn command_metrics(&self) -> Vec<CommandMetrics> {
+        self.gates.metrics()
+    }
+
+    /// current in-flight and queued requests per lane
+    pub fn lane_metrics(&self) -> Vec<LaneMetrics> {
+        self.gates.lane_metrics()
+    }
+
+    /// the limits themselves, for reading metrics without holding the
+    /// bridge (which is busy for as long as a command runs)
+    pub fn command_gates(&self) -> Arc<CommandGates> {
+        Arc::clone(&self.gates)
+    }
+
+    /// for cancelling tagged requests without holding the bridge, see
+    /// `with_request_tag`
+    pub fn request_canceller(&self) -> Arc<RequestCanceller> {
+        Arc::clone(&self.canceller)
+    }
+
+    /// serve tiles only from the cache, to give a big plot python's full
+    /// attention
+    ///
+    /// a cached tile is served as usual and `get_tile_with_fallback` still
+    /// finds cached ancestors. a tile lane command that would have to ask
+    /// python (overlay, base image, diff, comparison and overview tiles)
+    /// queues until `resume_tiles` when it comes through the `TileService`
+    /// and shows in `LaneMetrics::queued`. through the bridge it fails with
+    /// `TilesPaused` right away, waiting there would hold the bridge and keep
+    /// the plot it makes way for from running, so tiles a plot warms up
+    /// while paused are skipped. shows in `LaneMetrics::paused`
+    pub fn pause_tiles(&self) {
+        self.gates.pause_tiles();
+    }
+
+    pub fn resume_tiles(&self) {
+        self.gates.resume_tiles();
+    }
+
+    pub fn tiles_paused(&self) -> bool {
+        self.gates.tiles_paused()
+    }
+}
+
+// ===== Pyramid Estimate =====
+
+/// zoom levels python cuts for every overlay (`max_zoom` in its tiling)
+const PYRAMID_MAX_ZOOM: i32 = 4;
+/// rough size of python's quality-85 JPEG tiles, measured on typical
+/// tissue overlays. only used for estimates
+const EST_JPEG_BYTES_PER_PIXEL: f64 = 0.25;
+
+/// predicted size of an overlay's pyramid, see `estimate_overlay`
+#[derive(Debug, Clone, Serialize)]
+pub struct PyramidEstimate {
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    pub total_tiles: u64,
+    pub est_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputDimensions {
+    width: u32,
+    height: u32,
+}
+
+impl PythonBridge {
+    /// how big `plot_tissue_overlay` (and an export) of these inputs would be
+    ///
+    /// python only reports the image size, nothing is rendered. ids left as
+    /// `None` come from the context like for plot
+    pub fn estimate_overlay(
+        &mut self,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        seg_id: Option<&str>,
+        tile_size: u32,
+    ) -> Result<PyramidEstimate, BridgeError> {
+        let (dataset_id, img_id, seg_id) = self.context.resolve(dataset_id, img_id, seg_id)?;
+        self.check_image(&dataset_id, &img_id)?;
+        let params = serde_json::json!({
+            "dataset_id": dataset_id,
+            "img_id": img_id,
+            "seg_id": seg_id
+        });
+        let dims: InputDimensions =
+            self.send_command_as(PythonCommand::GetInputDimensions, params)?;
+
+        let tile_size = tile_size.max(1) as u64;
+        let mut total_tiles = 0;
+        let mut total_pixels = 0;
+        for zoom in 0..=PYRAMID_MAX_ZOOM {
+            // same flooring as python's resize
+            let scale = 1u64 << (PYRAMID_MAX_ZOOM - zoom);
+            let (width, height) = (dims.width as u64 / scale, dims.height as u64 / scale);
+            total_tiles += width.div_ceil(tile_size) * height.div_ceil(tile_size);
+            total_pixels += width * height;
+        }
+
+        Ok(PyramidEstimate {
+            width: dims.width,
+            height: dims.height,
+            levels: (PYRAMID_MAX_ZOOM + 1) as u32,
+            total_tiles,
+            est_bytes: (total_pixels as f64 * EST_JPEG_BYTES_PER_PIXEL) as u64,
+        })
+    }
+}
+
+// ===== Pyramid Mode =====
+
+/// when `plot_tissue_overlay` cuts the tile pyramid
+///
+/// `Eager` builds every tile of every level before plot returns, so no
+/// tile request ever waits on a render, at the cost of plot latency. once
+/// built python holds only the encoded tiles, about what
+/// `PyramidEstimate::est_bytes` predicts, whether they are ever viewed or
+/// not. `Lazy` returns as soon as the render is done and cuts each tile on
+/// its first request. for that python keeps the full resolution render
+/// (about 3 bytes per pixel) and every downsampled level it has touched
+/// (up to another third) for as long as the overlay lives, on top of the
+/// tiles cut so far. eager is the better fit for exports and kiosks that
+/// show the whole slide, lazy for browsing a few regions of a large one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PyramidMode {
+    #[default]
+    Eager,
+    Lazy,
+}
+
+/// an eager pyramid build finished a level, see
+/// `PythonBridgeBuilder::on_pyramid_progress`
+#[derive(Debug, Clone, Serialize)]
+pub struct PyramidProgress {
+    pub overlay_id: String,
+    pub zoom: i32,
+    /// levels built so far, out of `total`
+    pub done: u32,
+    pub total: u32,
+}
+
+/// receives `PyramidProgress` on the thread running the plot
+pub type PyramidProgressHandler = Arc<dyn Fn(PyramidProgress) + Send + Sync>;
+
+impl PythonBridge {
+    /// cut every tile of an overlay python holds as a lazy pyramid, coarsest
+    /// level first, reporting each level to the progress handler
+    ///
+    /// `plot_tissue_overlay` in `Eager` mode plots lazily and then calls
+    /// this, so a large pyramid shows progress rather than one long plot
+    fn build_pyramid(&mut self, info: &OverlayInfo) -> Result<(), BridgeError> {
+        let total = u32::try_from(info.max_zoom + 1).unwrap_or(0);
+        for (done, zoom) in (1..).zip(0..=info.max_zoom) {
+            let params = serde_json::json!({
+                "overlay_id": info.overlay_id,
+                "zoom": zoom
+            });
+            self.send_command(PythonCommand::BuildPyramidLevel, params)?;
+            if let Some(handler) = &self.on_pyramid_progress {
+                handler(PyramidProgress {
+                    overlay_id: info.overlay_id.clone(),
+                    zoom,
+                    done,
+                    total,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// ===== Idle Shutdown =====
+
+#[derive(Debug)]
+struct IdleState {
+    last_activity: Instant,
+    /// commands currently running, the child is never stopped under one
+    active: usize,
+}
+
+/// stops the python child once no command has run for `timeout`
+///
+/// a watcher thread wakes up when the quiet period could have elapsed. the
+/// child is closed under the same lock `begin` takes, so a command either
+/// sees the bridge already `Idle` (and respawns it) or keeps it awake
+struct IdleWatch {
+    timeout: Duration,
+    state: Mutex<IdleState>,
+    /// the child to stop, replaced on every respawn
+    connection: Mutex<Weak<Connection>>,
+    status: Arc<Mutex<BridgeStatus>>,
+}
+
+/// a running command, see `IdleWatch::begin`
+struct ActiveCommand {
+    watch: Arc<IdleWatch>,
+}
+
+impl IdleWatch {
+    fn start(
+        timeout: Duration,
+        connection: &Arc<Connection>,
+        status: Arc<Mutex<BridgeStatus>>,
+        shutdown: &CancellationToken,
+    ) -> Arc<Self> {
+        let watch = Arc::new(IdleWatch {
+            timeout,
+            state: Mutex::new(IdleState {
+                last_activity: Instant::now(),
+                active: 0,
+            }),
+            connection: Mutex::new(Arc::downgrade(connection)),
+            status,
+        });
+
+        let weak = Arc::downgrade(&watch);
+        let shutdown = shutdown.clone();
+        std::thread::spawn(move || loop {
+            let Some(watch) = weak.upgrade() else { return };
+            if shutdown.is_cancelled() {
+                return;
+            }
+            let wait = watch.check();
+            drop(watch);
+            std::thread::sleep(wait);
+        });
+
+        watch
+    }
+
+    fn begin(watch: &Arc<IdleWatch>) -> ActiveCommand {
+        let mut state = watch.state.lock().unwrap();
+        state.active += 1;
+        state.last_activity = Instant::now();
+        drop(state);
+        ActiveCommand {
+            watch: Arc::clone(watch),
+        }
+    }
+
+    fn watch(&self, connection: &Arc<Connection>) {
+        *self.connection.lock().unwrap() = Arc::downgrade(connection);
+    }
+
+    /// stop the child if it has been quiet long enough, returns how long
+    /// to sleep before checking again
+    fn check(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        let quiet = state.last_activity.elapsed();
+        if state.active > 0 || quiet < self.timeout {
+            return self.timeout.saturating_sub(quiet).max(Duration::from_secs(1));
+        }
+
+        let mut status = self.status.lock().unwrap();
+        if *status == BridgeStatus::Ready {
+            if let Some(connection) = self.connection.lock().unwrap().upgrade() {
+                bridge_debug!("PythonBridge: Idle for {:?}, stopping Python", quiet);
+                connection.close(BridgeError::ProcessExited);
+                *status = BridgeStatus::Idle;
+            }
+        }
+        self.timeout
+    }
+}
+
+impl Drop for ActiveCommand {
+    fn drop(&mut self) {
+        let mut state = self.watch.state.lock().unwrap();
+        state.active -= 1;
+        state.last_activity = Instant::now();
+    }
+}
+
+// ===== Wire Paths =====
+
+/// a path as a command param: a plain string when it is valid UTF-8,
+/// otherwise `{"bytes_b64": ...}` with its raw bytes (unix) or
+/// `{"wide_b64": ...}` with its UTF-16LE units (windows), base64 encoded
+///
+/// serializing a `Path` directly fails on anything that isn't UTF-8 and a
+/// lossy conversion points python at a different file. python's `wire_path`
+/// turns both forms back into the same OS path, `deserialize_wire_path`
+/// reads paths python sends back the same way
+fn wire_path(path: &Path) -> serde_json::Value {
+    match path.to_str() {
+        Some(text) => text.into(),
+        None => raw_wire_path(path),
+    }
+}
+
+#[cfg(unix)]
+fn raw_wire_path(path: &Path) -> serde_json::Value {
+    use std::os::unix::ffi::OsStrExt;
+    let bytes = path.as_os_str().as_bytes();
+    serde_json::json!({ "bytes_b64": encode_base64(bytes) })
+}
+
+#[cfg(windows)]
+fn raw_wire_path(path: &Path) -> serde_json::Value {
+    use std::os::windows::ffi::OsStrExt;
+    let bytes: Vec<u8> = path.as_os_str().encode_wide().flat_map(u16::to_le_bytes).collect();
+    serde_json::json!({ "wide_b64": encode_base64(&bytes) })
+}
+
+/// no byte level access to paths elsewhere, the best there is
+#[cfg(not(any(unix, windows)))]
+fn raw_wire_path(path: &Path) -> serde_json::Value {
+    path.to_string_lossy().into_owned().into()
+}
+
+/// the forms of `wire_path`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WirePath {
+    Text(String),
+    Bytes { bytes_b64: String },
+    Wide { wide_b64: String },
+}
+
+/// `deserialize_with` for paths in python's replies, see `wire_path`
+fn deserialize_wire_path<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PathBuf, D::Error> {
+    use serde::de::Error;
+    match WirePath::deserialize(deserializer)? {
+        WirePath::Text(text) => Ok(PathBuf::from(text)),
+        WirePath::Bytes { bytes_b64 } => {
+            let bytes = decode_base64(&bytes_b64).map_err(D::Error::custom)?;
+            path_from_bytes(bytes).map_err(D::Error::custom)
+        }
+        WirePath::Wide { wide_b64 } => {
+            let bytes = decode_base64(&wide_b64).map_err(D::Error::custom)?;
+            path_from_wide(bytes).map_err(D::Error::custom)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Result<PathBuf, String> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(_bytes: Vec<u8>) -> Result<PathBuf, String> {
+    Err("Python sent a unix byte path".to_string())
+}
+
+#[cfg(windows)]
+fn path_from_wide(bytes: Vec<u8>) -> Result<PathBuf, String> {
+    use std::os::windows::ffi::OsStringExt;
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    Ok(PathBuf::from(std::ffi::OsString::from_wide(&units)))
+}
+
+#[cfg(not(windows))]
+fn path_from_wide(_bytes: Vec<u8>) -> Result<PathBuf, String> {
+    Err("Python sent a windows wide path".to_string())
+}
+
+#[cfg(test)]
+mod wire_path_tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Reply {
+        #[serde(deserialize_with = "deserialize_wire_path")]
+        path: PathBuf,
+    }
+
+    fn round_trip(path: &Path) -> PathBuf {
+        let reply = serde_json::json!({ "path": wire_path(path) });
+        serde_json::from_value::<Reply>(reply).unwrap().path
+    }
+
+    #[test]
+    fn sends_utf8_paths_as_strings() {
+        let path = Path::new("/data/sample ü.h5ad");
+        assert_eq!(wire_path(path), serde_json::json!("/data/sample ü.h5ad"));
+        assert_eq!(round_trip(path), path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trips_non_utf8_paths_byte_for_byte() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        let bytes = b"/data/caf\xe9/\xff\xfe.h5ad".to_vec();
+        let path = PathBuf::from(OsString::from_vec(bytes.clone()));
+
+        let wire = wire_path(&path);
+        assert_eq!(wire, serde_json::json!({ "bytes_b64": encode_base64(&bytes) }));
+        assert_eq!(round_trip(&path).as_os_str().as_bytes(), &bytes[..]);
+    }
+}
+
+// ===== Dataset Download =====
+
+/// tries per download, each resuming where the previous one stopped
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+/// bytes between `download_progress` events
+const DOWNLOAD_PROGRESS_STEP: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub url: String,
+    pub downloaded: u64,
+    /// `None` if the server didn't send a content length
+    pub total: Option<u64>,
+}
+
+/// whether `load_dataset` hashes the file before reading it, which costs
+/// a full extra read
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ChecksumCheck {
+    #[default]
+    Skip,
+    /// report the checksum in `DatasetSummary::checksum`
+    Compute,
+    /// refuse to load a file with another checksum, "sha256:<hex>" or
+    /// bare hex
+    Verify { expected: String },
+}
+
+/// what `checksum_file` read
+#[derive(Debug, Deserialize)]
+struct FileChecksum {
+    /// "sha256:<hex>"
+    checksum: String,
+}
+
+/// `expected` in the "sha256:<hex>" form python reports, lowercased
+fn normalize_checksum(expected: &str) -> Result<String, BridgeError> {
+    let hex = expected.trim();
+    let hex = hex.strip_prefix("sha256:").unwrap_or(hex).to_ascii_lowercase();
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(BridgeError::Protocol(format!(
+            "Expected checksum must be a sha256 digest in hex, got {}",
+            expected
+        )));
+    }
+    Ok(format!("sha256:{}", hex))
+}
+
+#[derive(Debug, Deserialize)]
+struct UnloadedDataset {
+    /// overlays plotted from the dataset, python deletes them with it
+    overlays: Vec<String>,
+}
+
+impl PythonBridge {
+    /// read a local `.h5ad` file into python
+    ///
+    /// `dataset_id` defaults to the file name without extension. the path
+    /// needn't be UTF-8, see `wire_path`. with `checksum` other than `Skip`
+    /// the file is hashed first, a `Verify` that doesn't match fails with
+    /// `IntegrityMismatch` and loads nothing
+    pub fn load_dataset(
+        &mut self,
+        path: &Path,
+        dataset_id: Option<&str>,
+        checksum: &ChecksumCheck,
+    ) -> Result<DatasetSummary, BridgeError> {
+        let checksum = match checksum {
+            ChecksumCheck::Skip => None,
+            ChecksumCheck::Compute => Some(self.checksum_file(path)?),
+            ChecksumCheck::Verify { expected } => {
+                let expected = normalize_checksum(expected)?;
+                let actual = self.checksum_file(path)?;
+                if actual != expected {
+                    return Err(BridgeError::IntegrityMismatch {
+                        path: path.to_path_buf(),
+                        expected,
+                        actual,
+                    });
+                }
+                Some(actual)
+            }
+        };
+        let params = serde_json::json!({
+            "path": wire_path(path),
+            "dataset_id": dataset_id,
+            "checksum": checksum
+        });
+        let loaded: DatasetSummary = self.send_command_as(PythonCommand::LoadDataset, params)?;
+        // loading over an existing id replaces its data
+        self.result_cache.forget_dataset(&loaded.dataset_id);
+        self.base_tiles.clear();
+        Ok(loaded)
+    }
+
+    /// sha256 of a file as "sha256:<hex>", read by python in full
+    pub fn checksum_file(&mut self, path: &Path) -> Result<String, BridgeError> {
+        let hashed: FileChecksum = self.send_command_as(
+            PythonCommand::ChecksumFile,
+            serde_json::json!({ "path": wire_path(path) }),
+        )?;
+        Ok(hashed.checksum)
+    }
+
+    /// free a dataset in python, along with every overlay plotted from it and
+    /// its render contexts
+    ///
+    /// commands still waiting on results for the dataset fail with
+    /// `DatasetUnloaded` first. python finishes whatever of them it already
+    /// started before freeing anything, so a render never runs against
+    /// freed data, and their late results are dropped. unloading a dataset
+    /// that isn't loaded does nothing. returns the deleted overlay ids
+    pub fn unload_dataset(&mut self, dataset_id: &str) -> Result<Vec<String>, BridgeError> {
+        let _cancelled = self
+            .connection
+            .cancel_routed(dataset_id, &BridgeError::DatasetUnloaded(dataset_id.to_string()));
+        bridge_debug!(
+            "PythonBridge: Cancelled {} commands before unloading {}",
+            _cancelled,
+            dataset_id
+        );
+
+        let unloaded: UnloadedDataset = self.send_command_as(
+            PythonCommand::UnloadDataset,
+            serde_json::json!({ "dataset_id": dataset_id }),
+        )?;
+        for overlay_id in &unloaded.overlays {
+            self.overlays.remove(overlay_id);
+            self.tiles.cache().unpin(overlay_id, None);
+            self.overviews.remove(overlay_id);
+        }
+        self.result_cache.forget_dataset(dataset_id);
+        self.render_contexts.forget_dataset(dataset_id);
+        self.base_tiles.clear();
+        Ok(unloaded.overlays)
+    }
+
+    /// the datasets python has loaded, sorted by id
+    pub fn list_datasets(&mut self) -> Result<Vec<DatasetSummary>, BridgeError> {
+        self.send_command_as(PythonCommand::ListDatasets, serde_json::json!({}))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetSummary {
+    pub dataset_id: String,
+    pub n_obs: u64,
+    pub n_vars: u64,
+    /// "sha256:<hex>" of the file, when it was loaded with a checksum
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+/// where downloaded datasets are kept between runs
+fn download_cache_dir() -> PathBuf {
+    std::env::temp_dir().join("spatialviewer_downloads")
+}
+
+/// download `url` into `cache_dir` unless it is already there
+///
+/// data goes to a `.part` file that is only renamed once its length
+/// matches what the server announced, so an interrupted download is never
+/// mistaken for a finished one. later attempts (and later calls) resume
+/// the `.part` file with a range request, a server that ignores the range
+/// restarts it from scratch. client errors (4xx) aren't retried
+///
+/// downloads of the same url, from this process or another, take turns
+/// on a `.lock` file next to it. the later one finds the file in place
+pub fn download_to_cache(
+    url: &str,
+    cache_dir: &Path,
+    mut on_progress: impl FnMut(DownloadProgress),
+) -> Result<PathBuf, BridgeError> {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(BridgeError::Download(format!("Unsupported URL {}", url)));
+    }
+
+    let path = cache_dir.join(cache_file_name(url));
+    if path.exists() {
+        bridge_debug!("PythonBridge: Using cached download {:?}", path);
+        return Ok(path);
+    }
+    std::fs::create_dir_all(cache_dir).map_err(|e| BridgeError::Io(e.to_string()))?;
+
+    // released when the file is closed, a crashed download leaves no lock
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))
+        .and_then(|lock| lock.lock().map(|_| lock))
+        .map_err(|e| BridgeError::Io(e.to_string()))?;
+    if path.exists() {
+        bridge_debug!("PythonBridge: {:?} was downloaded meanwhile", path);
+        return Ok(path);
+    }
+
+    let part = path.with_extension("part");
+    // no overall timeout, datasets can take far longer than reqwest's 30s
+    // default. connecting is still bounded
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .connect_timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| BridgeError::Download(e.to_string()))?;
+
+    for attempt in 1..=DOWNLOAD_ATTEMPTS {
+        match download_attempt(&client, url, &part, &mut on_progress) {
+            Ok(()) => {
+                std::fs::rename(&part, &path).map_err(|e| BridgeError::Io(e.to_string()))?;
+                drop(lock);
+                return Ok(path);
+            }
+            Err((e, retryable)) if retryable && attempt < DOWNLOAD_ATTEMPTS => {
+                bridge_debug!("PythonBridge: Download attempt {} failed: {}", attempt, e);
+                std::thread::sleep(Duration::from_secs(attempt as u64));
+            }
+            Err((e, _)) => return Err(e),
+        }
+    }
+    unreachable!("the last attempt always returns")
+}
+
+/// one request, appending to `part`. the error says whether retrying may
+/// help
+fn download_attempt(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    part: &Path,
+    on_progress: &mut impl FnMut(DownloadProgress),
+) -> Result<(), (BridgeError, bool)> {
+    let io_error = |e: std::io::Error| (BridgeError::Io(e.to_string()), true);
+
+    let offset = std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+    }
+    let mut response = request
+        .send()
+        .map_err(|e| (BridgeError::Download(e.to_string()), true))?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // the part file doesn't match the remote file any more
+        let _ = std::fs::remove_file(part);
+        return Err((BridgeError::Download("Stale partial download".to_string()), true));
+    }
+    if !status.is_success() {
+        let retryable = status.is_server_error();
+        return Err((BridgeError::Download(format!("{} returned {}", url, status)), retryable));
+    }
+
+    let resumed = offset > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut downloaded = if resumed { offset } else { 0 };
+    let total = response.content_length().map(|length| length + downloaded);
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(part)
+        .map_err(io_error)?;
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut reported = downloaded;
+    loop {
+        let read = std::io::Read::read(&mut response, &mut buffer).map_err(io_error)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read]).map_err(io_error)?;
+        downloaded += read as u64;
+
+        if downloaded - reported >= DOWNLOAD_PROGRESS_STEP {
+            reported = downloaded;
+            on_progress(DownloadProgress {
+                url: url.to_string(),
+                downloaded,
+                total,
+            });
+        }
+    }
+    file.flush().map_err(io_error)?;
+
+    on_progress(DownloadProgress {
+        url: url.to_string(),
+        downloaded,
+        total,
+    });
+    match total {
+        Some(total) if downloaded != total => Err((
+            BridgeError::Download(format!("Got {} of {} bytes", downloaded, total)),
+            true,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// filesystem safe name for a url's download, the same in every run and
+/// build: the sha256 of the url and its last path segment
+fn cache_file_name(url: &str) -> String {
+    use sha2::Digest;
+
+    let digest = sha2::Sha256::digest(url.as_bytes());
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let stem = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("dataset");
+    format!("{}_{}", hex, sanitize_file_name(stem))
+}
+
+#[cfg(test)]
+mod download_tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn names_downloads_by_the_sha256_of_the_url() {
+        assert_eq!(
+            cache_file_name("https://example.org/data/sample.h5ad?token=1"),
+            "a41aa5fc08e09f7a72b9e21ca962fab61b30ccfac48bd973691ab49c09d19b1c_sample.h5ad"
+        );
+        assert!(cache_file_name("https://example.org/").ends_with("_dataset"));
+    }
+
+    /// serves `body` slowly to every request, counts the connections
+    fn serve(body: &'static [u8]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sample.h5ad", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&connections);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                counted.fetch_add(1, Ordering::SeqCst);
+                let mut request = Vec::new();
+                let mut byte = [0u8];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    request.push(byte[0]);
+                }
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                for chunk in body.chunks(body.len() / 4) {
+                    std::thread::sleep(Duration::from_millis(50));
+                    stream.write_all(chunk).unwrap();
+                }
+            }
+        });
+        (url, connections)
+    }
+
+    #[test]
+    fn downloads_a_url_once_for_concurrent_callers() {
+        let body: &'static [u8] = &[7u8; 64 * 1024];
+        let (url, connections) = serve(body);
+        let dir = std::env::temp_dir().join(format!("download_tests_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let paths: Vec<PathBuf> = std::thread::scope(|scope| {
+            let downloads: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| download_to_cache(&url, &dir, |_| {}).unwrap()))
+                .collect();
+            downloads.into_iter().map(|download| download.join().unwrap()).collect()
+        });
+
+        assert_eq!(paths[0], paths[1]);
+        assert_eq!(std::fs::read(&paths[0]).unwrap(), body);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
+
+// ===== Dataset Context =====
+
+/// default dataset, image and segmentation for commands that take them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetContext {
+    pub dataset_id: Option<String>,
+    pub img_id: Option<String>,
+    pub seg_id: Option<String>,
+}
+
+impl DatasetContext {
+    /// fill in omitted ids from the context
+    ///
+    /// an explicit id always wins, even when a context is set. an id that
+    /// is neither given nor in the context is an error naming it, python
+    /// is never asked with a missing id
+    fn resolve(
+        &self,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        seg_id: Option<&str>,
+    ) -> Result<(String, String, String), BridgeError> {
+        let (dataset_id, img_id, seg_id) = self.resolve_layers(dataset_id, img_id, seg_id)?;
+        let img_id = img_id.ok_or_else(|| {
+            BridgeError::Protocol("No img_id given and no context set".to_string())
+        })?;
+        Ok((dataset_id, img_id, seg_id))
+    }
+
+    /// `resolve` for commands that only need the dataset
+    fn resolve_dataset(&self, dataset_id: Option<&str>) -> Result<String, BridgeError> {
+        dataset_id
+            .map(str::to_string)
+            .or_else(|| self.dataset_id.clone())
+            .ok_or_else(|| {
+                BridgeError::Protocol("No dataset_id given and no context set".to_string())
+            })
+    }
+
+    /// `resolve` for commands that can do without an image, `None` when
+    /// neither the caller nor the context names one
+    fn resolve_layers(
+        &self,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        seg_id: Option<&str>,
+    ) -> Result<(String, Option<String>, String), BridgeError> {
+        fn pick(
+            name: &str,
+            explicit: Option<&str>,
+            context: &Option<String>,
+        ) -> Result<String, BridgeError> {
+            explicit
+                .map(str::to_string)
+                .or_else(|| context.clone())
+                .ok_or_else(|| {
+                    BridgeError::Protocol(format!("No {} given and no context set", name))
+                })
+        }
+
+        Ok((
+            pick("dataset_id", dataset_id, &self.dataset_id)?,
+            img_id.map(str::to_string).or_else(|| self.img_id.clone()),
+            pick("seg_id", seg_id, &self.seg_id)?,
+        ))
+    }
+}
+
+impl PythonBridge {
+    /// make these ids the default for commands that omit them
+    ///
+    /// replaces any previous context. purely rust side, python isn't told
+    pub fn set_context(&mut self, dataset_id: &str, img_id: &str, seg_id: &str) {
+        self.context = DatasetContext {
+            dataset_id: Some(dataset_id.to_string()),
+            img_id: Some(img_id.to_string()),
+            seg_id: Some(seg_id.to_string()),
+        };
+    }
+
+    pub fn clear_context(&mut self) {
+        self.context = DatasetContext::default();
+    }
+
+    pub fn context(&self) -> &DatasetContext {
+        &self.context
+    }
+}
+
+// ===== Render Contexts =====
+
+/// a plot's setup done once for a dataset, image and segmentation, see
+/// `PythonBridge::open_render_context`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderContext {
+    pub context_id: String,
+    pub dataset_id: String,
+    /// `None` for plots over a generated background
+    pub img_id: Option<String>,
+    pub seg_id: String,
+}
+
+impl RenderContext {
+    /// a plot's ids, those left out taken from the context. an id naming
+    /// other inputs than the context's is refused, python set up only these
+    fn resolve(
+        &self,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        seg_id: Option<&str>,
+    ) -> Result<(String, Option<String>, String), BridgeError> {
+        let check = |name: &str, given: Option<&str>, own: Option<&str>| match given {
+            Some(given) if Some(given) != own => Err(BridgeError::Protocol(format!(
+                "Render context {} is for {} {}, not {}",
+                self.context_id,
+                name,
+                own.unwrap_or("none"),
+                given
+            ))),
+            _ => Ok(()),
+        };
+        check("dataset", dataset_id, Some(&self.dataset_id))?;
+        check("image", img_id, self.img_id.as_deref())?;
+        check("segmentation", seg_id, Some(&self.seg_id))?;
+        Ok((self.dataset_id.clone(), self.img_id.clone(), self.seg_id.clone()))
+    }
+}
+
+/// render contexts opened through this bridge, by id
+#[derive(Default)]
+struct RenderContextRegistry {
+    contexts: HashMap<String, RenderContext>,
+}
+
+impl RenderContextRegistry {
+    fn get(&self, context_id: &str) -> Result<&RenderContext, BridgeError> {
+        self.contexts.get(context_id).ok_or_else(|| {
+            BridgeError::Protocol(format!("Render context {} not found", context_id))
+        })
+    }
+
+    /// an open context for a replot of `params`
+    fn find(&self, params: &PlotParams) -> Option<String> {
+        self.contexts
+            .values()
+            .find(|context| {
+                context.dataset_id == params.dataset_id
+                    && context.img_id == params.img_id
+                    && context.seg_id == params.seg_id
+            })
+            .map(|context| context.context_id.clone())
+    }
+
+    /// python closes a dataset's contexts when unloading it
+    fn forget_dataset(&mut self, dataset_id: &str) {
+        self.contexts.retain(|_, context| context.dataset_id != dataset_id);
+    }
+
+    fn clear(&mut self) {
+        self.contexts.clear();
+    }
+}
+
+impl PythonBridge {
+    /// do the setup of a plot once for plots of several keys on the same
+    /// inputs, pass the returned `context_id` to `plot_tissue_overlay`
+    ///
+    /// python checks the ids, builds the segmentation's index and writes
+    /// the image and segmentation where bin2cell reads them, which is most
+    /// of a plot that isn't rasterizing. plots with the context skip all of
+    /// that, except what a `transform` or `segment_limit` changes: those
+    /// write their own copy of the inputs as without a context. without an
+    /// image the background is written per plot as well. ids left as
+    /// `None` come from the dataset context. there is one context per set
+    /// of ids, opening it again returns the open one. it holds a copy of
+    /// the image on disk until `close_render_context`, unloading its
+    /// dataset or a respawn
+    pub fn open_render_context(
+        &mut self,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        seg_id: Option<&str>,
+    ) -> Result<RenderContext, BridgeError> {
+        let (dataset_id, img_id, seg_id) = self.context.resolve_layers(dataset_id, img_id, seg_id)?;
+        if let Some(ref img_id) = img_id {
+            self.check_image(&dataset_id, img_id)?;
+        }
+        let context: RenderContext = self.send_command_as(
+            PythonCommand::OpenRenderContext,
+            serde_json::json!({
+                "dataset_id": dataset_id,
+                "img_id": img_id,
+                "seg_id": seg_id
+            }),
+        )?;
+        self.render_contexts
+            .contexts
+            .insert(context.context_id.clone(), context.clone());
+        Ok(context)
+    }
+
+    /// free a render context, plots naming it fail afterwards. overlays
+    /// plotted with it stay
+    pub fn close_render_context(&mut self, context_id: &str) -> Result<(), BridgeError> {
+        self.render_contexts.get(context_id)?;
+        self.send_command(
+            PythonCommand::CloseRenderContext,
+            serde_json::json!({ "context_id": context_id }),
+        )?;
+        self.render_contexts.contexts.remove(context_id);
+        Ok(())
+    }
+
+    /// the open render contexts, sorted by id
+    pub fn render_contexts(&self) -> Vec<RenderContext> {
+        let mut contexts: Vec<RenderContext> =
+            self.render_contexts.contexts.values().cloned().collect();
+        contexts.sort_by(|a, b| a.context_id.cmp(&b.context_id));
+        contexts
+    }
+}
+
+// ===== Preprocessing =====
+
+/// one operation of a `preprocess_dataset` pipeline
+///
+/// the steps of a pipeline share a working copy of the dataset's counts,
+/// the dataset's own matrix is never changed, so overlays plotted from it
+/// stay valid. `Leiden`, `GeneScore` and `QcMetrics` add obs columns, which
+/// can be plotted as a `fill_key` like any other. a key the dataset already
+/// has fails the step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PreprocStep {
+    /// scale every cell of the working copy to `target_sum` counts (the
+    /// median cell's by default), then log1p
+    Normalize {
+        #[serde(default)]
+        target_sum: Option<f64>,
+    },
+    /// principal components of the working copy
+    Pca { n_comps: u32 },
+    /// nearest neighbor graph over the pca, needs a `Pca` before it
+    Neighbors { n_neighbors: u32 },
+    /// clusters of the neighbor graph as categorical column `key`, needs
+    /// `Neighbors` before it
+    Leiden { resolution: f64, key: String },
+    /// mean expression of `genes` in the working copy as column `key`
+    GeneScore { genes: Vec<String>, key: String },
+    /// `{prefix}total_counts` and `{prefix}n_genes` from the dataset's own
+    /// counts
+    QcMetrics {
+        #[serde(default)]
+        prefix: String,
+    },
+}
+
+impl PreprocStep {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PreprocStep::Normalize { .. } => "normalize",
+            PreprocStep::Pca { .. } => "pca",
+            PreprocStep::Neighbors { .. } => "neighbors",
+            PreprocStep::Leiden { .. } => "leiden",
+            PreprocStep::GeneScore { .. } => "gene_score",
+            PreprocStep::QcMetrics { .. } => "qc_metrics",
+        }
+    }
+
+    /// the obs columns the step adds
+    fn keys(&self) -> Vec<String> {
+        match self {
+            PreprocStep::Leiden { key, .. } | PreprocStep::GeneScore { key, .. } => {
+                vec![key.clone()]
+            }
+            PreprocStep::QcMetrics { prefix } => {
+                vec![format!("{}total_counts", prefix), format!("{}n_genes", prefix)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// check a pipeline before any of it runs: its parameters, that steps come
+/// after the ones they build on, and that no two add the same key
+fn validate_pipeline(steps: &[PreprocStep]) -> Result<(), BridgeError> {
+    if steps.is_empty() {
+        return Err(BridgeError::Protocol("A preprocessing pipeline needs a step".to_string()));
+    }
+    let invalid = |i: usize, step: &PreprocStep, problem: &str| {
+        BridgeError::Protocol(format!("Preprocessing step {} ({}): {}", i, step.name(), problem))
+    };
+    let positive = |value: f64| value.is_finite() && value > 0.0;
+
+    let mut keys = HashSet::new();
+    let (mut pca, mut neighbors) = (false, false);
+    for (i, step) in steps.iter().enumerate() {
+        match step {
+            PreprocStep::Normalize { target_sum } => {
+                if target_sum.is_some_and(|sum| !positive(sum)) {
+                    return Err(invalid(i, step, "target_sum must be positive"));
+                }
+            }
+            PreprocStep::Pca { n_comps } => {
+                if *n_comps == 0 {
+                    return Err(invalid(i, step, "n_comps must be at least 1"));
+                }
+                pca = true;
+            }
+            PreprocStep::Neighbors { n_neighbors } => {
+                if !pca {
+                    return Err(invalid(i, step, "needs a pca step before it"));
+                }
+                if *n_neighbors < 2 {
+                    return Err(invalid(i, step, "n_neighbors must be at least 2"));
+                }
+                neighbors = true;
+            }
+            PreprocStep::Leiden { resolution, .. } => {
+                if !neighbors {
+                    return Err(invalid(i, step, "needs a neighbors step before it"));
+                }
+                if !positive(*resolution) {
+                    return Err(invalid(i, step, "resolution must be positive"));
+                }
+            }
+            PreprocStep::GeneScore { genes, .. } => {
+                if genes.is_empty() {
+                    return Err(invalid(i, step, "needs at least one gene"));
+                }
+            }
+            PreprocStep::QcMetrics { .. } => {}
+        }
+        for key in step.keys() {
+            if key.is_empty() {
+                return Err(invalid(i, step, "key can't be empty"));
+            }
+            if !keys.insert(key.clone()) {
+                return Err(invalid(i, step, &format!("key {} is added twice", key)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// where a preprocessing pipeline stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreprocessState {
+    Running,
+    Completed,
+    /// stopped between steps, the keys of the finished steps stay
+    Cancelled,
+    /// a step failed, see `PreprocessStatus::error`
+    Failed,
+}
+
+/// progress and result of a preprocessing pipeline
+#[derive(Debug, Clone, Serialize)]
+pub struct PreprocessStatus {
+    pub preprocessing_id: String,
+    pub dataset_id: String,
+    /// steps finished
+    pub done: usize,
+    pub total: usize,
+    /// obs columns added so far, usable as `fill_key`
+    pub keys: Vec<String>,
+    pub state: PreprocessState,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PreprocessedStep {
+    keys: Vec<String>,
+}
+
+impl PythonBridge {
+    /// run `steps` on a dataset, returns the obs columns they added
+    ///
+    /// validated as a whole first, a pipeline with a bad step doesn't
+    /// start. checks `cancel` between steps, a step in progress runs to
+    /// its end. `on_progress` gets the status after every step. python
+    /// keeps the working copy only until the pipeline ends, however it
+    /// ends
+    pub fn preprocess_dataset(
+        &mut self,
+        dataset_id: &str,
+        steps: &[PreprocStep],
+        cancel: &CancellationToken,
+        mut on_progress: impl FnMut(&PreprocessStatus),
+    ) -> Result<PreprocessStatus, BridgeError> {
+        let mut status = self.start_preprocessing(dataset_id, steps)?;
+        let result = steps.iter().try_for_each(|step| {
+            if cancel.is_cancelled() || self.shutdown.is_cancelled() {
+                status.state = PreprocessState::Cancelled;
+                return Ok(());
+            }
+            let keys = self.preprocess_step(&status.preprocessing_id, dataset_id, step)?;
+            status.keys.extend(keys);
+            status.done += 1;
+            on_progress(&status);
+            Ok(())
+        });
+        let _ = self.end_preprocessing(&status.preprocessing_id);
+        result?;
+        if status.state == PreprocessState::Running {
+            status.state = PreprocessState::Completed;
+        }
+        Ok(status)
+    }
+
+    /// validate a pipeline and give it an id, nothing is sent yet
+    fn start_preprocessing(
+        &mut self,
+        dataset_id: &str,
+        steps: &[PreprocStep],
+    ) -> Result<PreprocessStatus, BridgeError> {
+        validate_pipeline(steps)?;
+        self.preprocessings += 1;
+        Ok(PreprocessStatus {
+            preprocessing_id: format!("preprocess-{}", self.preprocessings),
+            dataset_id: dataset_id.to_string(),
+            done: 0,
+            total: steps.len(),
+            keys: Vec::new(),
+            state: PreprocessState::Running,
+            error: None,
+        })
+    }
+
+    /// run one step on the pipeline's working copy, returns the keys it
+    /// added
+    fn preprocess_step(
+        &mut self,
+        preprocessing_id: &str,
+        dataset_id: &str,
+        step: &PreprocStep,
+    ) -> Result<Vec<String>, BridgeError> {
+        let params = serde_json::json!({
+            "preprocessing_id": preprocessing_id,
+            "dataset_id": dataset_id,
+            "step": step
+        });
+        let step: PreprocessedStep = self.send_command_as(PythonCommand::PreprocessStep, params)?;
+        if !step.keys.is_empty() {
+            // cached answers about the dataset predate the new columns
+            self.result_cache.forget_dataset(dataset_id);
+        }
+        Ok(step.keys)
+    }
+
+    /// free python's working copy of a pipeline
+    fn end_preprocessing(&mut self, preprocessing_id: &str) -> Result<(), BridgeError> {
+        let params = serde_json::json!({ "preprocessing_id": preprocessing_id });
+        self.send_command(PythonCommand::EndPreprocessing, params)?;
+        Ok(())
+    }
+}
+
+// ===== Render Defaults =====
+
+/// plot options applied to every plot that doesn't set them itself, see
+/// `PythonBridge::set_render_defaults`
+///
+/// no opacity: overlays are rendered opaque and the viewer sets the layer's
+/// opacity, so a default for it belongs to the frontend
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RenderDefaults {
+    /// colormap of continuous keys, sent as bin2cell's `cont_cmap`
+    #[serde(default)]
+    pub colormap: Option<String>,
+    #[serde(default)]
+    pub quality: Option<RenderQuality>,
+    #[serde(default)]
+    pub background: Option<[u8; 4]>,
+    #[serde(default)]
+    pub scalebar: Option<ScalebarOptions>,
+    #[serde(default)]
+    pub lod: Option<LodOptions>,
+    #[serde(default)]
+    pub orientation: Option<Orientation>,
+    #[serde(default)]
+    pub origin: Option<Origin>,
+    #[serde(default)]
+    pub pyramid_mode: Option<PyramidMode>,
+    #[serde(default)]
+    pub segment_limit: Option<SegmentLimit>,
+    /// defaults for the `extra` passthrough, key by key
+    #[serde(default)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl RenderDefaults {
+    fn validate(&self) -> Result<(), BridgeError> {
+        if let Some(ref scalebar) = self.scalebar {
+            scalebar.validate()?;
+        }
+        if let Some(ref lod) = self.lod {
+            lod.validate()?;
+        }
+        if let Some(ref segment_limit) = self.segment_limit {
+            segment_limit.validate()?;
+        }
+        if self.colormap.is_some() && self.extra.contains_key("cont_cmap") {
+            return Err(BridgeError::Protocol(
+                "Render defaults set the colormap both directly and as extra cont_cmap".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// a plot's `extra` on top of the default one, `None` if both are empty
+    fn extra_for(
+        &self,
+        extra: Option<&serde_json::Map<String, serde_json::Value>>,
+    ) -> Option<serde_json::Map<String, serde_json::Value>> {
+        let mut merged = self.extra.clone();
+        if let Some(ref colormap) = self.colormap {
+            merged.insert("cont_cmap".to_string(), colormap.clone().into());
+        }
+        for (key, value) in extra.into_iter().flatten() {
+            merged.insert(key.clone(), value.clone());
+        }
+        (!merged.is_empty()).then_some(merged)
+    }
+}
+
+impl PythonBridge {
+    /// options every later plot starts from, replacing earlier defaults
+    ///
+    /// lowest precedence: an option a `plot_tissue_overlay` call sets wins,
+    /// and `extra` is merged key by key, the call's keys over the defaults'.
+    /// the context only fills in ids, which defaults don't carry, so the
+    /// order is call, then context for ids, then defaults for the rest.
+    /// plots record the merged options, but a replot from saved params
+    /// (`restore_view_state`, a non-recolorable `recolor_overlay`) still
+    /// picks up defaults for what they left unset. purely rust side, like
+    /// the context
+    pub fn set_render_defaults(&mut self, defaults: RenderDefaults) -> Result<(), BridgeError> {
+        defaults.validate()?;
+        self.render_defaults = defaults;
+        Ok(())
+    }
+
+    pub fn render_defaults(&self) -> &RenderDefaults {
+        &self.render_defaults
+    }
+
+    pub fn reset_render_defaults(&mut self) {
+        self.render_defaults = RenderDefaults::default();
+    }
+}
+
+// ===== Diagnostics =====
+
+const DIAGNOSTICS_COMMANDS: usize = 50;
+const DIAGNOSTICS_ERRORS: usize = 20;
+const DIAGNOSTICS_STDERR_LINES: usize = 100;
+/// params and stderr lines are cut to this, tiles and legends would
+/// otherwise swamp a bug report
+const DIAGNOSTICS_PARAMS_BYTES: usize = 256;
+const DIAGNOSTICS_LINE_BYTES: usize = 512;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandRecord {
+    pub command: String,
+    /// the params sent, truncated
+    pub params: String,
+    pub duration_ms: f64,
+    pub ok: bool,
+    /// unix time in milliseconds
+    pub at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorRecord {
+    pub command: String,
+    pub error: String,
+    pub at_ms: u64,
+}
+
+/// everything `get_diagnostics` returns, oldest entries first
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsReport {
+    pub commands: Vec<CommandRecord>,
+    pub errors: Vec<ErrorRecord>,
+    pub stderr_tail: Vec<String>,
+}
+
+#[derive(Default)]
+struct DiagnosticsLog {
+    commands: VecDeque<CommandRecord>,
+    errors: VecDeque<ErrorRecord>,
+    stderr: VecDeque<String>,
+    wire: HashMap<PythonCommand, WireTotals>,
+    traces: VecDeque<RequestTrace>,
+}
+
+/// where the time of one response went, see `WireMetrics`
+#[derive(Debug, Clone, Copy, Default)]
+struct WireTiming {
+    serialize: Duration,
+    /// python's own figure for its handler
+    compute: Duration,
+    /// the rest of the round trip
+    ipc: Duration,
+    parse: Duration,
+    /// of the response line
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct WireTotals {
+    responses: u64,
+    decodes: u64,
+    bytes: u64,
+    max_bytes: u64,
+    serialize: Duration,
+    compute: Duration,
+    ipc: Duration,
+    parse: Duration,
+    decode: Duration,
+}
+
+/// where the time of one command's responses goes, see
+/// `PythonBridge::wire_metrics`. times are means per response in
+/// milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct WireMetrics {
+    pub command: String,
+    /// successful responses timed
+    pub responses: u64,
+    pub mean_bytes: f64,
+    pub max_bytes: u64,
+    /// writing the request as json
+    pub serialize_ms: f64,
+    /// python running the handler, 0 from older scripts
+    pub compute_ms: f64,
+    /// the rest of the round trip: python encoding its answer, the pipe,
+    /// and the wait for the waiter thread to pick it up
+    pub ipc_ms: f64,
+    /// parsing the response line, for a direct decode only the envelope
+    pub parse_ms: f64,
+    /// turning `data` into the caller's type, for a direct decode this is
+    /// where the data gets parsed. over the responses that were decoded,
+    /// some callers keep the raw json
+    pub decode_ms: f64,
+}
+
+fn mean_ms(total: Duration, count: u64) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    total.as_secs_f64() * 1000.0 / count as f64
+}
+
+/// bounded ring buffers of recent bridge activity
+///
+/// shared with the stderr forwarding thread, and outlives respawns so the
+/// lines leading up to a crash are still there afterwards
+#[derive(Default)]
+pub struct Diagnostics {
+    log: Mutex<DiagnosticsLog>,
+    /// see `PythonBridge::set_request_tracing`
+    tracing: AtomicBool,
+    last_trace_id: AtomicU64,
+}
+
+impl Diagnostics {
+    fn record_command<T>(
+        &self,
+        command: PythonCommand,
+        params: String,
+        duration: Duration,
+        result: &Result<T, BridgeError>,
+    ) {
+        let at_ms = unix_millis();
+        let mut log = self.log.lock().unwrap();
+
+        push_bounded(
+            &mut log.commands,
+            CommandRecord {
+                command: command.name().to_string(),
+                params,
+                duration_ms: duration.as_secs_f64() * 1000.0,
+                ok: result.is_ok(),
+                at_ms,
+            },
+            DIAGNOSTICS_COMMANDS,
+        );
+        if let Err(e) = result {
+            push_bounded(
+                &mut log.errors,
+                ErrorRecord {
+                    command: command.name().to_string(),
+                    error: truncate_preview(e.to_string(), DIAGNOSTICS_LINE_BYTES),
+                    at_ms,
+                },
+                DIAGNOSTICS_ERRORS,
+            );
+        }
+    }
+
+    fn record_wire(&self, command: PythonCommand, timing: &WireTiming) {
+        let mut log = self.log.lock().unwrap();
+        let totals = log.wire.entry(command).or_default();
+        totals.responses += 1;
+        totals.bytes += timing.bytes as u64;
+        totals.max_bytes = totals.max_bytes.max(timing.bytes as u64);
+        totals.serialize += timing.serialize;
+        totals.compute += timing.compute;
+        totals.ipc += timing.ipc;
+        totals.parse += timing.parse;
+    }
+
+    fn record_decode(&self, command: PythonCommand, took: Duration) {
+        let mut log = self.log.lock().unwrap();
+        let totals = log.wire.entry(command).or_default();
+        totals.decodes += 1;
+        totals.decode += took;
+    }
+
+    /// per command, in `PythonCommand::ALL` order, commands with no
+    /// response yet left out
+    pub fn wire_metrics(&self) -> Vec<WireMetrics> {
+        let log = self.log.lock().unwrap();
+        PythonCommand::ALL
+            .iter()
+            .filter_map(|command| {
+                let totals = log.wire.get(command)?;
+                let n = totals.responses;
+                Some(WireMetrics {
+                    command: command.name().to_string(),
+                    responses: n,
+                    mean_bytes: if n > 0 { totals.bytes as f64 / n as f64 } else { 0.0 },
+                    max_bytes: totals.max_bytes,
+                    serialize_ms: mean_ms(totals.serialize, n),
+                    compute_ms: mean_ms(totals.compute, n),
+                    ipc_ms: mean_ms(totals.ipc, n),
+                    parse_ms: mean_ms(totals.parse, n),
+                    decode_ms: mean_ms(totals.decode, totals.decodes),
+                })
+            })
+            .collect()
+    }
+
+    fn record_stderr(&self, line: String) {
+        let line = truncate_preview(line, DIAGNOSTICS_LINE_BYTES);
+        push_bounded(&mut self.log.lock().unwrap().stderr, line, DIAGNOSTICS_STDERR_LINES);
+    }
+
+    pub fn report(&self) -> DiagnosticsReport {
+        let log = self.log.lock().unwrap();
+        DiagnosticsReport {
+            commands: log.commands.iter().cloned().collect(),
+            errors: log.errors.iter().cloned().collect(),
+            stderr_tail: log.stderr.iter().cloned().collect(),
+        }
+    }
+}
+
+fn push_bounded<T>(buffer: &mut VecDeque<T>, item: T, cap: usize) {
+    if buffer.len() == cap {
+        buffer.pop_front();
+    }
+    buffer.push_back(item);
+}
+
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// `value` as JSON, cut to `max_bytes`
+fn preview_json(value: &serde_json::Value, max_bytes: usize) -> String {
+    truncate_preview(serde_json::to_string(value).unwrap_or_default(), max_bytes)
+}
+
+/// cut `text` to at most `max_bytes` on a char boundary, noting the
+/// original size
+fn truncate_preview(mut text: String, max_bytes: usize) -> String {
+    if text.len() > max_bytes {
+        let mut end = max_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let total = text.len();
+        text.truncate(end);
+        text.push_str(&format!("... ({} bytes total)", total));
+    }
+    text
+}
+
+impl PythonBridge {
+    pub fn get_diagnostics(&self) -> DiagnosticsReport {
+        self.diagnostics.report()
+    }
+
+    /// the buffers themselves, for reading while the bridge is busy
+    pub fn diagnostics_handle(&self) -> Arc<Diagnostics> {
+        Arc::clone(&self.diagnostics)
+    }
+
+    /// how long each command's responses spent being serialized, computed
+    /// in python, on the pipe, parsed and decoded, since the bridge was
+    /// built
+    ///
+    /// for finding the commands whose json costs more than their work,
+    /// candidates for `PythonBridgeBuilder::direct_decode`. survives
+    /// respawns like the rest of the diagnostics
+    pub fn wire_metrics(&self) -> Vec<WireMetrics> {
+        self.diagnostics.wire_metrics()
+    }
+}
+
+// ===== Request Tracing =====
+
+/// traced requests kept for `get_request_traces`
+const MAX_REQUEST_TRACES: usize = 200;
+
+/// a stage python timed within its handler: "load", "render" or "encode"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PythonSpan {
+    pub name: String,
+    /// from the start of the handler, the two sides share no clock
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+/// where the time of one traced request went on both sides of the pipe
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestTrace {
+    /// sent with the request, python's log records carry it too
+    pub trace_id: u64,
+    pub command: String,
+    /// unix time in milliseconds
+    pub at_ms: u64,
+    /// the whole request on our side, waiting for a concurrency permit and
+    /// respawns included
+    pub total_ms: f64,
+    pub serialize_ms: f64,
+    /// python's handler, `spans` break it down
+    pub python_ms: f64,
+    /// the rest of the round trip, see `WireMetrics::ipc_ms`
+    pub ipc_ms: f64,
+    pub parse_ms: f64,
+    /// empty from handlers (and older scripts) that time no stages
+    pub spans: Vec<PythonSpan>,
+}
+
+impl RequestTrace {
+    fn new(
+        trace_id: u64,
+        command: PythonCommand,
+        at_ms: u64,
+        started: Instant,
+        reply: &Reply,
+    ) -> Self {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        RequestTrace {
+            trace_id,
+            command: command.name().to_string(),
+            at_ms,
+            total_ms: ms(started.elapsed()),
+            serialize_ms: ms(reply.timing.serialize),
+            python_ms: ms(reply.timing.compute),
+            ipc_ms: ms(reply.timing.ipc),
+            parse_ms: ms(reply.timing.parse),
+            spans: reply.spans.clone(),
+        }
+    }
+}
+
+impl Diagnostics {
+    /// an id for the next request while tracing is on
+    fn next_trace_id(&self) -> Option<u64> {
+        self.tracing
+            .load(Ordering::Relaxed)
+            .then(|| self.last_trace_id.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    pub fn set_request_tracing(&self, enabled: bool) {
+        self.tracing.store(enabled, Ordering::Relaxed);
+    }
+
+    fn record_trace(&self, trace: RequestTrace) {
+        push_bounded(&mut self.log.lock().unwrap().traces, trace, MAX_REQUEST_TRACES);
+    }
+
+    /// the wire timings of an answered request, and its trace if it has one
+    fn record_reply(
+        &self,
+        command: PythonCommand,
+        dispatch: &Dispatch,
+        at_ms: u64,
+        started: Instant,
+        reply: &Reply,
+    ) {
+        self.record_wire(command, &reply.timing);
+        if let Some(trace_id) = dispatch.trace_id {
+            let trace = RequestTrace::new(trace_id, command, at_ms, started, reply);
+            bridge_debug!(
+                "PythonBridge: Trace {} {}: python {:.1} ms of {:.1} ms",
+                trace_id,
+                command.name(),
+                trace.python_ms,
+                trace.total_ms
+            );
+            self.record_trace(trace);
+        }
+    }
+
+    /// oldest first
+    pub fn request_traces(&self) -> Vec<RequestTrace> {
+        self.log.lock().unwrap().traces.iter().cloned().collect()
+    }
+}
+
+impl PythonBridge {
+    /// trace every request from now on, for working out where a slow
+    /// command's time goes
+    ///
+    /// each request carries a trace id. python times its handler's stages
+    /// (load, render, encode) under it and tags its log records with it,
+    /// our side adds serialization, the pipe and parsing. the latest
+    /// `MAX_REQUEST_TRACES` answered requests are kept, see
+    /// `get_request_traces`. off by default, the spans cost python a few
+    /// timer calls and every response a few bytes
+    pub fn set_request_tracing(&self, enabled: bool) {
+        self.diagnostics.set_request_tracing(enabled);
+    }
+
+    /// traced requests, oldest first. survives respawns like the rest of
+    /// the diagnostics
+    pub fn get_request_traces(&self) -> Vec<RequestTrace> {
+        self.diagnostics.request_traces()
+    }
+}
+
+// ===== Crash Report =====
+
+/// how long each python query of a crash report may take, a stuck python
+/// is a likely reason for the report
+const CRASH_REPORT_PYTHON_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// everything support asks for in one document, see `capture_crash_report`
+///
+/// sections that couldn't be gathered are `None` or empty and say why in
+/// `failures`. the user's home directory is replaced with `~` in paths,
+/// python's stderr and error messages
+#[derive(Debug, Clone, Serialize)]
+pub struct CrashReport {
+    /// unix time in milliseconds
+    pub captured_at_ms: u64,
+    pub status: BridgeStatus,
+    pub config: Option<BridgeConfig>,
+    pub command_metrics: Vec<CommandMetrics>,
+    pub lane_metrics: Vec<LaneMetrics>,
+    /// recent commands, errors and the stderr tail
+    pub diagnostics: Option<DiagnosticsReport>,
+    pub environment: Option<EnvironmentInfo>,
+    pub datasets: Option<Vec<DatasetSummary>>,
+    /// overlays plotted through the bridge, sorted by id
+    pub overlays: Vec<OverlayInfo>,
+    /// "section: reason" for everything left out
+    pub failures: Vec<String>,
+}
+
+impl CrashReport {
+    /// the parts that don't need the bridge itself
+    fn new(
+        status: BridgeStatus,
+        gates: Option<&CommandGates>,
+        diagnostics: Option<&Diagnostics>,
+    ) -> Self {
+        CrashReport {
+            captured_at_ms: unix_millis(),
+            status,
+            config: None,
+            command_metrics: gates.map(|gates| gates.metrics()).unwrap_or_default(),
+            lane_metrics: gates.map(|gates| gates.lane_metrics()).unwrap_or_default(),
+            diagnostics: diagnostics.map(|diagnostics| diagnostics.report()),
+            environment: None,
+            datasets: None,
+            overlays: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// replace the home directory with `~` wherever a path is likely to show
+    fn redact_paths(&mut self) {
+        let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))
+        else {
+            return;
+        };
+        let home = home.to_string_lossy().into_owned();
+        if home.is_empty() || home == "/" {
+            return;
+        }
+        let redact = |text: &mut String| {
+            if text.contains(&home) {
+                *text = text.replace(&home, "~");
+            }
+        };
+
+        if let Some(config) = &mut self.config {
+            for path in [&mut config.python_path, &mut config.script_path] {
+                let mut text = path.to_string_lossy().into_owned();
+                redact(&mut text);
+                *path = PathBuf::from(text);
+            }
+            config.python_env.values_mut().chain(config.env.values_mut()).for_each(redact);
+        }
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.commands.iter_mut().for_each(|record| redact(&mut record.params));
+            diagnostics.errors.iter_mut().for_each(|record| redact(&mut record.error));
+            diagnostics.stderr_tail.iter_mut().for_each(redact);
+        }
+        if let BridgeStatus::Failed { error } = &mut self.status {
+            redact(error);
+        }
+        self.failures.iter_mut().for_each(redact);
+    }
+}
+
+impl PythonBridge {
+    /// snapshot the bridge for a bug report, as complete as it can get
+    ///
+    /// never fails. python is asked for its environment and datasets with
+    /// a short deadline, and not at all once it has died, so making the
+    /// report doesn't respawn it. a failed query leaves its section out
+    /// and skips the rest, a timeout may have killed the child
+    pub fn capture_crash_report(&mut self) -> CrashReport {
+        let mut report =
+            CrashReport::new(self.status(), Some(&self.gates), Some(&self.diagnostics));
+        report.config = Some(self.get_bridge_config());
+        report.overlays = self.overlays.overlays.values().map(|entry| entry.info.clone()).collect();
+        report.overlays.sort_by(|a, b| a.overlay_id.cmp(&b.overlay_id));
+
+        if matches!(report.status, BridgeStatus::Dead | BridgeStatus::Idle) {
+            report.failures.push("python: not running, left alone for the report".to_string());
+        } else {
+            self.with_timeout(Some(CRASH_REPORT_PYTHON_TIMEOUT), |bridge| {
+                match bridge.get_environment_info(true) {
+                    Ok(info) => report.environment = Some(info),
+                    Err(e) => {
+                        report.failures.push(format!("environment: {}", e));
+                        report.failures.push("datasets: skipped, python failed".to_string());
+                        return;
+                    }
+                }
+                match bridge.list_datasets() {
+                    Ok(datasets) => report.datasets = Some(datasets),
+                    Err(e) => report.failures.push(format!("datasets: {}", e)),
+                }
+            });
+        }
+
+        report.redact_paths();
+        report
+    }
+}
+
+// ===== Benchmark =====
+
+/// most round trips one `benchmark` makes, more are capped
+pub const MAX_BENCHMARK_ITERATIONS: u32 = 1000;
+
+/// largest payload `benchmark` sends, larger sizes are capped. well past
+/// a full resolution tile, the biggest thing the bridge normally moves
+pub const MAX_BENCHMARK_PAYLOAD: usize = 16 * 1024 * 1024;
+
+/// how the bridge performed in `PythonBridge::benchmark`
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkResult {
+    /// round trips timed, after capping
+    pub iterations: u32,
+    /// bytes sent each way per round trip, after capping
+    pub payload_size: usize,
+    pub rtt_p50_ms: f64,
+    pub rtt_p95_ms: f64,
+    /// payload megabits per second over all timed round trips, both
+    /// directions counted
+    pub throughput_mbps: f64,
+}
+
+#[derive(Deserialize)]
+struct EchoResponse {
+    payload: String,
+}
+
+/// nearest rank percentile of ascending `sorted`, 0 for an empty slice
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl PythonBridge {
+    /// time `iterations` echo round trips of `payload_size` bytes each
+    ///
+    /// the payload goes through the same json lines pipe, lane and gates
+    /// as any command, while python does nothing but send it back. a slow
+    /// disk, a virus scanner on the pipe or a remote transport shows here
+    /// and not as slow rendering. one untimed call first keeps python's
+    /// startup out of the numbers. `iterations` is capped at
+    /// `MAX_BENCHMARK_ITERATIONS` (and at least 1), `payload_size` at
+    /// `MAX_BENCHMARK_PAYLOAD`
+    pub fn benchmark(
+        &mut self,
+        iterations: u32,
+        payload_size: usize,
+    ) -> Result<BenchmarkResult, BridgeError> {
+        let iterations = iterations.clamp(1, MAX_BENCHMARK_ITERATIONS);
+        let payload_size = payload_size.min(MAX_BENCHMARK_PAYLOAD);
+        // plain ascii, json passes it through without escaping. not one
+        // repeated byte, so a reordered or dropped chunk shows
+        let payload: String = (b'a'..=b'z').cycle().take(payload_size).map(char::from).collect();
+        let params = serde_json::json!({ "payload": payload });
+
+        self.send_command(PythonCommand::Echo, params.clone())?;
+
+        let mut rtts = Vec::with_capacity(iterations as usize);
+        let started = Instant::now();
+        for _ in 0..iterations {
+            let sent = Instant::now();
+            let echoed: EchoResponse = self.send_command_as(PythonCommand::Echo, params.clone())?;
+            rtts.push(sent.elapsed().as_secs_f64() * 1000.0);
+            if echoed.payload != payload {
+                return Err(BridgeError::Protocol(format!(
+                    "Echo returned {} bytes that differ from the {} sent",
+                    echoed.payload.len(),
+                    payload_size
+                )));
+            }
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        rtts.sort_by(f64::total_cmp);
+        let bits = 2.0 * 8.0 * payload_size as f64 * f64::from(iterations);
+        Ok(BenchmarkResult {
+            iterations,
+            payload_size,
+            rtt_p50_ms: percentile(&rtts, 50.0),
+            rtt_p95_ms: percentile(&rtts, 95.0),
+            throughput_mbps: if elapsed > 0.0 { bits / elapsed / 1e6 } else { 0.0 },
+        })
+    }
+}
+
+/// most rows `benchmark_decode` builds, more are capped
+pub const MAX_DECODE_BENCHMARK_ROWS: usize = 1_000_000;
+
+/// rounds `benchmark_decode` times each path for, the median is reported
+const DECODE_BENCHMARK_ROUNDS: usize = 5;
+
+/// what `benchmark_decode` measured
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodeBenchmark {
+    /// segments in the table, after capping
+    pub rows: usize,
+    /// size of the response line
+    pub bytes: usize,
+    /// median time to parse the line and decode the page through a
+    /// `serde_json::Value`
+    pub value_ms: f64,
+    /// the same for a direct decode
+    pub direct_ms: f64,
+    /// `value_ms / direct_ms`
+    pub speedup: f64,
+}
+
+/// time decoding a `query_segments_in_bbox` style response of `rows`
+/// segments both ways, see `PythonBridgeBuilder::direct_decode`
+///
+/// runs in rust alone on a made up segment table, python isn't involved.
+/// rows alternate numeric and categorical fill values. `rows` is capped at
+/// `MAX_DECODE_BENCHMARK_ROWS` (and at least 1)
+pub fn benchmark_decode(rows: usize) -> Result<DecodeBenchmark, BridgeError> {
+    use std::fmt::Write as _;
+
+    let rows = rows.clamp(1, MAX_DECODE_BENCHMARK_ROWS);
+    let mut line = String::from(r#"{"id":1,"success":true,"data":{"items":["#);
+    for i in 0..rows {
+        let (x, y) = ((i % 1000) * 40, (i / 1000) * 40);
+        let value = if i % 2 == 0 {
+            format!("{}", i as f64 * 0.25)
+        } else {
+            format!(r#""type_{}""#, i % 17)
+        };
+        let _ = write!(
+            line,
+            r#"{}{{"segment_id":"{}","bbox":[{},{},{},{}],"pixel_count":{},"value":{}}}"#,
+            if i == 0 { "" } else { "," },
+            i + 1,
+            x,
+            y,
+            x + 32,
+            y + 32,
+            700 + i % 300,
+            value
+        );
+    }
+    let _ = write!(line, r#"],"total":{},"next_page":null}},"error":null}}"#, rows);
+    let command = PythonCommand::QuerySegmentsInBbox;
+
+    let timed = |direct: bool| -> Result<f64, BridgeError> {
+        let mut times = Vec::with_capacity(DECODE_BENCHMARK_ROUNDS);
+        for _ in 0..DECODE_BENCHMARK_ROUNDS {
+            let started = Instant::now();
+            let page: Page<SegmentInfo> = parse_reply(line.clone(), direct)?.body.decode(command)?;
+            times.push(started.elapsed().as_secs_f64() * 1000.0);
+            if page.items.len() != rows {
+                return Err(BridgeError::Protocol(format!(
+                    "Decoded {} of {} segments",
+                    page.items.len(),
+                    rows
+                )));
+            }
+        }
+        times.sort_by(f64::total_cmp);
+        Ok(percentile(&times, 50.0))
+    };
+    let value_ms = timed(false)?;
+    let direct_ms = timed(true)?;
+
+    Ok(DecodeBenchmark {
+        rows,
+        bytes: line.len(),
+        value_ms,
+        direct_ms,
+        speedup: if direct_ms > 0.0 { value_ms / direct_ms } else { 0.0 },
+    })
+}
+
+// ===== Segments =====
+
+/// how ids that may not fit a javascript number reach the frontend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdEncoding {
+    /// decimal strings. segmentation labels can exceed 2^53, past which
+    /// javascript silently rounds numbers
+    #[default]
+    String,
+    /// plain JSON numbers, only safe while every id is below 2^53
+    Number,
+}
+
+/// a segmentation label
+///
+/// python sends it as a string (older helpers as a number, both parse)
+/// and it is kept as a `u64` on the rust side. towards the frontend it is
+/// serialized according to the bridge's `IdEncoding`
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentId {
+    pub value: u64,
+    encoding: IdEncoding,
+}
+
+impl SegmentId {
+    pub fn new(value: u64, encoding: IdEncoding) -> Self {
+        Self { value, encoding }
+    }
+}
+
+impl PartialEq for SegmentId {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for SegmentId {}
+
+impl Serialize for SegmentId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.encoding {
+            IdEncoding::String => serializer.serialize_str(&self.value.to_string()),
+            IdEncoding::Number => serializer.serialize_u64(self.value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SegmentId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawId {
+            Number(u64),
+            Text(String),
+        }
+
+        let value = match RawId::deserialize(deserializer)? {
+            RawId::Number(value) => value,
+            RawId::Text(text) => text.parse().map_err(|_| {
+                serde::de::Error::custom(format!("invalid segment id {:?}", text))
+            })?,
+        };
+        Ok(SegmentId::new(value, IdEncoding::default()))
+    }
+}
+
+/// a segment under a pixel, see `get_segment_at`
+///
+/// `segment_id` is the only string-encoded field by default,
+/// `pixel_count` and the bbox are bounded by the image size
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentInfo {
+    pub segment_id: SegmentId,
+    /// `[x0, y0, x1, y1]` in full resolution pixels, end exclusive
+    pub bbox: [u32; 4],
+    pub pixel_count: u64,
+    /// the overlay's fill value for this cell, `None` if the cell isn't in
+    /// the dataset
+    pub value: Option<serde_json::Value>,
+    /// pixels from the queried point to the centroid, only set by
+    /// `nearest_segments`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+}
+
+/// most segments `nearest_segments` returns, larger `k` are capped
+pub const MAX_NEAREST_SEGMENTS: usize = 100;
+
+/// side in full resolution pixels of the squares `hover_probe` answers for
+pub const HOVER_CELL_PX: u32 = 4;
+
+/// grid in full resolution pixels `get_neighborhood_summary` snaps the
+/// point and radius to, so nearby queries share a cached answer
+pub const NEIGHBORHOOD_STEP_PX: f64 = 8.0;
+
+/// the segments around a point, see `get_neighborhood_summary`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NeighborhoodSummary {
+    /// segments whose centroid is within `radius`
+    pub n_segments: u64,
+    /// of those, how many per category in legend order, categories none of
+    /// them has left out. empty for gene and numeric fills
+    pub category_composition: Vec<(String, u64)>,
+    /// mean fill value of those in the dataset, `None` for categorical
+    /// fills or when none is
+    pub mean_fill: Option<f64>,
+    /// the point and radius actually queried, snapped and capped
+    #[serde(default)]
+    pub x: f64,
+    #[serde(default)]
+    pub y: f64,
+    #[serde(default)]
+    pub radius: f64,
+}
+
+/// `SegmentInfo` cut down to what a hover tooltip shows
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentSummary {
+    pub segment_id: SegmentId,
+    /// `None` if the cell isn't in the dataset
+    pub value: Option<serde_json::Value>,
+}
+
+/// recent `hover_probe` answers by overlay generation and hover cell,
+/// background (`None`) included. oldest dropped first past `MAX_ENTRIES`
+#[derive(Default)]
+struct HoverCache {
+    entries: HashMap<(String, u64, u32, u32), Option<SegmentSummary>>,
+    order: VecDeque<(String, u64, u32, u32)>,
+}
+
+impl HoverCache {
+    /// a few screens of hovering
+    const MAX_ENTRIES: usize = 4096;
+
+    fn get(&self, key: &(String, u64, u32, u32)) -> Option<Option<SegmentSummary>> {
+        self.entries.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: (String, u64, u32, u32), summary: Option<SegmentSummary>) {
+        if self.entries.insert(key.clone(), summary).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > Self::MAX_ENTRIES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+impl PythonBridge {
+    /// the segment under a full resolution pixel, `None` over background
+    pub fn get_segment_at(
+        &mut self,
+        overlay_id: &str,
+        x: u32,
+        y: u32,
+    ) -> Result<Option<SegmentInfo>, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        if x >= info.width || y >= info.height {
+            return Ok(None);
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "x": x,
+            "y": y
+        });
+        let segment: Option<SegmentInfo> =
+            self.send_command_as(PythonCommand::GetSegmentAt, params)?;
+        Ok(segment.map(|mut segment| {
+            segment.segment_id.encoding = self.id_encoding;
+            segment
+        }))
+    }
+
+    /// the segment id and fill value under the mouse, the cheap
+    /// `get_segment_at` for every mousemove
+    ///
+    /// coordinates are quantized to `HOVER_CELL_PX` squares: the answer is
+    /// the segment under the center pixel of the square containing `x, y`
+    /// and is cached for the whole square, so moving within a few pixels
+    /// never reaches python. near a segment's edge it may name the
+    /// neighbour (or background) up to half a square away, below what a
+    /// pointer resolves once zoomed out. cached per overlay generation
+    /// until `clear_caches`. for debouncing see `createHoverProbe` in the
+    /// frontend api
+    pub fn hover_probe(
+        &mut self,
+        overlay_id: &str,
+        x: u32,
+        y: u32,
+    ) -> Result<Option<SegmentSummary>, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        if x >= info.width || y >= info.height {
+            return Ok(None);
+        }
+        let (cell_x, cell_y) = (x / HOVER_CELL_PX, y / HOVER_CELL_PX);
+        // the center pixel, pulled back inside the image on edge squares
+        let center = |cell: u32, size: u32| (cell * HOVER_CELL_PX + HOVER_CELL_PX / 2).min(size - 1);
+        let (probe_x, probe_y) = (center(cell_x, info.width), center(cell_y, info.height));
+
+        let generation = self.tiles.generation(overlay_id);
+        let key = (overlay_id.to_string(), generation, cell_x, cell_y);
+        if let Some(summary) = self.hover_cache.get(&key) {
+            return Ok(summary);
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "x": probe_x,
+            "y": probe_y
+        });
+        let summary: Option<SegmentSummary> =
+            self.send_command_as(PythonCommand::HoverProbe, params)?;
+        let summary = summary.map(|mut summary| {
+            summary.segment_id.encoding = self.id_encoding;
+            summary
+        });
+        self.hover_cache.insert(key, summary.clone());
+        Ok(summary)
+    }
+
+    /// up to `k` segments closest to a full resolution point, nearest first
+    ///
+    /// distance is measured to each segment's centroid, so the point
+    /// doesn't have to be on a segment or even inside the image. `k` is
+    /// capped at `MAX_NEAREST_SEGMENTS`, an overlay without segments yields
+    /// an empty list
+    pub fn nearest_segments(
+        &mut self,
+        overlay_id: &str,
+        x: f64,
+        y: f64,
+        k: usize,
+    ) -> Result<Vec<SegmentInfo>, BridgeError> {
+        if self.overlays.info(overlay_id).is_none() {
+            return Err(BridgeError::OverlayNotFound(overlay_id.to_string()));
+        }
+        let k = k.min(MAX_NEAREST_SEGMENTS);
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "x": x,
+            "y": y,
+            "k": k
+        });
+        let mut segments: Vec<SegmentInfo> =
+            self.send_command_as(PythonCommand::NearestSegments, params)?;
+        for segment in &mut segments {
+            segment.segment_id.encoding = self.id_encoding;
+        }
+        segments.truncate(k);
+        Ok(segments)
+    }
+
+    /// how many segments lie within `radius` full resolution pixels of a
+    /// point and what they are filled with, e.g. for a squidpy-style
+    /// neighborhood readout next to the cursor
+    ///
+    /// segments count by their centroid like for `nearest_segments`. the
+    /// point and radius are snapped to `NEIGHBORHOOD_STEP_PX` (the radius
+    /// rounded up) and the radius is capped at the overlay's diagonal,
+    /// beyond which nothing more falls in. a radius with no segments
+    /// yields a zeroed summary. cached per overlay generation like the
+    /// other read-only queries
+    pub fn get_neighborhood_summary(
+        &mut self,
+        overlay_id: &str,
+        x: f64,
+        y: f64,
+        radius: f64,
+    ) -> Result<NeighborhoodSummary, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        if !(x.is_finite() && y.is_finite() && radius.is_finite() && radius >= 0.0) {
+            return Err(BridgeError::Protocol(format!(
+                "Neighborhood at ({}, {}) radius {} must be finite with a radius of at least 0",
+                x, y, radius
+            )));
+        }
+        let diagonal = f64::from(info.width).hypot(f64::from(info.height));
+        let snap = |v: f64| (v / NEIGHBORHOOD_STEP_PX).round() * NEIGHBORHOOD_STEP_PX;
+        let (x, y) = (snap(x), snap(y));
+        let radius = ((radius / NEIGHBORHOOD_STEP_PX).ceil() * NEIGHBORHOOD_STEP_PX).min(diagonal);
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "x": x,
+            "y": y,
+            "radius": radius
+        });
+        let summary: NeighborhoodSummary =
+            self.send_cached_as(PythonCommand::GetNeighborhoodSummary, params, false)?;
+        Ok(NeighborhoodSummary {
+            x,
+            y,
+            radius,
+            ..summary
+        })
+    }
+
+    /// one page of the segments whose bbox overlaps `bbox` (`[x0, y0, x1,
+    /// y1]` in full resolution pixels, end exclusive)
+    ///
+    /// ordered by segment id, so the pages of one query line up across
+    /// calls while the segmentation stays loaded. `page` is 0 based,
+    /// `page_size` is clamped to 1..=`MAX_PAGE_SIZE`. a page past the end
+    /// is empty. see `segments_in_bbox` to walk every page
+    pub fn query_segments_in_bbox(
+        &mut self,
+        overlay_id: &str,
+        bbox: [u32; 4],
+        page: u32,
+        page_size: u32,
+    ) -> Result<Page<SegmentInfo>, BridgeError> {
+        if self.overlays.info(overlay_id).is_none() {
+            return Err(BridgeError::OverlayNotFound(overlay_id.to_string()));
+        }
+        let [x0, y0, x1, y1] = bbox;
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "x0": x0,
+            "y0": y0,
+            "x1": x1,
+            "y1": y1,
+            "page": page,
+            "page_size": page_size.clamp(1, MAX_PAGE_SIZE)
+        });
+        let mut page: Page<SegmentInfo> =
+            self.send_command_as(PythonCommand::QuerySegmentsInBbox, params)?;
+        for segment in &mut page.items {
+            segment.segment_id.encoding = self.id_encoding;
+        }
+        Ok(page)
+    }
+
+    /// every page of `query_segments_in_bbox`, fetched one at a time as
+    /// the iterator is advanced
+    ///
+    /// only a page is held at once, a caller can render each before asking
+    /// for the next. stops after the last page or the first error
+    pub fn segments_in_bbox(
+        &mut self,
+        overlay_id: &str,
+        bbox: [u32; 4],
+        page_size: u32,
+    ) -> SegmentPages<'_> {
+        SegmentPages {
+            bridge: self,
+            overlay_id: overlay_id.to_string(),
+            bbox,
+            page_size,
+            next_page: Some(0),
+        }
+    }
+}
+
+/// most items a page holds, larger page sizes are clamped
+pub const MAX_PAGE_SIZE: u32 = 5000;
+
+/// one page of a long result, see `query_segments_in_bbox`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// items over all pages
+    pub total: u64,
+    /// `None` on the last page
+    pub next_page: Option<u32>,
+}
+
+/// see `PythonBridge::segments_in_bbox`
+pub struct SegmentPages<'a> {
+    bridge: &'a mut PythonBridge,
+    overlay_id: String,
+    bbox: [u32; 4],
+    page_size: u32,
+    next_page: Option<u32>,
+}
+
+impl Iterator for SegmentPages<'_> {
+    type Item = Result<Vec<SegmentInfo>, BridgeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let page = self.next_page.take()?;
+        let result = self.bridge.query_segments_in_bbox(
+            &self.overlay_id,
+            self.bbox,
+            page,
+            self.page_size,
+        );
+        Some(result.map(|page| {
+            self.next_page = page.next_page;
+            page.items
+        }))
+    }
+}
+
+// ===== Plot Validation =====
+
+/// one problem `validate_plot_args` found, for an inline field error
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationError {
+    /// the argument at fault: `dataset_id`, `img_id`, `seg_id`, `fill_key`
+    /// or `border_key`
+    pub field: String,
+    pub message: String,
+}
+
+impl PythonBridge {
+    /// check plot arguments against what python has loaded, without
+    /// rendering anything
+    ///
+    /// ids left as `None` come from the context like for
+    /// `plot_tissue_overlay`. every problem found is returned at once in
+    /// `InvalidPlotArgs`, so the UI can mark each field and keep the plot
+    /// button disabled until they're fixed
+    pub fn validate_plot_args(
+        &mut self,
+        dataset_id: Option<&str>,
+        img_id: Option<&str>,
+        seg_id: Option<&str>,
+        fill_key: &str,
+        border_key: Option<&str>,
+    ) -> Result<(), BridgeError> {
+        let pick = |explicit: Option<&str>, context: &Option<String>| {
+            explicit.map(str::to_string).or_else(|| context.clone())
+        };
+        let params = serde_json::json!({
+            "dataset_id": pick(dataset_id, &self.context.dataset_id),
+            "img_id": pick(img_id, &self.context.img_id),
+            "seg_id": pick(seg_id, &self.context.seg_id),
+            "fill_key": fill_key,
+            "border_key": border_key
+        });
+        let errors: Vec<ValidationError> =
+            self.send_command_as(PythonCommand::ValidatePlotArgs, params)?;
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(BridgeError::InvalidPlotArgs(errors))
+        }
+    }
+}
+
+// ===== Recolor =====
+
+impl PythonBridge {
+    /// show an overlay with another colormap and/or value range
+    ///
+    /// a `recolorable` overlay (see `OverlayInfo::recolorable`) keeps its
+    /// segment geometry, python repaints each segment's fill pixels and cuts
+    /// the pyramid again, skipping bin2cell's rasterization. any other
+    /// overlay falls back to a full re-plot from its plot params with
+    /// `colormap` as bin2cell's `cont_cmap` (`cat_cmap` for categories).
+    /// either way the overlay gets a new generation, so only its own cached
+    /// tiles and overview are dropped
+    ///
+    /// `value_range` keeps the current range when `None`, and can't be set
+    /// for a categorical overlay
+    pub fn recolor_overlay(
+        &mut self,
+        overlay_id: &str,
+        colormap: &str,
+        value_range: Option<[f64; 2]>,
+    ) -> Result<OverlayInfo, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .cloned()
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        ColorScale {
+            value_range,
+            ..ColorScale::default()
+        }
+        .validate()?;
+        let continuous = info.value_range.is_some();
+        if value_range.is_some() && !continuous {
+            return Err(BridgeError::Protocol(format!(
+                "{} colors categories, a value range doesn't apply",
+                overlay_id
+            )));
+        }
+
+        // the params a re-plot would need to reproduce the new colors
+        let mut params = self.overlays.params(overlay_id).cloned();
+        if let Some(ref mut params) = params {
+            let key = if continuous { "cont_cmap" } else { "cat_cmap" };
+            params
+                .extra
+                .get_or_insert_with(serde_json::Map::new)
+                .insert(key.to_string(), colormap.into());
+            if value_range.is_some() {
+                params.color_scale = Some(ColorScale {
+                    value_range,
+                    ..ColorScale::default()
+                });
+            }
+        }
+
+        if !info.recolorable {
+            let params = params.ok_or_else(|| {
+                BridgeError::Protocol(format!(
+                    "{} can't be recolored in place and wasn't plotted through this bridge",
+                    overlay_id
+                ))
+            })?;
+            let context_id = self.render_contexts.find(&params);
+            return self.plot_tissue_overlay(
+                Some(&params.dataset_id),
+                params.img_id.as_deref(),
+                Some(&params.seg_id),
+                &params.fill_key,
+                &params.options(context_id),
+            );
+        }
+
+        let recolored: OverlayInfo = self.send_command_as(
+            PythonCommand::RecolorOverlay,
+            serde_json::json!({
+                "overlay_id": overlay_id,
+                "colormap": colormap,
+                "value_range": value_range
+            }),
+        )?;
+        self.overlays.register(recolored.clone(), params);
+        self.overviews.remove(overlay_id);
+
+        let mut recolored = recolored;
+        recolored.prerendered_tiles = self.warmup_tiles(&recolored);
+        Ok(recolored)
+    }
+}
+
+// ===== View State =====
+
+/// everything `plot_tissue_overlay` was called with, ids already resolved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlotParams {
+    pub dataset_id: String,
+    pub img_id: Option<String>,
+    pub seg_id: String,
+    pub fill_key: String,
+    pub border_key: Option<String>,
+    pub background: Option<[u8; 4]>,
+    pub scalebar: Option<ScalebarOptions>,
+    pub lod: Option<LodOptions>,
+    pub color_scale: Option<ColorScale>,
+    #[serde(default)]
+    pub quality: RenderQuality,
+    #[serde(default)]
+    pub recolorable: bool,
+    /// the seed actually used, a `deterministic` plot records its default
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub orientation: Orientation,
+    #[serde(default)]
+    pub origin: Origin,
+    #[serde(default)]
+    pub transform: Option<AffineTransform>,
+    #[serde(default)]
+    pub pyramid_mode: PyramidMode,
+    #[serde(default)]
+    pub segment_limit: Option<SegmentLimit>,
+    pub extra: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl PlotParams {
+    /// the options that plot these params again, through `context_id` if
+    /// their render context is still open
+    fn options(&self, context_id: Option<String>) -> PlotOptions {
+        PlotOptions {
+            border_key: self.border_key.clone(),
+            background: self.background,
+            scalebar: self.scalebar,
+            lod: self.lod,
+            color_scale: self.color_scale,
+            quality: Some(self.quality),
+            recolorable: self.recolorable,
+            seed: self.seed,
+            deterministic: false,
+            orientation: Some(self.orientation),
+            origin: Some(self.origin),
+            transform: self.transform,
+            pyramid_mode: Some(self.pyramid_mode),
+            segment_limit: self.segment_limit,
+            context_id,
+            extra: self.extra.clone(),
+        }
+    }
+}
+
+/// where the viewer is looking, owned by the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    pub zoom: f64,
+    /// full resolution pixels
+    pub center_x: f64,
+    pub center_y: f64,
+}
+
+/// a resumable session, see `save_view_state`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewState {
+    pub overlay_id: String,
+    pub params: PlotParams,
+    pub viewport: Viewport,
+    pub pinned_levels: Vec<i32>,
+}
+
+/// the overlay a `ViewState` was restored to and where to put the viewer
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoredView {
+    pub overlay: OverlayInfo,
+    pub viewport: Viewport,
+    /// false when the overlay was still plotted and was reused as is
+    pub replotted: bool,
+}
+
+impl PythonBridge {
+    /// capture the overlay on screen so it can be restored in a later session
+    ///
+    /// the bridge doesn't know what the viewer shows, the frontend passes
+    /// the overlay and viewport. the result is plain data to persist
+    /// however it likes
+    pub fn save_view_state(
+        &self,
+        overlay_id: &str,
+        viewport: Viewport,
+    ) -> Result<ViewState, BridgeError> {
+        let params = self
+            .overlays
+            .params(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        Ok(ViewState {
+            overlay_id: overlay_id.to_string(),
+            params: params.clone(),
+            viewport,
+            pinned_levels: self.tiles.cache().pinned_levels(overlay_id),
+        })
+    }
+
+    /// bring back a saved view, re-plotting the overlay unless it is still
+    /// plotted from the same params
+    ///
+    /// python's overlay ids are derived from the inputs, so a re-plot
+    /// normally gets the saved id back. fails with `InputsMissing` if the
+    /// dataset, image or segmentation isn't loaded
+    pub fn restore_view_state(&mut self, state: &ViewState) -> Result<RestoredView, BridgeError> {
+        let still_plotted = self.overlays.params(&state.overlay_id) == Some(&state.params);
+        let (overlay, replotted) = match self.overlays.info(&state.overlay_id) {
+            Some(info) if still_plotted => (info.clone(), false),
+            _ => {
+                let params = &state.params;
+                let missing: Vec<String> = self.send_command_as(
+                    PythonCommand::MissingInputs,
+                    serde_json::json!({
+                        "dataset_id": params.dataset_id,
+                        "img_id": params.img_id,
+                        "seg_id": params.seg_id
+                    }),
+                )?;
+                if !missing.is_empty() {
+                    return Err(BridgeError::InputsMissing(missing));
+                }
+
+                let context_id = self.render_contexts.find(params);
+                let info = self.plot_tissue_overlay(
+                    Some(&params.dataset_id),
+                    params.img_id.as_deref(),
+                    Some(&params.seg_id),
+                    &params.fill_key,
+                    &params.options(context_id),
+                )?;
+                (info, true)
+            }
+        };
+
+        self.tiles.cache().pin(&overlay.overlay_id, &state.pinned_levels);
+        Ok(RestoredView {
+            overlay,
+            viewport: state.viewport,
+            replotted,
+        })
+    }
+}
+
+// ===== Overlay Registry =====
+
+/// how `plot_tissue_overlay` renders, everything it takes besides the ids
+/// and `fill_key`
+///
+/// `PlotOptions::default()` is a plain plot. options left `None` come from
+/// the render defaults where those have them, see `set_render_defaults`.
+/// fields missing from the frontend's object are unset
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlotOptions {
+    /// column the segment outlines are colored by, none drawn without it
+    pub border_key: Option<String>,
+    /// rgba the segments are drawn over when there is no image, white by
+    /// default. tiles are stored as jpeg, so a translucent background is
+    /// blended onto white
+    pub background: Option<[u8; 4]>,
+    /// burned into the overlay at every zoom level. python refuses it for
+    /// a dataset without a pixel size calibration
+    pub scalebar: Option<ScalebarOptions>,
+    /// show the zoomed out levels as segments aggregated into bins, see
+    /// `LodOptions`
+    pub lod: Option<LodOptions>,
+    /// range a gene or numeric column is colored over, see `ColorScale`.
+    /// python refuses it for categorical keys
+    pub color_scale: Option<ColorScale>,
+    /// how the pyramid is downsampled, see `RenderQuality`
+    pub quality: Option<RenderQuality>,
+    /// keep the full resolution render in python so `recolor_overlay` can
+    /// swap the colormap without rasterizing again, at about 3 bytes per
+    /// pixel of python memory for as long as the overlay lives
+    pub recolorable: bool,
+    /// makes the render reproducible: python seeds its and numpy's global
+    /// random generators around the rasterization, which fixes e.g. the
+    /// colors bin2cell assigns to categories without stored ones.
+    /// downsampling, jpeg encoding and lod bins involve no randomness, so
+    /// the tiles come out byte-identical for the same inputs and library
+    /// versions. the seed used is in `OverlayInfo::seed`
+    pub seed: Option<u64>,
+    /// seed with `DETERMINISTIC_SEED` when no `seed` is given
+    pub deterministic: bool,
+    /// how the render is turned, see `Orientation`
+    pub orientation: Option<Orientation>,
+    /// where the overlay's y coordinates count from, match the viewer's
+    /// convention, see `Origin`
+    pub origin: Option<Origin>,
+    /// affine the image and segmentation are put through before
+    /// rasterizing, see `AffineTransform`
+    pub transform: Option<AffineTransform>,
+    /// whether the tiles are all cut before the plot returns or on demand,
+    /// see `PyramidMode` for the memory either takes. an eager pyramid is
+    /// built a level at a time after the render, each level reported to
+    /// `PythonBridgeBuilder::on_pyramid_progress`
+    pub pyramid_mode: Option<PyramidMode>,
+    /// render a sample of the segments, see `SegmentLimit`
+    pub segment_limit: Option<SegmentLimit>,
+    /// reuse a render context's setup, see `open_render_context`. ids left
+    /// out of the call come from it instead of the dataset context, ids
+    /// given must be the render context's
+    pub context_id: Option<String>,
+    /// extra kwargs for python's renderer, they must not repeat the
+    /// params above. see `merge_extra_params`
+    pub extra: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// metadata python returns after generating an overlay's tile pyramid
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverlayInfo {
+    pub overlay_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    pub max_zoom: i32,
+    pub fill_key: String,
+    pub is_gene: bool,
+    /// where the plot time went, missing from older python helpers
+    #[serde(default)]
+    pub timings: Option<RenderTimings>,
+    /// tiles already in the bridge's cache when plot returned
+    #[serde(default)]
+    pub prerendered_tiles: u32,
+    /// false when segments were drawn over a plain background, older
+    /// python helpers always render over an image
+    #[serde(default = "has_image_default")]
+    pub has_image: bool,
+    /// the scalebar burned into the tiles, `None` if there is none or the
+    /// python helper predates scalebars
+    #[serde(default)]
+    pub scalebar: Option<ScalebarOptions>,
+    /// how zoomed out levels were rendered, `None` if every level shows
+    /// individual segments
+    #[serde(default)]
+    pub lod: Option<LodOptions>,
+    /// [min, max] the colormap spans after resolving `ColorScale`, the same
+    /// as the legend's. `None` for categorical keys and older python helpers
+    #[serde(default)]
+    pub value_range: Option<[f64; 2]>,
+    /// how the pyramid was downsampled, older python helpers always render
+    /// `Balanced`
+    #[serde(default)]
+    pub quality: RenderQuality,
+    /// python kept what `recolor_overlay` needs to skip a re-render. only
+    /// continuous overlays plotted with `recolorable` whose fill colors are
+    /// still recognizable in the render (not blended into the image)
+    #[serde(default)]
+    pub recolorable: bool,
+    /// what the render was seeded with, plot again with it to reproduce
+    /// the overlay exactly. `None` for unseeded renders and older helpers
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// how the render was turned, `width` and `height` are after it
+    #[serde(default)]
+    pub orientation: Orientation,
+    /// where y coordinates count from, older python helpers are always
+    /// `TopLeft`
+    #[serde(default)]
+    pub origin: Origin,
+    /// affine the image and segmentation were put through before
+    /// rasterizing, `None` without one
+    #[serde(default)]
+    pub transform: Option<AffineTransform>,
+    /// whether the tiles were all cut up front, older python helpers always
+    /// cut them eagerly
+    #[serde(default)]
+    pub pyramid_mode: PyramidMode,
+    /// the cap the render was sampled down to, `None` renders every segment
+    #[serde(default)]
+    pub segment_limit: Option<SegmentLimit>,
+    /// segments left out of the render by `segment_limit`
+    #[serde(default)]
+    pub segments_dropped: u64,
+}
+
+/// seed of a `deterministic` plot without an explicit one
+pub const DETERMINISTIC_SEED: u64 = 0;
+
+fn has_image_default() -> bool {
+    true
+}
+
+/// a scalebar drawn into a rendered image
+///
+/// sized from the dataset's pixel size calibration, so the bar stays true
+/// to `length_um` at every zoom level and in downscaled exports
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScalebarOptions {
+    pub length_um: f64,
+    #[serde(default)]
+    pub position: ScalebarPosition,
+    /// rgba, opaque black by default
+    #[serde(default = "scalebar_color_default")]
+    pub color: [u8; 4],
+}
+
+fn scalebar_color_default() -> [u8; 4] {
+    [0, 0, 0, 255]
+}
+
+/// corner of the image a scalebar sits in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalebarPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// largest `LodOptions::bin_size`, coarser bins don't show anything
+const MAX_LOD_BIN_SIZE: u32 = 4096;
+
+/// level of detail: aggregate segments into bins when zoomed out
+///
+/// with a pyramid of `max_zoom` 4, `segments_from_zoom: 2` renders zoom 0
+/// and 1 as square bins colored by the segments whose centroids fall into
+/// them (mean value for genes and numeric columns, most common category
+/// otherwise), zoom 2 to 4 show individual segments as usual. each tile
+/// reports which it shows in `TileResponse::lod`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LodOptions {
+    /// side of a bin in full resolution pixels, at most `MAX_LOD_BIN_SIZE`
+    pub bin_size: u32,
+    /// first zoom level with individual segments, at least 1
+    pub segments_from_zoom: i32,
+}
+
+impl LodOptions {
+    fn validate(&self) -> Result<(), BridgeError> {
+        if !(2..=MAX_LOD_BIN_SIZE).contains(&self.bin_size) {
+            return Err(BridgeError::Protocol(format!(
+                "LOD bin size must be between 2 and {} pixels, got {}",
+                MAX_LOD_BIN_SIZE, self.bin_size
+            )));
+        }
+        if self.segments_from_zoom < 1 {
+            return Err(BridgeError::Protocol(format!(
+                "LOD must leave at least zoom 0 aggregated, got segments_from_zoom {}",
+                self.segments_from_zoom
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// render at most `max_segments` segments, to preview a huge segmentation
+/// before committing to a full render
+///
+/// only the render is sampled, the dropped segments show as background in
+/// tiles and exports of it. `get_segment_at`, statistics, segment exports
+/// and every other query still see every segment, and LOD bins aggregate
+/// all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentLimit {
+    /// at least 1
+    pub max_segments: u64,
+    #[serde(default)]
+    pub sampling: Sampling,
+}
+
+/// which segments a `SegmentLimit` keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sampling {
+    /// a random subset drawn from the plot's seed, `DETERMINISTIC_SEED`
+    /// when unseeded, so the same preview comes back every time
+    #[default]
+    Random,
+    /// the segments covering the most pixels
+    Largest,
+    /// no sampling, a segmentation over the limit fails the plot
+    None,
+}
+
+impl SegmentLimit {
+    fn validate(&self) -> Result<(), BridgeError> {
+        if self.max_segments == 0 {
+            return Err(BridgeError::Protocol("max_segments must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// what a tile shows, see `LodOptions`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LodMode {
+    Segments,
+    Bins,
+}
+
+impl ScalebarOptions {
+    fn validate(&self) -> Result<(), BridgeError> {
+        if self.length_um.is_finite() && self.length_um > 0.0 {
+            Ok(())
+        } else {
+            Err(BridgeError::Protocol(format!(
+                "Scalebar length must be a positive number of microns, got {}",
+                self.length_um
+            )))
+        }
+    }
+}
+
+/// how a gene or numeric column maps onto the colormap
+///
+/// by default the colormap spans the data's [min, max]. `value_range`
+/// fixes that span, values outside it get the end colors. `symmetric`
+/// makes the span symmetric around `center` (0 by default) for diverging
+/// data like log fold changes: [-m, m] with m the largest distance of the
+/// data from the center, or of `value_range` if that is symmetric around
+/// it too. an asymmetric `value_range` with `symmetric` is refused rather
+/// than silently widened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColorScale {
+    #[serde(default)]
+    pub value_range: Option<[f64; 2]>,
+    #[serde(default)]
+    pub symmetric: bool,
+    /// only meaningful with `symmetric`
+    #[serde(default)]
+    pub center: Option<f64>,
+}
+
+impl ColorScale {
+    fn validate(&self) -> Result<(), BridgeError> {
+        if let Some([min, max]) = self.value_range {
+            if !(min.is_finite() && max.is_finite() && min < max) {
+                return Err(BridgeError::Protocol(format!(
+                    "Color value range must be two finite numbers, min below max, got [{}, {}]",
+                    min, max
+                )));
+            }
+        }
+        let Some(center) = self.center else {
+            return self.check_symmetric(0.0);
+        };
+        if !center.is_finite() {
+            return Err(BridgeError::Protocol(format!(
+                "Color scale center must be finite, got {}",
+                center
+            )));
+        }
+        if !self.symmetric {
+            return Err(BridgeError::Protocol(
+                "A color scale center needs symmetric set".to_string(),
+            ));
+        }
+        self.check_symmetric(center)
+    }
+
+    fn check_symmetric(&self, center: f64) -> Result<(), BridgeError> {
+        let Some([min, max]) = self.value_range else {
+            return Ok(());
+        };
+        let (below, above) = (center - min, max - center);
+        // tolerate rounding in ranges computed on the frontend
+        if self.symmetric && (below - above).abs() > 1e-9 * below.abs().max(above.abs()) {
+            return Err(BridgeError::Protocol(format!(
+                "Color value range [{}, {}] is not symmetric around {}",
+                min, max, center
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// breakdown of how long python spent producing an overlay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderTimings {
+    /// preparing inputs (writing the image/segmentation for bin2cell)
+    pub load_ms: f64,
+    /// rasterizing segments onto the image
+    pub render_ms: f64,
+    /// cutting and encoding the tile pyramid
+    pub tile_ms: f64,
+}
+
+impl OverlayInfo {
+    /// pixel size of the image at a zoom level
+    ///
+    /// matches python's tiling: zoom 0 is the most zoomed out and each level
+    /// doubles the size up to the full resolution at `max_zoom`
+    pub fn level_size(&self, zoom: i32) -> (u32, u32) {
+        let scale = 1u32 << (self.max_zoom - zoom).clamp(0, 31);
+        (self.width / scale, self.height / scale)
+    }
+
+    /// number of tile columns and rows at a zoom level
+    pub fn tile_grid(&self, zoom: i32) -> (i32, i32) {
+        let (width, height) = self.level_size(zoom);
+        (
+            width.div_ceil(self.tile_size) as i32,
+            height.div_ceil(self.tile_size) as i32,
+        )
+    }
+
+    /// the lowest zoom level with at least `px_per_unit` level pixels per
+    /// full resolution pixel, `max_zoom` when even that has fewer
+    ///
+    /// level `z` holds `2^(z - max_zoom)` pixels per full resolution pixel,
+    /// so a density of 1 (one screen pixel per image pixel, drawn 1:1) is
+    /// `max_zoom`, 0.5 the level below and anything in between rounds up
+    /// to the sharper level. `px_per_unit` must be positive and finite
+    pub fn zoom_for_resolution(&self, px_per_unit: f64) -> Result<i32, BridgeError> {
+        if !(px_per_unit.is_finite() && px_per_unit > 0.0) {
+            return Err(BridgeError::Protocol(format!(
+                "Resolution must be a positive number of pixels, got {}",
+                px_per_unit
+            )));
+        }
+        // a density a rounding error above a level is still that level
+        let levels_up = (px_per_unit.log2() - ZOOM_RESOLUTION_TOLERANCE).ceil();
+        let zoom = f64::from(self.max_zoom) + levels_up;
+        Ok(zoom.clamp(0.0, f64::from(self.max_zoom.max(0))) as i32)
+    }
+}
+
+/// slack in levels (log2 of the density) `zoom_for_resolution` allows
+/// before picking the next sharper level
+const ZOOM_RESOLUTION_TOLERANCE: f64 = 1e-9;
+
+#[cfg(test)]
+mod zoom_for_resolution_tests {
+    use super::*;
+
+    fn overlay(max_zoom: i32) -> OverlayInfo {
+        serde_json::from_value(serde_json::json!({
+            "overlay_id": "o",
+            "width": 4096,
+            "height": 4096,
+            "tile_size": 256,
+            "max_zoom": max_zoom,
+            "fill_key": "leiden",
+            "is_gene": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn picks_the_level_at_or_above_the_density() {
+        let info = overlay(4);
+        let zoom = |px_per_unit| info.zoom_for_resolution(px_per_unit).unwrap();
+
+        assert_eq!(zoom(1.0), 4);
+        assert_eq!(zoom(0.5), 3);
+        assert_eq!(zoom(0.25), 2);
+        // between two levels is the sharper one
+        assert_eq!(zoom(0.5000001), 4);
+        assert_eq!(zoom(0.4999999), 3);
+        assert_eq!(zoom(0.3), 3);
+    }
+
+    #[test]
+    fn treats_a_rounding_error_above_a_level_as_that_level() {
+        let info = overlay(4);
+        assert_eq!(info.zoom_for_resolution(0.5 * (1.0 + 1e-12)).unwrap(), 3);
+        assert_eq!(info.zoom_for_resolution(0.25 * (1.0 + 1e-12)).unwrap(), 2);
+    }
+
+    #[test]
+    fn clamps_to_the_pyramid() {
+        let info = overlay(4);
+        assert_eq!(info.zoom_for_resolution(8.0).unwrap(), 4);
+        assert_eq!(info.zoom_for_resolution(1e-9).unwrap(), 0);
+        assert_eq!(overlay(0).zoom_for_resolution(0.01).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_densities_that_arent_positive() {
+        let info = overlay(4);
+        for px_per_unit in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                info.zoom_for_resolution(px_per_unit),
+                Err(BridgeError::Protocol(_))
+            ));
+        }
+    }
+}
+
+impl PythonBridge {
+    /// the crispest level to draw an overlay from at `target_px_per_unit`
+    /// screen pixels per full resolution pixel, see
+    /// `OverlayInfo::zoom_for_resolution`
+    ///
+    /// rust side only, the frontend doesn't need to redo the pyramid math
+    pub fn zoom_for_resolution(
+        &self,
+        overlay_id: &str,
+        target_px_per_unit: f64,
+    ) -> Result<i32, BridgeError> {
+        self.overlays
+            .info(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?
+            .zoom_for_resolution(target_px_per_unit)
+    }
+}
+
+/// a registered overlay and the generation its tiles belong to
+struct OverlayEntry {
+    info: OverlayInfo,
+    generation: u64,
+    /// what it was plotted from, `None` for overlays the bridge didn't plot
+    params: Option<PlotParams>,
+}
+
+/// what serving an overlay's tiles takes, see `TileService`
+#[derive(Clone)]
+struct TileSource {
+    info: OverlayInfo,
+    generation: u64,
+    /// the dataset it was plotted from, the routing key of its tiles
+    dataset_id: Option<String>,
+}
+
+/// the registered overlays as the tile service sees them
+type TileSources = Arc<RwLock<HashMap<String, TileSource>>>;
+
+/// hands out overlay generations, shared by the registry and the tile service
+#[derive(Default)]
+struct Generations {
+    /// monotonically increasing across all overlays, 0 is never handed out
+    last: AtomicU64,
+    /// overlays the bridge didn't plot (e.g. plotted before a restart), each
+    /// gets one the first time it is asked for. python is still asked for
+    /// their tiles and reports the real error
+    unregistered: Mutex<HashMap<String, u64>>,
+}
+
+impl Generations {
+    fn next(&self) -> u64 {
+        self.last.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn unregistered(&self, overlay_id: &str) -> u64 {
+        let mut unregistered = self.unregistered.lock().unwrap();
+        *unregistered.entry(overlay_id.to_string()).or_insert_with(|| self.next())
+    }
+
+    /// an overlay (re)plotted or deleted, asking for it unregistered again
+    /// moves it to a fresh generation
+    fn forget(&self, overlay_id: &str) {
+        self.unregistered.lock().unwrap().remove(overlay_id);
+    }
+}
+
+/// overlays generated through this bridge
+///
+/// every (re)plot or delete moves an overlay to a fresh generation. the
+/// generation is part of every tile cache key, so tiles rendered for an
+/// older version can never be served again and simply age out of the LRU
+#[derive(Default)]
+struct OverlayRegistry {
+    overlays: HashMap<String, OverlayEntry>,
+    /// shared with the tile service, every change is published to it
+    sources: TileSources,
+    /// shared with the tile service too
+    generations: Arc<Generations>,
+    /// registered overlays, most recently (re)plotted first, see
+    /// `PythonBridge::list_recent_overlays`
+    recent: VecDeque<RecentEntry>,
+}
+
+impl OverlayRegistry {
+    fn next_generation(&mut self) -> u64 {
+        self.generations.next()
+    }
+
+    fn register(&mut self, info: OverlayInfo, params: Option<PlotParams>) -> u64 {
+        let generation = self.next_generation();
+        self.recent.retain(|entry| entry.overlay_id != info.overlay_id);
+        self.recent.push_front(RecentEntry {
+            overlay_id: info.overlay_id.clone(),
+            plotted_at_ms: unix_millis(),
+            thumbnail: None,
+        });
+        self.recent.truncate(MAX_RECENT_OVERLAYS);
+        let overlay_id = info.overlay_id.clone();
+        self.overlays.insert(
+            overlay_id.clone(),
+            OverlayEntry {
+                info,
+                generation,
+                params,
+            },
+        );
+        self.publish(&overlay_id);
+        generation
+    }
+
+    fn remove(&mut self, overlay_id: &str) -> Option<OverlayInfo> {
+        // burn a generation so a stale entry can't be matched by a tile
+        // request racing the delete
+        self.next_generation();
+        self.recent.retain(|entry| entry.overlay_id != overlay_id);
+        let removed = self.overlays.remove(overlay_id).map(|entry| entry.info);
+        self.publish(overlay_id);
+        removed
+    }
+
+    /// forget every overlay, generations keep counting up from where they were
+    fn clear(&mut self) {
+        self.next_generation();
+        self.overlays.clear();
+        self.recent.clear();
+        self.sources.write().unwrap().clear();
+        self.generations.unregistered.lock().unwrap().clear();
+    }
+
+    /// the handle `TileService` reads the overlays through
+    fn sources(&self) -> TileSources {
+        Arc::clone(&self.sources)
+    }
+
+    /// the counter `TileService` gives unregistered overlays generations from
+    fn generations(&self) -> Arc<Generations> {
+        Arc::clone(&self.generations)
+    }
+
+    /// bring the tile service's view of one overlay up to date
+    fn publish(&self, overlay_id: &str) {
+        self.generations.forget(overlay_id);
+        let mut sources = self.sources.write().unwrap();
+        match self.overlays.get(overlay_id) {
+            Some(entry) => {
+                let source = TileSource {
+                    info: entry.info.clone(),
+                    generation: entry.generation,
+                    dataset_id: entry.params.as_ref().map(|p| p.dataset_id.clone()),
+                };
+                sources.insert(overlay_id.to_string(), source);
+            }
+            None => {
+                sources.remove(overlay_id);
+            }
+        }
+    }
+
+    fn generation(&self, overlay_id: &str) -> Option<u64> {
+        self.overlays.get(overlay_id).map(|entry| entry.generation)
+    }
+
+    fn info(&self, overlay_id: &str) -> Option<&OverlayInfo> {
+        self.overlays.get(overlay_id).map(|entry| &entry.info)
+    }
+
+    fn params(&self, overlay_id: &str) -> Option<&PlotParams> {
+        self.overlays.get(overlay_id)?.params.as_ref()
+    }
+
+    /// to a new generation too, see `PythonBridge::set_overlay_origin`
+    fn set_origin(&mut self, overlay_id: &str, origin: Origin) -> Option<OverlayInfo> {
+        let generation = self.next_generation();
+        let entry = self.overlays.get_mut(overlay_id)?;
+        entry.generation = generation;
+        entry.info.origin = origin;
+        if let Some(ref mut params) = entry.params {
+            params.origin = origin;
+        }
+        let info = entry.info.clone();
+        self.publish(overlay_id);
+        Some(info)
+    }
+
+    fn set_thumbnail(&mut self, overlay_id: &str, thumbnail: TileResponse) {
+        if let Some(entry) = self.recent.iter_mut().find(|entry| entry.overlay_id == overlay_id) {
+            entry.thumbnail = Some(thumbnail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod generation_tests {
+    use super::*;
+
+    fn info(overlay_id: &str) -> OverlayInfo {
+        serde_json::from_value(serde_json::json!({
+            "overlay_id": overlay_id,
+            "width": 256,
+            "height": 256,
+            "tile_size": 256,
+            "max_zoom": 0,
+            "fill_key": "leiden",
+            "is_gene": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn gives_unregistered_overlays_their_own_generation() {
+        let mut registry = OverlayRegistry::default();
+        let generations = registry.generations();
+        let (a, b) = (generations.unregistered("a"), generations.unregistered("b"));
+        assert!(a != 0 && b != 0 && a != b);
+        assert_eq!(generations.unregistered("a"), a);
+
+        // plotted, then deleted: never back to a generation it had
+        let plotted = registry.register(info("a"), None);
+        assert!(plotted > b);
+        registry.remove("a");
+        let deleted = generations.unregistered("a");
+        assert!(deleted > plotted);
+
+        registry.clear();
+        assert!(generations.unregistered("b") > deleted);
+    }
+}
+
+// ===== Orientation =====
+
+/// how a plot is turned before tiling, for slides scanned the wrong way
+/// round. rotations are clockwise
+///
+/// python turns the render itself, so tiles, `get_segment_at` and every
+/// other full resolution coordinate of the overlay are in the turned frame.
+/// `OverlayBounds` converts to and from the segmentation's own pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Orientation {
+    /// wire name, as python spells it
+    pub fn name(self) -> &'static str {
+        match self {
+            Orientation::Rotate0 => "rotate0",
+            Orientation::Rotate90 => "rotate90",
+            Orientation::Rotate180 => "rotate180",
+            Orientation::Rotate270 => "rotate270",
+            Orientation::FlipHorizontal => "flip_horizontal",
+            Orientation::FlipVertical => "flip_vertical",
+        }
+    }
+
+    /// width and height trade places
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Orientation::Rotate90 | Orientation::Rotate270)
+    }
+
+    /// the orientation that turns it back
+    pub fn inverse(self) -> Orientation {
+        match self {
+            Orientation::Rotate90 => Orientation::Rotate270,
+            Orientation::Rotate270 => Orientation::Rotate90,
+            other => other,
+        }
+    }
+
+    /// size of a `width` x `height` image once turned
+    pub fn turned_size(self, width: u32, height: u32) -> (u32, u32) {
+        if self.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// where a point of a `width` x `height` image lands once turned
+    ///
+    /// continuous coordinates: pixel `(x, y)` covers `x..x + 1`, so a
+    /// pixel's center maps to the center of the pixel python moves it to
+    pub fn map_point(self, x: f64, y: f64, width: u32, height: u32) -> (f64, f64) {
+        let (width, height) = (f64::from(width), f64::from(height));
+        match self {
+            Orientation::Rotate0 => (x, y),
+            Orientation::Rotate90 => (height - y, x),
+            Orientation::Rotate180 => (width - x, height - y),
+            Orientation::Rotate270 => (y, width - x),
+            Orientation::FlipHorizontal => (width - x, y),
+            Orientation::FlipVertical => (x, height - y),
+        }
+    }
+}
+
+/// a 2x3 affine `[a, b, c, d, e, f]` registering a plot with another
+/// dataset: `x' = a*x + b*y + c`, `y' = d*x + e*y + f`
+///
+/// python puts the image and segmentation through it before rasterizing,
+/// onto a canvas the size of the segmentation, anything mapped outside is
+/// cut off. continuous coordinates like `Orientation::map_point`. applied
+/// before the orientation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AffineTransform(pub [f64; 6]);
+
+impl AffineTransform {
+    fn determinant(&self) -> f64 {
+        let [a, b, _, d, e, _] = self.0;
+        a * e - b * d
+    }
+
+    /// the same check python makes, finite and invertible
+    fn validate(&self) -> Result<(), BridgeError> {
+        if !self.0.iter().all(|v| v.is_finite()) {
+            return Err(BridgeError::Protocol(format!(
+                "Transform must be six finite numbers, got {:?}",
+                self.0
+            )));
+        }
+        if self.determinant().abs() < 1e-9 {
+            return Err(BridgeError::Protocol(format!(
+                "Transform {:?} is not invertible",
+                self.0
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * x + b * y + c, d * x + e * y + f)
+    }
+
+    /// the transform undoing this one, only for validated transforms
+    pub fn inverse(&self) -> AffineTransform {
+        let [a, b, c, d, e, f] = self.0;
+        let det = self.determinant();
+        let (ia, ib, id, ie) = (e / det, -b / det, -d / det, a / det);
+        AffineTransform([ia, ib, -(ia * c + ib * f), id, ie, -(id * c + ie * f)])
+    }
+}
+
+/// extent of an overlay in the frame its coordinates use and in the
+/// segmentation's own, see `PythonBridge::get_overlay_bounds`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OverlayBounds {
+    /// full resolution size as shown, after `orientation`
+    pub width: u32,
+    pub height: u32,
+    /// full resolution size of the image and segmentation as loaded, also
+    /// the canvas `transform` draws onto
+    pub source_width: u32,
+    pub source_height: u32,
+    pub orientation: Orientation,
+    pub transform: Option<AffineTransform>,
+    /// where the shown y counts from, the segmentation's own is top-left
+    pub origin: Origin,
+}
+
+impl OverlayBounds {
+    pub fn new(
+        width: u32,
+        height: u32,
+        orientation: Orientation,
+        transform: Option<AffineTransform>,
+        origin: Origin,
+    ) -> Self {
+        let (source_width, source_height) = orientation.inverse().turned_size(width, height);
+        OverlayBounds {
+            width,
+            height,
+            source_width,
+            source_height,
+            orientation,
+            transform,
+            origin,
+        }
+    }
+
+    /// a point of the loaded segmentation as the overlay shows it
+    pub fn to_display(&self, x: f64, y: f64) -> (f64, f64) {
+        let (x, y) = match self.transform {
+            Some(transform) => transform.apply(x, y),
+            None => (x, y),
+        };
+        let (x, y) = self.orientation.map_point(x, y, self.source_width, self.source_height);
+        (x, self.flip_y(y))
+    }
+
+    /// a point of the overlay (e.g. a click) in the loaded segmentation
+    pub fn to_source(&self, x: f64, y: f64) -> (f64, f64) {
+        let y = self.flip_y(y);
+        let (x, y) = self.orientation.inverse().map_point(x, y, self.width, self.height);
+        match self.transform {
+            Some(transform) => transform.inverse().apply(x, y),
+            None => (x, y),
+        }
+    }
+
+    /// a shown y between `origin` and top-left, either way round
+    fn flip_y(&self, y: f64) -> f64 {
+        match self.origin {
+            Origin::TopLeft => y,
+            Origin::BottomLeft => f64::from(self.height) - y,
+        }
+    }
+}
+
+impl PythonBridge {
+    /// size, orientation, transform and origin of a registered overlay
+    pub fn get_overlay_bounds(&self, overlay_id: &str) -> Result<OverlayBounds, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        Ok(OverlayBounds::new(
+            info.width,
+            info.height,
+            info.orientation,
+            info.transform,
+            info.origin,
+        ))
+    }
+}
+
+// ===== Origin =====
+
+/// which corner an overlay's y coordinates count from. viewers disagree,
+/// and a mismatch shows the overlay upside down
+///
+/// with `BottomLeft` tile rows, `get_segment_at`, bounding boxes, regions
+/// and every other y of the overlay count up from its bottom edge. the
+/// pyramid is still stored and cached top-left with the grid anchored
+/// there, so when the height isn't a multiple of the tile size the short
+/// row is row 0. python flips segment coordinates, tile rows and pixel
+/// rectangles are flipped on our side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    #[default]
+    TopLeft,
+    BottomLeft,
+}
+
+impl Origin {
+    /// wire name, as python spells it
+    pub fn name(self) -> &'static str {
+        match self {
+            Origin::TopLeft => "top_left",
+            Origin::BottomLeft => "bottom_left",
+        }
+    }
+}
+
+impl OverlayInfo {
+    /// a tile between the overlay's `origin` and the top-left rows it is
+    /// stored under, either way round
+    pub fn flip_tile(&self, coord: TileCoord) -> TileCoord {
+        match self.origin {
+            Origin::TopLeft => coord,
+            Origin::BottomLeft => TileCoord {
+                y: self.tile_grid(coord.zoom).1 - 1 - coord.y,
+                ..coord
+            },
+        }
+    }
+
+    /// a `y0..y1` span of an image `height` pixels tall between the
+    /// overlay's `origin` and top-left, either way round
+    pub fn flip_span(&self, y0: i64, y1: i64, height: i64) -> (i64, i64) {
+        match self.origin {
+            Origin::TopLeft => (y0, y1),
+            Origin::BottomLeft => (height - y1, height - y0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod origin_tests {
+    use super::*;
+
+    /// 1000 x 600 at full resolution: 4 x 3 tiles, the bottom row 88 px
+    fn overlay(origin: Origin) -> OverlayInfo {
+        serde_json::from_value(serde_json::json!({
+            "overlay_id": "o",
+            "width": 1000,
+            "height": 600,
+            "tile_size": 256,
+            "max_zoom": 1,
+            "fill_key": "leiden",
+            "is_gene": false,
+            "origin": origin,
+        }))
+        .unwrap()
+    }
+
+    /// where a segment centered at `(100, 50)` of the segmentation shows up:
+    /// its point, and the tile and row span holding it
+    fn segment(origin: Origin, zoom: i32) -> ((f64, f64), TileCoord, (i64, i64)) {
+        let info = overlay(origin);
+        let bounds = OverlayBounds::new(info.width, info.height, info.orientation, None, origin);
+        let shown = bounds.to_display(100.0, 50.0);
+        assert_eq!(bounds.to_source(shown.0, shown.1), (100.0, 50.0));
+
+        // stored top-left, row 0 at every level
+        let tile = info.flip_tile(TileCoord { zoom, x: 0, y: 0 });
+        let height = i64::from(info.level_size(zoom).1);
+        let span = info.flip_span(0, 256.min(height), height);
+        (shown, tile, span)
+    }
+
+    #[test]
+    fn leaves_top_left_overlays_as_stored() {
+        let (shown, tile, span) = segment(Origin::TopLeft, 1);
+        assert_eq!(shown, (100.0, 50.0));
+        assert_eq!(tile, TileCoord { zoom: 1, x: 0, y: 0 });
+        assert_eq!(span, (0, 256));
+    }
+
+    #[test]
+    fn counts_bottom_left_rows_and_points_from_the_bottom() {
+        let (shown, tile, span) = segment(Origin::BottomLeft, 1);
+        assert_eq!(shown, (100.0, 550.0));
+        assert_eq!(tile, TileCoord { zoom: 1, x: 0, y: 2 });
+        assert_eq!(span, (344, 600));
+        assert!((span.0..span.1).contains(&(shown.1 as i64)));
+
+        // 500 x 300 one level down, two rows
+        let (_, tile, span) = segment(Origin::BottomLeft, 0);
+        assert_eq!(tile, TileCoord { zoom: 0, x: 0, y: 1 });
+        assert_eq!(span, (44, 300));
+    }
+
+    #[test]
+    fn flips_tiles_both_ways() {
+        let info = overlay(Origin::BottomLeft);
+        for y in 0..3 {
+            let coord = TileCoord { zoom: 1, x: 3, y };
+            assert_eq!(info.flip_tile(info.flip_tile(coord)), coord);
+        }
+        assert_eq!(info.flip_span(0, 88, 600), (512, 600));
+    }
+}
+
+impl PythonBridge {
+    /// `OverlayInfo::flip_tile` of a registered overlay, an unknown one is
+    /// left for the fetch to report
+    fn flip_tile(&self, overlay_id: &str, coord: TileCoord) -> TileCoord {
+        match self.overlays.info(overlay_id) {
+            Some(info) => info.flip_tile(coord),
+            None => coord,
+        }
+    }
+
+    /// where an overlay's y coordinates count from, without plotting it
+    /// again
+    ///
+    /// the render stays as it is. the overlay still moves to a new
+    /// generation, cached hover answers and results were asked for with
+    /// coordinates that now mean something else
+    pub fn set_overlay_origin(
+        &mut self,
+        overlay_id: &str,
+        origin: Origin,
+    ) -> Result<OverlayInfo, BridgeError> {
+        if self.overlays.info(overlay_id).is_none() {
+            return Err(BridgeError::OverlayNotFound(overlay_id.to_string()));
+        }
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "origin": origin
+        });
+        self.send_command(PythonCommand::SetOverlayOrigin, params)?;
+        self.overlays
+            .set_origin(overlay_id, origin)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))
+    }
+}
+
+// ===== Tile Cache =====
+
+/// a single encoded tile as returned by python
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileResponse {
+    /// base64 encoded image bytes
+    pub tile: String,
+    pub format: String,
+    /// e.g. "image/png", sent with overlay and base image tiles. with
+    /// `TileFormatMode::Auto` tiles of one layer differ, build data urls
+    /// from this rather than assuming a format. missing from older python
+    /// helpers, it's then `image/{format}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// segments or bins, only reported for overlay tiles by python helpers
+    /// that know about LOD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lod: Option<LodMode>,
+    /// color channels are already multiplied by alpha
+    ///
+    /// false by default: straight alpha, what PNG specifies and what a
+    /// decoded `<img>` or canvas `drawImage` expects. a WebGL pipeline
+    /// uploading with `UNPACK_PREMULTIPLY_ALPHA_WEBGL` off and blending
+    /// with `ONE, ONE_MINUS_SRC_ALPHA` wants premultiplied tiles instead,
+    /// straight ones fringe dark at soft edges there. only RGBA tiles can
+    /// be premultiplied, which today are `ChangedMask` diffs. overlay tiles
+    /// are opaque jpeg (or png re-encoded from it), for them both are the
+    /// same and this stays false
+    #[serde(default)]
+    pub premultiplied: bool,
+}
+
+/// encoding python uses for a tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileFormat {
+    Jpeg,
+    Png,
+}
+
+impl TileFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TileFormat::Jpeg => "jpg",
+            TileFormat::Png => "png",
+        }
+    }
+}
+
+/// the format overlay and base image tiles are served in, see
+/// `PythonBridge::set_tile_format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileFormatMode {
+    /// every tile jpeg, what tiles always were
+    #[default]
+    Jpeg,
+    /// every tile png, lossless but several times the bytes of photographic
+    /// tiles
+    Png,
+    /// python picks per tile by its content
+    Auto,
+}
+
+impl From<TileFormat> for TileFormatMode {
+    fn from(format: TileFormat) -> Self {
+        match format {
+            TileFormat::Jpeg => TileFormatMode::Jpeg,
+            TileFormat::Png => TileFormatMode::Png,
+        }
+    }
+}
+
+impl PythonBridge {
+    /// pick the format of the tiles the viewer is served: overlay tiles
+    /// (whole, fractional, batched, prefetched) and base image tiles
+    ///
+    /// with `Auto` python decides per tile: png for tiles with transparent
+    /// pixels, which jpeg can't keep, and for flat ones (segment fills,
+    /// empty background) whose luminance entropy is below 4 bits, where png
+    /// is smaller and exact. jpeg for the rest, i.e. photographic tissue.
+    /// each `TileResponse` reports what was picked in `mime`. the pick
+    /// depends on the tile's pixels only, so it is the same every time, but
+    /// which tiles end up png can change with a replot; force `Jpeg` or
+    /// `Png` where output must not depend on content, e.g. for tests or
+    /// pixel diffs. exports, comparison and diff tiles keep their own
+    /// formats. tiles cached in the previous format are dropped
+    pub fn set_tile_format(&mut self, mode: TileFormatMode) {
+        if self.tiles.set_format(mode) {
+            self.base_tiles.clear();
+        }
+    }
+
+    pub fn tile_format(&self) -> TileFormatMode {
+        self.tiles.format()
+    }
+}
+
+/// speed versus sharpness of rendered images
+///
+/// for a plot it picks how the pyramid is downsampled and encoded:
+/// - `Fast`: nearest neighbour, jpeg quality 75. plots quickest but segment
+///   borders alias into jagged lines and moire at low zoom
+/// - `Balanced`: lanczos, jpeg quality 85. the default, what plots always
+///   looked like
+/// - `High`: lanczos, jpeg quality 95 without chroma subsampling. crisp
+///   colored borders, tiles take roughly twice the bytes
+///
+/// for a tile `Fast` serves a half resolution version of the stored tile
+/// (jpeg quality 60, about a quarter of the bytes to encode, pass and
+/// decode) for the viewer to stretch while panning. `Balanced` and `High`
+/// both serve the stored tile, it can't be sharper than its plot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderQuality {
+    Fast,
+    #[default]
+    Balanced,
+    High,
+}
+
+impl RenderQuality {
+    /// the tile variant this quality is served as, keeps `Balanced` and
+    /// `High` requests on one cache entry
+    fn for_tiles(self) -> RenderQuality {
+        match self {
+            RenderQuality::Fast => RenderQuality::Fast,
+            RenderQuality::Balanced | RenderQuality::High => RenderQuality::Balanced,
+        }
+    }
+}
+
+/// fractional zooms are rounded to this many steps per level, see
+/// `PythonBridge::get_fractional_tile`
+pub const ZOOM_STEPS_PER_LEVEL: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct TileKey {
+    overlay_id: String,
+    generation: u64,
+    zoom: i32,
+    x: i32,
+    y: i32,
+    /// as asked for, `Auto` tiles are cached apart from forced ones. the
+    /// format python resolves an auto tile to follows from its pixels, so
+    /// the key still determines it
+    format: TileFormatMode,
+    /// `Fast` tiles are a different image than the stored one
+    quality: RenderQuality,
+}
+
+/// a tile as the cache holds it
+enum CachedTile {
+    /// as python sent it
+    Encoded(TileResponse),
+    /// the image bytes decoded from base64, a quarter smaller, with
+    /// `meta.tile` left empty. encoded again on every hit
+    Compact { meta: TileResponse, bytes: Vec<u8> },
+}
+
+impl CachedTile {
+    /// `tile` as it should be cached, tiles that aren't valid base64 are
+    /// kept as they are
+    fn pack(tile: TileResponse, compact: bool) -> Self {
+        if !compact {
+            return CachedTile::Encoded(tile);
+        }
+        match decode_base64(&tile.tile) {
+            Ok(bytes) => CachedTile::Compact {
+                meta: TileResponse {
+                    tile: String::new(),
+                    ..tile
+                },
+                bytes,
+            },
+            Err(_) => CachedTile::Encoded(tile),
+        }
+    }
+
+    fn unpack(&self) -> TileResponse {
+        match self {
+            CachedTile::Encoded(tile) => tile.clone(),
+            CachedTile::Compact { meta, bytes } => TileResponse {
+                tile: encode_base64(bytes),
+                ..meta.clone()
+            },
+        }
+    }
+
+    /// what the tile counts against the cache's capacity
+    fn size(&self) -> usize {
+        match self {
+            CachedTile::Encoded(tile) => tile.tile.len(),
+            CachedTile::Compact { bytes, .. } => bytes.len(),
+        }
+    }
+}
+
+/// LRU cache of tiles bounded by the total size of their encoded data
+///
+/// tiles are requested over and over while panning back and forth, caching
+/// them here saves a python round trip per tile
+///
+/// tiles on pinned levels are kept outside the LRU and never evicted. they
+/// count against the overall capacity but may only fill
+/// `pinned_capacity_bytes` of it, a pinned tile past that is cached as a
+/// regular one. a regular tile that doesn't fit next to them isn't cached
+/// at all. with `compact` tiles are held as bytes instead of base64,
+/// see `PythonBridgeBuilder::compact_tile_cache`
+struct TileCache {
+    entries: HashMap<TileKey, (CachedTile, u64)>,
+    /// last-use tick -> key, oldest first
+    order: BTreeMap<u64, TileKey>,
+    tick: u64,
+    bytes: usize,
+    capacity_bytes: usize,
+    /// overlay id -> pinned zoom levels
+    pinned_levels: HashMap<String, HashSet<i32>>,
+    pinned: HashMap<TileKey, CachedTile>,
+    pinned_bytes: usize,
+    pinned_capacity_bytes: usize,
+    compact: bool,
+    hits: u64,
+    misses: u64,
+    /// tiles pushed out of the LRU to make room, see `evict_until_fits`
+    evictions: u64,
+}
+
+impl TileCache {
+    fn new(capacity_bytes: usize, pinned_capacity_bytes: usize, compact: bool) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            capacity_bytes,
+            pinned_levels: HashMap::new(),
+            pinned: HashMap::new(),
+            pinned_bytes: 0,
+            pinned_capacity_bytes: pinned_capacity_bytes.min(capacity_bytes),
+            compact,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    fn get(&mut self, key: &TileKey) -> Option<TileResponse> {
+        let tile = self.touch(key);
+        self.record_lookup(tile.is_some());
+        tile
+    }
+
+    /// `get` without counting a lookup, for probing several keys on behalf
+    /// of one request, which then counts itself with `record_lookup`
+    fn touch(&mut self, key: &TileKey) -> Option<TileResponse> {
+        if let Some(tile) = self.pinned.get(key) {
+            return Some(tile.unpack());
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+
+        let (tile, last_used) = self.entries.get_mut(key)?;
+        self.order.remove(last_used);
+        *last_used = tick;
+        self.order.insert(tick, key.clone());
+        Some(tile.unpack())
+    }
+
+    fn record_lookup(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    /// drops every tile, pinned levels stay pinned
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+        self.pinned.clear();
+        self.pinned_bytes = 0;
+    }
+
+    fn insert(&mut self, key: TileKey, tile: TileResponse) {
+        let tile = CachedTile::pack(tile, self.compact);
+        self.insert_cached(key, tile);
+    }
+
+    fn insert_cached(&mut self, key: TileKey, tile: CachedTile) {
+        let size = tile.size();
+        if size > self.capacity_bytes {
+            return;
+        }
+
+        self.tick += 1;
+        self.remove(&key);
+
+        if self.is_pinned(&key) {
+            // a re-plot bumps the generation, the old pinned tiles are dead
+            let stale: Vec<TileKey> = self
+                .pinned
+                .keys()
+                .filter(|k| k.overlay_id == key.overlay_id && k.generation < key.generation)
+                .cloned()
+                .collect();
+            for k in stale {
+                self.remove(&k);
+            }
+
+            if self.pinned_bytes + size <= self.pinned_capacity_bytes {
+                self.evict_until_fits(size);
+                self.pinned_bytes += size;
+                self.pinned.insert(key, tile);
+                return;
+            }
+        }
+
+        self.evict_until_fits(size);
+        // pinned tiles hold the rest and can't be evicted
+        if self.bytes + self.pinned_bytes + size > self.capacity_bytes {
+            return;
+        }
+        self.bytes += size;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (tile, self.tick));
+    }
+
+    fn is_pinned(&self, key: &TileKey) -> bool {
+        self.pinned_levels
+            .get(&key.overlay_id)
+            .is_some_and(|levels| levels.contains(&key.zoom))
+    }
+
+    fn remove(&mut self, key: &TileKey) -> Option<CachedTile> {
+        if let Some(tile) = self.pinned.remove(key) {
+            self.pinned_bytes -= tile.size();
+            return Some(tile);
+        }
+        let (tile, last_used) = self.entries.remove(key)?;
+        self.order.remove(&last_used);
+        self.bytes -= tile.size();
+        Some(tile)
+    }
+
+    /// evict unpinned tiles until `size` more bytes fit
+    fn evict_until_fits(&mut self, size: usize) {
+        while self.bytes + self.pinned_bytes + size > self.capacity_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = self.entries.remove(&oldest) {
+                self.bytes -= evicted.size();
+                self.evictions += 1;
+            }
+        }
+    }
+
+    /// exempt an overlay's zoom levels from eviction, tiles already cached
+    /// there are moved over
+    fn pin(&mut self, overlay_id: &str, zoom_levels: &[i32]) {
+        self.pinned_levels
+            .entry(overlay_id.to_string())
+            .or_default()
+            .extend(zoom_levels);
+
+        let cached: Vec<TileKey> = self
+            .entries
+            .keys()
+            .filter(|k| k.overlay_id == overlay_id && zoom_levels.contains(&k.zoom))
+            .cloned()
+            .collect();
+        for key in cached {
+            if let Some(tile) = self.remove(&key) {
+                self.insert_cached(key, tile);
+            }
+        }
+    }
+
+    fn pinned_levels(&self, overlay_id: &str) -> Vec<i32> {
+        let mut levels: Vec<i32> = self
+            .pinned_levels
+            .get(overlay_id)
+            .map(|levels| levels.iter().copied().collect())
+            .unwrap_or_default();
+        levels.sort_unstable();
+        levels
+    }
+
+    /// return an overlay's levels to the LRU, all of them for `None`
+    fn unpin(&mut self, overlay_id: &str, zoom_levels: Option<&[i32]>) {
+        match zoom_levels {
+            Some(zooms) => {
+                if let Some(levels) = self.pinned_levels.get_mut(overlay_id) {
+                    levels.retain(|zoom| !zooms.contains(zoom));
+                    if levels.is_empty() {
+                        self.pinned_levels.remove(overlay_id);
+                    }
+                }
+            }
+            None => {
+                self.pinned_levels.remove(overlay_id);
+            }
+        }
+
+        let released: Vec<TileKey> = self
+            .pinned
+            .keys()
+            .filter(|k| k.overlay_id == overlay_id && !self.is_pinned(k))
+            .cloned()
+            .collect();
+        for key in released {
+            if let Some(tile) = self.remove(&key) {
+                self.insert_cached(key, tile);
+            }
+        }
+    }
+
+    fn stats(&self) -> TileCacheStats {
+        let lookups = self.hits + self.misses;
+        TileCacheStats {
+            entries: self.entries.len() + self.pinned.len(),
+            bytes: self.bytes + self.pinned_bytes,
+            capacity_bytes: self.capacity_bytes,
+            pinned_bytes: self.pinned_bytes,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            hit_rate: (lookups > 0).then(|| self.hits as f64 / lookups as f64),
+            compact: self.compact,
+        }
+    }
+
+    /// start counting hits, misses and evictions from zero, the tiles stay
+    fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+    }
+
+    /// whether `key` is held and where, without counting a lookup or
+    /// touching its recency
+    fn status(&self, key: &TileKey) -> TileCacheStatus {
+        if self.pinned.contains_key(key) {
+            TileCacheStatus::Pinned
+        } else if self.entries.contains_key(key) {
+            TileCacheStatus::Cached
+        } else {
+            TileCacheStatus::Missing
+        }
+    }
+
+    /// every cached tile, pinned ones first and then least recently used
+    /// first, the order that inserting them again keeps the recency
+    fn tiles(&self) -> Vec<(TileKey, TileResponse)> {
+        let pinned = self.pinned.iter().map(|(key, tile)| (key.clone(), tile.unpack()));
+        let lru = self.order.values().filter_map(|key| {
+            let (tile, _) = self.entries.get(key)?;
+            Some((key.clone(), tile.unpack()))
+        });
+        pinned.chain(lru).collect()
+    }
+}
+
+#[cfg(test)]
+mod tile_cache_tests {
+    use super::*;
+
+    fn key(zoom: i32, x: i32) -> TileKey {
+        TileKey {
+            overlay_id: "o".to_string(),
+            generation: 0,
+            zoom,
+            x,
+            y: 0,
+            format: TileFormatMode::Png,
+            quality: RenderQuality::Balanced,
+        }
+    }
+
+    fn tile(bytes: usize) -> TileResponse {
+        TileResponse {
+            tile: "a".repeat(bytes),
+            format: "png".to_string(),
+            mime: None,
+            lod: None,
+            premultiplied: false,
+        }
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_tile() {
+        let mut cache = TileCache::new(10, 0, false);
+        cache.insert(key(1, 0), tile(4));
+        cache.insert(key(1, 1), tile(4));
+        cache.get(&key(1, 0));
+        cache.insert(key(1, 2), tile(4));
+
+        assert!(cache.get(&key(1, 0)).is_some());
+        assert!(cache.get(&key(1, 1)).is_none());
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn refuses_a_tile_when_pinned_levels_fill_it() {
+        let mut cache = TileCache::new(10, 10, false);
+        cache.pin("o", &[0]);
+        cache.insert(key(0, 0), tile(10));
+        cache.insert(key(1, 0), tile(4));
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes), (1, 10));
+        assert!(cache.get(&key(1, 0)).is_none());
+        assert!(cache.get(&key(0, 0)).is_some());
+    }
+}
+
+/// occupancy and lookups of a tile cache since the bridge started or the
+/// last `reset_tile_cache_stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct TileCacheStats {
+    /// pinned tiles included
+    pub entries: usize,
+    /// what the tiles count against `capacity_bytes`, their compact size
+    /// with `compact`
+    pub bytes: usize,
+    pub capacity_bytes: usize,
+    /// the part of `bytes` on pinned levels, see `PythonBridge::pin_tiles`
+    pub pinned_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// tiles dropped to make room for others. many evictions with a low
+    /// `hit_rate` while panning over the same area mean the cache is too
+    /// small for it
+    pub evictions: u64,
+    /// hits over lookups, `None` before the first lookup
+    pub hit_rate: Option<f64>,
+    pub compact: bool,
+}
+
+impl PythonBridge {
+    /// the overlay tile cache's numbers, e.g. to compare hit rates with and
+    /// without `PythonBridgeBuilder::compact_tile_cache`
+    pub fn tile_cache_stats(&self) -> TileCacheStats {
+        self.tiles.cache().stats()
+    }
+
+    /// the same for the base image tiles of `get_base_image_tile`, a
+    /// quarter of the overlay cache's size
+    pub fn base_tile_cache_stats(&self) -> TileCacheStats {
+        self.base_tiles.stats()
+    }
+
+    /// zero the lookup counters of both tile caches, e.g. before trying a
+    /// panning pattern. cached tiles are kept
+    pub fn reset_tile_cache_stats(&mut self) {
+        self.tiles.cache().reset_stats();
+        self.base_tiles.reset_stats();
+    }
+}
+
+impl PythonBridge {
+    /// keep tiles of these zoom levels cached no matter how much else is
+    /// fetched, e.g. the levels behind a minimap
+    ///
+    /// only marks the levels, tiles are cached as they are fetched. pinned
+    /// tiles may use at most `PythonBridgeBuilder::pinned_tile_bytes` of the
+    /// cache, tiles past that are cached normally. the overview levels
+    /// fetched by plot warmup are pinned automatically
+    pub fn pin_tiles(&mut self, overlay_id: &str, zoom_levels: &[i32]) {
+        self.tiles.cache().pin(overlay_id, zoom_levels);
+    }
+
+    /// let tiles of these levels be evicted again
+    pub fn unpin_tiles(&mut self, overlay_id: &str, zoom_levels: &[i32]) {
+        self.tiles.cache().unpin(overlay_id, Some(zoom_levels));
+    }
+}
+
+/// position of a tile in an overlay's pyramid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TileCoord {
+    pub zoom: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl TileCoord {
+    /// the tile one level down that covers this one, `None` at zoom 0
+    ///
+    /// each level doubles the grid, so the parent covers a 2x2 block of
+    /// children
+    pub fn parent(self) -> Option<TileCoord> {
+        if self.zoom <= 0 {
+            return None;
+        }
+        Some(TileCoord {
+            zoom: self.zoom - 1,
+            x: self.x.div_euclid(2),
+            y: self.y.div_euclid(2),
+        })
+    }
+}
+
+/// best tile available right now, see `get_tile_with_fallback`
+#[derive(Debug, Clone, Serialize)]
+pub struct FallbackTile {
+    pub tile: TileResponse,
+    /// where `tile` actually comes from. when provisional this is an
+    /// ancestor and the requested tile is the
+    /// `2^(zoom - source.zoom)`-th part of it, to be scaled up
+    pub source: TileCoord,
+    /// a lower-resolution stand-in, request the real tile to replace it
+    pub provisional: bool,
+}
+
+impl PythonBridge {
+    /// the requested tile if cached, otherwise the closest cached ancestor
+    ///
+    /// keeps zoom-in from flashing blank tiles: the viewer draws the scaled
+    /// up stand-in immediately and then asks `get_tissue_overlay_tile` for
+    /// the real one. when no ancestor is cached either the real tile is
+    /// fetched here
+    pub fn get_tile_with_fallback(
+        &mut self,
+        overlay_id: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+    ) -> Result<FallbackTile, BridgeError> {
+        self.serve_tiles(|tiles, call| tiles.get_tile_with_fallback(overlay_id, zoom, x, y, call))
+    }
+
+    /// every tile covering a pixel rectangle of one zoom level
+    ///
+    /// `x0, y0` inclusive and `x1, y1` exclusive, in that level's pixels.
+    /// the rectangle is clipped to the level so off-image tiles are never
+    /// requested. each tile carries its own result, one failed tile doesn't
+    /// hide the rest, see `get_tiles_batch`
+    pub fn get_tiles_for_region(
+        &mut self,
+        overlay_id: &str,
+        zoom: i32,
+        x0: i64,
+        y0: i64,
+        x1: i64,
+        y1: i64,
+    ) -> Result<Vec<BatchResult>, BridgeError> {
+        self.serve_tiles(|tiles, call| {
+            tiles.get_tiles_for_region(overlay_id, zoom, x0, y0, x1, y1, call)
+        })
+    }
+}
+
+impl TileService {
+    /// see `PythonBridge::get_tile_with_fallback`
+    pub fn get_tile_with_fallback(
+        &self,
+        overlay_id: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        call: &mut TileCall,
+    ) -> Result<FallbackTile, BridgeError> {
+        let asked = TileCoord { zoom, x, y };
+        let requested = self.flip_tile(overlay_id, asked);
+        let format = self.format();
+
+        // one lookup for the request: a hit only for the tile asked for, a
+        // miss for a stand-in. without either `fetch_tile_at` counts the miss
+        let mut candidate = Some(requested);
+        while let Some(coord) = candidate {
+            let key = self.tile_key(overlay_id, coord.zoom, coord.x, coord.y, format, RenderQuality::Balanced);
+            let cached = {
+                let mut cache = self.cache();
+                let cached = cache.touch(&key);
+                if cached.is_some() {
+                    cache.record_lookup(coord == requested);
+                }
+                cached
+            };
+            if let Some(tile) = cached {
+                return Ok(FallbackTile {
+                    tile: self.after_cache(tile),
+                    source: self.flip_tile(overlay_id, coord),
+                    provisional: coord != requested,
+                });
+            }
+            candidate = coord.parent();
+        }
+
+        let tile = self.fetch_tile_at(
+            overlay_id,
+            zoom,
+            requested.x,
+            requested.y,
+            format,
+            RenderQuality::Balanced,
+            call,
+        )?;
+        Ok(FallbackTile {
+            tile: self.after_cache(tile),
+            source: asked,
+            provisional: false,
+        })
+    }
+
+    /// see `PythonBridge::get_tiles_for_region`
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_tiles_for_region(
+        &self,
+        overlay_id: &str,
+        zoom: i32,
+        x0: i64,
+        y0: i64,
+        x1: i64,
+        y1: i64,
+        call: &mut TileCall,
+    ) -> Result<Vec<BatchResult>, BridgeError> {
+        let queue = self.region_queue(overlay_id, zoom, x0, y0, x1, y1)?;
+        self.fetch_by_priority(overlay_id, queue, call)
+    }
+
+    /// the tiles under a pixel rectangle with their distance from its
+    /// center, in grid order. rectangle and tiles count from the overlay's
+    /// origin
+    fn region_queue(
+        &self,
+        overlay_id: &str,
+        zoom: i32,
+        x0: i64,
+        y0: i64,
+        x1: i64,
+        y1: i64,
+    ) -> Result<Vec<(TileCoord, f64)>, BridgeError> {
+        let info = self.info(overlay_id)?;
+        if !(0..=info.max_zoom).contains(&zoom) {
+            return Err(BridgeError::Protocol(format!(
+                "zoom {} outside 0..={}",
+                zoom, info.max_zoom
+            )));
+        }
+
+        let (cols, rows) = info.tile_grid(zoom);
+        let (y0, y1) = info.flip_span(y0, y1, i64::from(info.level_size(zoom).1));
+        let tile_size = info.tile_size.max(1) as i64;
+        // tile index range covering [start, end) pixels, clipped to the grid
+        let span = |start: i64, end: i64, count: i32| {
+            let (start, end) = (start.min(end), start.max(end));
+            let first = start.max(0) / tile_size;
+            let last = (end.clamp(0, count as i64 * tile_size) + tile_size - 1) / tile_size;
+            first as i32..last as i32
+        };
+
+        // the region is typically the viewport, so its middle fills first
+        let center = ((x0 + x1) as f64 / 2.0, (y0 + y1) as f64 / 2.0);
+        let mut queue = Vec::new();
+        for y in span(y0, y1, rows) {
+            for x in span(x0, x1, cols) {
+                let coord = TileCoord { zoom, x, y };
+                let distance = center_distance(coord, info.tile_size, center);
+                queue.push((info.flip_tile(coord), distance));
+            }
+        }
+        Ok(queue)
+    }
+}
+
+// ===== Tile Service =====
+
+/// what a `TileService` call brings along, the counterpart of the bridge's
+/// `with_timeout`, `with_request_tag` and `with_warnings`
+#[derive(Debug, Clone, Default)]
+pub struct TileCall {
+    /// response deadline of each request, see `PythonBridge::with_timeout`.
+    /// `None` keeps the usual deadlines
+    pub timeout: Option<Duration>,
+    /// see `PythonBridge::with_request_tag`
+    pub tag: Option<String>,
+    /// what python warned about during the call, each warning also goes to
+    /// `PythonBridgeBuilder::on_python_warning`
+    pub warnings: Vec<PythonWarning>,
+    /// a miss while paused fails with `TilesPaused` instead of queueing,
+    /// for calls through the bridge, see `PythonBridge::pause_tiles`
+    fail_when_paused: bool,
+}
+
+/// serves overlay tiles through `&self`, while another command holds the
+/// bridge
+///
+/// the bridge keeps its overlay tiles here (cache, processors, format and
+/// what the registered overlays look like) and hands out the service like
+/// its gates, see `PythonBridge::tile_service`. `AppState` sends the tile
+/// commands and viewport subscriptions through it instead of locking the
+/// bridge, so tiles keep coming during a plot or an export and concurrent
+/// tile requests run up to the tile lane's limit. its locks are held for
+/// lookups only, never across a round trip
+///
+/// it can't respawn python. with the child gone (crashed, stopped while
+/// idle) a request fails with `ProcessExited` and the next one through the
+/// bridge starts a new child, `AppState` retries there
+pub struct TileService {
+    /// the current child, pointed at the new one on every respawn
+    connection: Mutex<Weak<Connection>>,
+    namespace: String,
+    status: Arc<Mutex<BridgeStatus>>,
+    shutdown: CancellationToken,
+    gates: Arc<CommandGates>,
+    diagnostics: Arc<Diagnostics>,
+    idle: Option<Arc<IdleWatch>>,
+    /// the bridge's deadlines, see `PythonBridge::timeout_for`
+    command_timeouts: HashMap<PythonCommand, Duration>,
+    deadline: Option<Duration>,
+    direct_decode: HashSet<PythonCommand>,
+    on_python_warning: Option<PythonWarningHandler>,
+    /// the bridge's overlays, kept up to date by its registry
+    overlays: TileSources,
+    generations: Arc<Generations>,
+    /// recently served tiles, keyed by overlay generation
+    cache: Mutex<TileCache>,
+    /// how long the latest overlay tiles fetched from python took, newest
+    /// first, see `get_tile_provenance`
+    fetches: Mutex<VecDeque<(TileKey, f64)>>,
+    /// adjustments applied to served tiles, see `TileProcessor`
+    processing: RwLock<TileProcessing>,
+    /// format of the tiles the viewer is served, see `set_tile_format`
+    format: Mutex<TileFormatMode>,
+}
+
+impl PythonBridge {
+    /// the overlay tiles, for serving them without holding the bridge
+    pub fn tile_service(&self) -> Arc<TileService> {
+        Arc::clone(&self.tiles)
+    }
+}
+
+impl TileService {
+    /// see `PythonBridge::get_tissue_overlay_tile`
+    pub fn get_tissue_overlay_tile(
+        &self,
+        overlay_id: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        quality: RenderQuality,
+        call: &mut TileCall,
+    ) -> Result<TileResponse, BridgeError> {
+        let TileCoord { x, y, .. } = self.flip_tile(overlay_id, TileCoord { zoom, x, y });
+        let tile = self.fetch_tile_at(overlay_id, zoom, x, y, self.format(), quality, call)?;
+        Ok(self.after_cache(tile))
+    }
+
+    /// see `PythonBridge::get_fractional_tile`
+    pub fn get_fractional_tile(
+        &self,
+        overlay_id: &str,
+        zoom: f64,
+        x: i32,
+        y: i32,
+        quality: RenderQuality,
+        call: &mut TileCall,
+    ) -> Result<TileResponse, BridgeError> {
+        if !zoom.is_finite() || zoom < 0.0 {
+            return Err(BridgeError::Protocol(format!("Invalid zoom level {}", zoom)));
+        }
+        let steps = (zoom * f64::from(ZOOM_STEPS_PER_LEVEL)).round() as i64;
+        let level = i32::try_from(steps / i64::from(ZOOM_STEPS_PER_LEVEL))
+            .map_err(|_| BridgeError::Protocol(format!("Invalid zoom level {}", zoom)))?;
+        let step = (steps % i64::from(ZOOM_STEPS_PER_LEVEL)) as u32;
+        if step == 0 {
+            return self.get_tissue_overlay_tile(overlay_id, level, x, y, quality, call);
+        }
+
+        let fraction = f64::from(step) / f64::from(ZOOM_STEPS_PER_LEVEL);
+        let source = self.source(overlay_id);
+        let y = match source {
+            // the in-between size has its own number of rows
+            Some(TileSource { ref info, .. }) if info.origin == Origin::BottomLeft => {
+                let scale = 2f64.powf(f64::from(level) + fraction - f64::from(info.max_zoom));
+                let height = (f64::from(info.height) * scale) as u32;
+                height.div_ceil(info.tile_size.max(1)) as i32 - 1 - y
+            }
+            _ => y,
+        };
+        let quality = quality.for_tiles();
+        let key = TileKey {
+            // a different image than the stored level's tile
+            overlay_id: format!("{}\u{0}zoom+{}/{}", overlay_id, step, ZOOM_STEPS_PER_LEVEL),
+            generation: self.generation(overlay_id),
+            zoom: level,
+            x,
+            y,
+            format: self.format(),
+            quality,
+        };
+        // bound first, a guard in the `if let` would live through the block
+        let cached = self.cache().get(&key);
+        if let Some(tile) = cached {
+            return Ok(self.after_cache(tile));
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "zoom": level,
+            "x": x,
+            "y": y,
+            "format": key.format,
+            "quality": quality,
+            "fraction": fraction
+        });
+        let mut tile: TileResponse =
+            self.request_as(PythonCommand::GetTissueOverlayTile, params, call)?;
+        self.before_cache(&mut tile);
+        self.cache().insert(key, tile.clone());
+        Ok(self.after_cache(tile))
+    }
+
+    /// a stored tile from the cache or python, as cached: without the
+    /// processors that run after the cache. `y` counts from the top
+    #[allow(clippy::too_many_arguments)]
+    fn fetch_tile_at(
+        &self,
+        overlay_id: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        format: TileFormatMode,
+        quality: RenderQuality,
+        call: &mut TileCall,
+    ) -> Result<TileResponse, BridgeError> {
+        let quality = quality.for_tiles();
+        let key = self.tile_key(overlay_id, zoom, x, y, format, quality);
+
+        let cached = self.cache().get(&key);
+        if let Some(tile) = cached {
+            return Ok(tile);
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "zoom": zoom,
+            "x": x,
+            "y": y,
+            "format": format,
+            "quality": quality
+        });
+        let started = Instant::now();
+        let mut tile: TileResponse =
+            self.request_as(PythonCommand::GetTissueOverlayTile, params, call)?;
+        self.record_tile_fetch(key.clone(), started.elapsed().as_secs_f64() * 1000.0);
+        self.before_cache(&mut tile);
+        self.cache().insert(key, tile.clone());
+        Ok(tile)
+    }
+
+    /// send a tile command and decode its `data` into `T`, what
+    /// `send_command_as` is for the bridge minus the respawn
+    fn request_as<T: DeserializeOwned>(
+        &self,
+        command: PythonCommand,
+        params: serde_json::Value,
+        call: &mut TileCall,
+    ) -> Result<T, BridgeError> {
+        let started = Instant::now();
+        let params_preview = preview_json(&params, DIAGNOSTICS_PARAMS_BYTES);
+        let _active = self.idle.as_ref().map(IdleWatch::begin);
+        let result = self.exchange(command, params, call);
+        self.diagnostics
+            .record_command(command, params_preview, started.elapsed(), &result);
+
+        let body = result?;
+        let started = Instant::now();
+        let decoded = body.decode(command);
+        self.diagnostics.record_decode(command, started.elapsed());
+        decoded
+    }
+
+    fn exchange(
+        &self,
+        command: PythonCommand,
+        params: serde_json::Value,
+        call: &mut TileCall,
+    ) -> Result<Body, BridgeError> {
+        self.check_alive()?;
+        let (started, at_ms) = (Instant::now(), unix_millis());
+        let _permit = if call.fail_when_paused {
+            self.gates.acquire(command)?
+        } else {
+            self.gates.acquire_queued(command)?
+        };
+
+        let routing_key = params
+            .get("overlay_id")
+            .and_then(|id| id.as_str())
+            .and_then(|id| self.source(id)?.dataset_id);
+        let dispatch = Dispatch {
+            lane: command.lane(),
+            idempotent: command.is_idempotent(),
+            direct: self.direct_decode.contains(&command),
+            routing_key,
+            tag: call.tag.clone(),
+            trace_id: self.diagnostics.next_trace_id(),
+            timeout: call
+                .timeout
+                .or_else(|| self.command_timeouts.get(&command).copied())
+                .or(self.deadline),
+        };
+        let connection = self.connection.lock().unwrap().upgrade();
+        let Some(connection) = connection else {
+            return Err(BridgeError::ProcessExited);
+        };
+        let reply = match connection.request(&command.wire_name(&self.namespace), params, &dispatch) {
+            // sent again by the bridge once it has respawned python
+            Outcome::Redispatch | Outcome::Answered(Err(BridgeError::ProcessExited)) => {
+                bridge_debug!("PythonBridge: Python exited during {}", command.name());
+                // unless the bridge has replaced the child meanwhile
+                if self.is_current(&connection) {
+                    *self.status.lock().unwrap() = BridgeStatus::Dead;
+                }
+                Err(BridgeError::ProcessExited)
+            }
+            outcome => outcome.into_result(),
+        }?;
+        self.diagnostics.record_reply(command, &dispatch, at_ms, started, &reply);
+        for warning in reply.warnings {
+            let warning = warning.for_command(command);
+            bridge_debug!("PythonBridge: {} warned: {:?}", command.name(), warning);
+            if let Some(handler) = &self.on_python_warning {
+                handler(warning.clone());
+            }
+            call.warnings.push(warning);
+        }
+        Ok(reply.body)
+    }
+
+    /// fail early when there is no child to ask, respawning takes the bridge
+    fn check_alive(&self) -> Result<(), BridgeError> {
+        if self.shutdown.is_cancelled() {
+            return Err(BridgeError::ShuttingDown);
+        }
+        match *self.status.lock().unwrap() {
+            BridgeStatus::Dead | BridgeStatus::Idle => Err(BridgeError::ProcessExited),
+            _ => Ok(()),
+        }
+    }
+
+    /// point at the child a respawn started
+    fn watch(&self, connection: &Arc<Connection>) {
+        *self.connection.lock().unwrap() = Arc::downgrade(connection);
+    }
+
+    /// whether `connection` is still the bridge's child, not one a respawn
+    /// has replaced
+    fn is_current(&self, connection: &Arc<Connection>) -> bool {
+        let current = self.connection.lock().unwrap().upgrade();
+        current.is_some_and(|current| Arc::ptr_eq(&current, connection))
+    }
+
+    fn source(&self, overlay_id: &str) -> Option<TileSource> {
+        self.overlays.read().unwrap().get(overlay_id).cloned()
+    }
+
+    /// the overlay's current generation, registered or not
+    fn generation(&self, overlay_id: &str) -> u64 {
+        match self.overlays.read().unwrap().get(overlay_id) {
+            Some(source) => source.generation,
+            None => self.generations.unregistered(overlay_id),
+        }
+    }
+
+    /// the overlay's geometry, `OverlayNotFound` once it is gone
+    fn info(&self, overlay_id: &str) -> Result<OverlayInfo, BridgeError> {
+        self.source(overlay_id)
+            .map(|source| source.info)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))
+    }
+
+    /// `OverlayInfo::flip_tile` of a registered overlay, an unknown one is
+    /// left for the fetch to report
+    fn flip_tile(&self, overlay_id: &str, coord: TileCoord) -> TileCoord {
+        match self.source(overlay_id) {
+            Some(source) => source.info.flip_tile(coord),
+            None => coord,
+        }
+    }
+
+    /// cache key of an overlay tile at its current generation
+    fn tile_key(
+        &self,
+        overlay_id: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        format: TileFormatMode,
+        quality: RenderQuality,
+    ) -> TileKey {
+        TileKey {
+            overlay_id: overlay_id.to_string(),
+            generation: self.generation(overlay_id),
+            zoom,
+            x,
+            y,
+            format,
+            quality: quality.for_tiles(),
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, TileCache> {
+        self.cache.lock().unwrap()
+    }
+
+    fn format(&self) -> TileFormatMode {
+        *self.format.lock().unwrap()
+    }
+
+    /// switch formats, dropping the tiles cached in the old one. false if
+    /// `mode` is the current format
+    fn set_format(&self, mode: TileFormatMode) -> bool {
+        let mut format = self.format.lock().unwrap();
+        if *format == mode {
+            return false;
+        }
+        *format = mode;
+        self.cache().clear();
+        true
+    }
+
+    fn before_cache(&self, tile: &mut TileResponse) {
+        self.processing.read().unwrap().before_cache(tile);
+    }
+
+    fn after_cache(&self, tile: TileResponse) -> TileResponse {
+        self.processing.read().unwrap().after_cache(tile)
+    }
+}
+
+// ===== Tile Provenance =====
+
+/// tile fetch times kept for `get_tile_provenance`
+const MAX_TILE_FETCHES: usize = 1024;
+
+/// where a tile is in the bridge's tile cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileCacheStatus {
+    /// on a pinned level, never evicted
+    Pinned,
+    /// in the LRU
+    Cached,
+    /// the next request goes to python
+    Missing,
+}
+
+/// how a tile was produced, see `PythonBridge::get_tile_provenance`
+#[derive(Debug, Clone, Serialize)]
+pub struct TileProvenance {
+    pub overlay_id: String,
+    /// what the overlay was plotted from, `None` for overlays the bridge
+    /// didn't plot
+    pub overlay_params: Option<PlotParams>,
+    /// the overlay's generation, part of the cache key. a tile from before
+    /// the latest (re)plot has an older one and is never served
+    pub generation: u64,
+    /// ms python took to hand the tile over, round trip included, the last
+    /// time this bridge fetched it. `None` if it was never fetched or too
+    /// long ago. eagerly cut overlays were cut during the plot, their cost
+    /// is the overlay's `timings.tile_ms`
+    pub render_ms: Option<f64>,
+    /// segments whose bounding box overlaps the tile, including ones a
+    /// `segment_limit` left out of the render
+    pub n_segments_in_tile: u64,
+    /// whether the tile is served from the cache at its current generation
+    pub cache_status: TileCacheStatus,
+    /// the tile in full resolution pixels, `[x0, y0, x1, y1]` end exclusive
+    pub bbox: [u32; 4],
+}
+
+impl PythonBridge {
+    /// the parameters and stats behind one overlay tile, for debugging a
+    /// tile that looks wrong (misregistered, stale)
+    ///
+    /// metadata only, nothing is rendered and the cache's recency and
+    /// hit counts don't move. python is asked for the segment count,
+    /// which builds the segmentation's index on first use
+    pub fn get_tile_provenance(
+        &mut self,
+        overlay_id: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        quality: RenderQuality,
+    ) -> Result<TileProvenance, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        let (cols, rows) = info.tile_grid(zoom);
+        let inside = (0..cols).contains(&x) && (0..rows).contains(&y);
+        if !(0..=info.max_zoom).contains(&zoom) || !inside {
+            return Err(BridgeError::Protocol(format!(
+                "Tile ({}, {}) at zoom {} is outside overlay {}",
+                x, y, zoom, overlay_id
+            )));
+        }
+        let TileCoord { y: row, .. } = info.flip_tile(TileCoord { zoom, x, y });
+        let span = u64::from(info.tile_size) << (info.max_zoom - zoom);
+        let edge = |tile: i32, size: u32| (tile as u64 * span).min(u64::from(size)) as i64;
+        let (y0, y1) = info.flip_span(
+            edge(row, info.height),
+            edge(row + 1, info.height),
+            i64::from(info.height),
+        );
+        let bbox = [
+            edge(x, info.width) as u32,
+            y0 as u32,
+            edge(x + 1, info.width) as u32,
+            y1 as u32,
+        ];
+
+        let key = self.tiles.tile_key(overlay_id, zoom, x, row, self.tiles.format(), quality);
+        let render_ms = self
+            .tiles
+            .fetches
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(fetched, _)| *fetched == key)
+            .map(|&(_, ms)| ms);
+        let cache_status = self.tiles.cache().status(&key);
+        let overlay_params = self.overlays.params(overlay_id).cloned();
+        let segments = self.query_segments_in_bbox(overlay_id, bbox, 0, 1)?;
+        Ok(TileProvenance {
+            overlay_id: overlay_id.to_string(),
+            overlay_params,
+            generation: key.generation,
+            render_ms,
+            n_segments_in_tile: segments.total,
+            cache_status,
+            bbox,
+        })
+    }
+}
+
+impl TileService {
+    fn record_tile_fetch(&self, key: TileKey, ms: f64) {
+        let mut fetches = self.fetches.lock().unwrap();
+        fetches.retain(|(fetched, _)| *fetched != key);
+        fetches.push_front((key, ms));
+        fetches.truncate(MAX_TILE_FETCHES);
+    }
+}
+
+// ===== Cache Snapshots =====
+
+/// format of the files `save_cache_snapshot` writes
+const CACHE_SNAPSHOT_VERSION: u32 = 1;
+
+/// the overlay tile cache as `save_cache_snapshot` writes it, json
+#[derive(Serialize, Deserialize)]
+struct CacheSnapshot {
+    version: u32,
+    overlays: Vec<SnapshotOverlay>,
+    tiles: Vec<SnapshotTile>,
+}
+
+/// what the cached tiles of an overlay were rendered from
+#[derive(Serialize, Deserialize)]
+struct SnapshotOverlay {
+    overlay_id: String,
+    generation: u64,
+    params: Option<PlotParams>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotTile {
+    key: TileKey,
+    tile: TileResponse,
+}
+
+/// how many tiles a cache snapshot held and how many made it into the
+/// cache, see `PythonBridge::load_cache_snapshot`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SnapshotCounts {
+    /// in the file
+    pub tiles: usize,
+    /// written to the file, or put back into the cache on load
+    pub restored: usize,
+    /// of overlays that are gone, were plotted again or plotted from other
+    /// params since the snapshot. always 0 on save
+    pub discarded: usize,
+}
+
+fn write_snapshot(path: &Path, snapshot: &CacheSnapshot) -> Result<(), BridgeError> {
+    let file = std::fs::File::create(path).map_err(|e| BridgeError::Io(e.to_string()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    serde_json::to_writer(&mut writer, snapshot).map_err(|e| BridgeError::Io(e.to_string()))?;
+    writer.flush().map_err(|e| BridgeError::Io(e.to_string()))
+}
+
+impl PythonBridge {
+    /// write the overlay tile cache, pinned tiles included, to one file at
+    /// `path`
+    ///
+    /// explicit archival of a known good working set, e.g. to re-prime the
+    /// cache after clearing it. tiles are kept with the generation of
+    /// their overlay and the params it was plotted from, see
+    /// `load_cache_snapshot`. the file is a `.partial` until complete, an
+    /// existing snapshot at `path` is only replaced by a finished one.
+    /// base image tiles aren't included
+    pub fn save_cache_snapshot(&self, path: &Path) -> Result<SnapshotCounts, BridgeError> {
+        let tiles: Vec<SnapshotTile> = self
+            .tiles
+            .cache()
+            .tiles()
+            .into_iter()
+            .filter(|(key, _)| self.overlays.generation(&key.overlay_id) == Some(key.generation))
+            .map(|(key, tile)| SnapshotTile { key, tile })
+            .collect();
+        let mut overlay_ids: Vec<&str> = tiles.iter().map(|t| t.key.overlay_id.as_str()).collect();
+        overlay_ids.sort_unstable();
+        overlay_ids.dedup();
+        let overlays = overlay_ids
+            .into_iter()
+            .filter_map(|overlay_id| {
+                Some(SnapshotOverlay {
+                    overlay_id: overlay_id.to_string(),
+                    generation: self.overlays.generation(overlay_id)?,
+                    params: self.overlays.params(overlay_id).cloned(),
+                })
+            })
+            .collect();
+        let snapshot = CacheSnapshot {
+            version: CACHE_SNAPSHOT_VERSION,
+            overlays,
+            tiles,
+        };
+
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let written = write_snapshot(&partial, &snapshot).and_then(|_| {
+            std::fs::rename(&partial, path).map_err(|e| BridgeError::Io(e.to_string()))
+        });
+        if written.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        written?;
+
+        let count = snapshot.tiles.len();
+        Ok(SnapshotCounts {
+            tiles: count,
+            restored: count,
+            discarded: 0,
+        })
+    }
+
+    /// put the tiles of a `save_cache_snapshot` file back into the cache
+    ///
+    /// a tile is only restored while its overlay is still registered with
+    /// the generation and plot params it had when saved, anything else
+    /// would serve a stale render. generations count plots since the
+    /// bridge started, so a snapshot from an earlier session only matches
+    /// after replaying the same plots in the same order. restored tiles
+    /// go through the usual capacity and pinning, tiles already cached
+    /// are replaced
+    pub fn load_cache_snapshot(&mut self, path: &Path) -> Result<SnapshotCounts, BridgeError> {
+        let file = std::fs::File::open(path).map_err(|e| BridgeError::Io(e.to_string()))?;
+        let snapshot: CacheSnapshot = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| BridgeError::Io(format!("Invalid cache snapshot {:?}: {}", path, e)))?;
+        if snapshot.version != CACHE_SNAPSHOT_VERSION {
+            return Err(BridgeError::Io(format!(
+                "Unsupported cache snapshot version {} in {:?}",
+                snapshot.version, path
+            )));
+        }
+
+        let current: HashSet<(&str, u64)> = snapshot
+            .overlays
+            .iter()
+            .filter(|overlay| {
+                self.overlays.generation(&overlay.overlay_id) == Some(overlay.generation)
+                    && self.overlays.params(&overlay.overlay_id) == overlay.params.as_ref()
+            })
+            .map(|overlay| (overlay.overlay_id.as_str(), overlay.generation))
+            .collect();
+
+        let tiles = snapshot.tiles.len();
+        let mut restored = 0;
+        for SnapshotTile { key, tile } in snapshot.tiles {
+            if current.contains(&(key.overlay_id.as_str(), key.generation)) {
+                self.tiles.cache().insert(key, tile);
+                restored += 1;
+            }
+        }
+        Ok(SnapshotCounts {
+            tiles,
+            restored,
+            discarded: tiles - restored,
+        })
+    }
+}
+
+// ===== Tile Processing =====
+
+/// a rust side adjustment to tiles on their way to the viewer
+///
+/// python's renders stay canonical, a processor only changes what is
+/// served, e.g. brightness while the user drags a slider. register with
+/// `PythonBridge::add_tile_processor`, for the built-in adjustments see
+/// `TileAdjustment`. `map_tile_pixels` does the decoding for processors
+/// that work on pixels. a tile the processor can't handle should be left
+/// as it is
+pub trait TileProcessor: Send + Sync {
+    fn process(&self, tile: &mut TileResponse);
+}
+
+/// when processors run, see `PythonBridgeBuilder::process_tiles_before_cache`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessStage {
+    /// on every tile served, the canonical tile is cached once and a new
+    /// adjustment applies to cached tiles right away. costs a decode and
+    /// encode per tile served
+    #[default]
+    AfterCache,
+    /// once, before the tile is cached. cache hits are free, but changing
+    /// processors drops every cached tile, and tile exports read the
+    /// processed tiles
+    BeforeCache,
+}
+
+/// jpeg quality of tiles re-encoded after processing, above python's 85 so
+/// the second encode doesn't add visible artifacts
+const PROCESSED_JPEG_QUALITY: u8 = 92;
+
+/// decode a tile, run `f` on every rgba pixel and encode it again in the
+/// same format
+pub fn map_tile_pixels(tile: &mut TileResponse, f: impl Fn(&mut [u8; 4])) -> Result<(), String> {
+    let mut pixels = decode_tile_pixels(tile)?;
+    for pixel in pixels.pixels_mut() {
+        f(&mut pixel.0);
+    }
+    tile.tile = encode_tile_pixels(pixels, &tile.format)?;
+    Ok(())
+}
+
+/// a tile's pixels as rgba
+fn decode_tile_pixels(tile: &TileResponse) -> Result<image::RgbaImage, String> {
+    let bytes = decode_base64(&tile.tile)?;
+    Ok(image::load_from_memory(&bytes)
+        .map_err(|e| format!("Can't decode {} tile: {}", tile.format, e))?
+        .into_rgba8())
+}
+
+/// `pixels` encoded as `format` (jpeg or png) and base64'd, for a
+/// `TileResponse`
+fn encode_tile_pixels(pixels: image::RgbaImage, format: &str) -> Result<String, String> {
+    let image = image::DynamicImage::ImageRgba8(pixels);
+    let mut out = Vec::new();
+    let encoded = if format == "jpeg" {
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, PROCESSED_JPEG_QUALITY)
+            .encode_image(&image.to_rgb8())
+    } else {
+        image.write_to(&mut std::io::Cursor::new(&mut out), image::ImageFormat::Png)
+    };
+    encoded.map_err(|e| format!("Can't encode {} tile: {}", format, e))?;
+    Ok(encode_base64(&out))
+}
+
+/// built-in brightness, contrast and gamma, applied in that order to the
+/// color channels. alpha is left alone
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TileAdjustment {
+    /// added to every channel, -1 (black) to 1 (white), 0 leaves it
+    #[serde(default)]
+    pub brightness: f64,
+    /// scales channels away from mid gray, 0 flattens to gray, 1 leaves it
+    #[serde(default = "unit_default")]
+    pub contrast: f64,
+    /// above 1 brightens midtones, below darkens, 1 leaves it
+    #[serde(default = "unit_default")]
+    pub gamma: f64,
+}
+
+fn unit_default() -> f64 {
+    1.0
+}
+
+impl Default for TileAdjustment {
+    fn default() -> Self {
+        TileAdjustment {
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl TileAdjustment {
+    fn validate(&self) -> Result<(), BridgeError> {
+        if !(self.brightness.is_finite() && (-1.0..=1.0).contains(&self.brightness)) {
+            return Err(BridgeError::Protocol(format!(
+                "Brightness must be between -1 and 1, got {}",
+                self.brightness
+            )));
+        }
+        if !(self.contrast.is_finite() && self.contrast >= 0.0) {
+            return Err(BridgeError::Protocol(format!(
+                "Contrast must be a non-negative number, got {}",
+                self.contrast
+            )));
+        }
+        if !(self.gamma.is_finite() && self.gamma > 0.0) {
+            return Err(BridgeError::Protocol(format!(
+                "Gamma must be a positive number, got {}",
+                self.gamma
+            )));
+        }
+        Ok(())
+    }
+
+    fn is_identity(&self) -> bool {
+        *self == TileAdjustment::default()
+    }
+
+    /// the adjusted value of every channel value
+    fn lookup_table(&self) -> [u8; 256] {
+        let mut table = [0; 256];
+        for (value, out) in table.iter_mut().enumerate() {
+            let v = value as f64 / 255.0;
+            let v = ((v - 0.5) * self.contrast + 0.5 + self.brightness).clamp(0.0, 1.0);
+            *out = (v.powf(1.0 / self.gamma) * 255.0).round() as u8;
+        }
+        table
+    }
+}
+
+impl TileProcessor for TileAdjustment {
+    fn process(&self, tile: &mut TileResponse) {
+        if self.is_identity() {
+            return;
+        }
+        let table = self.lookup_table();
+        let adjust = |pixel: &mut [u8; 4]| {
+            for channel in &mut pixel[..3] {
+                *channel = table[usize::from(*channel)];
+            }
+        };
+        if let Err(_e) = map_tile_pixels(tile, adjust) {
+            bridge_debug!("PythonBridge: Serving tile unadjusted: {}", _e);
+        }
+    }
+}
+
+/// the active adjustment and custom processors of a bridge
+#[derive(Default)]
+struct TileProcessing {
+    stage: ProcessStage,
+    adjustment: Option<TileAdjustment>,
+    custom: Vec<Box<dyn TileProcessor>>,
+}
+
+impl TileProcessing {
+    fn apply(&self, tile: &mut TileResponse) {
+        if let Some(ref adjustment) = self.adjustment {
+            adjustment.process(tile);
+        }
+        for processor in &self.custom {
+            processor.process(tile);
+        }
+    }
+
+    /// run the processors on a tile python just sent, if they run before
+    /// the cache
+    fn before_cache(&self, tile: &mut TileResponse) {
+        if self.stage == ProcessStage::BeforeCache {
+            self.apply(tile);
+        }
+    }
+
+    /// run the processors on a tile about to be served, if they run after
+    /// the cache
+    fn after_cache(&self, mut tile: TileResponse) -> TileResponse {
+        if self.stage == ProcessStage::AfterCache {
+            self.apply(&mut tile);
+        }
+        tile
+    }
+}
+
+impl PythonBridge {
+    /// brightness/contrast/gamma for overlay and base image tiles, `None`
+    /// (or the identity) to serve them as python rendered them
+    ///
+    /// runs before any processors added with `add_tile_processor`. with
+    /// `ProcessStage::BeforeCache` the cached tiles are dropped
+    pub fn set_tile_adjustment(
+        &mut self,
+        adjustment: Option<TileAdjustment>,
+    ) -> Result<(), BridgeError> {
+        if let Some(ref adjustment) = adjustment {
+            adjustment.validate()?;
+        }
+        self.tiles.processing.write().unwrap().adjustment = adjustment.filter(|a| !a.is_identity());
+        self.processors_changed();
+        Ok(())
+    }
+
+    pub fn tile_adjustment(&self) -> Option<TileAdjustment> {
+        self.tiles.processing.read().unwrap().adjustment
+    }
+
+    /// run `processor` on every overlay and base image tile served, after
+    /// the adjustment and earlier processors
+    pub fn add_tile_processor(&mut self, processor: Box<dyn TileProcessor>) {
+        self.tiles.processing.write().unwrap().custom.push(processor);
+        self.processors_changed();
+    }
+
+    /// remove the processors added with `add_tile_processor`, the
+    /// adjustment stays
+    pub fn clear_tile_processors(&mut self) {
+        self.tiles.processing.write().unwrap().custom.clear();
+        self.processors_changed();
+    }
+
+    /// cached tiles went through the old processors before the cache
+    fn processors_changed(&mut self) {
+        if self.tiles.processing.read().unwrap().stage == ProcessStage::BeforeCache {
+            self.tiles.cache().clear();
+            self.base_tiles.clear();
+        }
+    }
+}
+
+// ===== Overview Cache =====
+
+/// the last overview rendered for each overlay, for the mini-map
+///
+/// one slot per overlay, apart from the tile LRU so panning around can
+/// never push it out. a slot only serves the generation and size it was
+/// rendered for, asking for another size replaces it. past
+/// `MAX_OVERLAYS` overlays the least recently used slot is dropped
+#[derive(Default)]
+struct OverviewCache {
+    slots: HashMap<String, OverviewSlot>,
+    tick: u64,
+}
+
+struct OverviewSlot {
+    generation: u64,
+    max_dimension: u32,
+    overview: TileResponse,
+    last_used: u64,
+}
+
+impl OverviewCache {
+    /// a mini-map per open overlay, a handful at most
+    const MAX_OVERLAYS: usize = 8;
+
+    fn get(
+        &mut self,
+        overlay_id: &str,
+        generation: u64,
+        max_dimension: u32,
+    ) -> Option<TileResponse> {
+        self.tick += 1;
+        let slot = self.slots.get_mut(overlay_id)?;
+        if slot.generation != generation || slot.max_dimension != max_dimension {
+            return None;
+        }
+        slot.last_used = self.tick;
+        Some(slot.overview.clone())
+    }
+
+    fn insert(
+        &mut self,
+        overlay_id: &str,
+        generation: u64,
+        max_dimension: u32,
+        overview: TileResponse,
+    ) {
+        self.tick += 1;
+        if !self.slots.contains_key(overlay_id) && self.slots.len() >= Self::MAX_OVERLAYS {
+            let oldest = self
+                .slots
+                .iter()
+                .min_by_key(|(_, slot)| slot.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                self.slots.remove(&oldest);
+            }
+        }
+        self.slots.insert(
+            overlay_id.to_string(),
+            OverviewSlot {
+                generation,
+                max_dimension,
+                overview,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, overlay_id: &str) {
+        self.slots.remove(overlay_id);
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+    }
+}
+
+impl PythonBridge {
+    /// the whole overlay as one jpeg whose longer side is at most
+    /// `max_dimension`, for the mini-map
+    ///
+    /// rendered once per overlay generation and size, then served from
+    /// its own slot (see `OverviewCache`) however often the mini-map
+    /// repaints
+    pub fn get_overview(
+        &mut self,
+        overlay_id: &str,
+        max_dimension: u32,
+    ) -> Result<TileResponse, BridgeError> {
+        let generation = self
+            .overlays
+            .generation(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        if max_dimension == 0 {
+            return Err(BridgeError::Protocol("max_dimension must be positive".to_string()));
+        }
+        if let Some(overview) = self.overviews.get(overlay_id, generation, max_dimension) {
+            return Ok(overview);
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "max_dimension": max_dimension
+        });
+        let overview: TileResponse = self.send_command_as(PythonCommand::GetOverview, params)?;
+        self.overviews
+            .insert(overlay_id, generation, max_dimension, overview.clone());
+        Ok(overview)
+    }
+}
+
+// ===== Minimap =====
+
+/// color of the viewport box `get_minimap` draws, opaque so jpeg keeps it
+const MINIMAP_BOX_COLOR: [u8; 4] = [255, 48, 48, 255];
+/// line width of the box in minimap pixels
+const MINIMAP_BOX_WIDTH: u32 = 2;
+
+/// a rectangle in an overlay's full resolution pixels, `x1` and `y1`
+/// exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub x0: f64,
+    pub y0: f64,
+    pub x1: f64,
+    pub y1: f64,
+}
+
+impl Bounds {
+    /// non-empty and inside a `width` x `height` overlay
+    fn validate(&self, width: u32, height: u32) -> Result<(), BridgeError> {
+        let finite = [self.x0, self.y0, self.x1, self.y1].iter().all(|v| v.is_finite());
+        let inside = self.x0 >= 0.0
+            && self.y0 >= 0.0
+            && self.x1 <= f64::from(width)
+            && self.y1 <= f64::from(height);
+        if finite && inside && self.x0 < self.x1 && self.y0 < self.y1 {
+            Ok(())
+        } else {
+            Err(BridgeError::Protocol(format!(
+                "Viewport {:?} is not a non-empty rectangle inside the {}x{} overlay",
+                self, width, height
+            )))
+        }
+    }
+}
+
+impl PythonBridge {
+    /// the overview with `viewport` outlined on it, for a navigation
+    /// mini-map. `max_dimension` is as for `get_overview`
+    ///
+    /// the overview comes from its `OverviewCache` slot, only the box is
+    /// drawn per call, on our side so moving the viewport costs no trip to
+    /// python. the box is in the overlay's own frame (after orientation)
+    /// and at least a pixel across however small the viewport
+    pub fn get_minimap(
+        &mut self,
+        overlay_id: &str,
+        viewport: Bounds,
+        max_dimension: u32,
+    ) -> Result<TileResponse, BridgeError> {
+        let bounds = self.get_overlay_bounds(overlay_id)?;
+        viewport.validate(bounds.width, bounds.height)?;
+        let viewport = match bounds.origin {
+            Origin::TopLeft => viewport,
+            // the overview is an image, drawn top-left
+            Origin::BottomLeft => Bounds {
+                y0: f64::from(bounds.height) - viewport.y1,
+                y1: f64::from(bounds.height) - viewport.y0,
+                ..viewport
+            },
+        };
+
+        let mut minimap = self.get_overview(overlay_id, max_dimension)?;
+        let mut pixels = decode_tile_pixels(&minimap).map_err(BridgeError::Protocol)?;
+        draw_viewport_box(&mut pixels, &viewport, bounds.width, bounds.height);
+        minimap.tile = encode_tile_pixels(pixels, &minimap.format).map_err(BridgeError::Protocol)?;
+        Ok(minimap)
+    }
+}
+
+/// outline `viewport` of a `width` x `height` overlay on `pixels`, the
+/// overlay scaled down
+fn draw_viewport_box(pixels: &mut image::RgbaImage, viewport: &Bounds, width: u32, height: u32) {
+    let (columns, rows) = (pixels.width(), pixels.height());
+    if columns == 0 || rows == 0 {
+        return;
+    }
+    let scale_x = f64::from(columns) / f64::from(width);
+    let scale_y = f64::from(rows) / f64::from(height);
+
+    // inclusive pixel edges of the box
+    let left = ((viewport.x0 * scale_x).floor() as u32).min(columns - 1);
+    let top = ((viewport.y0 * scale_y).floor() as u32).min(rows - 1);
+    let right = ((viewport.x1 * scale_x).ceil() as u32).clamp(left + 1, columns) - 1;
+    let bottom = ((viewport.y1 * scale_y).ceil() as u32).clamp(top + 1, rows) - 1;
+
+    for y in top..=bottom {
+        for x in left..=right {
+            let edge = x - left < MINIMAP_BOX_WIDTH
+                || right - x < MINIMAP_BOX_WIDTH
+                || y - top < MINIMAP_BOX_WIDTH
+                || bottom - y < MINIMAP_BOX_WIDTH;
+            if edge {
+                pixels.get_pixel_mut(x, y).0 = MINIMAP_BOX_COLOR;
+            }
+        }
+    }
+}
+
+// ===== Recent Overlays =====
+
+/// overlays `list_recent_overlays` remembers, older ones are forgotten
+const MAX_RECENT_OVERLAYS: usize = 32;
+
+/// longer side of a recent overlay's thumbnail in pixels
+const RECENT_THUMBNAIL_SIZE: u32 = 160;
+
+/// a plot of this session, for a gallery of recent renders
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentOverlay {
+    pub overlay_id: String,
+    /// what it was plotted with, `None` for overlays the bridge didn't plot
+    pub params: Option<PlotParams>,
+    /// a jpeg of the whole overlay, `None` if python couldn't render one
+    pub thumbnail: Option<TileResponse>,
+    /// when it was last plotted or recolored
+    pub plotted_at_ms: u64,
+}
+
+/// an overlay in `OverlayRegistry::recent`
+struct RecentEntry {
+    overlay_id: String,
+    plotted_at_ms: u64,
+    /// rendered on first listing, the entry is replaced on every new
+    /// generation of the overlay so it never goes stale
+    thumbnail: Option<TileResponse>,
+}
+
+impl PythonBridge {
+    /// up to `limit` overlays of this session, most recently plotted first
+    ///
+    /// the registry remembers the last `MAX_RECENT_OVERLAYS` plots that
+    /// still exist. a deleted or unloaded overlay leaves the list, a plot
+    /// of the same id again moves it to the front. thumbnails are rendered
+    /// once per entry, apart from the mini-map's `get_overview` slot
+    pub fn list_recent_overlays(&mut self, limit: usize) -> Vec<RecentOverlay> {
+        let missing: Vec<String> = self
+            .overlays
+            .recent
+            .iter()
+            .take(limit)
+            .filter(|entry| entry.thumbnail.is_none())
+            .map(|entry| entry.overlay_id.clone())
+            .collect();
+        for overlay_id in missing {
+            let params = serde_json::json!({
+                "overlay_id": overlay_id,
+                "max_dimension": RECENT_THUMBNAIL_SIZE
+            });
+            match self.send_command_as::<TileResponse>(PythonCommand::GetOverview, params) {
+                Ok(thumbnail) => self.overlays.set_thumbnail(&overlay_id, thumbnail),
+                Err(_e) => {
+                    bridge_debug!("PythonBridge: No thumbnail for {}: {}", overlay_id, _e);
+                }
+            }
+        }
+
+        self.overlays
+            .recent
+            .iter()
+            .take(limit)
+            .map(|entry| RecentOverlay {
+                overlay_id: entry.overlay_id.clone(),
+                params: self.overlays.params(&entry.overlay_id).cloned(),
+                thumbnail: entry.thumbnail.clone(),
+                plotted_at_ms: entry.plotted_at_ms,
+            })
+            .collect()
+    }
+}
+
+// ===== Tile Priority =====
+
+/// one tile of a batch, see `get_tiles_batch`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TileRequest {
+    pub zoom: i32,
+    pub x: i32,
+    pub y: i32,
+    /// lower is fetched sooner, overrides the viewport distance
+    #[serde(default)]
+    pub priority: Option<f64>,
+}
+
+/// distance in level pixels from a tile's center to `center`
+///
+/// used as a priority, the eye lands on the middle of the viewport so
+/// those tiles should paint first
+fn center_distance(coord: TileCoord, tile_size: u32, center: (f64, f64)) -> f64 {
+    let half = tile_size as f64 / 2.0;
+    let tile_x = coord.x as f64 * tile_size as f64 + half;
+    let tile_y = coord.y as f64 * tile_size as f64 + half;
+    (tile_x - center.0).hypot(tile_y - center.1)
+}
+
+/// lowest priority value first, ties keep their order (the sort is stable)
+fn fetch_order(mut queue: Vec<(TileCoord, f64)>) -> Vec<TileCoord> {
+    queue.sort_by(|a, b| a.1.total_cmp(&b.1));
+    queue.into_iter().map(|(coord, _)| coord).collect()
+}
+
+#[cfg(test)]
+mod tile_priority_tests {
+    use super::*;
+
+    /// how long the tiles around the viewport center take to paint, fetched
+    /// in row order and ranked by distance. a sleep stands in for python's
+    /// render of each tile. run with `cargo test -- --ignored fill`
+    #[test]
+    #[ignore = "timing benchmark"]
+    fn fills_the_viewport_center_first() {
+        const RENDER: Duration = Duration::from_millis(5);
+        // 8 x 6 tiles of 256 px, four of them around the center
+        let center = (4.0 * 256.0, 3.0 * 256.0);
+        let queue = |ranked: bool| -> Vec<(TileCoord, f64)> {
+            let coords = (0..6).flat_map(|y| (0..8).map(move |x| TileCoord { zoom: 3, x, y }));
+            coords
+                .map(|coord| match ranked {
+                    true => (coord, center_distance(coord, 256, center)),
+                    false => (coord, f64::INFINITY),
+                })
+                .collect()
+        };
+        let fill_time = |order: Vec<TileCoord>| {
+            let (started, mut filled) = (Instant::now(), Duration::ZERO);
+            for coord in order {
+                std::thread::sleep(RENDER);
+                if center_distance(coord, 256, center) < 256.0 {
+                    filled = started.elapsed();
+                }
+            }
+            filled
+        };
+
+        let row_order = fill_time(fetch_order(queue(false)));
+        let ranked = fill_time(fetch_order(queue(true)));
+        println!("viewport center filled after {:?} in row order, {:?} ranked", row_order, ranked);
+        assert!(ranked * 4 < row_order);
+    }
+}
+
+impl PythonBridge {
+    /// fetch a set of tiles, most important first
+    ///
+    /// a tile's explicit `priority` wins. the others are ranked by distance
+    /// from `viewport_center` (in pixels of their zoom level) or, without a
+    /// center, keep their order after the prioritized ones. results come
+    /// back in fetch order, each with its own outcome
+    ///
+    /// a tile python fails to render (e.g. a corrupt region) is a
+    /// `BridgeError::Python` next to the others, draw the successes and
+    /// retry or placeholder the rest. a round trip that fails as a whole
+    /// (python exited, tiles paused) fails each tile it carried. only an
+    /// unknown overlay fails the batch
+    pub fn get_tiles_batch(
+        &mut self,
+        overlay_id: &str,
+        tiles: &[TileRequest],
+        viewport_center: Option<(f64, f64)>,
+    ) -> Result<Vec<BatchResult>, BridgeError> {
+        self.serve_tiles(|service, call| {
+            service.get_tiles_batch(overlay_id, tiles, viewport_center, call)
+        })
+    }
+}
+
+impl TileService {
+    /// see `PythonBridge::get_tiles_batch`
+    pub fn get_tiles_batch(
+        &self,
+        overlay_id: &str,
+        tiles: &[TileRequest],
+        viewport_center: Option<(f64, f64)>,
+        call: &mut TileCall,
+    ) -> Result<Vec<BatchResult>, BridgeError> {
+        let tile_size = self.info(overlay_id)?.tile_size;
+
+        let queue = tiles
+            .iter()
+            .map(|request| {
+                let coord = TileCoord {
+                    zoom: request.zoom,
+                    x: request.x,
+                    y: request.y,
+                };
+                let priority = request
+                    .priority
+                    .or_else(|| viewport_center.map(|c| center_distance(coord, tile_size, c)))
+                    .unwrap_or(f64::INFINITY);
+                (coord, priority)
+            })
+            .collect();
+        self.fetch_by_priority(overlay_id, queue, call)
+    }
+
+    /// fetch tiles in `fetch_order`. tiles count from the overlay's origin
+    ///
+    /// cached tiles are served here, python gets the others up to
+    /// `MAX_BATCH_TILES` per round trip. with the child gone this fails as
+    /// a whole, for the caller to respawn it and try again
+    fn fetch_by_priority(
+        &self,
+        overlay_id: &str,
+        queue: Vec<(TileCoord, f64)>,
+        call: &mut TileCall,
+    ) -> Result<Vec<BatchResult>, BridgeError> {
+        self.check_alive()?;
+        let coords = fetch_order(queue);
+        let mut results = Vec::with_capacity(coords.len());
+        for chunk in coords.chunks(MAX_BATCH_TILES) {
+            results.extend(self.fetch_tile_batch(overlay_id, chunk, call));
+        }
+        Ok(results)
+    }
+
+    /// `coords` through the tile cache, the uncached ones in a single
+    /// `GetTilesBatch`. cached or not, each tile is served processed
+    fn fetch_tile_batch(
+        &self,
+        overlay_id: &str,
+        coords: &[TileCoord],
+        call: &mut TileCall,
+    ) -> Vec<BatchResult> {
+        let (format, quality) = (self.format(), RenderQuality::Balanced);
+        let stored: Vec<TileCoord> =
+            coords.iter().map(|&coord| self.flip_tile(overlay_id, coord)).collect();
+        let keys: Vec<TileKey> = stored
+            .iter()
+            .map(|c| self.tile_key(overlay_id, c.zoom, c.x, c.y, format, quality))
+            .collect();
+        let mut tiles: Vec<Option<Result<TileResponse, BridgeError>>> = {
+            let mut cache = self.cache();
+            keys.iter().map(|key| cache.get(key).map(Ok)).collect()
+        };
+        let missing: Vec<usize> = (0..tiles.len()).filter(|&i| tiles[i].is_none()).collect();
+
+        if !missing.is_empty() {
+            let params = serde_json::json!({
+                "overlay_id": overlay_id,
+                "tiles": missing.iter().map(|&i| stored[i]).collect::<Vec<_>>(),
+                "format": format,
+                "quality": quality
+            });
+            let started = Instant::now();
+            let batch = self
+                .request_as::<TileBatch>(PythonCommand::GetTilesBatch, params, call)
+                .and_then(|batch| {
+                    if batch.tiles.len() == missing.len() {
+                        Ok(batch.tiles)
+                    } else {
+                        Err(BridgeError::Protocol(format!(
+                            "{} tiles in the response to a batch of {}",
+                            batch.tiles.len(),
+                            missing.len()
+                        )))
+                    }
+                });
+            // rendered one after another, each takes its share of the trip
+            let ms = started.elapsed().as_secs_f64() * 1000.0 / missing.len() as f64;
+            match batch {
+                Ok(outcomes) => {
+                    for (&i, outcome) in missing.iter().zip(outcomes) {
+                        tiles[i] = Some(match outcome {
+                            BatchTile::Tile(mut tile) => {
+                                self.record_tile_fetch(keys[i].clone(), ms);
+                                self.before_cache(&mut tile);
+                                self.cache().insert(keys[i].clone(), tile.clone());
+                                Ok(tile)
+                            }
+                            BatchTile::Failed { error } => Err(BridgeError::Python(error)),
+                        });
+                    }
+                }
+                Err(e) => {
+                    for &i in &missing {
+                        tiles[i] = Some(Err(e.clone()));
+                    }
+                }
+            }
+        }
+
+        coords
+            .iter()
+            .zip(tiles)
+            .map(|(&coord, tile)| {
+                let tile = tile.expect("every uncached tile was fetched");
+                (coord, tile.map(|tile| self.after_cache(tile)))
+            })
+            .collect()
+    }
+}
+
+/// a tile of a batch with its own outcome, see `get_tiles_batch`
+pub type BatchResult = (TileCoord, Result<TileResponse, BridgeError>);
+
+/// most uncached tiles sent to python in one round trip, a large batch
+/// still paints its first tiles early
+const MAX_BATCH_TILES: usize = 64;
+
+/// python's answer to `GetTilesBatch`, one outcome per tile asked for
+#[derive(Deserialize)]
+struct TileBatch {
+    tiles: Vec<BatchTile>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BatchTile {
+    /// first, a rendered tile has no `error`
+    Failed { error: String },
+    Tile(TileResponse),
+}
+
+/// the same tile position from two overlays, see `get_comparison_tile`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonTile {
+    pub a: TileResponse,
+    pub b: TileResponse,
+}
+
+impl PythonBridge {
+    /// one tile from each of two overlays for swipe/side-by-side views
+    ///
+    /// both overlays must share their pyramid geometry, otherwise the same
+    /// coordinates would show different parts of the tissue. the pair is
+    /// fetched in a single round trip unless both halves are cached
+    pub fn get_comparison_tile(
+        &mut self,
+        overlay_a: &str,
+        overlay_b: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+    ) -> Result<ComparisonTile, BridgeError> {
+        self.check_same_geometry(overlay_a, overlay_b)?;
+        let TileCoord { x, y, .. } = self.flip_tile(overlay_a, TileCoord { zoom, x, y });
+
+        let key = |bridge: &Self, overlay_id: &str| TileKey {
+            overlay_id: overlay_id.to_string(),
+            generation: bridge.tiles.generation(overlay_id),
+            zoom,
+            x,
+            y,
+            // python sends comparison halves as jpeg
+            format: TileFormatMode::Jpeg,
+            quality: RenderQuality::Balanced,
+        };
+        let (key_a, key_b) = (key(self, overlay_a), key(self, overlay_b));
+
+        let cached = {
+            let mut cache = self.tiles.cache();
+            (cache.get(&key_a), cache.get(&key_b))
+        };
+        if let (Some(a), Some(b)) = cached {
+            let (a, b) = (self.tiles.after_cache(a), self.tiles.after_cache(b));
+            return Ok(ComparisonTile { a, b });
+        }
+
+        let params = serde_json::json!({
+            "overlay_a": overlay_a,
+            "overlay_b": overlay_b,
+            "zoom": zoom,
+            "x": x,
+            "y": y
+        });
+        let mut pair: ComparisonTile =
+            self.send_command_as(PythonCommand::GetComparisonTile, params)?;
+        self.tiles.before_cache(&mut pair.a);
+        self.tiles.before_cache(&mut pair.b);
+        {
+            let mut cache = self.tiles.cache();
+            cache.insert(key_a, pair.a.clone());
+            cache.insert(key_b, pair.b.clone());
+        }
+        let (a, b) = (self.tiles.after_cache(pair.a), self.tiles.after_cache(pair.b));
+        Ok(ComparisonTile { a, b })
+    }
+
+    /// both overlays are known and share their pyramid geometry, so the
+    /// same tile coordinates cover the same part of the tissue
+    fn check_same_geometry(&self, overlay_a: &str, overlay_b: &str) -> Result<(), BridgeError> {
+        let info = |id: &str| {
+            self.overlays
+                .info(id)
+                .ok_or_else(|| BridgeError::OverlayNotFound(id.to_string()))
+        };
+        let geometry = |info: &OverlayInfo| {
+            format!(
+                "{}x{}, tile {}, max zoom {}, origin {}",
+                info.width,
+                info.height,
+                info.tile_size,
+                info.max_zoom,
+                info.origin.name()
+            )
+        };
+        let (a, b) = (geometry(info(overlay_a)?), geometry(info(overlay_b)?));
+        if a != b {
+            return Err(BridgeError::Protocol(format!(
+                "Can't compare {} ({}) with {} ({})",
+                overlay_a, a, overlay_b, b
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// how `diff_tiles` shows where two tiles differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffMode {
+    /// per-channel absolute difference, black where the tiles agree
+    #[default]
+    Absolute,
+    /// changed pixels highlighted over a transparent background, for
+    /// drawing on top of either overlay
+    ChangedMask,
+}
+
+impl DiffMode {
+    fn name(self) -> &'static str {
+        match self {
+            DiffMode::Absolute => "absolute",
+            DiffMode::ChangedMask => "changed_mask",
+        }
+    }
+}
+
+impl PythonBridge {
+    /// a png of where the same tile of two overlays differs, e.g. two
+    /// timepoints or two colorings of one section
+    ///
+    /// the overlays must share their pyramid geometry like for
+    /// `get_comparison_tile`. diffs go through the tile cache under a key
+    /// holding both overlays' generations, so re-plotting either one makes
+    /// its old diffs unreachable and they age out of the LRU
+    ///
+    /// `premultiplied` only affects `ChangedMask` diffs, see
+    /// `TileResponse::premultiplied`
+    #[allow(clippy::too_many_arguments)]
+    pub fn diff_tiles(
+        &mut self,
+        overlay_a: &str,
+        overlay_b: &str,
+        zoom: i32,
+        x: i32,
+        y: i32,
+        mode: DiffMode,
+        premultiplied: bool,
+    ) -> Result<TileResponse, BridgeError> {
+        self.check_same_geometry(overlay_a, overlay_b)?;
+        let TileCoord { x, y, .. } = self.flip_tile(overlay_a, TileCoord { zoom, x, y });
+
+        // absolute diffs are opaque, premultiplying them changes nothing
+        let premultiplied = premultiplied && mode == DiffMode::ChangedMask;
+        let generation = |id: &str| self.tiles.generation(id);
+        let key = TileKey {
+            overlay_id: format!(
+                "{}\u{0}diff\u{0}{}@{}\u{0}{}{}",
+                overlay_a,
+                overlay_b,
+                generation(overlay_b),
+                mode.name(),
+                if premultiplied { "\u{0}premultiplied" } else { "" }
+            ),
+            generation: generation(overlay_a),
+            zoom,
+            x,
+            y,
+            format: TileFormatMode::Png,
+            quality: RenderQuality::Balanced,
+        };
+        let cached = self.tiles.cache().get(&key);
+        if let Some(tile) = cached {
+            return Ok(tile);
+        }
+
+        let params = serde_json::json!({
+            "overlay_a": overlay_a,
+            "overlay_b": overlay_b,
+            "zoom": zoom,
+            "x": x,
+            "y": y,
+            "mode": mode,
+            "premultiplied": premultiplied
+        });
+        let tile: TileResponse = self.send_command_as(PythonCommand::DiffTiles, params)?;
+        self.tiles.cache().insert(key, tile.clone());
+        Ok(tile)
+    }
+}
+
+/// one tile of `get_tiles_for_region`, exactly one of `tile`/`error` is set
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionTile {
+    pub coord: TileCoord,
+    pub tile: Option<TileResponse>,
+    pub error: Option<String>,
+}
+
+impl RegionTile {
+    /// a `BatchResult` as the frontend gets it
+    pub fn new((coord, result): BatchResult) -> Self {
+        match result {
+            Ok(tile) => RegionTile {
+                coord,
+                tile: Some(tile),
+                error: None,
+            },
+            Err(e) => RegionTile {
+                coord,
+                tile: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+// ===== Prefetch =====
+
+/// most tiles one `prefetch_levels` call fetches, the rest are skipped
+pub const MAX_PREFETCH_TILES: usize = 256;
+
+/// what `prefetch_levels` did
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PrefetchReport {
+    /// tiles in the rings after the `MAX_PREFETCH_TILES` cap
+    pub scheduled: usize,
+    /// of those, already in the tile cache
+    pub cached: usize,
+    /// fetched from python into the cache
+    pub fetched: usize,
+    pub failed: usize,
+    /// ring tiles left out by the cap, farthest from the center first
+    pub skipped: usize,
+    /// stopped early by the cancel token or shutdown, the counts cover
+    /// the tiles handled until then
+    pub cancelled: bool,
+}
+
+impl PythonBridge {
+    /// warm the tile cache around a point at several zoom levels, so both
+    /// panning and zooming from there are instant
+    ///
+    /// `center_x`, `center_y` are full resolution pixels. at each level the
+    /// tiles up to `radius` tiles from the one under the center (a square
+    /// ring, clipped to the grid) are fetched through the tile cache,
+    /// innermost rings of every level first, so a cap or a cancel cuts the
+    /// outskirts. a tile asked for twice is fetched once. at most
+    /// `MAX_PREFETCH_TILES` tiles, levels outside the pyramid are ignored.
+    /// `cancel` (or shutdown) is checked between tiles
+    #[allow(clippy::too_many_arguments)]
+    pub fn prefetch_levels(
+        &mut self,
+        overlay_id: &str,
+        center_x: f64,
+        center_y: f64,
+        zoom_levels: &[i32],
+        radius: u32,
+        cancel: &CancellationToken,
+    ) -> Result<PrefetchReport, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .cloned()
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+        // the rings are laid out in the stored, top-left rows
+        let center_y = match info.origin {
+            Origin::TopLeft => center_y,
+            Origin::BottomLeft => f64::from(info.height) - center_y,
+        };
+
+        let radius = i32::try_from(radius).unwrap_or(i32::MAX);
+        let mut seen = HashSet::new();
+        let mut queue = Vec::new();
+        for &zoom in zoom_levels {
+            if !(0..=info.max_zoom).contains(&zoom) {
+                continue;
+            }
+            let scale = f64::from(1u32 << (info.max_zoom - zoom));
+            let tile_size = f64::from(info.tile_size.max(1));
+            let (cols, rows) = info.tile_grid(zoom);
+            let center = (
+                (center_x / scale / tile_size).floor() as i32,
+                (center_y / scale / tile_size).floor() as i32,
+            );
+            let span = |center: i32, count: i32| {
+                center.saturating_sub(radius).max(0)..=center.saturating_add(radius).min(count - 1)
+            };
+            let (xs, ys) = (span(center.0, cols), span(center.1, rows));
+            for y in ys {
+                for x in xs.clone() {
+                    let coord = TileCoord { zoom, x, y };
+                    if seen.insert(coord) {
+                        let ring = (x - center.0).abs().max((y - center.1).abs());
+                        queue.push((coord, ring));
+                    }
+                }
+            }
+        }
+        // stable, so within a ring the levels keep the order they were given
+        queue.sort_by_key(|&(_, ring)| ring);
+
+        let mut report = PrefetchReport {
+            skipped: queue.len().saturating_sub(MAX_PREFETCH_TILES),
+            ..PrefetchReport::default()
+        };
+        queue.truncate(MAX_PREFETCH_TILES);
+        report.scheduled = queue.len();
+
+        for (coord, _) in queue {
+            if cancel.is_cancelled() || self.shutdown.is_cancelled() {
+                report.cancelled = true;
+                break;
+            }
+            let format = self.tiles.format();
+            let key = self.tiles.tile_key(
+                overlay_id,
+                coord.zoom,
+                coord.x,
+                coord.y,
+                format,
+                RenderQuality::Balanced,
+            );
+            if self.tiles.cache().get(&key).is_some() {
+                report.cached += 1;
+                continue;
+            }
+            match self.fetch_tile(overlay_id, coord.zoom, coord.x, coord.y, format) {
+                Ok(_) => report.fetched += 1,
+                Err(_e) => {
+                    bridge_debug!(
+                        "PythonBridge: Prefetch of {} tile {}/{}/{} failed: {}",
+                        overlay_id,
+                        coord.zoom,
+                        coord.x,
+                        coord.y,
+                        _e
+                    );
+                    report.failed += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+// ===== Viewport Subscriptions =====
+
+/// payload of the `viewport_tile` events a subscription streams, see
+/// `subscribe_viewport_cmd`
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewportTileEvent {
+    pub subscription_id: String,
+    /// the `update_viewport_cmd` call the event belongs to, from 1
+    pub update: u64,
+    #[serde(flatten)]
+    pub event: ViewportEvent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ViewportEvent {
+    /// a tile of the current viewport, center first
+    TileArrived { coord: TileCoord, tile: TileResponse },
+    /// a queued tile that won't arrive. `error` is set for `Failed`
+    TileDropped {
+        coord: TileCoord,
+        reason: TileDropReason,
+        error: Option<String>,
+    },
+    /// every tile of the update arrived or was dropped
+    Idle,
+    /// the subscription is over, nothing follows
+    Ended { reason: SubscriptionEnd },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileDropReason {
+    /// the viewport moved off it before it was fetched
+    Superseded,
+    Failed,
+    /// the subscription ended before it was fetched
+    Ended,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionEnd {
+    Unsubscribed,
+    /// the overlay was removed, or python restarted and took it along
+    OverlayGone,
+    ShuttingDown,
+}
+
+/// one subscription, shared by the tauri commands and its worker thread
+pub struct ViewportSubscription {
+    overlay_id: String,
+    quality: RenderQuality,
+    inbox: Mutex<SubscriptionInbox>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct SubscriptionInbox {
+    updates: u64,
+    /// the latest update the worker hasn't picked up, an older one still
+    /// waiting is overwritten
+    pending: Option<(u64, Vec<TileCoord>)>,
+    end: Option<SubscriptionEnd>,
+}
+
+impl ViewportSubscription {
+    fn new(overlay_id: String, quality: RenderQuality) -> Self {
+        ViewportSubscription {
+            overlay_id,
+            quality,
+            inbox: Mutex::new(SubscriptionInbox::default()),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// hand the worker the tiles of a new viewport, returns the update's
+    /// number
+    fn update(&self, tiles: Vec<TileCoord>) -> u64 {
+        let mut inbox = self.inbox.lock().unwrap();
+        inbox.updates += 1;
+        let update = inbox.updates;
+        inbox.pending = Some((update, tiles));
+        self.wake.notify_all();
+        update
+    }
+
+    /// stop the worker, the first reason wins
+    fn end(&self, reason: SubscriptionEnd) {
+        let mut inbox = self.inbox.lock().unwrap();
+        inbox.end.get_or_insert(reason);
+        self.wake.notify_all();
+    }
+
+    /// the end or the next update, whichever came. with `wait` blocks until
+    /// one of them does, otherwise `None` when neither has
+    fn next(&self, wait: bool) -> Option<Result<(u64, Vec<TileCoord>), SubscriptionEnd>> {
+        let mut inbox = self.inbox.lock().unwrap();
+        loop {
+            if let Some(reason) = inbox.end {
+                return Some(Err(reason));
+            }
+            if let Some(update) = inbox.pending.take() {
+                return Some(Ok(update));
+            }
+            if !wait {
+                return None;
+            }
+            inbox = self.wake.wait(inbox).unwrap();
+        }
+    }
+}
+
+/// the worker's view of what is queued and what the frontend already has
+#[derive(Default)]
+struct ViewportStream {
+    update: u64,
+    queue: VecDeque<TileCoord>,
+    /// arrived since they came into view, not sent again while they stay
+    shown: HashSet<TileCoord>,
+}
+
+impl ViewportStream {
+    /// queue the tiles of a new viewport, returns the queued ones it no
+    /// longer covers
+    fn retarget(&mut self, update: u64, tiles: Vec<TileCoord>) -> Vec<TileCoord> {
+        let wanted: HashSet<TileCoord> = tiles.iter().copied().collect();
+        let superseded = self.queue.drain(..).filter(|c| !wanted.contains(c)).collect();
+        self.shown.retain(|c| wanted.contains(c));
+        self.queue = tiles.into_iter().filter(|c| !self.shown.contains(c)).collect();
+        self.update = update;
+        superseded
+    }
+}
+
+impl TileService {
+    /// the tile a viewport subscription streams next, served like
+    /// `get_tissue_overlay_tile`. tagged with the subscription, so
+    /// unsubscribing cancels it. the overlay check notices a removal or a
+    /// respawn
+    pub fn subscription_tile(
+        &self,
+        subscription_id: &str,
+        overlay_id: &str,
+        coord: TileCoord,
+        quality: RenderQuality,
+        call: &mut TileCall,
+    ) -> Result<TileResponse, BridgeError> {
+        self.info(overlay_id)?;
+        call.tag = Some(subscription_id.to_string());
+        let TileCoord { zoom, x, y } = coord;
+        self.get_tissue_overlay_tile(overlay_id, zoom, x, y, quality, call)
+    }
+
+    /// every tile covering a pixel rectangle of one zoom level, center
+    /// first. the rectangle is handled like in `get_tiles_for_region`
+    pub fn viewport_tiles(
+        &self,
+        overlay_id: &str,
+        zoom: i32,
+        x0: i64,
+        y0: i64,
+        x1: i64,
+        y1: i64,
+    ) -> Result<Vec<TileCoord>, BridgeError> {
+        let mut queue = self.region_queue(overlay_id, zoom, x0, y0, x1, y1)?;
+        queue.sort_by(|a, b| a.1.total_cmp(&b.1));
+        Ok(queue.into_iter().map(|(coord, _)| coord).collect())
+    }
+}
+
+// ===== Result Cache =====
+
+/// how long a read-only command's response may be reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// always ask python
+    Never,
+    /// valid until `clear_caches` or the overlay's generation changes
+    Forever,
+    Ttl(Duration),
+}
+
+impl PythonCommand {
+    /// default caching for each command, overridable on the builder
+    fn default_cache_policy(self) -> CachePolicy {
+        match self {
+            // pure functions of an overlay generation
+            PythonCommand::GetOverlayStats
+            | PythonCommand::GetOverlayLegend
+            | PythonCommand::GetCategoryCounts
+            | PythonCommand::GetNeighborhoodSummary => CachePolicy::Forever,
+            // mostly static, but includes live memory usage
+            PythonCommand::GetEnvironmentInfo => CachePolicy::Ttl(Duration::from_secs(5)),
+            // fixed by the installed matplotlib
+            PythonCommand::ListColormaps => CachePolicy::Forever,
+            // a pure function of the dataset, dropped when it is (re)loaded
+            // or unloaded
+            PythonCommand::GetValueHistogram => CachePolicy::Forever,
+            // changes with the dataset, or when a TIFF is loaded, which
+            // `check_image` covers by refreshing before giving up
+            PythonCommand::ListImages => CachePolicy::Forever,
+            _ => CachePolicy::Never,
+        }
+    }
+}
+
+/// responses of read-only commands keyed by command and params
+///
+/// panels that poll stats/legends would otherwise cost a python round trip
+/// per refresh. the overlay generation is part of the key so a re-plotted
+/// or deleted overlay never serves an old response
+struct ResultCache {
+    entries: HashMap<(PythonCommand, String), CachedResult>,
+    policies: HashMap<PythonCommand, CachePolicy>,
+}
+
+struct CachedResult {
+    value: serde_json::Value,
+    expires_at: Option<Instant>,
+    /// the `dataset_id` param, so a dataset's results can be dropped
+    dataset_id: Option<String>,
+}
+
+impl ResultCache {
+    /// polling panels only ever need a handful of entries
+    const MAX_ENTRIES: usize = 256;
+
+    fn new(policies: HashMap<PythonCommand, CachePolicy>) -> Self {
+        Self {
+            entries: HashMap::new(),
+            policies,
+        }
+    }
+
+    fn policy(&self, command: PythonCommand) -> CachePolicy {
+        self.policies
+            .get(&command)
+            .copied()
+            .unwrap_or_else(|| command.default_cache_policy())
+    }
+
+    fn get(&mut self, key: &(PythonCommand, String)) -> Option<serde_json::Value> {
+        match self.entries.get(key) {
+            Some(CachedResult {
+                expires_at: Some(expires_at),
+                ..
+            }) if *expires_at <= Instant::now() => {
+                self.entries.remove(key);
+                None
+            }
+            Some(entry) => Some(entry.value.clone()),
+            None => None,
+        }
+    }
+
+    fn insert(
+        &mut self,
+        key: (PythonCommand, String),
+        value: serde_json::Value,
+        dataset_id: Option<String>,
+    ) {
+        let expires_at = match self.policy(key.0) {
+            CachePolicy::Never => return,
+            CachePolicy::Forever => None,
+            CachePolicy::Ttl(ttl) => Some(Instant::now() + ttl),
+        };
+
+        if self.entries.len() >= Self::MAX_ENTRIES {
+            let now = Instant::now();
+            self.entries
+                .retain(|_, entry| entry.expires_at.is_none_or(|at| at > now));
+            if self.entries.len() >= Self::MAX_ENTRIES {
+                self.entries.clear();
+            }
+        }
+
+        self.entries.insert(
+            key,
+            CachedResult {
+                value,
+                expires_at,
+                dataset_id,
+            },
+        );
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// drop results computed from a dataset that was reloaded or unloaded
+    fn forget_dataset(&mut self, dataset_id: &str) {
+        self.entries
+            .retain(|_, entry| entry.dataset_id.as_deref() != Some(dataset_id));
+    }
+}
+
+impl PythonBridge {
+    /// `send_command` for read-only commands, served from the result cache
+    /// when allowed. `force_refresh` always asks python and refreshes the
+    /// cached copy
+    fn send_cached_as<T: DeserializeOwned>(
+        &mut self,
+        command: PythonCommand,
+        params: serde_json::Value,
+        force_refresh: bool,
+    ) -> Result<T, BridgeError> {
+        let generation = params
+            .get("overlay_id")
+            .and_then(|id| id.as_str())
+            .and_then(|id| self.overlays.generation(id))
+            .unwrap_or(0);
+        let key = (command, format!("{}#{}", params, generation));
+
+        let cached = if force_refresh {
+            None
+        } else {
+            self.result_cache.get(&key)
+        };
+
+        let data = match cached {
+            Some(data) => data,
+            None => {
+                let dataset_id = params
+                    .get("dataset_id")
+                    .and_then(|id| id.as_str())
+                    .map(str::to_string);
+                let data = self.send_command(command, params)?;
+                self.result_cache.insert(key, data.clone(), dataset_id);
+                data
+            }
+        };
+
+        decode_response(command, &data)
+    }
+
+    /// drop every cached tile and command result on the rust side
+    pub fn clear_caches(&mut self) {
+        self.tiles.cache().clear();
+        self.base_tiles.clear();
+        self.overviews.clear();
+        self.hover_cache.clear();
+        self.result_cache.clear();
+    }
+
+    /// tile counts and sizes per zoom level of an overlay
+    pub fn get_overlay_stats(
+        &mut self,
+        overlay_id: &str,
+        force_refresh: bool,
+    ) -> Result<OverlayStats, BridgeError> {
+        self.send_cached_as(
+            PythonCommand::GetOverlayStats,
+            serde_json::json!({ "overlay_id": overlay_id }),
+            force_refresh,
+        )
+    }
+
+    /// what the overlay's colors mean: category swatches or a value range
+    pub fn get_overlay_legend(
+        &mut self,
+        overlay_id: &str,
+        force_refresh: bool,
+    ) -> Result<OverlayLegend, BridgeError> {
+        self.send_cached_as(
+            PythonCommand::GetOverlayLegend,
+            serde_json::json!({ "overlay_id": overlay_id }),
+            force_refresh,
+        )
+    }
+
+    /// segments per category of a categorical overlay, in legend order.
+    /// fails for gene and numeric fills
+    pub fn get_category_counts(
+        &mut self,
+        overlay_id: &str,
+        force_refresh: bool,
+    ) -> Result<Vec<(String, u64)>, BridgeError> {
+        self.send_cached_as(
+            PythonCommand::GetCategoryCounts,
+            serde_json::json!({ "overlay_id": overlay_id }),
+            force_refresh,
+        )
+    }
+
+    /// python interpreter, package versions and memory usage
+    pub fn get_environment_info(
+        &mut self,
+        force_refresh: bool,
+    ) -> Result<EnvironmentInfo, BridgeError> {
+        self.send_cached_as(
+            PythonCommand::GetEnvironmentInfo,
+            serde_json::json!({}),
+            force_refresh,
+        )
+    }
+
+    /// colormaps python can render with, for a picker instead of a free
+    /// text field. reversed `_r` variants are accepted but not listed
+    pub fn list_colormaps(&mut self) -> Result<Vec<ColormapInfo>, BridgeError> {
+        self.send_cached_as(PythonCommand::ListColormaps, serde_json::json!({}), false)
+    }
+
+    /// distribution of a gene's or numeric column's values over all cells,
+    /// e.g. to preview a `value_range` slider
+    ///
+    /// `bins` equal width bins between the smallest and largest value, at
+    /// most `MAX_HISTOGRAM_BINS`. cells without a value are left out.
+    /// python refuses categorical keys. cached per dataset, key and bin
+    /// count until the dataset is reloaded or unloaded
+    pub fn get_value_histogram(
+        &mut self,
+        dataset_id: Option<&str>,
+        fill_key: &str,
+        bins: u32,
+    ) -> Result<Histogram, BridgeError> {
+        if !(1..=MAX_HISTOGRAM_BINS).contains(&bins) {
+            return Err(BridgeError::Protocol(format!(
+                "Histogram bins must be between 1 and {}, got {}",
+                MAX_HISTOGRAM_BINS, bins
+            )));
+        }
+        let dataset_id = self.context.resolve_dataset(dataset_id)?;
+        self.send_cached_as(
+            PythonCommand::GetValueHistogram,
+            serde_json::json!({
+                "dataset_id": dataset_id,
+                "fill_key": fill_key,
+                "bins": bins
+            }),
+            false,
+        )
+    }
+}
+
+impl PythonBridge {
+    /// images the dataset's overlays can be drawn over, for an image
+    /// switcher: loaded TIFFs first, then those registered in the dataset
+    /// (`img_id` "<library>/<name>")
+    ///
+    /// cached per dataset until it is reloaded or unloaded. a TIFF loaded
+    /// since only shows up with `force_refresh`
+    pub fn list_images(
+        &mut self,
+        dataset_id: Option<&str>,
+        force_refresh: bool,
+    ) -> Result<Vec<ImageInfo>, BridgeError> {
+        let dataset_id = self.context.resolve_dataset(dataset_id)?;
+        self.send_cached_as(
+            PythonCommand::ListImages,
+            serde_json::json!({ "dataset_id": dataset_id }),
+            force_refresh,
+        )
+    }
+
+    /// `ImageNotFound` unless `img_id` is one of the dataset's images.
+    /// asks python again before failing, the cached list may predate it
+    fn check_image(&mut self, dataset_id: &str, img_id: &str) -> Result<(), BridgeError> {
+        let known = |images: &[ImageInfo]| images.iter().any(|image| image.img_id == img_id);
+        if known(&self.list_images(Some(dataset_id), false)?)
+            || known(&self.list_images(Some(dataset_id), true)?)
+        {
+            Ok(())
+        } else {
+            Err(BridgeError::ImageNotFound(img_id.to_string()))
+        }
+    }
+}
+
+/// see `list_images`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub img_id: String,
+    /// the file's id for a TIFF, the image key (e.g. "hires") otherwise
+    pub name: String,
+    /// `[width, height]` in pixels
+    pub dimensions: [u32; 2],
+    pub channels: u32,
+}
+
+/// most bins `get_value_histogram` computes, finer than a slider can show
+pub const MAX_HISTOGRAM_BINS: u32 = 1024;
+
+/// see `get_value_histogram`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Histogram {
+    /// `counts.len() + 1` bin boundaries, ascending
+    pub edges: Vec<f64>,
+    pub counts: Vec<u64>,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelStats {
+    pub zoom: i32,
+    pub width: u32,
+    pub height: u32,
+    pub tiles: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverlayStats {
+    pub overlay_id: String,
+    pub levels: Vec<LevelStats>,
+    pub total_tiles: usize,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegendCategory {
+    pub name: String,
+    /// hex color, if the dataset defines a palette for the key
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OverlayLegend {
+    Categorical { categories: Vec<LegendCategory> },
+    /// the range the colormap spans, see `ColorScale`
+    Continuous { min: f64, max: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvironmentInfo {
+    pub python_version: String,
+    pub platform: String,
+    pub pid: u32,
+    /// package name -> version, `None` if it isn't installed
+    pub packages: HashMap<String, Option<String>>,
+    pub memory_rss_bytes: Option<u64>,
+    /// limits in effect inside the child as python reads them, `None` where
+    /// python can't tell (windows) or from older python helpers
+    #[serde(default)]
+    pub resource_limits: Option<ResourceLimits>,
+    /// `None` from older python helpers
+    #[serde(default)]
+    pub render_backend: Option<RenderBackend>,
+}
+
+/// matplotlib's backend in the python child, see `BridgeError::BadRenderBackend`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderBackend {
+    pub name: String,
+    /// a GUI backend, it needs a display to draw anything
+    pub interactive: bool,
+    /// python found no display to connect to
+    pub headless: bool,
+}
+
+impl RenderBackend {
+    /// fail for a GUI backend without a display
+    pub fn check(&self) -> Result<(), BridgeError> {
+        if self.interactive && self.headless {
+            return Err(BridgeError::BadRenderBackend(self.name.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColormapKind {
+    Sequential,
+    /// meant to be centered on a midpoint
+    Diverging,
+    /// distinct colors for categories
+    Qualitative,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColormapInfo {
+    pub name: String,
+    pub kind: ColormapKind,
+    /// rgba samples evenly spaced from one end to the other, for swatches
+    pub preview_stops: Vec<[u8; 4]>,
+}
+
+// ===== Tile Pyramid Export =====
+
+/// progress of a running export, one event per tile written or failed
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportProgress {
+    pub overlay_id: String,
+    pub done: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedTile {
+    pub zoom: i32,
+    pub x: i32,
+    pub y: i32,
+    pub error: String,
+}
+
+/// outcome of an export, tiles that failed don't abort the rest
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportReport {
+    /// the `.dzi` descriptor, open this in a Deep Zoom viewer
+    pub dzi_path: PathBuf,
+    pub tiles_written: usize,
+    pub failed: Vec<FailedTile>,
+}
+
+/// how an export ended
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ExportOutcome {
+    Completed(ExportReport),
+    /// cancelled part way, the partial output has been removed
+    Aborted { done: usize, total: usize },
+}
+
+/// a region written by `export_region_png`, or a legend by `export_legend`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionExport {
+    #[serde(deserialize_with = "deserialize_wire_path")]
+    pub path: PathBuf,
+    /// size of the written image, smaller than the region if it was
+    /// downscaled to `max_dimension`
+    pub width: u32,
+    pub height: u32,
+    /// the `max_dimension` the region was exported with, lower than the
+    /// one asked for if `clamped`
+    #[serde(default)]
+    pub max_dimension: Option<u32>,
+    /// downscaled further than asked to stay within the export memory
+    /// budget, see `PythonBridgeBuilder::export_memory_budget`
+    #[serde(default)]
+    pub clamped: bool,
+}
+
+/// bytes per pixel python holds while exporting, the stitched region and
+/// the downscaled copy are both rgb
+const EXPORT_BYTES_PER_PIXEL: u64 = 3;
+
+/// default of `PythonBridgeBuilder::export_memory_budget`
+pub const DEFAULT_EXPORT_MEMORY_BUDGET: u64 = 512 * 1024 * 1024;
+
+/// what `export_region_png` would produce and hold in memory, see
+/// `PythonBridge::estimate_region_export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExportEstimate {
+    /// size of the written image
+    pub width: u32,
+    pub height: u32,
+    /// zoom level python stitches the region from
+    pub zoom: i32,
+    /// peak image memory in python, stitched region plus downscaled copy
+    pub est_bytes: u64,
+}
+
+impl ExportEstimate {
+    /// mirrors python's export_region_png: stitched from the coarsest
+    /// level still at least `max_dimension` across, then downscaled so the
+    /// longer side fits
+    fn new(width: u32, height: u32, max_zoom: i32, max_dimension: Option<u32>) -> Self {
+        let (width, height) = (u64::from(width), u64::from(height));
+        let long = width.max(height);
+        let mut zoom = max_zoom;
+        if let Some(max_dimension) = max_dimension {
+            let max_dimension = u64::from(max_dimension.max(1));
+            while zoom > 0 && long >> (max_zoom - zoom + 1).clamp(0, 63) >= max_dimension {
+                zoom -= 1;
+            }
+        }
+        let scale = 1u64 << (max_zoom - zoom).clamp(0, 63);
+        let stitched = width.div_ceil(scale) * height.div_ceil(scale);
+
+        let (mut out_width, mut out_height) = (width, height);
+        let mut est_bytes = stitched * EXPORT_BYTES_PER_PIXEL;
+        if let Some(max_dimension) = max_dimension.filter(|&d| long > u64::from(d)) {
+            let fit = f64::from(max_dimension.max(1)) / long as f64;
+            out_width = ((width as f64 * fit).round() as u64).max(1);
+            out_height = ((height as f64 * fit).round() as u64).max(1);
+            est_bytes += out_width * out_height * EXPORT_BYTES_PER_PIXEL;
+        }
+        ExportEstimate {
+            width: out_width as u32,
+            height: out_height as u32,
+            zoom,
+            est_bytes,
+        }
+    }
+}
+
+/// which way `export_legend` lays a legend out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LegendOrientation {
+    /// a bar or a column of swatches, for beside a figure
+    #[default]
+    Vertical,
+    /// for below one
+    Horizontal,
+}
+
+/// smallest and largest `size` of `export_legend`
+pub const LEGEND_SIZE_RANGE: std::ops::RangeInclusive<u32> = 64..=4096;
+
+/// file format for `export_segments`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    Csv,
+    /// needs pyarrow in the python environment
+    Parquet,
+}
+
+impl TableFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// a table written by `export_segments`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentsExport {
+    #[serde(deserialize_with = "deserialize_wire_path")]
+    pub path: PathBuf,
+    pub rows: u64,
+}
+
+impl PythonBridge {
+    /// write an overlay's legend to a png, to place beside an export
+    ///
+    /// continuous values get a gradient bar over the overlay's actual range
+    /// and colormap, categories a list of swatches. `size` is the long
+    /// side in pixels (see `LEGEND_SIZE_RANGE`), the image is trimmed to
+    /// what's drawn so the returned size is the one to lay out with.
+    /// python refuses categories it has no colors for
+    pub fn export_legend(
+        &mut self,
+        overlay_id: &str,
+        path: &Path,
+        orientation: LegendOrientation,
+        size: u32,
+    ) -> Result<RegionExport, BridgeError> {
+        if !LEGEND_SIZE_RANGE.contains(&size) {
+            return Err(BridgeError::Protocol(format!(
+                "Legend size must be between {} and {} pixels, got {}",
+                LEGEND_SIZE_RANGE.start(),
+                LEGEND_SIZE_RANGE.end(),
+                size
+            )));
+        }
+        if self.overlays.info(overlay_id).is_none() {
+            return Err(BridgeError::OverlayNotFound(overlay_id.to_string()));
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "path": wire_path(path),
+            "orientation": orientation,
+            "size": size
+        });
+        self.send_command_as(PythonCommand::ExportLegend, params)
+    }
+
+    /// a region's corners in order and clamped to the overlay
+    fn export_region(
+        &self,
+        overlay_id: &str,
+        x0: i64,
+        y0: i64,
+        x1: i64,
+        y1: i64,
+    ) -> Result<(i64, i64, i64, i64, i32), BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+
+        let (width, height) = (i64::from(info.width), i64::from(info.height));
+        let (x0, x1) = (x0.min(x1).clamp(0, width), x0.max(x1).clamp(0, width));
+        let (y0, y1) = (y0.min(y1).clamp(0, height), y0.max(y1).clamp(0, height));
+        if x0 == x1 || y0 == y1 {
+            return Err(BridgeError::Protocol(format!(
+                "Region is empty within the {}x{} overlay",
+                width, height
+            )));
+        }
+        // python crops the stored, top-left render
+        let (y0, y1) = info.flip_span(y0, y1, height);
+        Ok((x0, y0, x1, y1, info.max_zoom))
+    }
+
+    /// size and python's memory use of an `export_region_png`, nothing is
+    /// sent to python
+    pub fn estimate_region_export(
+        &self,
+        overlay_id: &str,
+        x0: i64,
+        y0: i64,
+        x1: i64,
+        y1: i64,
+        max_dimension: Option<u32>,
+    ) -> Result<ExportEstimate, BridgeError> {
+        let (x0, y0, x1, y1, max_zoom) = self.export_region(overlay_id, x0, y0, x1, y1)?;
+        Ok(ExportEstimate::new((x1 - x0) as u32, (y1 - y0) as u32, max_zoom, max_dimension))
+    }
+
+    /// write a full resolution rectangle of an overlay to a png
+    ///
+    /// the corners may come in any order and are clamped to the overlay,
+    /// a rectangle with nothing left inside is an error. python stitches it
+    /// from the tiles, far cheaper than a whole export when a figure panel
+    /// is all that's needed. `max_dimension` downscales so the longer side
+    /// fits. a `scalebar` is drawn onto the exported image only, after
+    /// downscaling, so it's crisp and true to scale
+    ///
+    /// an export estimated over the bridge's export memory budget is
+    /// downscaled to the largest `max_dimension` that fits instead of
+    /// risking python running out of memory, the result says so with
+    /// `clamped`. `force_full_size` skips the budget. past what even the
+    /// coarsest zoom level fits in, the export fails
+    #[allow(clippy::too_many_arguments)]
+    pub fn export_region_png(
+        &mut self,
+        overlay_id: &str,
+        x0: i64,
+        y0: i64,
+        x1: i64,
+        y1: i64,
+        path: &Path,
+        max_dimension: Option<u32>,
+        scalebar: Option<ScalebarOptions>,
+        force_full_size: bool,
+    ) -> Result<RegionExport, BridgeError> {
+        if let Some(ref scalebar) = scalebar {
+            scalebar.validate()?;
+        }
+        let (x0, y0, x1, y1, max_zoom) = self.export_region(overlay_id, x0, y0, x1, y1)?;
+
+        let (width, height) = ((x1 - x0) as u32, (y1 - y0) as u32);
+        let mut max_dimension = max_dimension;
+        let mut clamped = false;
+        let budget = self.config.export_memory_budget.filter(|_| !force_full_size);
+        if let Some(budget) = budget {
+            let fits = |d: Option<u32>| {
+                ExportEstimate::new(width, height, max_zoom, d).est_bytes <= budget
+            };
+            if !fits(max_dimension) {
+                // the estimate grows with max_dimension, find the largest
+                // one that fits
+                let (mut lo, mut hi) = (0, max_dimension.unwrap_or(width.max(height)));
+                while lo < hi {
+                    let mid = lo + (hi - lo).div_ceil(2);
+                    if fits(Some(mid)) {
+                        lo = mid;
+                    } else {
+                        hi = mid - 1;
+                    }
+                }
+                if lo == 0 {
+                    return Err(BridgeError::Protocol(format!(
+                        "Exporting the {}x{} region needs more than the {} MiB export \
+                         memory budget at any size",
+                        width,
+                        height,
+                        budget / (1024 * 1024)
+                    )));
+                }
+                bridge_debug!(
+                    "PythonBridge: Clamped export of {} to max_dimension {}",
+                    overlay_id,
+                    lo
+                );
+                max_dimension = Some(lo);
+                clamped = true;
+            }
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "x0": x0,
+            "y0": y0,
+            "x1": x1,
+            "y1": y1,
+            "path": wire_path(path),
+            "max_dimension": max_dimension,
+            "scalebar": scalebar
+        });
+        let mut export: RegionExport =
+            self.send_command_as(PythonCommand::ExportRegionPng, params)?;
+        export.max_dimension = max_dimension;
+        export.clamped = clamped;
+        Ok(export)
+    }
+
+    /// write the per-segment table behind an overlay for analysis elsewhere
+    ///
+    /// one row per segment of its segmentation: id, centroid, bounding box,
+    /// pixel count, the overlay's fill value and the dataset's obs columns.
+    /// python writes it in chunks, so a few million segments don't have to
+    /// fit in memory as one table, and renames it into place at the end
+    pub fn export_segments(
+        &mut self,
+        overlay_id: &str,
+        path: &Path,
+        format: TableFormat,
+    ) -> Result<SegmentsExport, BridgeError> {
+        if self.overlays.info(overlay_id).is_none() {
+            return Err(BridgeError::OverlayNotFound(overlay_id.to_string()));
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "path": wire_path(path),
+            "format": format
+        });
+        self.send_command_as(PythonCommand::ExportSegments, params)
+    }
+
+    /// write an overlay's whole tile pyramid to disk as a Deep Zoom image
+    ///
+    /// produces `out_dir/{overlay}.dzi` and
+    /// `out_dir/{overlay}_files/{level}/{x}_{y}.{ext}`. Deep Zoom numbers
+    /// levels from a 1x1 image upwards, so our zoom levels are shifted to
+    /// end at the DZI level of the full resolution image; the smaller DZI
+    /// levels below our zoom 0 are not written. tiles come through the tile
+    /// cache so ones already viewed aren't fetched from python again
+    ///
+    /// `cancel` (or app shutdown) is checked between tiles. python only
+    /// ever renders single tiles for this, so there is nothing to abort on
+    /// its side; the bridge deletes what it wrote so far and reports
+    /// `Aborted`. the `.dzi` descriptor is written last, a folder without
+    /// one is never a finished export
+    pub fn export_tile_pyramid(
+        &mut self,
+        overlay_id: &str,
+        out_dir: &Path,
+        format: TileFormat,
+        cancel: &CancellationToken,
+        mut on_progress: impl FnMut(ExportProgress),
+    ) -> Result<ExportOutcome, BridgeError> {
+        let info = self
+            .overlays
+            .info(overlay_id)
+            .cloned()
+            .ok_or_else(|| BridgeError::OverlayNotFound(overlay_id.to_string()))?;
+
+        let name = sanitize_file_name(overlay_id);
+        let tiles_dir = out_dir.join(format!("{}_files", name));
+        let dzi_path = out_dir.join(format!("{}.dzi", name));
+
+        let full_level = dzi_max_level(info.width, info.height) as i32;
+        let level_offset = full_level - info.max_zoom;
+
+        let total: usize = (0..=info.max_zoom)
+            .map(|zoom| {
+                let (cols, rows) = info.tile_grid(zoom);
+                (cols * rows) as usize
+            })
+            .sum();
+        let mut done = 0;
+        let mut tiles_written = 0;
+        let mut failed = Vec::new();
+
+        for zoom in 0..=info.max_zoom {
+            let level_dir = tiles_dir.join((zoom + level_offset).to_string());
+            std::fs::create_dir_all(&level_dir).map_err(|e| BridgeError::Io(e.to_string()))?;
+
+            let (cols, rows) = info.tile_grid(zoom);
+            for y in 0..rows {
+                for x in 0..cols {
+                    if cancel.is_cancelled() || self.shutdown.is_cancelled() {
+                        bridge_debug!("PythonBridge: Export of {} aborted at {}/{}", overlay_id, done, total);
+                        if let Err(_e) = std::fs::remove_dir_all(&tiles_dir) {
+                            bridge_debug!("PythonBridge: Failed to remove {:?}: {}", tiles_dir, _e);
+                        }
+                        return Ok(ExportOutcome::Aborted { done, total });
+                    }
+
+                    let path = level_dir.join(format!("{}_{}.{}", x, y, format.extension()));
+                    let written = self
+                        .fetch_tile(overlay_id, zoom, x, y, format.into())
+                        .and_then(|tile| {
+                            decode_base64(&tile.tile).map_err(BridgeError::Protocol)
+                        })
+                        .and_then(|bytes| {
+                            std::fs::write(&path, bytes).map_err(|e| BridgeError::Io(e.to_string()))
+                        });
+
+                    match written {
+                        Ok(()) => tiles_written += 1,
+                        Err(e) => failed.push(FailedTile {
+                            zoom,
+                            x,
+                            y,
+                            error: e.to_string(),
+                        }),
+                    }
+
+                    done += 1;
+                    on_progress(ExportProgress {
+                        overlay_id: overlay_id.to_string(),
+                        done,
+                        total,
+                    });
+                }
+            }
+        }
+
+        let descriptor = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" \
+             Format=\"{}\" Overlap=\"0\" TileSize=\"{}\">\n  \
+             <Size Width=\"{}\" Height=\"{}\"/>\n\
+             </Image>\n",
+            format.extension(),
+            info.tile_size,
+            info.width,
+            info.height
+        );
+        std::fs::write(&dzi_path, descriptor).map_err(|e| BridgeError::Io(e.to_string()))?;
+
+        Ok(ExportOutcome::Completed(ExportReport {
+            dzi_path,
+            tiles_written,
+            failed,
+        }))
+    }
+}
+
+/// Deep Zoom level of the full resolution image: ceil(log2(max(w, h)))
+fn dzi_max_level(width: u32, height: u32) -> u32 {
+    let longest = width.max(height).max(1);
+    u32::BITS - (longest - 1).leading_zeros()
+}
+
+/// overlay ids contain ':' and arbitrary key names, keep them filesystem safe
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// decode standard padded base64 as produced by python's `b64encode`,
+/// missing padding or stray trailing bits are an error like a bad character
+fn decode_base64(input: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(input)
+        .map_err(|e| format!("Invalid base64: {}", e))
+}
+
+/// standard padded base64, what python's `b64decode` expects
+fn encode_base64(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+#[cfg(test)]
+mod base64_tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_length() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..8 {
+            let encoded = encode_base64(&bytes[..len]);
+            assert_eq!(encoded.len() % 4, 0);
+            assert_eq!(decode_base64(&encoded).unwrap(), &bytes[..len]);
+        }
+        assert_eq!(encode_base64(b"tile"), "dGlsZQ==");
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        for input in ["dGlsZQ", "dGlsZQ=", "dGlsZR==", "dGl*ZQ==", "dGlsZQ==dG"] {
+            assert!(decode_base64(input).is_err(), "{:?}", input);
+        }
+    }
+}
+
+// ===== OME-TIFF Export =====
+
+/// python's answer to `begin_ome_tiff`
+#[derive(Debug, Deserialize)]
+struct OmeTiffPlan {
+    levels: u32,
+}
+
+/// a pyramidal OME-TIFF written by `export_ome_tiff`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OmeTiffReport {
+    #[serde(deserialize_with = "deserialize_wire_path")]
+    pub path: PathBuf,
+    /// full resolution size
+    pub width: u32,
+    pub height: u32,
+    /// channel names in file order, the overlay's red, green and blue, then
+    /// the base image's if it was included
+    pub channels: Vec<String>,
+    /// resolution levels, each half the size of the one before
+    pub levels: u32,
+    /// microns per full resolution pixel, as written to the OME metadata.
+    /// `None` for a dataset without a pixel size calibration
+    pub pixel_size: Option<f64>,
+}
+
+/// how an OME-TIFF export ended
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum OmeTiffOutcome {
+    Completed(OmeTiffReport),
+    /// cancelled part way, the partial file has been removed. counts levels
+    Aborted { done: usize, total: usize },
+}
+
+impl PythonBridge {
+    /// write an overlay as a pyramidal OME-TIFF, for QuPath, napari and
+    /// other bioimage tools
+    ///
+    /// the overlay's rendered pixels are written as red, green and blue
+    /// channels, with `include_image` followed by the base image's, at
+    /// every zoom level. the dataset's pixel size goes into the metadata
+    ///
+    /// python writes one level per command, full resolution first, so
+    /// `cancel` (or app shutdown) is checked between levels and progress is
+    /// reported per level. the file is a `.partial` next to `path` until the
+    /// last level is in, a cancelled or failed export deletes it
+    pub fn export_ome_tiff(
+        &mut self,
+        overlay_id: &str,
+        path: &Path,
+        include_image: bool,
+        cancel: &CancellationToken,
+        mut on_progress: impl FnMut(ExportProgress),
+    ) -> Result<OmeTiffOutcome, BridgeError> {
+        if self.overlays.info(overlay_id).is_none() {
+            return Err(BridgeError::OverlayNotFound(overlay_id.to_string()));
+        }
+
+        let params = serde_json::json!({
+            "overlay_id": overlay_id,
+            "path": wire_path(path),
+            "include_image": include_image
+        });
+        let plan: OmeTiffPlan = self.send_command_as(PythonCommand::BeginOmeTiff, params)?;
+        let total = plan.levels as usize;
+
+        for level in 0..plan.levels {
+            let done = level as usize;
+            if cancel.is_cancelled() || self.shutdown.is_cancelled() {
+                bridge_debug!(
+                    "PythonBridge: OME-TIFF export of {} aborted at {}/{}",
+                    overlay_id,
+                    done,
+                    total
+                );
+                self.abort_ome_tiff(path);
+                return Ok(OmeTiffOutcome::Aborted { done, total });
+            }
+
+            let params = serde_json::json!({ "path": wire_path(path), "level": level });
+            if let Err(e) = self.send_command(PythonCommand::WriteOmeTiffLevel, params) {
+                self.abort_ome_tiff(path);
+                return Err(e);
+            }
+            on_progress(ExportProgress {
+                overlay_id: overlay_id.to_string(),
+                done: done + 1,
+                total,
+            });
+        }
+
+        let params = serde_json::json!({ "path": wire_path(path) });
+        self.send_command_as(PythonCommand::FinishOmeTiff, params).map(OmeTiffOutcome::Completed)
+    }
+
+    /// have python drop an unfinished export and its partial file
+    fn abort_ome_tiff(&mut self, path: &Path) {
+        let params = serde_json::json!({ "path": wire_path(path) });
+        if let Err(_e) = self.send_command(PythonCommand::AbortOmeTiff, params) {
+            bridge_debug!("PythonBridge: Failed to abort OME-TIFF export {:?}: {}", path, _e);
+        }
+    }
+}
+
+// ===== Comparison Export =====
+
+/// how `export_comparison` puts the two overlays into one figure
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ComparisonLayout {
+    /// `overlay_a` left of `overlay_b`, each under a label
+    SideBySide,
+    /// a single panel, `overlay_a` left of `split` (a fraction of the
+    /// width, between 0 and 1) and `overlay_b` right of it
+    Swipe { split: f64 },
+}
+
+/// how a comparison export ended
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ComparisonOutcome {
+    /// `width` and `height` are the composite's, `max_dimension` and
+    /// `clamped` each panel's
+    Completed(RegionExport),
+    /// cancelled part way, nothing was written. counts steps
+    Aborted { done: usize, total: usize },
+}
+
+/// exporting each panel, then composing them
+const COMPARISON_STEPS: usize = 3;
+
+/// where a panel of the comparison figure at `path` is kept until composed
+fn comparison_panel_path(path: &Path, side: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}.partial", side));
+    PathBuf::from(name)
+}
+
+impl PythonBridge {
+    /// write a figure of the same region of two overlays to a png, for
+    /// comparing plots of the same tissue
+    ///
+    /// the overlays must share their geometry (see `get_comparison_tile`).
+    /// the region is as for `export_region_png`, each panel is exported
+    /// through it with `max_dimension` (and the export memory budget) and
+    /// python composes them, labeled with their fill keys. `cancel` (or app
+    /// shutdown) is checked between the steps and progress is reported per
+    /// step. the panels are removed whatever happens
+    #[allow(clippy::too_many_arguments)]
+    pub fn export_comparison(
+        &mut self,
+        overlay_a: &str,
+        overlay_b: &str,
+        region: (i64, i64, i64, i64),
+        path: &Path,
+        layout: ComparisonLayout,
+        max_dimension: Option<u32>,
+        cancel: &CancellationToken,
+        on_progress: impl FnMut(ExportProgress),
+    ) -> Result<ComparisonOutcome, BridgeError> {
+        self.check_same_geometry(overlay_a, overlay_b)?;
+        if let ComparisonLayout::Swipe { split } = layout {
+            if !(split > 0.0 && split < 1.0) {
+                return Err(BridgeError::Protocol(format!(
+                    "Swipe split must be between 0 and 1, got {}",
+                    split
+                )));
+            }
+        }
+
+        let panels = [comparison_panel_path(path, "a"), comparison_panel_path(path, "b")];
+        let result = self.compose_comparison(
+            [overlay_a, overlay_b],
+            region,
+            &panels,
+            path,
+            layout,
+            max_dimension,
+            cancel,
+            on_progress,
+        );
+        for panel in &panels {
+            let _ = std::fs::remove_file(panel);
+        }
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn compose_comparison(
+        &mut self,
+        overlays: [&str; 2],
+        region: (i64, i64, i64, i64),
+        panels: &[PathBuf; 2],
+        path: &Path,
+        layout: ComparisonLayout,
+        max_dimension: Option<u32>,
+        cancel: &CancellationToken,
+        mut on_progress: impl FnMut(ExportProgress),
+    ) -> Result<ComparisonOutcome, BridgeError> {
+        let (x0, y0, x1, y1) = region;
+        let total = COMPARISON_STEPS;
+        let mut progress = |done| {
+            on_progress(ExportProgress {
+                overlay_id: overlays[0].to_string(),
+                done,
+                total,
+            })
+        };
+
+        // b is exported at a's max_dimension, so a clamped a can't leave
+        // the panels at different sizes
+        let mut max_dimension = max_dimension;
+        let mut panel = None;
+        for (done, (overlay_id, panel_path)) in overlays.into_iter().zip(panels).enumerate() {
+            if cancel.is_cancelled() || self.shutdown.is_cancelled() {
+                return Ok(ComparisonOutcome::Aborted { done, total });
+            }
+            let exported = self.export_region_png(
+                overlay_id,
+                x0,
+                y0,
+                x1,
+                y1,
+                panel_path,
+                max_dimension,
+                None,
+                false,
+            )?;
+            max_dimension = exported.max_dimension.or(max_dimension);
+            panel = Some(exported);
+            progress(done + 1);
+        }
+        if cancel.is_cancelled() || self.shutdown.is_cancelled() {
+            return Ok(ComparisonOutcome::Aborted {
+                done: overlays.len(),
+                total,
+            });
+        }
+
+        let labels: Vec<_> = overlays
+            .iter()
+            .map(|id| self.overlays.info(id).map(|info| info.fill_key.clone()))
+            .collect();
+        let params = serde_json::json!({
+            "panel_a": wire_path(&panels[0]),
+            "panel_b": wire_path(&panels[1]),
+            "path": wire_path(path),
+            "layout": layout,
+            "labels": labels
+        });
+        let mut composed: RegionExport =
+            self.send_command_as(PythonCommand::ComposeComparison, params)?;
+        progress(total);
+        if let Some(panel) = panel {
+            composed.max_dimension = panel.max_dimension;
+            composed.clamped = panel.clamped;
+        }
+        Ok(ComparisonOutcome::Completed(composed))
+    }
+}
+
+// ===== Bridge Config =====
+
+/// environment variables python's behaviour depends on, reported in
+/// `BridgeConfig::python_env`
+const PYTHON_ENV_PREFIXES: [&str; 4] = ["PYTHON", "CONDA_", "VIRTUAL_ENV", "OMP_"];
+
+/// variable name fragments whose values are never reported
+const SENSITIVE_ENV_MARKERS: [&str; 5] = ["TOKEN", "SECRET", "PASSWORD", "KEY", "CREDENTIAL"];
+
+/// how the bridge was actually configured, see `get_bridge_config`
+///
+/// for support: shows whether builder settings took effect and which
+/// defaults filled the rest. durations in milliseconds, `None` for off
+#[derive(Debug, Clone, Serialize)]
+pub struct BridgeConfig {
+    pub python_path: PathBuf,
+    /// where the script runs from, a temp file for an embedded script
+    pub script_path: PathBuf,
+    pub embedded_script: bool,
+    /// command prefix, "" without a namespace
+    pub namespace: String,
+    pub startup_timeout_ms: u64,
+    pub response_timeout_ms: Option<u64>,
+    /// command name -> its own timeout, see `command_timeout`
+    pub command_timeouts_ms: BTreeMap<String, u64>,
+    pub kill_on_timeout: bool,
+    /// commands decoded without a `serde_json::Value`, see
+    /// `PythonBridgeBuilder::direct_decode`
+    pub direct_decode: Vec<String>,
+    pub idle_timeout_ms: Option<u64>,
+    pub tile_cache_bytes: usize,
+    pub pinned_tile_bytes: usize,
+    pub compact_tile_cache: bool,
+    pub tile_processing_stage: ProcessStage,
+    /// see `PythonBridgeBuilder::export_memory_budget`
+    pub export_memory_budget: Option<u64>,
+    pub warmup_levels: u32,
+    /// lane name -> limit, unlimited lanes are left out
+    pub lane_limits: BTreeMap<String, usize>,
+    /// command name -> limit, only commands with a limit
+    pub concurrency_limits: BTreeMap<String, usize>,
+    pub id_encoding: IdEncoding,
+    pub resource_limits: ResourceLimits,
+    pub respawn_on_desync: bool,
+    pub capture_wire_frames: bool,
+    pub strict_commands: bool,
+    /// commands of `PythonCommand` the helper didn't list at startup, they
+    /// fail when called. always empty with `strict_commands`
+    pub missing_commands: Vec<String>,
+    /// from python's handshake, `None` from helpers that don't send it
+    pub protocol_version: Option<u32>,
+    /// python's pid from the handshake
+    pub python_pid: Option<u32>,
+    /// pid of the spawned program when it isn't python itself, i.e.
+    /// `python_path` is a launcher (pyenv shim, conda stub, venv
+    /// redirector) that re-exec'd or handed over to the real interpreter
+    pub launcher_pid: Option<u32>,
+    /// how requests reach python, see `TransportInfo`
+    pub transport: String,
+    /// python related variables python inherits from the app, values of
+    /// anything that looks like a secret replaced with "<redacted>"
+    pub python_env: BTreeMap<String, String>,
+    /// variables set through `PythonBridgeBuilder::env`, redacted the same
+    /// way. values that aren't UTF-8 are shown lossily
+    pub env: BTreeMap<String, String>,
+}
+
+/// `value`, or "<redacted>" if `name` looks like it holds a secret
+fn redact_env(name: &str, value: String) -> String {
+    let upper = name.to_ascii_uppercase();
+    if SENSITIVE_ENV_MARKERS.iter().any(|marker| upper.contains(marker)) {
+        "<redacted>".to_string()
+    } else {
+        value
+    }
+}
+
+/// the `PYTHON_ENV_PREFIXES` variables of this process, secrets redacted
+fn python_env() -> BTreeMap<String, String> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| {
+            let name = name.into_string().ok()?;
+            if !PYTHON_ENV_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+                return None;
+            }
+            let value = redact_env(&name, value.to_string_lossy().into_owned());
+            Some((name, value))
+        })
+        .collect()
+}
+
+impl CommandGates {
+    /// (lane limits, command limits) by name, unlimited ones left out
+    fn limits(&self) -> (BTreeMap<String, usize>, BTreeMap<String, usize>) {
+        let lanes = self
+            .lanes
+            .iter()
+            .filter_map(|(lane, gate)| Some((lane.name().to_string(), gate.limit?)))
+            .collect();
+        let commands = self
+            .gates
+            .iter()
+            .filter_map(|(command, gate)| Some((command.name().to_string(), gate.limit?)))
+            .collect();
+        (lanes, commands)
+    }
+}
+
+impl PythonBridge {
+    /// the effective configuration, for support and debugging
+    pub fn get_bridge_config(&self) -> BridgeConfig {
+        let mut config = self.config.clone();
+        let (lane_limits, concurrency_limits) = self.gates.limits();
+        config.lane_limits = lane_limits;
+        config.concurrency_limits = concurrency_limits;
+        config.python_env = python_env();
+        config
+    }
+}
+
+// ===== Transport =====
+
+/// how requests reach python
+///
+/// only `Stdio` is built in, the others are recognized so that asking for
+/// them fails with `UnsupportedTransport` instead of a parse error
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TransportConfig {
+    /// json lines over the child's stdin and stdout
+    #[default]
+    Stdio,
+    UnixSocket { path: PathBuf },
+    Tcp { host: String, port: u16 },
+    SharedMemory { name: String },
+}
+
+impl TransportConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransportConfig::Stdio => "stdio",
+            TransportConfig::UnixSocket { .. } => "unix_socket",
+            TransportConfig::Tcp { .. } => "tcp",
+            TransportConfig::SharedMemory { .. } => "shared_memory",
+        }
+    }
+}
+
+/// the transports `reconfigure_transport` can switch to
+pub const SUPPORTED_TRANSPORTS: &[&str] = &["stdio"];
+
+/// the connection to python as it is right now, see
+/// `PythonBridge::get_transport_info`
+#[derive(Debug, Clone, Serialize)]
+pub struct TransportInfo {
+    pub transport: TransportConfig,
+    /// how messages are delimited, "json_lines"
+    pub framing: String,
+    /// see `SUPPORTED_TRANSPORTS`
+    pub supported: Vec<String>,
+    pub status: BridgeStatus,
+    /// python's pid from the handshake
+    pub python_pid: Option<u32>,
+    /// see `BridgeConfig::launcher_pid`
+    pub launcher_pid: Option<u32>,
+    /// connections set up again since the bridge was built, respawns after
+    /// a crash or idle shutdown included
+    pub reconnects: u64,
+}
+
+impl PythonBridge {
+    /// which transport carries the requests, and how the connection is
+    pub fn get_transport_info(&self) -> TransportInfo {
+        TransportInfo {
+            transport: TransportConfig::Stdio,
+            framing: "json_lines".to_string(),
+            supported: SUPPORTED_TRANSPORTS.iter().map(|name| name.to_string()).collect(),
+            status: self.status(),
+            python_pid: self.config.python_pid,
+            launcher_pid: self.config.launcher_pid,
+            reconnects: self.reconnects,
+        }
+    }
+
+    /// tear the connection down and set it up again over `config`
+    ///
+    /// goes through the respawn a crash triggers: python starts over, so
+    /// its overlays and loaded datasets are gone afterwards, as after any
+    /// respawn. switching to `Stdio` reconnects over fresh pipes, e.g. to
+    /// rule out a wedged pipe. a transport this build lacks fails with
+    /// `UnsupportedTransport` and leaves the connection alone
+    pub fn reconfigure_transport(
+        &mut self,
+        config: TransportConfig,
+    ) -> Result<TransportInfo, BridgeError> {
+        if !SUPPORTED_TRANSPORTS.contains(&config.name()) {
+            return Err(BridgeError::UnsupportedTransport {
+                requested: config.name().to_string(),
+                supported: SUPPORTED_TRANSPORTS.iter().map(|name| name.to_string()).collect(),
+            });
+        }
+        if self.shutdown.is_cancelled() {
+            return Err(BridgeError::ShuttingDown);
+        }
+        self.respawn()?;
+        self.prewarm();
+        Ok(self.get_transport_info())
+    }
+}
+
+// ===== Resource Limits =====
+
+/// caps on the python child's resources, see `PythonBridgeBuilder::memory_limit`
+///
+/// on unix they are applied with `setrlimit` between fork and exec, so the
+/// child starts out limited and can't raise them again. a child over its
+/// memory limit sees allocations fail (python raises `MemoryError`), one
+/// over its cpu limit is killed with `SIGXCPU` and respawned by the next
+/// command. windows has no rlimits, the closest equivalent would be a job
+/// object with `JOB_OBJECT_LIMIT_PROCESS_MEMORY` and a cpu rate cap. that
+/// isn't implemented, so asking for limits there fails the spawn instead of
+/// silently running unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// address space in bytes (`RLIMIT_AS`), `None` for unlimited
+    pub memory_bytes: Option<u64>,
+    /// total cpu time in seconds (`RLIMIT_CPU`), `None` for unlimited
+    pub cpu_seconds: Option<u64>,
+}
+
+impl ResourceLimits {
+    fn is_empty(&self) -> bool {
+        self.memory_bytes.is_none() && self.cpu_seconds.is_none()
+    }
+
+    /// make `command` apply the limits to the child it spawns
+    #[cfg(unix)]
+    fn apply_to(&self, command: &mut Command) -> Result<(), BridgeError> {
+        use std::os::unix::process::CommandExt;
+
+        if self.is_empty() {
+            return Ok(());
+        }
+        let limits = *self;
+        // SAFETY: the hook runs in the forked child before exec, it only
+        // calls setrlimit (async-signal-safe) and doesn't allocate
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(bytes) = limits.memory_bytes {
+                    check_rlimit(libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes)))?;
+                }
+                if let Some(seconds) = limits.cpu_seconds {
+                    check_rlimit(libc::setrlimit(libc::RLIMIT_CPU, &rlimit(seconds)))?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn apply_to(&self, _command: &mut Command) -> Result<(), BridgeError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        Err(BridgeError::Spawn(format!(
+            "Resource limits {:?} are only supported on unix",
+            self
+        )))
+    }
+}
+
+/// both the soft and the hard limit, so the child can't raise it again
+///
+/// `setrlimit` is called directly with the `RLIMIT_*` constant since its
+/// resource type differs between libcs (`c_int`, or `u32` on glibc)
+#[cfg(unix)]
+fn rlimit(value: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    }
+}
+
+#[cfg(unix)]
+fn check_rlimit(rc: libc::c_int) -> std::io::Result<()> {
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+// ===== Bridge Builder =====
+
+/// where the python helper comes from
+enum ScriptSource {
+    /// a `.py` file on disk
+    Path(PathBuf),
+    /// script source compiled into the binary (e.g. via `include_str!`)
+    Embedded(Vec<u8>),
+}
+
+/// configures how the python child process is spawned
+///
+/// ```ignore
+/// let bridge = PythonBridge::builder()
+///     .embedded_script(include_str!("python_tiling.py"))
+///     .build()?;
+/// ```
+pub struct PythonBridgeBuilder {
+    python_path: PathBuf,
+    script: ScriptSource,
+    namespace: String,
+    tile_cache_bytes: usize,
+    pinned_tile_bytes: Option<usize>,
+    compact_tile_cache: bool,
+    tile_processing_stage: ProcessStage,
+    export_memory_budget: Option<u64>,
+    cache_policies: HashMap<PythonCommand, CachePolicy>,
+    shutdown: CancellationToken,
+    warmup_levels: u32,
+    concurrency_limits: HashMap<PythonCommand, usize>,
+    lane_limits: HashMap<Lane, usize>,
+    deadline: Option<ResponseDeadline>,
+    command_timeouts: HashMap<PythonCommand, Duration>,
+    direct_decode: HashSet<PythonCommand>,
+    id_encoding: IdEncoding,
+    startup_timeout: Duration,
+    idle_timeout: Option<Duration>,
+    on_python_log: Option<PythonLogHandler>,
+    on_pyramid_progress: Option<PyramidProgressHandler>,
+    on_python_warning: Option<PythonWarningHandler>,
+    respawn_on_desync: bool,
+    capture_wire_frames: bool,
+    strict_commands: bool,
+    resource_limits: ResourceLimits,
+    env: BTreeMap<String, OsString>,
+}
+
+impl Default for PythonBridgeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PythonBridgeBuilder {
+    pub fn new() -> Self {
+        Self {
+            python_path: PathBuf::from("python3"),
+            script: ScriptSource::Path(PathBuf::from("python_tiling.py")),
+            namespace: String::new(),
+            tile_cache_bytes: 64 * 1024 * 1024,
+            pinned_tile_bytes: None,
+            compact_tile_cache: false,
+            tile_processing_stage: ProcessStage::AfterCache,
+            export_memory_budget: Some(DEFAULT_EXPORT_MEMORY_BUDGET),
+            cache_policies: HashMap::new(),
+            shutdown: CancellationToken::new(),
+            warmup_levels: 1,
+            concurrency_limits: HashMap::new(),
+            lane_limits: HashMap::new(),
+            deadline: None,
+            command_timeouts: HashMap::new(),
+            direct_decode: DIRECT_DECODE_COMMANDS.iter().copied().collect(),
+            id_encoding: IdEncoding::default(),
+            startup_timeout: Duration::from_secs(120),
+            idle_timeout: None,
+            on_python_log: None,
+            on_pyramid_progress: None,
+            on_python_warning: None,
+            respawn_on_desync: false,
+            capture_wire_frames: false,
+            strict_commands: false,
+            resource_limits: ResourceLimits::default(),
+            env: BTreeMap::new(),
+        }
+    }
+
+    /// python interpreter to run, defaults to `python3` on PATH
+    pub fn python_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.python_path = path.into();
+        self
+    }
+
+    /// run the helper from a script on disk
+    pub fn script_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.script = ScriptSource::Path(path.into());
+        self
+    }
+
+    /// run the helper from source embedded in the app
+    ///
+    /// the source is written to a temp file before spawning and the file is
+    /// removed when the bridge is dropped. this keeps the python helper in
+    /// lockstep with the rust side it was compiled with
+    pub fn embedded_script(mut self, source: impl Into<Vec<u8>>) -> Self {
+        self.script = ScriptSource::Embedded(source.into());
+        self
+    }
+
+    /// route every command through a namespace on the python side
+    ///
+    /// with `namespace("tissue")` commands go out as
+    /// `"tissue.plot_tissue_overlay"`, so one python dispatcher can host
+    /// several bridges. the dispatcher splits the command on its last `.`
+    /// and looks the remainder up in that namespace's command table. the
+    /// helper script is told the namespace in `SPATIALVIEWER_NAMESPACE` and
+    /// serves its own commands under it
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        let namespace = namespace.trim_end_matches('.');
+        if namespace.is_empty() {
+            self.namespace = String::new();
+            self.env.remove(NAMESPACE_ENV);
+        } else {
+            self.namespace = format!("{}.", namespace);
+            self.env.insert(NAMESPACE_ENV.to_string(), namespace.into());
+        }
+        self
+    }
+
+    /// upper bound on the encoded tile data kept in memory, 64 MiB by default
+    pub fn tile_cache_bytes(mut self, bytes: usize) -> Self {
+        self.tile_cache_bytes = bytes;
+        self
+    }
+
+    /// how much of the tile cache pinned tiles may take, a quarter of it
+    /// by default. capped at `tile_cache_bytes`
+    pub fn pinned_tile_bytes(mut self, bytes: usize) -> Self {
+        self.pinned_tile_bytes = Some(bytes);
+        self
+    }
+
+    /// hold cached tiles as raw bytes instead of python's base64
+    ///
+    /// a quarter more tiles fit the same `tile_cache_bytes`, the budget
+    /// counts the compact size, at the cost of base64 encoding each tile
+    /// again on every hit (well under a millisecond for a 256 pixel tile).
+    /// the image bytes themselves are jpeg or png and don't compress any
+    /// further. off by default, compare `tile_cache_stats` hit rates with
+    /// and without it. the base image tile cache follows the same setting
+    pub fn compact_tile_cache(mut self) -> Self {
+        self.compact_tile_cache = true;
+        self
+    }
+
+    /// run tile processors once before tiles are cached instead of on
+    /// every tile served, see `ProcessStage::BeforeCache`
+    ///
+    /// for adjustments that rarely change, or custom processors too slow
+    /// to run per hit
+    pub fn process_tiles_before_cache(mut self) -> Self {
+        self.tile_processing_stage = ProcessStage::BeforeCache;
+        self
+    }
+
+    /// how much image memory an `export_region_png` may need in python
+    /// before it is downscaled to fit, 512 MiB by default
+    ///
+    /// an estimate of the stitched region plus its downscaled copy, python
+    /// itself and the loaded data come on top. keep it well under
+    /// `memory_limit` if one is set. a single export can still ask for the
+    /// full size with `force_full_size`
+    pub fn export_memory_budget(mut self, bytes: u64) -> Self {
+        self.export_memory_budget = Some(bytes);
+        self
+    }
+
+    /// override how long a read-only command's result is cached
+    pub fn cache_policy(mut self, command: PythonCommand, policy: CachePolicy) -> Self {
+        self.cache_policies.insert(command, policy);
+        self
+    }
+
+    /// zoom levels to render into the tile cache before plot returns
+    ///
+    /// defaults to 1, just the overview at zoom 0. 0 turns warmup off, each
+    /// extra level costs four times the tiles of the one before
+    pub fn warmup_levels(mut self, levels: u32) -> Self {
+        self.warmup_levels = levels;
+        self
+    }
+
+    /// cap how many `command` requests can be in flight at once
+    ///
+    /// requests over the limit queue in the bridge. unlimited by default,
+    /// the command's lane still applies, see `lane_limit`
+    pub fn concurrency_limit(mut self, command: PythonCommand, limit: usize) -> Self {
+        self.concurrency_limits.insert(command, limit.max(1));
+        self
+    }
+
+    /// cap how many requests of a lane can be in flight at once, on top of
+    /// the per-command limits. defaults are 1 in the plot lane, 8 in the
+    /// tile lane and unlimited control requests
+    pub fn lane_limit(mut self, lane: Lane, limit: usize) -> Self {
+        self.lane_limits.insert(lane, limit.max(1));
+        self
+    }
+
+    /// fail a command with `Timeout` if python hasn't answered in `after`
+    ///
+    /// off by default. with `kill_on_timeout` the child is also killed and
+    /// the next command respawns it, otherwise only the caller gives up and
+    /// the late response is discarded. prewarm is exempt
+    pub fn response_timeout(mut self, after: Duration, kill_on_timeout: bool) -> Self {
+        self.deadline = Some(ResponseDeadline {
+            after,
+            kill_on_timeout,
+        });
+        self
+    }
+
+    /// give `command` `after` to answer instead of the `response_timeout`
+    ///
+    /// works with the global timeout off too. a call's own override wins
+    /// over both, see `PythonBridge::with_timeout`
+    pub fn command_timeout(mut self, command: PythonCommand, after: Duration) -> Self {
+        self.command_timeouts.insert(command, after);
+        self
+    }
+
+    /// decode `command`'s responses straight into their rust type
+    /// (`enabled`) or through a `serde_json::Value` as usual
+    ///
+    /// a direct decode parses `data` once instead of building every object
+    /// and string of it as a `Value` first, and on the waiting thread
+    /// rather than the one reader all responses pass. worth it for large
+    /// responses, see `PythonBridge::wire_metrics` and `benchmark_decode`.
+    /// on by default for `DIRECT_DECODE_COMMANDS`. commands whose response
+    /// is cached or passed on as raw json are parsed into a `Value` anyway
+    pub fn direct_decode(mut self, command: PythonCommand, enabled: bool) -> Self {
+        if enabled {
+            self.direct_decode.insert(command);
+        } else {
+            self.direct_decode.remove(&command);
+        }
+        self
+    }
+
+    /// give up on a python that hasn't completed its handshake by then
+    ///
+    /// the half-started child is killed and `build` fails with
+    /// `StartupTimeout`. defaults to 2 minutes, enough for a cold start of
+    /// the scientific stack on a slow disk, short enough that a broken
+    /// environment doesn't hang the app forever
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// stop python after `timeout` without commands, off by default
+    ///
+    /// frees the interpreter's memory (often gigabytes with a dataset
+    /// loaded) at the cost of a respawn and prewarm on the next command.
+    /// python's in-memory state goes with it, datasets and overlays have
+    /// to be loaded and plotted again, so only enable this where the app
+    /// restores them. meant for memory constrained machines, anything that
+    /// pings python periodically to keep it warm defeats it
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// receive python's log records as they are written
+    ///
+    /// called on the reader thread, rate limited to
+    /// `LOG_RECORDS_PER_SECOND`. without a handler they are discarded
+    pub fn on_python_log(mut self, handler: impl Fn(PythonLog) + Send + Sync + 'static) -> Self {
+        self.on_python_log = Some(Arc::new(handler));
+        self
+    }
+
+    /// be told about each level an eager `plot_tissue_overlay` builds
+    pub fn on_pyramid_progress(
+        mut self,
+        handler: impl Fn(PyramidProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_pyramid_progress = Some(Arc::new(handler));
+        self
+    }
+
+    /// receive the warnings python attaches to successful responses, see
+    /// `PythonWarning`. without a handler they only reach `with_warnings`
+    pub fn on_python_warning(
+        mut self,
+        handler: impl Fn(PythonWarning) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_python_warning = Some(Arc::new(handler));
+        self
+    }
+
+    /// restart python when its responses stop matching the requests
+    ///
+    /// a desync always fails the requests in flight with `ProtocolDesync`.
+    /// without this the child is kept and later requests are answered as
+    /// usual, with it the child is killed and the next command respawns it,
+    /// losing python's loaded datasets and overlays but nothing it could
+    /// still have gotten wrong
+    pub fn respawn_on_desync(mut self) -> Self {
+        self.respawn_on_desync = true;
+        self
+    }
+
+    /// keep the raw bytes of the latest request and its response, see
+    /// `PythonBridge::get_last_wire_frames`
+    ///
+    /// off by default, it copies every line that crosses the pipe. for
+    /// debugging desyncs and encoding issues
+    pub fn capture_wire_frames(mut self) -> Self {
+        self.capture_wire_frames = true;
+        self
+    }
+
+    /// fail `build` with `MissingCommands` unless the helper lists every
+    /// `PythonCommand`
+    ///
+    /// off by default, so partial or experimental helpers still start. the
+    /// commands they lack are reported in `BridgeConfig::missing_commands`
+    /// and fail when called
+    pub fn strict_commands(mut self) -> Self {
+        self.strict_commands = true;
+        self
+    }
+
+    /// cap the address space of the python child, unlimited by default
+    ///
+    /// leave headroom: the scientific stack maps far more address space
+    /// than it touches, under a couple of GiB python may not even import
+    /// numpy. unix only, see `ResourceLimits`. also applies to respawns
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.resource_limits.memory_bytes = Some(bytes);
+        self
+    }
+
+    /// cap the total cpu time of the python child, unlimited by default
+    ///
+    /// counted over the child's whole life, not per command, and rounded up
+    /// to whole seconds. a child that hits it is killed and the next command
+    /// respawns it. unix only, see `ResourceLimits`
+    pub fn cpu_limit(mut self, cpu_time: Duration) -> Self {
+        let seconds = cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0);
+        self.resource_limits.cpu_seconds = Some(seconds.max(1));
+        self
+    }
+
+    /// set an environment variable for the python child, on top of the
+    /// ones it inherits from the app. also applies to respawns
+    ///
+    /// the value is passed as is, e.g. a path that isn't valid UTF-8
+    pub fn env(mut self, name: &str, value: impl AsRef<OsStr>) -> Self {
+        self.env.insert(name.to_string(), value.as_ref().to_os_string());
+        self
+    }
+
+    /// render through matplotlib's Agg backend whatever the environment says
+    ///
+    /// for headless deployments, where a GUI backend picked up from
+    /// `MPLBACKEND` or a matplotlibrc hangs the first plot. same as
+    /// `env("MPLBACKEND", "Agg")`
+    pub fn force_agg_backend(self) -> Self {
+        self.env("MPLBACKEND", "Agg")
+    }
+
+    /// how segment ids are sent to the frontend, strings by default
+    ///
+    /// only switch to `IdEncoding::Number` if every label is known to stay
+    /// below 2^53, otherwise javascript rounds them to neighbouring ids
+    pub fn id_encoding(mut self, encoding: IdEncoding) -> Self {
+        self.id_encoding = encoding;
+        self
+    }
+
+    /// token that aborts the bridge when cancelled
+    ///
+    /// every in-flight command resolves right away with `ShuttingDown` and
+    /// the child is killed. hold on to the token outside the bridge's lock,
+    /// e.g. in `AppState`, so app exit can cancel it while a command runs
+    pub fn shutdown_token(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    /// spawn the python child process and connect to its stdin/stdout
+    pub fn build(self) -> Result<PythonBridge, BridgeError> {
+        if self.shutdown.is_cancelled() {
+            return Err(BridgeError::ShuttingDown);
+        }
+
+        let (script_path, embedded_script_file) = match self.script {
+            ScriptSource::Path(path) => (path, None),
+            ScriptSource::Embedded(source) => {
+                let script = EmbeddedScript::write(&source)?;
+                (script.path.clone(), Some(script))
+            }
+        };
+
+        let diagnostics = Arc::new(Diagnostics::default());
+        let spawned = spawn_python(
+            &self.python_path,
+            &script_path,
+            &diagnostics,
+            self.startup_timeout,
+            &self.resource_limits,
+            &self.env,
+        );
+        // a failed spawn drops `embedded_script_file`, which removes it
+        let (process, stdin, stdout, handshake) = spawned?;
+
+        // from here on the bridge's Drop kills the child and removes the
+        // temp script if anything else fails
+        let status = Arc::new(Mutex::new(BridgeStatus::Ready));
+        let connection_options = ConnectionOptions {
+            deadline: self.deadline,
+            on_log: self.on_python_log,
+            respawn_on_desync: self.respawn_on_desync,
+            capture_frames: self.capture_wire_frames,
+        };
+        let connection = Connection::start(
+            process,
+            handshake.reexec_pid(),
+            stdin,
+            stdout,
+            Arc::clone(&status),
+            &self.shutdown,
+            connection_options.clone(),
+        );
+        let gates = CommandGates::new(&self.concurrency_limits, &self.lane_limits, &self.shutdown);
+        let pinned_tile_bytes = self.pinned_tile_bytes.unwrap_or(self.tile_cache_bytes / 4);
+        let config = BridgeConfig {
+            python_path: self.python_path.clone(),
+            script_path: script_path.clone(),
+            embedded_script: embedded_script_file.is_some(),
+            namespace: self.namespace.clone(),
+            startup_timeout_ms: self.startup_timeout.as_millis() as u64,
+            response_timeout_ms: self.deadline.map(|d| d.after.as_millis() as u64),
+            command_timeouts_ms: self
+                .command_timeouts
+                .iter()
+                .map(|(command, after)| (command.name().to_string(), after.as_millis() as u64))
+                .collect(),
+            kill_on_timeout: self.deadline.is_some_and(|d| d.kill_on_timeout),
+            direct_decode: PythonCommand::ALL
+                .iter()
+                .filter(|command| self.direct_decode.contains(command))
+                .map(|command| command.name().to_string())
+                .collect(),
+            idle_timeout_ms: self.idle_timeout.map(|t| t.as_millis() as u64),
+            tile_cache_bytes: self.tile_cache_bytes,
+            // pinned tiles can't take more than the whole cache
+            pinned_tile_bytes: pinned_tile_bytes.min(self.tile_cache_bytes),
+            compact_tile_cache: self.compact_tile_cache,
+            tile_processing_stage: self.tile_processing_stage,
+            export_memory_budget: self.export_memory_budget,
+            warmup_levels: self.warmup_levels,
+            lane_limits: BTreeMap::new(),
+            concurrency_limits: BTreeMap::new(),
+            id_encoding: self.id_encoding,
+            resource_limits: self.resource_limits,
+            respawn_on_desync: self.respawn_on_desync,
+            capture_wire_frames: self.capture_wire_frames,
+            strict_commands: self.strict_commands,
+            missing_commands: Vec::new(),
+            protocol_version: handshake.protocol_version,
+            python_pid: handshake.pid,
+            launcher_pid: handshake.launcher_pid,
+            transport: TransportConfig::Stdio.name().to_string(),
+            python_env: BTreeMap::new(),
+            env: self
+                .env
+                .iter()
+                .map(|(name, value)| {
+                    let value = value.to_string_lossy().into_owned();
+                    (name.clone(), redact_env(name, value))
+                })
+                .collect(),
+        };
+        let canceller = RequestCanceller::new(
+            &connection,
+            PythonCommand::CancelTagged.wire_name(&self.namespace),
+        );
+        let idle = self.idle_timeout.map(|timeout| {
+            IdleWatch::start(timeout, &connection, Arc::clone(&status), &self.shutdown)
+        });
+        let overlays = OverlayRegistry::default();
+        let tiles = Arc::new(TileService {
+            connection: Mutex::new(Arc::downgrade(&connection)),
+            namespace: self.namespace.clone(),
+            status: Arc::clone(&status),
+            shutdown: self.shutdown.clone(),
+            gates: Arc::clone(&gates),
+            diagnostics: Arc::clone(&diagnostics),
+            idle: idle.clone(),
+            command_timeouts: self.command_timeouts.clone(),
+            deadline: self.deadline.map(|d| d.after),
+            direct_decode: self.direct_decode.clone(),
+            on_python_warning: self.on_python_warning.clone(),
+            overlays: overlays.sources(),
+            generations: overlays.generations(),
+            cache: Mutex::new(TileCache::new(
+                self.tile_cache_bytes,
+                pinned_tile_bytes,
+                self.compact_tile_cache,
+            )),
+            fetches: Mutex::new(VecDeque::new()),
+            processing: RwLock::new(TileProcessing {
+                stage: self.tile_processing_stage,
+                ..TileProcessing::default()
+            }),
+            format: Mutex::new(TileFormatMode::default()),
+        });
+        let mut bridge = PythonBridge {
+            python_path: self.python_path,
+            script_path,
+            namespace: self.namespace,
+            connection,
+            embedded_script_file,
+            status,
+            shutdown: self.shutdown,
+            overlays,
+            render_contexts: RenderContextRegistry::default(),
+            tiles,
+            base_tiles: TileCache::new(self.tile_cache_bytes / 4, 0, self.compact_tile_cache),
+            overviews: OverviewCache::default(),
+            hover_cache: HoverCache::default(),
+            result_cache: ResultCache::new(self.cache_policies),
+            warmup_levels: self.warmup_levels,
+            gates,
+            connection_options,
+            command_timeouts: self.command_timeouts,
+            direct_decode: self.direct_decode,
+            call_timeout: None,
+            request_tag: None,
+            canceller,
+            context: DatasetContext::default(),
+            render_defaults: RenderDefaults::default(),
+            diagnostics,
+            id_encoding: self.id_encoding,
+            startup_timeout: self.startup_timeout,
+            resource_limits: self.resource_limits,
+            env: self.env,
+            config,
+            idle,
+            on_pyramid_progress: self.on_pyramid_progress,
+            on_python_warning: self.on_python_warning,
+            warnings: None,
+            reconnects: 0,
+            preprocessings: 0,
+        };
+
+        bridge.check_supported_commands(self.strict_commands)?;
+
+        Ok(bridge)
+    }
+}
+
+/// spawn the python helper and wait for its startup handshake
+fn spawn_python(
+    python_path: &Path,
+    script_path: &Path,
+    diagnostics: &Arc<Diagnostics>,
+    startup_timeout: Duration,
+    resource_limits: &ResourceLimits,
+    env: &BTreeMap<String, OsString>,
+) -> Result<(Child, ChildStdin, BufReader<ChildStdout>, Handshake), BridgeError> {
+    bridge_debug!("PythonBridge: Spawning {:?} {:?}", python_path, script_path);
+
+    // -u: unbuffered stdout so responses are not stuck in python's buffer
+    // stderr is piped through us so the tail can go into diagnostics
+    let mut command = Command::new(python_path);
+    command
+        .arg("-u")
+        .arg(script_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .envs(env);
+    resource_limits.apply_to(&mut command)?;
+    let mut process = command.spawn().map_err(|e| {
+        if resource_limits.is_empty() {
+            BridgeError::Spawn(format!("{:?}: {}", python_path, e))
+        } else {
+            // a failing pre_exec hook surfaces as the spawn error
+            BridgeError::Spawn(format!(
+                "{:?} with resource limits {:?}: {}",
+                python_path, resource_limits, e
+            ))
+        }
+    })?;
+
+    let (Some(stdin), Some(stdout), Some(stderr)) =
+        (process.stdin.take(), process.stdout.take(), process.stderr.take())
+    else {
+        let _ = process.kill();
+        return Err(BridgeError::Spawn("Failed to open Python stdio".to_string()));
+    };
+    let stdout = BufReader::new(stdout);
+
+    // still echoed to our stderr so python's debug output ends up in our logs
+    let diagnostics = Arc::clone(diagnostics);
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else { break };
+            eprintln!("{}", line);
+            diagnostics.record_stderr(line);
+        }
+    });
+
+    // the handshake is read on a helper thread so a python that never
+    // gets there can be given up on. killing the child ends that read
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdout = stdout;
+        let handshake = read_handshake(&mut stdout);
+        let _ = tx.send(handshake.map(|handshake| (handshake, stdout)));
+    });
+
+    let result = rx
+        .recv_timeout(startup_timeout)
+        .unwrap_or(Err(BridgeError::StartupTimeout(startup_timeout)));
+
+    match result {
+        Ok((mut handshake, stdout)) => {
+            bridge_debug!("PythonBridge: Handshake complete: {:?}", handshake);
+            // a launcher that re-execs keeps its pid, one that forks or
+            // spawns the interpreter doesn't. python holds the pipes either
+            // way, so it is tracked by its stdout rather than `process`
+            if let Some(pid) = handshake.pid.filter(|&pid| pid != process.id()) {
+                eprintln!(
+                    "PythonBridge: {:?} (pid {}) handed over to Python pid {}, tracking that one",
+                    python_path,
+                    process.id(),
+                    pid
+                );
+                handshake.launcher_pid = Some(process.id());
+            }
+            Ok((process, stdin, stdout, handshake))
+        }
+        Err(e) => {
+            let _ = process.kill();
+            let _ = process.wait();
+            Err(e)
+        }
+    }
+}
+
+/// kill a process we didn't spawn ourselves, best effort
+#[cfg(unix)]
+fn kill_pid(pid: u32) {
+    // SAFETY: kill takes no pointers, a stale pid fails with ESRCH
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+fn kill_pid(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/F", "/PID", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(not(any(unix, windows)))]
+fn kill_pid(_pid: u32) {}
+
+/// attempts at a fresh directory name before `EmbeddedScript::write` gives up
+const EMBEDDED_SCRIPT_ATTEMPTS: u32 = 16;
+
+/// an embedded script written out for python to run, removed on drop
+///
+/// the file sits in a directory of its own that only we can enter (0700
+/// on unix) and is created fresh with `create_new` (0600), so another user
+/// can neither read the script nor swap in their own between writing it
+/// and spawning. the bridge keeps it until it is dropped, respawns run it
+/// again
+struct EmbeddedScript {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl EmbeddedScript {
+    fn write(source: &[u8]) -> Result<Self, BridgeError> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let failed = |path: &Path, e: std::io::Error| {
+            BridgeError::Spawn(format!("Failed to write embedded script to {:?}: {}", path, e))
+        };
+        // the name only has to be unlikely, creating the directory fails
+        // rather than reuse one that already exists
+        let mut attempt = 0;
+        let dir = loop {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.subsec_nanos())
+                .unwrap_or(0);
+            let dir = std::env::temp_dir().join(format!(
+                "spatialviewer_bridge_{}_{}_{:08x}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+            match create_private_dir(&dir) {
+                Ok(()) => break dir,
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    attempt += 1;
+                    if attempt == EMBEDDED_SCRIPT_ATTEMPTS {
+                        return Err(failed(&dir, e));
+                    }
+                }
+                Err(e) => return Err(failed(&dir, e)),
+            }
+        };
+
+        // from here on dropping `script` cleans up whatever was written
+        let script = EmbeddedScript {
+            path: dir.join("python_tiling.py"),
+            dir,
+        };
+        create_private_file(&script.path)
+            .and_then(|mut file| file.write_all(source))
+            .map_err(|e| failed(&script.path, e))?;
+        Ok(script)
+    }
+}
+
+impl Drop for EmbeddedScript {
+    fn drop(&mut self) {
+        let removed = std::fs::remove_file(&self.path).and_then(|_| std::fs::remove_dir(&self.dir));
+        if let Err(_e) = removed {
+            bridge_debug!("PythonBridge: Failed to remove embedded script {:?}: {}", self.path, _e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new().mode(0o700).create(dir)
+}
+
+/// the temp dir is per user elsewhere (windows) or has no permissions
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir(dir)
+}
+
+fn create_private_file(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+#[cfg(test)]
+mod embedded_script_tests {
+    use super::*;
+
+    #[test]
+    fn writes_the_source_into_a_fresh_directory() {
+        let first = EmbeddedScript::write(b"print('hi')").unwrap();
+        let second = EmbeddedScript::write(b"").unwrap();
+
+        assert_ne!(first.dir, second.dir);
+        assert_eq!(std::fs::read(&first.path).unwrap(), b"print('hi')");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_can_read_it() {
+        use std::os::unix::fs::PermissionsExt;
+        let script = EmbeddedScript::write(b"").unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&script.dir), 0o700);
+        assert_eq!(mode(&script.path), 0o600);
+    }
+
+    #[test]
+    fn drop_removes_the_file_and_its_directory() {
+        let script = EmbeddedScript::write(b"").unwrap();
+        let dir = script.dir.clone();
+
+        drop(script);
+        assert!(!dir.exists());
+    }
+}
+
+// ===== Tauri Commands =====
+
+/// makes sure only one caller spawns the bridge, see `AppState::ensure_bridge`
+///
+/// spawning runs outside the `python` lock (the handshake can take a
+/// minute), so without this two first commands could each see no bridge
+/// and start a child. the first caller spawns, everyone arriving meanwhile
+/// waits for it and gets its result. a failed spawn isn't cached, the next
+/// caller tries again
+#[derive(Default)]
+struct InitGate {
+    state: Mutex<InitRound>,
+    done: Condvar,
+}
+
+#[derive(Default)]
+struct InitRound {
+    spawning: bool,
+    /// bumped whenever a spawn finishes, how waiters notice theirs did
+    round: u64,
+    result: Option<Result<(), String>>,
+}
+
+enum InitTurn<'a> {
+    /// this caller spawns, the guard publishes the result
+    Spawn(InitGuard<'a>),
+    /// someone else spawned meanwhile, this is how it went
+    Waited(Result<(), String>),
+}
+
+impl InitGate {
+    fn is_spawning(&self) -> bool {
+        self.state.lock().unwrap().spawning
+    }
+
+    fn begin(&self) -> InitTurn<'_> {
+        let mut state = self.state.lock().unwrap();
+        if !state.spawning {
+            state.spawning = true;
+            return InitTurn::Spawn(InitGuard {
+                gate: self,
+                finished: false,
+            });
+        }
+        let round = state.round;
+        while state.spawning && state.round == round {
+            state = self.done.wait(state).unwrap();
+        }
+        InitTurn::Waited(
+            state
+                .result
+                .clone()
+                .unwrap_or_else(|| Err("Failed to initialize Python bridge".to_string())),
+        )
+    }
+
+    /// run `spawn` unless `ready` says it isn't needed, once however many
+    /// callers arrive at the same time
+    fn run(
+        &self,
+        ready: impl Fn() -> bool,
+        spawn: impl FnOnce() -> Result<(), String>,
+    ) -> Result<(), String> {
+        if ready() {
+            return Ok(());
+        }
+        let guard = match self.begin() {
+            InitTurn::Spawn(guard) => guard,
+            InitTurn::Waited(result) => return result,
+        };
+        // whoever spawned before us may have finished since the check above
+        if ready() {
+            return guard.finish(Ok(()));
+        }
+        guard.finish(spawn())
+    }
+}
+
+struct InitGuard<'a> {
+    gate: &'a InitGate,
+    finished: bool,
+}
+
+impl InitGuard<'_> {
+    fn finish(mut self, result: Result<(), String>) -> Result<(), String> {
+        self.publish(result.clone());
+        self.finished = true;
+        result
+    }
+
+    fn publish(&self, result: Result<(), String>) {
+        let mut state = self.gate.state.lock().unwrap();
+        state.spawning = false;
+        state.round += 1;
+        state.result = Some(result);
+        self.gate.done.notify_all();
+    }
+}
+
+impl Drop for InitGuard<'_> {
+    /// a panicking spawn must not leave the waiters blocked forever
+    fn drop(&mut self) {
+        if !self.finished {
+            self.publish(Err("Python bridge initialization panicked".to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod init_gate_tests {
+    use super::*;
+
+    /// `callers` first uses at once of a spawn taking 100 ms that gives
+    /// `result`, how many spawns ran and what each caller got
+    fn first_uses(
+        gate: &InitGate,
+        callers: usize,
+        result: Result<(), String>,
+    ) -> (usize, Vec<Result<(), String>>) {
+        let ready = AtomicBool::new(false);
+        let spawns = AtomicUsize::new(0);
+        let start = std::sync::Barrier::new(callers);
+        let results = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..callers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        gate.run(
+                            || ready.load(Ordering::SeqCst),
+                            || {
+                                spawns.fetch_add(1, Ordering::SeqCst);
+                                std::thread::sleep(Duration::from_millis(100));
+                                ready.store(result.is_ok(), Ordering::SeqCst);
+                                result.clone()
+                            },
+                        )
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        (spawns.into_inner(), results)
+    }
+
+    #[test]
+    fn spawns_once_for_concurrent_first_uses() {
+        let gate = InitGate::default();
+        let (spawns, results) = first_uses(&gate, 16, Ok(()));
+
+        assert_eq!(spawns, 1);
+        assert!(results.iter().all(Result::is_ok));
+        assert!(!gate.is_spawning());
+    }
+
+    #[test]
+    fn shares_a_failed_spawn_without_caching_it() {
+        let gate = InitGate::default();
+        let failed = Err("no python".to_string());
+        let (spawns, results) = first_uses(&gate, 16, failed.clone());
+
+        // everyone who waited got the one failure, nobody spawned again
+        assert_eq!(spawns, 1);
+        assert!(results.iter().all(|result| *result == failed));
+
+        // the next first use tries again
+        let (spawns, results) = first_uses(&gate, 1, Ok(()));
+        assert_eq!(spawns, 1);
+        assert_eq!(results, vec![Ok(())]);
+    }
+}
+
+// global state for Python bridge
+pub struct AppState {
+    pub python: Mutex<Option<PythonBridge>>,
+    /// serializes first-use spawning, see `InitGate`
+    init: InitGate,
+    /// cancel on app exit, kept outside the mutex so it works mid-command
+    pub shutdown: CancellationToken,
+    /// the current bridge's limits, readable while a command holds `python`
+    gates: Mutex<Option<Arc<CommandGates>>>,
+    /// the current bridge's diagnostics, same reason
+    diagnostics: Mutex<Option<Arc<Diagnostics>>>,
+    /// the current bridge's canceller, same reason
+    canceller: Mutex<Option<Arc<RequestCanceller>>>,
+    /// the current bridge's tiles, served without `python`, see `with_tiles`
+    tiles: Mutex<Option<Arc<TileService>>>,
+    /// cancel tokens of running exports by export id
+    exports: Mutex<HashMap<String, CancellationToken>>,
+    /// cancel tokens of running prefetches by prefetch id
+    prefetches: Mutex<HashMap<String, CancellationToken>>,
+    /// running viewport subscriptions by subscription id
+    subscriptions: Mutex<HashMap<String, Arc<ViewportSubscription>>>,
+    next_subscription: AtomicU64,
+    /// running preprocessing pipelines by preprocessing id
+    preprocessing: Mutex<HashMap<String, Arc<PreprocessJob>>>,
+    /// latest progress of running commands by the id the frontend picked,
+    /// see `get_command_progress_cmd`
+    progress: Mutex<HashMap<String, Progress>>,
+    /// where python's log records are emitted, see `forward_logs`
+    app: Mutex<Option<tauri::AppHandle>>,
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        Self {
+            python: Mutex::new(None),
+            init: InitGate::default(),
+            shutdown: CancellationToken::new(),
+            gates: Mutex::new(None),
+            diagnostics: Mutex::new(None),
+            canceller: Mutex::new(None),
+            tiles: Mutex::new(None),
+            exports: Mutex::new(HashMap::new()),
+            prefetches: Mutex::new(HashMap::new()),
+            subscriptions: Mutex::new(HashMap::new()),
+            next_subscription: AtomicU64::new(1),
+            preprocessing: Mutex::new(HashMap::new()),
+            progress: Mutex::new(HashMap::new()),
+            app: Mutex::new(None),
+        }
+    }
+
+    /// abort every in-flight command and stop python, call on app exit
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// emit python's log records as `python_log` events (a `PythonLog`
+    /// payload), its warnings as `python_warning` events (a `PythonWarning`)
+    /// and eager pyramid builds as `pyramid_progress` events from bridges
+    /// spawned after this call. `eager_init` does this
+    pub fn forward_logs(&self, app: tauri::AppHandle) {
+        *self.app.lock().unwrap() = Some(app);
+    }
+
+    /// spawn the bridge unless there already is one
+    ///
+    /// exactly one caller spawns even if many arrive at once, the others
+    /// wait for its result. the `python` lock isn't held meanwhile, so e.g.
+    /// `get_bridge_status_cmd` answers during the handshake
+    fn ensure_bridge(&self) -> Result<(), String> {
+        self.init.run(
+            || self.python.lock().unwrap().is_some(),
+            || {
+                let bridge = self.spawn_bridge().map_err(|e| e.to_string())?;
+                *self.python.lock().unwrap() = Some(bridge);
+                Ok(())
+            },
+        )
+    }
+
+    fn spawn_bridge(&self) -> Result<PythonBridge, BridgeError> {
+        let mut builder = PythonBridge::builder().shutdown_token(self.shutdown.clone());
+        if let Some(app) = self.app.lock().unwrap().clone() {
+            let progress_app = app.clone();
+            let warning_app = app.clone();
+            builder = builder.on_python_log(move |log| {
+                let _ = app.emit("python_log", log);
+            });
+            builder = builder.on_pyramid_progress(move |progress| {
+                let _ = progress_app.emit("pyramid_progress", progress);
+            });
+            builder = builder.on_python_warning(move |warning| {
+                let _ = warning_app.emit("python_warning", warning);
+            });
+        }
+        let bridge = builder.build()?;
+        *self.gates.lock().unwrap() = Some(bridge.command_gates());
+        *self.diagnostics.lock().unwrap() = Some(bridge.diagnostics_handle());
+        *self.canceller.lock().unwrap() = Some(bridge.request_canceller());
+        *self.tiles.lock().unwrap() = Some(bridge.tile_service());
+        Ok(bridge)
+    }
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// run `f` against the bridge, spawning it first if this is the first use
+///
+/// errors are flattened to strings for the frontend
+fn with_bridge<T>(
+    state: &AppState,
+    f: impl FnOnce(&mut PythonBridge) -> Result<T, BridgeError>,
+) -> Result<T, String> {
+    state.ensure_bridge()?;
+    let mut python = state.python.lock().unwrap();
+
+    if let Some(ref mut bridge) = *python {
+        f(bridge).map_err(|e| e.to_string())
+    } else {
+        Err("Failed to initialize Python bridge".to_string())
+    }
+}
+
+/// `with_bridge` with every python command `f` sends given `timeout_ms` to
+/// answer, see `PythonBridge::with_timeout`
+///
+/// for the `timeout_ms` argument of the tauri commands: that call's
+/// override > the command's `command_timeout` > the global
+/// `response_timeout`. `None` keeps the configured deadlines
+fn with_bridge_timeout<T>(
+    state: &AppState,
+    timeout_ms: Option<u64>,
+    f: impl FnOnce(&mut PythonBridge) -> Result<T, BridgeError>,
+) -> Result<T, String> {
+    let timeout = timeout_ms.map(Duration::from_millis);
+    with_bridge(state, |bridge| bridge.with_timeout(timeout, f))
+}
+
+/// run `f` against the bridge's tile service, without taking the `python`
+/// lock a plot or an export may be holding for minutes
+///
+/// `timeout_ms` is what it is for `with_bridge_timeout`
+fn with_tiles<T>(
+    state: &AppState,
+    timeout_ms: Option<u64>,
+    f: impl Fn(&TileService, &mut TileCall) -> Result<T, BridgeError>,
+) -> Result<T, String> {
+    if state.tiles.lock().unwrap().is_none() {
+        state.ensure_bridge()?;
+    }
+    serve_tiles(state, timeout_ms, f).map_err(|e| e.to_string())
+}
+
+/// `with_tiles` for callers that match on the error. when the child is gone
+/// `f` runs again through the bridge, which respawns it first
+fn serve_tiles<T>(
+    state: &AppState,
+    timeout_ms: Option<u64>,
+    f: impl Fn(&TileService, &mut TileCall) -> Result<T, BridgeError>,
+) -> Result<T, BridgeError> {
+    let timeout = timeout_ms.map(Duration::from_millis);
+    let tiles = state.tiles.lock().unwrap().clone();
+    if let Some(tiles) = tiles {
+        let mut call = TileCall { timeout, ..TileCall::default() };
+        match f(&tiles, &mut call) {
+            Err(BridgeError::ProcessExited) => {}
+            result => return result,
+        }
+    }
+    match state.python.lock().unwrap().as_mut() {
+        Some(bridge) => bridge.with_timeout(timeout, |bridge| bridge.serve_tiles(&f)),
+        None => Err(BridgeError::ProcessExited),
+    }
+}
+
+/// spawn and prewarm the bridge at app startup instead of on first use
+///
+/// call from tauri's `setup` hook. runs on its own thread so the window isn't
+/// held up by the python handshake, commands issued meanwhile wait for this
+/// spawn instead of starting their own. if spawning fails here the commands
+/// retry lazily. also turns on `python_log` events, see
+/// `AppState::forward_logs`
+pub fn eager_init(app: tauri::AppHandle) {
+    app.state::<AppState>().forward_logs(app.clone());
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        if let Err(_e) = state.ensure_bridge() {
+            bridge_debug!("PythonBridge: Eager init failed: {}", _e);
+            return;
+        }
+
+        let python = state.python.lock().unwrap();
+        if let Some(ref bridge) = *python {
+            bridge.prewarm();
+        }
+    });
+}
+
+/// tauri command to report whether the bridge is ready for fast commands
+#[tauri::command]
+pub async fn get_bridge_status_cmd(state: State<'_, AppState>) -> Result<BridgeStatus, String> {
+    let python = state.python.lock().unwrap();
+
+    Ok(match python.as_ref() {
+        Some(bridge) => bridge.status(),
+        // the first spawn is still waiting on python's handshake
+        None if state.init.is_spawning() => BridgeStatus::Starting,
+        None => BridgeStatus::NotStarted,
+    })
+}
+
+/// tauri command to size up an overlay before plotting or exporting it
+#[tauri::command]
+pub async fn estimate_overlay_cmd(
+    dataset_id: Option<String>,
+    img_id: Option<String>,
+    seg_id: Option<String>,
+    tile_size: Option<u32>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<PyramidEstimate, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.estimate_overlay(
+            dataset_id.as_deref(),
+            img_id.as_deref(),
+            seg_id.as_deref(),
+            tile_size.unwrap_or(256),
+        )
+    })
+}
+
+/// tauri command to download a remote `.h5ad` and load it. emits
+/// `download_progress` events while downloading
+///
+/// the download runs off the bridge lock, only the final load takes it. a
+/// download that fails `checksum` verification is deleted from the cache,
+/// the next call fetches it again
+#[tauri::command]
+pub async fn load_dataset_from_url_cmd(
+    url: String,
+    dataset_id: Option<String>,
+    checksum: Option<ChecksumCheck>,
+    app: tauri::AppHandle,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<DatasetSummary, String> {
+    let path = tauri::async_runtime::spawn_blocking(move || {
+        download_to_cache(&url, &download_cache_dir(), |progress| {
+            let _ = app.emit("download_progress", progress);
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        let checksum = checksum.unwrap_or_default();
+        let loaded = bridge.load_dataset(&path, dataset_id.as_deref(), &checksum);
+        if let Err(BridgeError::IntegrityMismatch { .. }) = loaded {
+            let _ = std::fs::remove_file(&path);
+        }
+        loaded
+    })
+}
+
+/// tauri command to load a local `.h5ad`, optionally checking its checksum
+#[tauri::command]
+pub async fn load_dataset_cmd(
+    path: PathBuf,
+    dataset_id: Option<String>,
+    checksum: Option<ChecksumCheck>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<DatasetSummary, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.load_dataset(&path, dataset_id.as_deref(), &checksum.unwrap_or_default())
+    })
+}
+
+/// tauri command to hash a file as python reads it, "sha256:<hex>"
+#[tauri::command]
+pub async fn checksum_file_cmd(
+    path: PathBuf,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.checksum_file(&path))
+}
+
+/// tauri command to free a dataset and its overlays in python
+#[tauri::command]
+pub async fn unload_dataset_cmd(
+    dataset_id: String,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.unload_dataset(&dataset_id))
+}
+
+/// tauri command to set the ids later commands default to
+#[tauri::command]
+pub async fn set_context_cmd(
+    dataset_id: String,
+    img_id: String,
+    seg_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.set_context(&dataset_id, &img_id, &seg_id);
+        Ok(())
+    })
+}
+
+/// tauri command to forget the default ids
+#[tauri::command]
+pub async fn clear_context_cmd(state: State<'_, AppState>) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.clear_context();
+        Ok(())
+    })
+}
+
+/// tauri command to generate tissue overlay and tiles
+///
+/// `options` is a `PlotOptions`, left out it is a plain plot
+#[tauri::command]
+pub async fn plot_tissue_overlay_cmd(
+    dataset_id: Option<String>,
+    img_id: Option<String>,
+    seg_id: Option<String>,
+    fill_key: String,
+    options: Option<PlotOptions>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<OverlayInfo, String> {
+    let options = options.unwrap_or_default();
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.plot_tissue_overlay(
+            dataset_id.as_deref(),
+            img_id.as_deref(),
+            seg_id.as_deref(),
+            &fill_key,
+            &options,
+        )
+    })
+}
+
+/// tauri command to set up plots of one dataset, image and segmentation
+/// once, see `PythonBridge::open_render_context`
+#[tauri::command]
+pub async fn open_render_context_cmd(
+    dataset_id: Option<String>,
+    img_id: Option<String>,
+    seg_id: Option<String>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<RenderContext, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.open_render_context(dataset_id.as_deref(), img_id.as_deref(), seg_id.as_deref())
+    })
+}
+
+/// tauri command to free a render context
+#[tauri::command]
+pub async fn close_render_context_cmd(
+    context_id: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| bridge.close_render_context(&context_id))
+}
+
+/// tauri command to list the open render contexts
+#[tauri::command]
+pub async fn list_render_contexts_cmd(
+    state: State<'_, AppState>,
+) -> Result<Vec<RenderContext>, String> {
+    with_bridge(&state, |bridge| Ok(bridge.render_contexts()))
+}
+
+/// tauri command to set the options later plots start from, see
+/// `PythonBridge::set_render_defaults`
+#[tauri::command]
+pub async fn set_render_defaults_cmd(
+    defaults: RenderDefaults,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| bridge.set_render_defaults(defaults))
+}
+
+/// tauri command to get the render defaults
+#[tauri::command]
+pub async fn get_render_defaults_cmd(state: State<'_, AppState>) -> Result<RenderDefaults, String> {
+    with_bridge(&state, |bridge| Ok(bridge.render_defaults().clone()))
+}
+
+/// tauri command to go back to plots without defaults
+#[tauri::command]
+pub async fn reset_render_defaults_cmd(state: State<'_, AppState>) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.reset_render_defaults();
+        Ok(())
+    })
+}
+
+/// tauri command to set the brightness/contrast/gamma of served tiles,
+/// `None` to serve them unadjusted
+#[tauri::command]
+pub async fn set_tile_adjustment_cmd(
+    adjustment: Option<TileAdjustment>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| bridge.set_tile_adjustment(adjustment))
+}
+
+/// tauri command to get the active tile adjustment
+#[tauri::command]
+pub async fn get_tile_adjustment_cmd(
+    state: State<'_, AppState>,
+) -> Result<Option<TileAdjustment>, String> {
+    with_bridge(&state, |bridge| Ok(bridge.tile_adjustment()))
+}
+
+/// tauri command to serve tiles as jpeg, png or picked per tile by content
+#[tauri::command]
+pub async fn set_tile_format_cmd(
+    mode: TileFormatMode,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.set_tile_format(mode);
+        Ok(())
+    })
+}
+
+/// tauri command to get the format tiles are served in
+#[tauri::command]
+pub async fn get_tile_format_cmd(state: State<'_, AppState>) -> Result<TileFormatMode, String> {
+    with_bridge(&state, |bridge| Ok(bridge.tile_format()))
+}
+
+/// tauri command to measure bridge round trips, see `PythonBridge::benchmark`
+#[tauri::command]
+pub async fn benchmark_cmd(
+    iterations: u32,
+    payload_size: usize,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<BenchmarkResult, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.benchmark(iterations, payload_size))
+}
+
+/// tauri command to time a large response's decode with and without a
+/// `serde_json::Value`, see `benchmark_decode`
+///
+/// doesn't touch the bridge
+#[tauri::command]
+pub async fn benchmark_decode_cmd(rows: usize) -> Result<DecodeBenchmark, String> {
+    benchmark_decode(rows).map_err(|e| e.to_string())
+}
+
+/// tauri command to write the tile cache to a file, see
+/// `PythonBridge::save_cache_snapshot`
+#[tauri::command]
+pub async fn save_cache_snapshot_cmd(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<SnapshotCounts, String> {
+    with_bridge(&state, |bridge| bridge.save_cache_snapshot(&path))
+}
+
+/// tauri command to restore the tile cache from a snapshot file, see
+/// `PythonBridge::load_cache_snapshot`
+#[tauri::command]
+pub async fn load_cache_snapshot_cmd(
+    path: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<SnapshotCounts, String> {
+    with_bridge(&state, |bridge| bridge.load_cache_snapshot(&path))
+}
+
+/// tauri command to see how requests reach python, see
+/// `PythonBridge::get_transport_info`
+#[tauri::command]
+pub async fn get_transport_info_cmd(state: State<'_, AppState>) -> Result<TransportInfo, String> {
+    with_bridge(&state, |bridge| Ok(bridge.get_transport_info()))
+}
+
+/// tauri command to reconnect to python over another transport, see
+/// `PythonBridge::reconfigure_transport`
+#[tauri::command]
+pub async fn reconfigure_transport_cmd(
+    config: TransportConfig,
+    state: State<'_, AppState>,
+) -> Result<TransportInfo, String> {
+    with_bridge(&state, |bridge| bridge.reconfigure_transport(config))
+}
+
+/// tauri command for the pyramid level to draw at a screen resolution, see
+/// `PythonBridge::zoom_for_resolution`
+#[tauri::command]
+pub async fn zoom_for_resolution_cmd(
+    overlay_id: String,
+    target_px_per_unit: f64,
+    state: State<'_, AppState>,
+) -> Result<i32, String> {
+    with_bridge(&state, |bridge| bridge.zoom_for_resolution(&overlay_id, target_px_per_unit))
+}
+
+/// tauri command for the parameters and stats behind a tile, see
+/// `PythonBridge::get_tile_provenance`. `quality` picks which of the
+/// tile's cached variants `cache_status` and `render_ms` are about,
+/// balanced by default
+#[tauri::command]
+pub async fn get_tile_provenance_cmd(
+    overlay_id: String,
+    zoom: i32,
+    x: i32,
+    y: i32,
+    quality: Option<RenderQuality>,
+    state: State<'_, AppState>,
+) -> Result<TileProvenance, String> {
+    with_bridge(&state, |bridge| {
+        bridge.get_tile_provenance(&overlay_id, zoom, x, y, quality.unwrap_or_default())
+    })
+}
+
+/// tauri command to get an overlay's size, orientation, transform and origin
+#[tauri::command]
+pub async fn get_overlay_bounds_cmd(
+    overlay_id: String,
+    state: State<'_, AppState>,
+) -> Result<OverlayBounds, String> {
+    with_bridge(&state, |bridge| bridge.get_overlay_bounds(&overlay_id))
+}
+
+/// tauri command to switch where an overlay's y coordinates count from
+#[tauri::command]
+pub async fn set_overlay_origin_cmd(
+    overlay_id: String,
+    origin: Origin,
+    state: State<'_, AppState>,
+) -> Result<OverlayInfo, String> {
+    with_bridge(&state, |bridge| bridge.set_overlay_origin(&overlay_id, origin))
+}
+
+/// tauri command to check plot arguments as the user fills in the form
+///
+/// an empty list means the arguments are fine
+#[tauri::command]
+pub async fn validate_plot_args_cmd(
+    dataset_id: Option<String>,
+    img_id: Option<String>,
+    seg_id: Option<String>,
+    fill_key: String,
+    border_key: Option<String>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ValidationError>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        match bridge.validate_plot_args(
+            dataset_id.as_deref(),
+            img_id.as_deref(),
+            seg_id.as_deref(),
+            &fill_key,
+            border_key.as_deref(),
+        ) {
+            Ok(()) => Ok(Vec::new()),
+            Err(BridgeError::InvalidPlotArgs(errors)) => Ok(errors),
+            Err(e) => Err(e),
+        }
+    })
+}
+
+/// tauri command to get a specific tile
+/// this is called frequently as the user pans/zooms
+#[tauri::command]
+pub async fn get_tissue_overlay_tile_cmd(
+    overlay_id: String,
+    zoom: i32,
+    x: i32,
+    y: i32,
+    quality: Option<RenderQuality>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<TileResponse, String> {
+    with_tiles(&state, timeout_ms, |tiles, call| {
+        let quality = quality.unwrap_or_default();
+        tiles.get_tissue_overlay_tile(&overlay_id, zoom, x, y, quality, call)
+    })
+}
+
+/// tauri command for a tile at a fractional zoom, see
+/// `PythonBridge::get_fractional_tile`
+#[tauri::command]
+pub async fn get_fractional_tile_cmd(
+    overlay_id: String,
+    zoom: f64,
+    x: i32,
+    y: i32,
+    quality: Option<RenderQuality>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<TileResponse, String> {
+    with_tiles(&state, timeout_ms, |tiles, call| {
+        let quality = quality.unwrap_or_default();
+        tiles.get_fractional_tile(&overlay_id, zoom, x, y, quality, call)
+    })
+}
+
+/// tauri command for a tile or a cached lower-zoom stand-in
+///
+/// when the result is provisional, follow up with
+/// `get_tissue_overlay_tile_cmd` and swap the real tile in
+#[tauri::command]
+pub async fn get_tile_with_fallback_cmd(
+    overlay_id: String,
+    zoom: i32,
+    x: i32,
+    y: i32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<FallbackTile, String> {
+    with_tiles(&state, timeout_ms, |tiles, call| {
+        tiles.get_tile_with_fallback(&overlay_id, zoom, x, y, call)
+    })
+}
+
+/// tauri command to fetch the tiles under a pixel rectangle
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_tiles_for_region_cmd(
+    overlay_id: String,
+    zoom: i32,
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<RegionTile>, String> {
+    with_tiles(&state, timeout_ms, |tiles, call| {
+        let tiles = tiles.get_tiles_for_region(&overlay_id, zoom, x0, y0, x1, y1, call)?;
+        Ok(tiles.into_iter().map(RegionTile::new).collect())
+    })
+}
+
+/// tauri command for a difference image of the same tile of two overlays
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn diff_tiles_cmd(
+    overlay_a: String,
+    overlay_b: String,
+    zoom: i32,
+    x: i32,
+    y: i32,
+    mode: DiffMode,
+    premultiplied: Option<bool>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<TileResponse, String> {
+    let premultiplied = premultiplied.unwrap_or(false);
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.diff_tiles(&overlay_a, &overlay_b, zoom, x, y, mode, premultiplied)
+    })
+}
+
+/// tauri command for the tile cache's occupancy and hit rate, the base
+/// image tile cache's with `base_image`
+#[tauri::command]
+pub async fn get_tile_cache_stats_cmd(
+    base_image: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<TileCacheStats, String> {
+    with_bridge(&state, |bridge| {
+        Ok(if base_image.unwrap_or(false) {
+            bridge.base_tile_cache_stats()
+        } else {
+            bridge.tile_cache_stats()
+        })
+    })
+}
+
+/// tauri command to count tile cache hits, misses and evictions from zero
+#[tauri::command]
+pub async fn reset_tile_cache_stats_cmd(state: State<'_, AppState>) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.reset_tile_cache_stats();
+        Ok(())
+    })
+}
+
+/// tauri command for the mini-map's whole-overlay image
+#[tauri::command]
+pub async fn get_overview_cmd(
+    overlay_id: String,
+    max_dimension: u32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<TileResponse, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.get_overview(&overlay_id, max_dimension))
+}
+
+/// tauri command for the mini-map with the viewport outlined on it
+#[tauri::command]
+pub async fn get_minimap_cmd(
+    overlay_id: String,
+    viewport: Bounds,
+    max_dimension: u32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<TileResponse, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_minimap(&overlay_id, viewport, max_dimension)
+    })
+}
+
+/// tauri command for the gallery of this session's plots, newest first.
+/// `limit` defaults to every remembered one
+#[tauri::command]
+pub async fn list_recent_overlays_cmd(
+    limit: Option<usize>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<RecentOverlay>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        Ok(bridge.list_recent_overlays(limit.unwrap_or(MAX_RECENT_OVERLAYS)))
+    })
+}
+
+/// tauri command for the same tile of two overlays, for comparison views
+#[tauri::command]
+pub async fn get_comparison_tile_cmd(
+    overlay_a: String,
+    overlay_b: String,
+    zoom: i32,
+    x: i32,
+    y: i32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<ComparisonTile, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_comparison_tile(&overlay_a, &overlay_b, zoom, x, y)
+    })
+}
+
+/// tauri command for the segment under a full resolution pixel
+#[tauri::command]
+pub async fn get_segment_at_cmd(
+    overlay_id: String,
+    x: u32,
+    y: u32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Option<SegmentInfo>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.get_segment_at(&overlay_id, x, y))
+}
+
+/// tauri command for the hover tooltip, see `PythonBridge::hover_probe`
+#[tauri::command]
+pub async fn hover_probe_cmd(
+    overlay_id: String,
+    x: u32,
+    y: u32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Option<SegmentSummary>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.hover_probe(&overlay_id, x, y))
+}
+
+/// tauri command for snap-to-nearest-cell, up to `k` segments nearest first
+#[tauri::command]
+pub async fn nearest_segments_cmd(
+    overlay_id: String,
+    x: f64,
+    y: f64,
+    k: usize,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SegmentInfo>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.nearest_segments(&overlay_id, x, y, k))
+}
+
+/// tauri command for the segments around a point, see
+/// `PythonBridge::get_neighborhood_summary`
+#[tauri::command]
+pub async fn get_neighborhood_summary_cmd(
+    overlay_id: String,
+    x: f64,
+    y: f64,
+    radius: f64,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<NeighborhoodSummary, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_neighborhood_summary(&overlay_id, x, y, radius)
+    })
+}
+
+/// tauri command to fetch many tiles, central or high priority ones first
+///
+/// `center_x`/`center_y` are the viewport center in pixels of the tiles'
+/// zoom level, tiles without an explicit priority are ranked by distance
+/// to it
+#[tauri::command]
+pub async fn get_tiles_batch_cmd(
+    overlay_id: String,
+    tiles: Vec<TileRequest>,
+    center_x: Option<f64>,
+    center_y: Option<f64>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<RegionTile>, String> {
+    let center = center_x.zip(center_y);
+    with_tiles(&state, timeout_ms, |service, call| {
+        let tiles = service.get_tiles_batch(&overlay_id, &tiles, center, call)?;
+        Ok(tiles.into_iter().map(RegionTile::new).collect())
+    })
+}
+
+/// tauri command to free an overlay that is no longer displayed
+#[tauri::command]
+pub async fn delete_overlay_cmd(
+    overlay_id: String,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.delete_overlay(&overlay_id))
+}
+
+/// tauri command to keep zoom levels of an overlay cached, e.g. for a minimap
+#[tauri::command]
+pub async fn pin_tiles_cmd(
+    overlay_id: String,
+    zoom_levels: Vec<i32>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.pin_tiles(&overlay_id, &zoom_levels);
+        Ok(())
+    })
+}
+
+/// tauri command to let pinned zoom levels be evicted again
+#[tauri::command]
+pub async fn unpin_tiles_cmd(
+    overlay_id: String,
+    zoom_levels: Vec<i32>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.unpin_tiles(&overlay_id, &zoom_levels);
+        Ok(())
+    })
+}
+
+/// tauri command to capture the current view for the next session
+#[tauri::command]
+pub async fn save_view_state_cmd(
+    overlay_id: String,
+    viewport: Viewport,
+    state: State<'_, AppState>,
+) -> Result<ViewState, String> {
+    with_bridge(&state, |bridge| bridge.save_view_state(&overlay_id, viewport))
+}
+
+/// tauri command to reopen a saved view, re-plotting if needed
+#[tauri::command]
+pub async fn restore_view_state_cmd(
+    view: ViewState,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<RestoredView, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.restore_view_state(&view))
+}
+
+/// tauri command for the developer console: what the python helper supports
+#[tauri::command]
+pub async fn list_supported_commands_cmd(
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<CommandSpec>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.list_supported_commands())
+}
+
+/// how far a running command has got, see `get_command_progress_cmd`
+#[derive(Debug, Clone, Serialize)]
+pub struct Progress {
+    /// 0 to 1
+    pub fraction: f64,
+    /// what it is doing, e.g. "waiting" for the bridge or "tiles"
+    pub stage: String,
+}
+
+impl Progress {
+    fn new(fraction: f64, stage: &str) -> Self {
+        Progress {
+            fraction: fraction.clamp(0.0, 1.0),
+            stage: stage.to_string(),
+        }
+    }
+}
+
+/// `export_progress` event payload
+#[derive(Debug, Clone, Serialize)]
+struct ExportProgressEvent {
+    export_id: String,
+    #[serde(flatten)]
+    progress: ExportProgress,
+}
+
+/// tauri command to write an overlay's tile pyramid to a Deep Zoom folder
+/// emits `export_progress` events as tiles are written
+///
+/// `export_id` is picked by the frontend and passed to `cancel_export_cmd`
+#[tauri::command]
+pub async fn export_tile_pyramid_cmd(
+    export_id: String,
+    overlay_id: String,
+    out_dir: PathBuf,
+    format: TileFormat,
+    app: tauri::AppHandle,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<ExportOutcome, String> {
+    let cancel = CancellationToken::new();
+    {
+        let mut exports = state.exports.lock().unwrap();
+        if exports.contains_key(&export_id) {
+            return Err(format!("Export {} is already running", export_id));
+        }
+        exports.insert(export_id.clone(), cancel.clone());
+    }
+    let set_progress = |progress: Progress| {
+        state.progress.lock().unwrap().insert(export_id.clone(), progress);
+    };
+    set_progress(Progress::new(0.0, "waiting"));
+
+    let result = with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.export_tile_pyramid(&overlay_id, &out_dir, format, &cancel, |progress| {
+            let fraction = progress.done as f64 / progress.total.max(1) as f64;
+            set_progress(Progress::new(fraction, "tiles"));
+            let event = ExportProgressEvent {
+                export_id: export_id.clone(),
+                progress,
+            };
+            let _ = app.emit("export_progress", event);
+        })
+    });
+
+    state.progress.lock().unwrap().remove(&export_id);
+    state.exports.lock().unwrap().remove(&export_id);
+    result
+}
+
+/// tauri command to write an overlay as a pyramidal OME-TIFF, see
+/// `PythonBridge::export_ome_tiff`. emits `export_progress` events per level
+/// written, cancelled with `cancel_export_cmd` like the pyramid export
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_ome_tiff_cmd(
+    export_id: String,
+    overlay_id: String,
+    path: PathBuf,
+    include_image: Option<bool>,
+    app: tauri::AppHandle,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<OmeTiffOutcome, String> {
+    let cancel = CancellationToken::new();
+    {
+        let mut exports = state.exports.lock().unwrap();
+        if exports.contains_key(&export_id) {
+            return Err(format!("Export {} is already running", export_id));
+        }
+        exports.insert(export_id.clone(), cancel.clone());
+    }
+    let set_progress = |progress: Progress| {
+        state.progress.lock().unwrap().insert(export_id.clone(), progress);
+    };
+    set_progress(Progress::new(0.0, "waiting"));
+
+    let include_image = include_image.unwrap_or(false);
+    let result = with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.export_ome_tiff(&overlay_id, &path, include_image, &cancel, |progress| {
+            let fraction = progress.done as f64 / progress.total.max(1) as f64;
+            set_progress(Progress::new(fraction, "levels"));
+            let event = ExportProgressEvent {
+                export_id: export_id.clone(),
+                progress,
+            };
+            let _ = app.emit("export_progress", event);
+        })
+    });
+
+    state.progress.lock().unwrap().remove(&export_id);
+    state.exports.lock().unwrap().remove(&export_id);
+    result
+}
+
+/// tauri command to write a two-overlay comparison figure, see
+/// `PythonBridge::export_comparison`. emits `export_progress` events per
+/// step, cancelled with `cancel_export_cmd` like the other exports.
+/// `region` is `[x0, y0, x1, y1]`
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_comparison_cmd(
+    export_id: String,
+    overlay_a: String,
+    overlay_b: String,
+    region: [i64; 4],
+    path: PathBuf,
+    layout: ComparisonLayout,
+    max_dimension: Option<u32>,
+    app: tauri::AppHandle,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<ComparisonOutcome, String> {
+    let cancel = CancellationToken::new();
+    {
+        let mut exports = state.exports.lock().unwrap();
+        if exports.contains_key(&export_id) {
+            return Err(format!("Export {} is already running", export_id));
+        }
+        exports.insert(export_id.clone(), cancel.clone());
+    }
+    let set_progress = |progress: Progress| {
+        state.progress.lock().unwrap().insert(export_id.clone(), progress);
+    };
+    set_progress(Progress::new(0.0, "waiting"));
+
+    let [x0, y0, x1, y1] = region;
+    let result = with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.export_comparison(
+            &overlay_a,
+            &overlay_b,
+            (x0, y0, x1, y1),
+            &path,
+            layout,
+            max_dimension,
+            &cancel,
+            |progress| {
+                let fraction = progress.done as f64 / progress.total.max(1) as f64;
+                set_progress(Progress::new(fraction, "steps"));
+                let event = ExportProgressEvent {
+                    export_id: export_id.clone(),
+                    progress,
+                };
+                let _ = app.emit("export_progress", event);
+            },
+        )
+    });
+
+    state.progress.lock().unwrap().remove(&export_id);
+    state.exports.lock().unwrap().remove(&export_id);
+    result
+}
+
+/// tauri command for the latest progress of a running command by the id
+/// it was started with (`export_id` of the export commands, or a
+/// `preprocessing_id`), `None` once it finished or for ids never seen
+///
+/// the pull side of the progress events, for a window that attached late
+/// or missed some. doesn't wait on the bridge
+#[tauri::command]
+pub async fn get_command_progress_cmd(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<Option<Progress>, String> {
+    Ok(state.progress.lock().unwrap().get(&id).cloned())
+}
+
+/// tauri command to warm several zoom levels around a point
+///
+/// `prefetch_id` is picked by the frontend and passed to
+/// `cancel_prefetch_cmd`
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn prefetch_levels_cmd(
+    prefetch_id: String,
+    overlay_id: String,
+    center_x: f64,
+    center_y: f64,
+    zoom_levels: Vec<i32>,
+    radius: u32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<PrefetchReport, String> {
+    let cancel = CancellationToken::new();
+    {
+        let mut prefetches = state.prefetches.lock().unwrap();
+        if prefetches.contains_key(&prefetch_id) {
+            return Err(format!("Prefetch {} is already running", prefetch_id));
+        }
+        prefetches.insert(prefetch_id.clone(), cancel.clone());
+    }
+
+    let result = with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.prefetch_levels(&overlay_id, center_x, center_y, &zoom_levels, radius, &cancel)
+    });
+
+    state.prefetches.lock().unwrap().remove(&prefetch_id);
+    result
+}
+
+/// tauri command to stop a running prefetch, false if it isn't running
+///
+/// doesn't wait on the bridge, the prefetch notices between tiles
+#[tauri::command]
+pub async fn cancel_prefetch_cmd(
+    prefetch_id: String,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    match state.prefetches.lock().unwrap().get(&prefetch_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// a pipeline started by `preprocess_dataset_cmd`
+struct PreprocessJob {
+    cancel: CancellationToken,
+    status: Mutex<PreprocessStatus>,
+}
+
+/// tauri command to run a preprocessing pipeline on a dataset, returns the
+/// preprocessing id
+///
+/// the pipeline is validated before this returns, then runs in the
+/// background one step per bridge lock. each finished step, and the end,
+/// emits a `preprocess_progress` event (`PreprocessStatus`), whose `keys`
+/// are the new obs columns. follow it with `get_preprocessing_cmd` or
+/// `get_command_progress_cmd`, stop it with `cancel_preprocessing_cmd`
+#[tauri::command]
+pub async fn preprocess_dataset_cmd(
+    dataset_id: String,
+    steps: Vec<PreprocStep>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let status = with_bridge(&state, |bridge| bridge.start_preprocessing(&dataset_id, &steps))?;
+    let preprocessing_id = status.preprocessing_id.clone();
+    let job = Arc::new(PreprocessJob {
+        cancel: CancellationToken::new(),
+        status: Mutex::new(status),
+    });
+    state
+        .preprocessing
+        .lock()
+        .unwrap()
+        .insert(preprocessing_id.clone(), Arc::clone(&job));
+    state
+        .progress
+        .lock()
+        .unwrap()
+        .insert(preprocessing_id.clone(), Progress::new(0.0, "waiting"));
+
+    std::thread::spawn(move || run_preprocessing(app, job, steps));
+    Ok(preprocessing_id)
+}
+
+/// tauri command for a running pipeline's status, `None` once it finished
+/// (its last `preprocess_progress` event has the outcome) or for ids never
+/// seen
+#[tauri::command]
+pub async fn get_preprocessing_cmd(
+    preprocessing_id: String,
+    state: State<'_, AppState>,
+) -> Result<Option<PreprocessStatus>, String> {
+    let jobs = state.preprocessing.lock().unwrap();
+    Ok(jobs.get(&preprocessing_id).map(|job| job.status.lock().unwrap().clone()))
+}
+
+/// tauri command to stop a pipeline, false if it isn't running
+///
+/// doesn't wait on the bridge, the pipeline stops after the step in
+/// progress and keeps the keys of the finished ones
+#[tauri::command]
+pub async fn cancel_preprocessing_cmd(
+    preprocessing_id: String,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    match state.preprocessing.lock().unwrap().get(&preprocessing_id) {
+        Some(job) => {
+            job.cancel.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// worker thread of a preprocessing pipeline, runs one step per bridge
+/// lock so other commands get their turn in between
+fn run_preprocessing(app: tauri::AppHandle, job: Arc<PreprocessJob>, steps: Vec<PreprocStep>) {
+    let state = app.state::<AppState>();
+    let (preprocessing_id, dataset_id) = {
+        let status = job.status.lock().unwrap();
+        (status.preprocessing_id.clone(), status.dataset_id.clone())
+    };
+    let emit = |status: &PreprocessStatus| {
+        let _ = app.emit("preprocess_progress", status.clone());
+    };
+
+    for (i, step) in steps.iter().enumerate() {
+        if job.cancel.is_cancelled() || state.shutdown.is_cancelled() {
+            job.status.lock().unwrap().state = PreprocessState::Cancelled;
+            break;
+        }
+        let fraction = i as f64 / steps.len() as f64;
+        state
+            .progress
+            .lock()
+            .unwrap()
+            .insert(preprocessing_id.clone(), Progress::new(fraction, step.name()));
+
+        let keys = match state.python.lock().unwrap().as_mut() {
+            Some(bridge) => bridge.preprocess_step(&preprocessing_id, &dataset_id, step),
+            None => Err(BridgeError::ProcessExited),
+        };
+        let mut status = job.status.lock().unwrap();
+        match keys {
+            Ok(keys) => {
+                status.keys.extend(keys);
+                status.done += 1;
+                if status.done < status.total {
+                    emit(&status);
+                }
+            }
+            Err(e) => {
+                status.state = PreprocessState::Failed;
+                status.error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+
+    if let Some(bridge) = state.python.lock().unwrap().as_mut() {
+        let _ = bridge.end_preprocessing(&preprocessing_id);
+    }
+    let status = {
+        let mut status = job.status.lock().unwrap();
+        if status.state == PreprocessState::Running {
+            status.state = PreprocessState::Completed;
+        }
+        status.clone()
+    };
+    state.progress.lock().unwrap().remove(&preprocessing_id);
+    state.preprocessing.lock().unwrap().remove(&preprocessing_id);
+    emit(&status);
+}
+
+/// tauri command to stream an overlay's tiles for a moving viewport,
+/// returns the subscription id
+///
+/// after each `update_viewport_cmd` the missing tiles of the new viewport
+/// arrive center first as `viewport_tile` events (`ViewportTileEvent`),
+/// queued tiles the viewport moved off are dropped. a tile already being
+/// fetched when the viewport moves still arrives. the subscription ends,
+/// with an `ended` event, on `unsubscribe_viewport_cmd`, when the overlay
+/// is gone (removed, or python restarted) and on app exit
+#[tauri::command]
+pub async fn subscribe_viewport_cmd(
+    overlay_id: String,
+    quality: Option<RenderQuality>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    with_tiles(&state, None, |tiles, _| tiles.info(&overlay_id).map(|_| ()))?;
+
+    let subscription_id = format!(
+        "viewport-{}",
+        state.next_subscription.fetch_add(1, Ordering::Relaxed)
+    );
+    let subscription = Arc::new(ViewportSubscription::new(overlay_id, quality.unwrap_or_default()));
+    state
+        .subscriptions
+        .lock()
+        .unwrap()
+        .insert(subscription_id.clone(), Arc::clone(&subscription));
+    let weak = Arc::downgrade(&subscription);
+    state.shutdown.on_cancel(move || {
+        if let Some(subscription) = weak.upgrade() {
+            subscription.end(SubscriptionEnd::ShuttingDown);
+        }
+    });
+
+    let worker_id = subscription_id.clone();
+    std::thread::spawn(move || stream_viewport(app, worker_id, subscription));
+    Ok(subscription_id)
+}
+
+/// tauri command to move a subscription's viewport to `[x0, x1) x [y0, y1)`
+/// in pixels of `zoom`'s level, returns the update number its events carry
+#[tauri::command]
+pub async fn update_viewport_cmd(
+    subscription_id: String,
+    zoom: i32,
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    let subscription = state
+        .subscriptions
+        .lock()
+        .unwrap()
+        .get(&subscription_id)
+        .cloned()
+        .ok_or_else(|| format!("No viewport subscription {}", subscription_id))?;
+    let tiles = with_tiles(&state, None, |tiles, _| {
+        tiles.viewport_tiles(&subscription.overlay_id, zoom, x0, y0, x1, y1)
+    })?;
+    Ok(subscription.update(tiles))
+}
+
+/// tauri command to end a viewport subscription, false if it already ended
+///
+/// doesn't wait on the bridge. the tile in flight is cancelled and arrives
+/// as dropped with the queued ones, python skips it unless it already
+/// started rendering it
+#[tauri::command]
+pub async fn unsubscribe_viewport_cmd(
+    subscription_id: String,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    let subscription = state.subscriptions.lock().unwrap().remove(&subscription_id);
+    match subscription {
+        Some(subscription) => {
+            subscription.end(SubscriptionEnd::Unsubscribed);
+            if let Some(canceller) = state.canceller.lock().unwrap().as_ref() {
+                canceller.cancel_tagged(&subscription_id);
+            }
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// worker thread of a viewport subscription, fetches one tile at a time
+/// through the tile service
+fn stream_viewport(
+    app: tauri::AppHandle,
+    subscription_id: String,
+    subscription: Arc<ViewportSubscription>,
+) {
+    let state = app.state::<AppState>();
+    let emit = |update: u64, event: ViewportEvent| {
+        let event = ViewportTileEvent {
+            subscription_id: subscription_id.clone(),
+            update,
+            event,
+        };
+        let _ = app.emit("viewport_tile", event);
+    };
+
+    let mut stream = ViewportStream::default();
+    let reason = loop {
+        match subscription.next(stream.queue.is_empty()) {
+            Some(Err(reason)) => break reason,
+            Some(Ok((update, tiles))) => {
+                let previous = stream.update;
+                for coord in stream.retarget(update, tiles) {
+                    let reason = TileDropReason::Superseded;
+                    emit(previous, ViewportEvent::TileDropped { coord, reason, error: None });
+                }
+                if stream.queue.is_empty() {
+                    emit(update, ViewportEvent::Idle);
+                }
+                continue;
+            }
+            None => {}
+        }
+        let Some(coord) = stream.queue.pop_front() else { continue };
+
+        let (overlay_id, quality) = (&subscription.overlay_id, subscription.quality);
+        let tile = serve_tiles(&state, None, |tiles, call| {
+            tiles.subscription_tile(&subscription_id, overlay_id, coord, quality, call)
+        });
+        match tile {
+            Ok(tile) => {
+                stream.shown.insert(coord);
+                emit(stream.update, ViewportEvent::TileArrived { coord, tile });
+            }
+            Err(BridgeError::OverlayNotFound(_)) => {
+                stream.queue.push_front(coord);
+                break SubscriptionEnd::OverlayGone;
+            }
+            // unsubscribed, dropped as ended along with the queue
+            Err(BridgeError::Cancelled(_)) => {
+                stream.queue.push_front(coord);
+                continue;
+            }
+            Err(e) => {
+                let (reason, error) = (TileDropReason::Failed, Some(e.to_string()));
+                emit(stream.update, ViewportEvent::TileDropped { coord, reason, error });
+            }
+        }
+        if stream.queue.is_empty() {
+            emit(stream.update, ViewportEvent::Idle);
+        }
+    };
+
+    for coord in stream.queue.drain(..) {
+        let reason = TileDropReason::Ended;
+        emit(stream.update, ViewportEvent::TileDropped { coord, reason, error: None });
+    }
+    emit(stream.update, ViewportEvent::Ended { reason });
+    state.subscriptions.lock().unwrap().remove(&subscription_id);
+}
+
+/// tauri command to save a rectangle of an overlay as a png figure panel
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn export_region_png_cmd(
+    overlay_id: String,
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+    path: PathBuf,
+    max_dimension: Option<u32>,
+    scalebar: Option<ScalebarOptions>,
+    force_full_size: Option<bool>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<RegionExport, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.export_region_png(
+            &overlay_id,
+            x0,
+            y0,
+            x1,
+            y1,
+            &path,
+            max_dimension,
+            scalebar,
+            force_full_size.unwrap_or(false),
+        )
+    })
+}
+
+/// tauri command to predict an export's size and memory use
+#[tauri::command]
+pub async fn estimate_region_export_cmd(
+    overlay_id: String,
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+    max_dimension: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<ExportEstimate, String> {
+    with_bridge(&state, |bridge| {
+        bridge.estimate_region_export(&overlay_id, x0, y0, x1, y1, max_dimension)
+    })
+}
+
+/// tauri command to save an overlay's segment table as csv or parquet
+#[tauri::command]
+pub async fn export_segments_cmd(
+    overlay_id: String,
+    path: PathBuf,
+    format: TableFormat,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<SegmentsExport, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.export_segments(&overlay_id, &path, format))
+}
+
+/// tauri command to stop a running export, false if it isn't running
+///
+/// doesn't wait on the bridge, the export notices between tiles
+#[tauri::command]
+pub async fn cancel_export_cmd(
+    export_id: String,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    match state.exports.lock().unwrap().get(&export_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// tauri command for the overlay info panel
+#[tauri::command]
+pub async fn get_overlay_stats_cmd(
+    overlay_id: String,
+    force_refresh: Option<bool>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<OverlayStats, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_overlay_stats(&overlay_id, force_refresh.unwrap_or(false))
+    })
+}
+
+/// tauri command to get the legend of an overlay
+#[tauri::command]
+pub async fn get_overlay_legend_cmd(
+    overlay_id: String,
+    force_refresh: Option<bool>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<OverlayLegend, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_overlay_legend(&overlay_id, force_refresh.unwrap_or(false))
+    })
+}
+
+/// tauri command for the segment counts beside a categorical legend
+#[tauri::command]
+pub async fn get_category_counts_cmd(
+    overlay_id: String,
+    force_refresh: Option<bool>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<(String, u64)>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_category_counts(&overlay_id, force_refresh.unwrap_or(false))
+    })
+}
+
+/// tauri command for the colormap picker
+#[tauri::command]
+pub async fn list_colormaps_cmd(
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ColormapInfo>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.list_colormaps())
+}
+
+/// tauri command to report the python environment
+#[tauri::command]
+pub async fn get_environment_info_cmd(
+    force_refresh: Option<bool>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<EnvironmentInfo, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_environment_info(force_refresh.unwrap_or(false))
+    })
+}
+
+/// tauri command to change an overlay's colormap, in place when possible
+#[tauri::command]
+pub async fn recolor_overlay_cmd(
+    overlay_id: String,
+    colormap: String,
+    value_range: Option<[f64; 2]>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<OverlayInfo, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.recolor_overlay(&overlay_id, &colormap, value_range)
+    })
+}
+
+/// tauri command for the distribution behind a value range slider
+#[tauri::command]
+pub async fn get_value_histogram_cmd(
+    dataset_id: Option<String>,
+    fill_key: String,
+    bins: u32,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Histogram, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_value_histogram(dataset_id.as_deref(), &fill_key, bins)
+    })
+}
+
+/// tauri command for the image switcher
+#[tauri::command]
+pub async fn list_images_cmd(
+    dataset_id: Option<String>,
+    force_refresh: Option<bool>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ImageInfo>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.list_images(dataset_id.as_deref(), force_refresh.unwrap_or(false))
+    })
+}
+
+/// tauri command for one page of the segments in a box, `page_size`
+/// defaults to 1000
+#[tauri::command]
+pub async fn query_segments_in_bbox_cmd(
+    overlay_id: String,
+    bbox: [u32; 4],
+    page: Option<u32>,
+    page_size: Option<u32>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<Page<SegmentInfo>, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.query_segments_in_bbox(
+            &overlay_id,
+            bbox,
+            page.unwrap_or(0),
+            page_size.unwrap_or(1000),
+        )
+    })
+}
+
+/// tauri command for the image layer under an overlay
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_base_image_tile_cmd(
+    dataset_id: Option<String>,
+    img_id: Option<String>,
+    zoom: i32,
+    x: i32,
+    y: i32,
+    quality: Option<RenderQuality>,
+    orientation: Option<Orientation>,
+    origin: Option<Origin>,
+    transform: Option<AffineTransform>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<TileResponse, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.get_base_image_tile(
+            dataset_id.as_deref(),
+            img_id.as_deref(),
+            zoom,
+            x,
+            y,
+            quality.unwrap_or_default(),
+            orientation.unwrap_or_default(),
+            origin.unwrap_or_default(),
+            transform,
+        )
+    })
+}
+
+/// tauri command to save an overlay's legend for a figure, `size` defaults
+/// to 400 pixels
+#[tauri::command]
+pub async fn export_legend_cmd(
+    overlay_id: String,
+    path: PathBuf,
+    orientation: Option<LegendOrientation>,
+    size: Option<u32>,
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<RegionExport, String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| {
+        bridge.export_legend(
+            &overlay_id,
+            &path,
+            orientation.unwrap_or_default(),
+            size.unwrap_or(400),
+        )
+    })
+}
+
+/// tauri command for the effective bridge configuration, for support
+#[tauri::command]
+pub async fn get_bridge_config_cmd(state: State<'_, AppState>) -> Result<BridgeConfig, String> {
+    with_bridge(&state, |bridge| Ok(bridge.get_bridge_config()))
+}
+
+/// tauri command for per-command in-flight and queued counts
+///
+/// reads the limits directly so it answers even while another command is
+/// running, and never spawns the bridge
+#[tauri::command]
+pub async fn get_command_metrics_cmd(
+    state: State<'_, AppState>,
+) -> Result<Vec<CommandMetrics>, String> {
+    Ok(state
+        .gates
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|gates| gates.metrics())
+        .unwrap_or_default())
+}
+
+/// tauri command for the in-flight numbers of the plot, tile and control lanes
+///
+/// like `get_command_metrics_cmd` it answers while a command is running
+#[tauri::command]
+pub async fn get_lane_metrics_cmd(
+    state: State<'_, AppState>,
+) -> Result<Vec<LaneMetrics>, String> {
+    Ok(state
+        .gates
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|gates| gates.lane_metrics())
+        .unwrap_or_default())
+}
+
+/// tauri command for per-command serialization and transfer times, see
+/// `PythonBridge::wire_metrics`
+///
+/// like `get_command_metrics_cmd` it answers while a command is running
+#[tauri::command]
+pub async fn get_wire_metrics_cmd(
+    state: State<'_, AppState>,
+) -> Result<Vec<WireMetrics>, String> {
+    Ok(state
+        .diagnostics
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|diagnostics| diagnostics.wire_metrics())
+        .unwrap_or_default())
+}
+
+/// tauri command for the raw request and response of the latest command,
+/// see `PythonBridge::get_last_wire_frames`. `None` unless the bridge was
+/// built with `capture_wire_frames`
+#[tauri::command]
+pub async fn get_last_wire_frames_cmd(
+    state: State<'_, AppState>,
+) -> Result<Option<WireFrames>, String> {
+    with_bridge(&state, |bridge| Ok(bridge.get_last_wire_frames()))
+}
+
+/// tauri command to serve tiles only from the cache, see
+/// `PythonBridge::pause_tiles`
+///
+/// goes through the limits like the metrics commands, so it takes effect
+/// while a plot holds the bridge
+#[tauri::command]
+pub async fn pause_tiles_cmd(state: State<'_, AppState>) -> Result<(), String> {
+    let gates = state.gates.lock().unwrap();
+    let gates = gates.as_ref().ok_or("Python bridge not started")?;
+    gates.pause_tiles();
+    Ok(())
+}
+
+/// tauri command to let tile fetches reach python again
+#[tauri::command]
+pub async fn resume_tiles_cmd(state: State<'_, AppState>) -> Result<(), String> {
+    if let Some(gates) = state.gates.lock().unwrap().as_ref() {
+        gates.resume_tiles();
+    }
+    Ok(())
+}
+
+/// tauri command for the "copy diagnostics" button
+///
+/// recent commands, errors and python stderr. answers while a command is
+/// running and never spawns the bridge
+#[tauri::command]
+pub async fn get_diagnostics_cmd(
+    state: State<'_, AppState>,
+) -> Result<Option<DiagnosticsReport>, String> {
+    Ok(state
+        .diagnostics
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|diagnostics| diagnostics.report()))
+}
+
+/// tauri command to start or stop tracing requests, see
+/// `PythonBridge::set_request_tracing`
+#[tauri::command]
+pub async fn set_request_tracing_cmd(
+    enabled: bool,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.set_request_tracing(enabled);
+        Ok(())
+    })
+}
+
+/// tauri command for the traces of `set_request_tracing_cmd`, answers
+/// while a command is running
+#[tauri::command]
+pub async fn get_request_traces_cmd(
+    state: State<'_, AppState>,
+) -> Result<Vec<RequestTrace>, String> {
+    Ok(state
+        .diagnostics
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|diagnostics| diagnostics.request_traces())
+        .unwrap_or_default())
+}
+
+/// tauri command for the "save crash report" button
+///
+/// never spawns the bridge or waits on a command holding it, the report
+/// is cut down to what `AppState` has on hand in those cases
+#[tauri::command]
+pub async fn capture_crash_report_cmd(state: State<'_, AppState>) -> Result<CrashReport, String> {
+    let reason = match state.python.try_lock() {
+        Ok(mut python) => match python.as_mut() {
+            Some(bridge) => return Ok(bridge.capture_crash_report()),
+            None => "bridge: not started",
+        },
+        Err(_) => "bridge: busy with another command",
+    };
+
+    let status = if state.init.is_spawning() {
+        BridgeStatus::Starting
+    } else {
+        BridgeStatus::NotStarted
+    };
+    let gates = state.gates.lock().unwrap().clone();
+    let diagnostics = state.diagnostics.lock().unwrap().clone();
+    let mut report = CrashReport::new(status, gates.as_deref(), diagnostics.as_deref());
+    report.failures.push(reason.to_string());
+    report.redact_paths();
+    Ok(report)
+}
+
+/// tauri command that resolves once earlier commands have completed
+#[tauri::command]
+pub async fn barrier_cmd(
+    timeout_ms: Option<u64>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_bridge_timeout(&state, timeout_ms, |bridge| bridge.barrier())
+}
+
+/// tauri command to drop all cached tiles and results
+#[tauri::command]
+pub async fn clear_caches_cmd(state: State<'_, AppState>) -> Result<(), String> {
+    with_bridge(&state, |bridge| {
+        bridge.clear_caches();
+        Ok(())
+    })
+}
+
+// ===== Fake Python =====
+
+/// stand-in for the python helper that speaks the same stdio protocol
+///
+/// behind the `fake-python` cargo feature, meant for dev/test builds. the
+/// `fake_python` binary target runs it, pass that to
+/// `PythonBridgeBuilder::python_path` to drive spawn, handshake, timeouts
+/// and crash recovery without a python install (see tests/). it doubles as
+/// a reference implementation of the protocol
+///
+/// like the interpreter it is started as `<binary> -u <script>`. the script
+/// file isn't run, it holds `key = value` lines tuning the fake:
+///
+/// - `handshake_delay_ms`: wait before the handshake
+/// - `delay_ms`: wait before every response
+/// - `crash_on`: exit without answering when this command arrives
+/// - `crash_after`: exit without answering the n-th request
+/// - `hang_on`: never answer this command, and nothing else in its lane
+/// - `stray_after`: answer request 0, which the bridge never sends, before
+///   the n-th request's response
+/// - `duplicate_after`: answer the n-th request twice
+/// - `not_ready`: handshake with `ready: false`
+/// - `reexec`: act like a launcher that hands over to the interpreter,
+///   start a copy of itself on the same pipes and exit
+/// - `omit_command`: leave this command out of `list_supported_commands`
+///   and answer it as unknown, a helper older than the bridge
+/// - `fail_tile`: a `zoom/x/y` that `get_tiles_batch` reports as failed,
+///   the rest of the batch renders
+/// - `slow_on`, `slow_ms`: wait this long before answering this command,
+///   on top of `delay_ms`
+/// - `overlay_id`: the id of every plot, each one replots that overlay
+/// - `close_stdin_after`: answer the n-th request, a control lane one, then
+///   close stdin and hang without exiting, so the bridge's next write
+///   fails (unix only)
+/// - `render_backend`: matplotlib's backend as prewarm and
+///   get_environment_info report it, `agg` by default. anything else is a
+///   GUI backend without a display
+///
+/// requests run in their "lane" as in the real helper: plot and tile each
+/// on their own thread, the rest in arrival order on the command loop.
+/// barrier and unload_dataset wait for both lanes to drain first
+///
+/// ```ignore
+/// let bridge = PythonBridge::builder()
+///     .python_path(env!("CARGO_BIN_EXE_fake_python"))
+///     .embedded_script("delay_ms = 50\ncrash_on = get_tissue_overlay_tile")
+///     .build()?;
+/// ```
+#[cfg(feature = "fake-python")]
+pub mod fake_python {
+    use super::PythonCommand;
+    use std::collections::HashMap;
+    use std::io::{BufRead, Write};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{mpsc, Arc, Condvar, Mutex};
+    use std::time::Duration;
+
+    /// kept in step with `PROTOCOL_VERSION` in python_tiling.py
+    const PROTOCOL_VERSION: u32 = 1;
+
+    /// lanes with a worker thread each, as in python_tiling.py
+    const WORKER_LANES: [&str; 2] = ["plot", "tile"];
+
+    /// set in the copy `reexec` starts, so it doesn't start another
+    const REEXECED: &str = "FAKE_PYTHON_REEXECED";
+
+    /// 1x1 transparent png returned for every tile
+    const CANNED_TILE: &str = concat!(
+        "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA",
+        "60e6kgAAAABJRU5ErkJggg==",
+    );
+
+    #[derive(Debug, Default)]
+    struct Config {
+        handshake_delay: Duration,
+        delay: Duration,
+        crash_on: Option<String>,
+        crash_after: Option<u64>,
+        stray_after: Option<u64>,
+        duplicate_after: Option<u64>,
+        not_ready: bool,
+        reexec: bool,
+        omit_command: Option<String>,
+        fail_tile: Option<String>,
+        slow_on: Option<String>,
+        slow: Duration,
+        overlay_id: Option<String>,
+        close_stdin_after: Option<u64>,
+        hang_on: Option<String>,
+        render_backend: Option<String>,
+    }
+
+    impl Config {
+        fn parse(source: &str) -> Self {
+            let mut config = Config::default();
+            for line in source.lines() {
+                let Some((key, value)) = line.split_once('=') else { continue };
+                let value = value.trim();
+                let millis = || Duration::from_millis(value.parse().unwrap_or(0));
+                match key.trim() {
+                    "handshake_delay_ms" => config.handshake_delay = millis(),
+                    "delay_ms" => config.delay = millis(),
+                    "crash_on" => config.crash_on = Some(value.to_string()),
+                    "crash_after" => config.crash_after = value.parse().ok(),
+                    "stray_after" => config.stray_after = value.parse().ok(),
+                    "duplicate_after" => config.duplicate_after = value.parse().ok(),
+                    "not_ready" => config.not_ready = value == "true",
+                    "reexec" => config.reexec = value == "true",
+                    "omit_command" => config.omit_command = Some(value.to_string()),
+                    "fail_tile" => config.fail_tile = Some(value.to_string()),
+                    "slow_on" => config.slow_on = Some(value.to_string()),
+                    "slow_ms" => config.slow = millis(),
+                    "overlay_id" => config.overlay_id = Some(value.to_string()),
+                    "close_stdin_after" => config.close_stdin_after = value.parse().ok(),
+                    "hang_on" => config.hang_on = Some(value.to_string()),
+                    "render_backend" => config.render_backend = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            config
+        }
+    }
+
+    /// run the command loop on stdin/stdout until stdin closes
+    pub fn run() {
+        // the script path comes after the interpreter's `-u`
+        let config = std::env::args()
+            .skip(1)
+            .find(|arg| arg != "-u")
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|source| Config::parse(&source))
+            .unwrap_or_default();
+
+        // the copy inherits stdin/stdout/stderr and handshakes with its own
+        // pid, the bridge is left holding a child that has already exited
+        if config.reexec && std::env::var_os(REEXECED).is_none() {
+            let started = std::env::current_exe().and_then(|exe| {
+                std::process::Command::new(exe)
+                    .args(std::env::args_os().skip(1))
+                    .env(REEXECED, "1")
+                    .spawn()
+            });
+            std::process::exit(if started.is_ok() { 0 } else { 1 });
+        }
+
+        let stdin = std::io::stdin();
+        let fake = Fake {
+            config,
+            stdout: Mutex::new(std::io::stdout()),
+            overlays: AtomicU64::new(0),
+            namespace: std::env::var(super::NAMESPACE_ENV).unwrap_or_default(),
+        };
+
+        std::thread::sleep(fake.config.handshake_delay);
+        let handshake = serde_json::json!({
+            "success": true,
+            "data": {
+                "ready": !fake.config.not_ready,
+                "pid": std::process::id(),
+                "protocol_version": PROTOCOL_VERSION,
+            },
+            "error": null,
+        });
+        fake.write_line(&handshake);
+
+        std::thread::scope(|scope| {
+            let lanes: HashMap<&str, Lane> = WORKER_LANES
+                .iter()
+                .map(|&lane| {
+                    let (sender, receiver) = mpsc::channel::<(u64, serde_json::Value)>();
+                    let pending = Arc::new((Mutex::new(0usize), Condvar::new()));
+                    let done = Arc::clone(&pending);
+                    let fake = &fake;
+                    scope.spawn(move || {
+                        for (seen, request) in receiver {
+                            fake.handle(seen, &request);
+                            let (count, drained) = &*done;
+                            *count.lock().unwrap() -= 1;
+                            drained.notify_all();
+                        }
+                    });
+                    (lane, Lane { sender, pending })
+                })
+                .collect();
+
+            for (seen, line) in (1u64..).zip(stdin.lock().lines()) {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let request: serde_json::Value = match serde_json::from_str(&line) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let response = serde_json::json!({
+                            "id": null,
+                            "success": false,
+                            "data": null,
+                            "error": format!("Invalid request: {}", e),
+                        });
+                        fake.write_line(&response);
+                        continue;
+                    }
+                };
+
+                if let Some(lane) = request["lane"].as_str().and_then(|lane| lanes.get(lane)) {
+                    *lane.pending.0.lock().unwrap() += 1;
+                    let _ = lane.sender.send((seen, request));
+                    continue;
+                }
+                let wire_name = request["command"].as_str().unwrap_or_default();
+                if matches!(
+                    resolve(wire_name, &fake.namespace),
+                    Some(PythonCommand::Barrier | PythonCommand::UnloadDataset)
+                ) {
+                    for lane in lanes.values() {
+                        lane.drain();
+                    }
+                }
+                fake.handle(seen, &request);
+            }
+            // dropping the senders ends the lane threads
+        });
+    }
+
+    /// a worker lane: its queue and how many requests are still in it
+    struct Lane {
+        sender: mpsc::Sender<(u64, serde_json::Value)>,
+        pending: Arc<(Mutex<usize>, Condvar)>,
+    }
+
+    impl Lane {
+        /// wait until every request sent to this lane has been answered
+        fn drain(&self) {
+            let (count, drained) = &*self.pending;
+            drop(drained.wait_while(count.lock().unwrap(), |count| *count > 0).unwrap());
+        }
+    }
+
+    /// state shared by the command loop and the lane threads
+    struct Fake {
+        config: Config,
+        stdout: Mutex<std::io::Stdout>,
+        overlays: AtomicU64,
+        /// the bridge's, see `PythonBridgeBuilder::namespace`
+        namespace: String,
+    }
+
+    impl Fake {
+        /// answer the `seen`-th request, nothing for notifications
+        fn handle(&self, seen: u64, request: &serde_json::Value) {
+            let config = &self.config;
+            let wire_name = request["command"].as_str().unwrap_or_default();
+            let command = resolve(wire_name, &self.namespace)
+                .filter(|command| config.omit_command.as_deref() != Some(command.name()));
+
+            if config.crash_after == Some(seen)
+                || command.is_some_and(|c| config.crash_on.as_deref() == Some(c.name()))
+            {
+                eprintln!("fake python: crashing on {}", wire_name);
+                std::process::exit(1);
+            }
+
+            if command.is_some_and(|c| config.hang_on.as_deref() == Some(c.name())) {
+                loop {
+                    std::thread::park();
+                }
+            }
+            std::thread::sleep(config.delay);
+            if command.is_some_and(|c| config.slow_on.as_deref() == Some(c.name())) {
+                std::thread::sleep(config.slow);
+            }
+            if request["notification"].as_bool() == Some(true) {
+                return;
+            }
+
+            let result = match command {
+                Some(command) => Ok(respond(command, &request["params"], config, &self.overlays)),
+                None => Err(format!("Unknown command: {}", wire_name)),
+            };
+            let response = match result {
+                Ok(data) => serde_json::json!({
+                    "id": request["id"],
+                    "success": true,
+                    "data": data,
+                    "error": null,
+                }),
+                Err(error) => serde_json::json!({
+                    "id": request["id"],
+                    "success": false,
+                    "data": null,
+                    "error": error,
+                }),
+            };
+            if config.stray_after == Some(seen) {
+                let stray = serde_json::json!({
+                    "id": 0,
+                    "success": true,
+                    "data": null,
+                    "error": null,
+                });
+                self.write_line(&stray);
+            }
+            // closed before answering, so the bridge can't have written
+            // its next request yet
+            let hang = cfg!(unix) && config.close_stdin_after == Some(seen);
+            #[cfg(unix)]
+            if hang {
+                // SAFETY: nothing reads stdin again, this thread hangs below
+                unsafe { libc::close(0) };
+            }
+            self.write_line(&response);
+            if config.duplicate_after == Some(seen) {
+                self.write_line(&response);
+            }
+            if hang {
+                loop {
+                    std::thread::park();
+                }
+            }
+        }
+
+        fn write_line(&self, response: &serde_json::Value) {
+            let line = serde_json::to_string(response).expect("json values always serialize");
+            let mut stdout = self.stdout.lock().unwrap();
+            // the bridge hung up, nothing left to answer
+            if writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).is_err() {
+                std::process::exit(0);
+            }
+        }
+    }
+
+    /// the command, as python's `resolve_command` finds it: in the default
+    /// namespace or the one the bridge was configured with
+    fn resolve(wire_name: &str, namespace: &str) -> Option<PythonCommand> {
+        let (prefix, name) = wire_name.rsplit_once('.').unwrap_or(("", wire_name));
+        if !prefix.is_empty() && prefix != namespace {
+            return None;
+        }
+        PythonCommand::ALL.iter().copied().find(|command| command.name() == name)
+    }
+
+    /// canned data for a command, anything without a canned answer echoes
+    /// its params back
+    fn respond(
+        command: PythonCommand,
+        params: &serde_json::Value,
+        config: &Config,
+        overlays: &AtomicU64,
+    ) -> serde_json::Value {
+        match command {
+            PythonCommand::ListSupportedCommands => PythonCommand::ALL
+                .iter()
+                .filter(|command| config.omit_command.as_deref() != Some(command.name()))
+                .map(|command| serde_json::json!({ "name": command.name(), "params": [] }))
+                .collect(),
+            PythonCommand::PlotTissueOverlay => {
+                let overlay = overlays.fetch_add(1, Ordering::SeqCst) + 1;
+                let overlay_id = config.overlay_id.clone();
+                serde_json::json!({
+                    "overlay_id": overlay_id.unwrap_or_else(|| format!("fake-{}", overlay)),
+                    "width": 1024,
+                    "height": 1024,
+                    "tile_size": 256,
+                    "max_zoom": 2,
+                    "fill_key": params["fill_key"],
+                    "is_gene": false,
+                })
+            }
+            PythonCommand::GetTissueOverlayTile => {
+                serde_json::json!({ "tile": CANNED_TILE, "format": "png" })
+            }
+            PythonCommand::GetComparisonTile => {
+                let half = serde_json::json!({ "tile": CANNED_TILE, "format": "jpeg" });
+                serde_json::json!({ "a": half, "b": half })
+            }
+            PythonCommand::ListImages => serde_json::json!([{
+                "img_id": "i1",
+                "name": "hires",
+                "dimensions": [1024, 1024],
+                "channels": 3,
+            }]),
+            PythonCommand::OpenRenderContext => serde_json::json!({
+                "context_id": format!("fake:{}:{}", params["dataset_id"], params["seg_id"]),
+                "dataset_id": params["dataset_id"],
+                "img_id": params["img_id"],
+                "seg_id": params["seg_id"],
+            }),
+            PythonCommand::GetTilesBatch => {
+                let tiles = params["tiles"].as_array().cloned().unwrap_or_default();
+                let tiles: Vec<_> = tiles
+                    .iter()
+                    .map(|t| {
+                        let coord = format!("{}/{}/{}", t["zoom"], t["x"], t["y"]);
+                        match config.fail_tile.as_deref() {
+                            Some(failed) if failed == coord => serde_json::json!({
+                                "error": format!("Failed to get tile {}", coord)
+                            }),
+                            _ => serde_json::json!({ "tile": CANNED_TILE, "format": "png" }),
+                        }
+                    })
+                    .collect();
+                serde_json::json!({ "tiles": tiles })
+            }
+            // python's report, nothing imported
+            PythonCommand::Prewarm => serde_json::json!({
+                "modules": [],
+                "elapsed_ms": 0.0,
+                "render_backend": render_backend(config),
+            }),
+            PythonCommand::GetEnvironmentInfo => serde_json::json!({
+                "python_version": "3.11.0",
+                "platform": "fake",
+                "pid": std::process::id(),
+                "packages": {},
+                "memory_rss_bytes": null,
+                "render_backend": render_backend(config),
+            }),
+            PythonCommand::DeleteOverlay | PythonCommand::Barrier => serde_json::Value::Null,
+            // overlays aren't tracked by dataset, none are deleted with it
+            PythonCommand::UnloadDataset => serde_json::json!({ "overlays": [] }),
+            PythonCommand::ExportSegments => {
+                // an empty table, written for real so the path can be
+                // checked to have arrived byte for byte
+                if let Some(path) = read_wire_path(&params["path"]) {
+                    let _ = std::fs::write(path, "segment_id\n");
+                }
+                serde_json::json!({ "path": params["path"], "rows": 0 })
+            }
+            _ => params.clone(),
+        }
+    }
+
+    /// a path param, as python's `wire_path` reads it
+    fn read_wire_path(path: &serde_json::Value) -> Option<std::path::PathBuf> {
+        #[derive(serde::Deserialize)]
+        struct WirePath(
+            #[serde(deserialize_with = "super::deserialize_wire_path")] std::path::PathBuf,
+        );
+        let WirePath(path) = serde_json::from_value(path.clone()).ok()?;
+        Some(path)
+    }
+
+    /// see `render_backend` in the config
+    fn render_backend(config: &Config) -> serde_json::Value {
+        let name = config.render_backend.as_deref().unwrap_or("agg");
+        serde_json::json!({ "name": name, "interactive": name != "agg", "headless": true })
+    }
+
+}
@@ -0,0 +1,690 @@
+//! the bridge against the `fake_python` stand-in: spawn, handshake, the
+//! json lines framing, deadlines and crash recovery
+
+use rust_bridge::{
+    BridgeError, BridgeStatus, CancellationToken, Lane, Orientation, Origin, OverlayInfo,
+    PlotOptions, PythonBridge, PythonBridgeBuilder, PythonCommand, RenderDefaults, RenderQuality,
+    SegmentLimit, TableFormat, TileCall, TileCoord, TileProcessor, TileRequest, TileResponse,
+    Viewport,
+};
+use std::time::{Duration, Instant};
+
+/// a builder for the fake helper tuned by `config`, see `fake_python`
+fn fake(config: &str) -> PythonBridgeBuilder {
+    PythonBridge::builder()
+        .python_path(env!("CARGO_BIN_EXE_fake_python"))
+        .embedded_script(config)
+}
+
+/// the status once prewarm has finished
+fn prewarmed(bridge: &PythonBridge) -> BridgeStatus {
+    bridge.prewarm();
+    let started = Instant::now();
+    while bridge.status() == BridgeStatus::Starting && started.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    bridge.status()
+}
+
+/// an overlay of fake dataset `d1`
+fn plot(bridge: &mut PythonBridge, fill_key: &str) -> Result<OverlayInfo, BridgeError> {
+    bridge.plot_tissue_overlay(Some("d1"), None, Some("s1"), fill_key, &PlotOptions::default())
+}
+
+#[test]
+fn handshakes_and_answers_commands() {
+    let mut bridge = fake("").build().unwrap();
+
+    assert_eq!(bridge.status(), BridgeStatus::Ready);
+    let config = bridge.get_bridge_config();
+    assert_eq!(config.protocol_version, Some(1));
+    assert!(config.python_pid.is_some());
+    assert!(config.missing_commands.is_empty());
+    assert_eq!(bridge.list_supported_commands().unwrap().len(), PythonCommand::ALL.len());
+}
+
+#[test]
+fn serves_commands_in_the_bridges_namespace() {
+    let mut bridge = fake("").namespace("tissue").build().unwrap();
+
+    assert!(bridge.get_bridge_config().missing_commands.is_empty());
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let tile = bridge.get_tissue_overlay_tile(&info.overlay_id, 0, 0, 0, RenderQuality::default());
+    assert!(tile.is_ok(), "{:?}", tile.map(|_| ()));
+}
+
+#[test]
+fn refuses_to_start_without_a_command_when_strict() {
+    let result = fake("omit_command = list_colormaps").strict_commands().build();
+
+    match result {
+        Err(BridgeError::MissingCommands { missing }) => assert_eq!(missing, ["list_colormaps"]),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn reports_missing_commands_when_lenient() {
+    let bridge = fake("omit_command = list_colormaps").build().unwrap();
+
+    assert_eq!(bridge.status(), BridgeStatus::Ready);
+    assert_eq!(bridge.get_bridge_config().missing_commands, ["list_colormaps"]);
+}
+
+#[test]
+fn echoes_payloads() {
+    let mut bridge = fake("").build().unwrap();
+
+    let result = bridge.benchmark(3, 64 * 1024).unwrap();
+    assert_eq!(result.iterations, 3);
+    assert_eq!(result.payload_size, 64 * 1024);
+}
+
+#[test]
+fn round_trips_a_multi_megabyte_request() {
+    let mut bridge = fake("").build().unwrap();
+
+    // compared byte for byte against what was sent
+    let result = bridge.benchmark(2, 8 * 1024 * 1024).unwrap();
+    assert_eq!(result.payload_size, 8 * 1024 * 1024);
+}
+
+#[test]
+fn gives_up_on_a_slow_response() {
+    let mut bridge = fake("delay_ms = 2000")
+        .response_timeout(Duration::from_millis(100), false)
+        .build()
+        .unwrap();
+
+    let started = Instant::now();
+    let result = bridge.barrier();
+    assert!(matches!(result, Err(BridgeError::Timeout(_))), "{:?}", result);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn kills_a_child_that_never_answers() {
+    let mut bridge = fake("hang_on = barrier")
+        .response_timeout(Duration::from_millis(100), true)
+        .build()
+        .unwrap();
+
+    assert!(matches!(bridge.barrier(), Err(BridgeError::Timeout(_))));
+    assert_eq!(bridge.status(), BridgeStatus::Dead);
+    assert!(bridge.list_supported_commands().is_ok());
+    assert_eq!(bridge.status(), BridgeStatus::Ready);
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+}
+
+#[test]
+fn keeps_the_child_after_a_timeout_unless_asked() {
+    let mut bridge = fake("hang_on = get_tissue_overlay_tile")
+        .response_timeout(Duration::from_millis(100), false)
+        .warmup_levels(0)
+        .build()
+        .unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+
+    let tile = bridge.get_tissue_overlay_tile(&info.overlay_id, 0, 0, 0, RenderQuality::default());
+    assert!(matches!(tile, Err(BridgeError::Timeout(_))), "{:?}", tile.map(|_| ()));
+    // only the tile lane is stuck
+    assert!(bridge.list_supported_commands().is_ok());
+    assert_eq!(bridge.get_transport_info().reconnects, 0);
+}
+
+#[test]
+fn gives_up_on_a_slow_handshake() {
+    let started = Instant::now();
+    let result = fake("handshake_delay_ms = 5000")
+        .startup_timeout(Duration::from_millis(200))
+        .build();
+
+    assert!(matches!(result, Err(BridgeError::StartupTimeout(_))), "{:?}", result.map(|_| ()));
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn respawns_after_a_crash() {
+    let mut bridge = fake("crash_on = barrier").build().unwrap();
+
+    // not idempotent, so it isn't sent to the new child again
+    assert_eq!(bridge.barrier(), Err(BridgeError::ProcessExited));
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+    assert!(bridge.list_supported_commands().is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn respawns_when_stdin_is_closed() {
+    // the first request is the build's `list_supported_commands`
+    let mut bridge = fake("close_stdin_after = 1").build().unwrap();
+
+    // the write fails, the command is sent again to a new child
+    assert_eq!(bridge.list_supported_commands().unwrap().len(), PythonCommand::ALL.len());
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+}
+
+#[test]
+fn fails_requests_in_flight_on_a_stray_response() {
+    // the first request is the build's `list_supported_commands`, the
+    // response to the second comes after one with an id never sent
+    let mut bridge = fake("stray_after = 2").build().unwrap();
+
+    assert!(matches!(bridge.barrier(), Err(BridgeError::ProtocolDesync(_))));
+    // the child is kept, its answer to the barrier is dropped
+    assert!(bridge.list_supported_commands().is_ok());
+    assert_eq!(bridge.get_transport_info().reconnects, 0);
+}
+
+#[test]
+fn respawns_on_a_stray_response_if_asked() {
+    let mut bridge = fake("stray_after = 2").respawn_on_desync().build().unwrap();
+
+    assert!(matches!(bridge.barrier(), Err(BridgeError::ProtocolDesync(_))));
+    assert!(bridge.list_supported_commands().is_ok());
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+}
+
+#[test]
+fn respawns_on_a_duplicate_response_if_asked() {
+    let mut bridge = fake("duplicate_after = 2").respawn_on_desync().build().unwrap();
+
+    // answered by the first copy, the second one has no request left
+    assert!(bridge.barrier().is_ok());
+    let started = Instant::now();
+    while bridge.status() != BridgeStatus::Dead && started.elapsed() < Duration::from_secs(5) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(bridge.status(), BridgeStatus::Dead);
+    assert!(bridge.list_supported_commands().is_ok());
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+}
+
+#[test]
+fn retries_idempotent_commands_after_a_crash() {
+    // the first request is the build's `list_supported_commands`
+    let mut bridge = fake("crash_after = 2").build().unwrap();
+
+    assert_eq!(bridge.list_supported_commands().unwrap().len(), PythonCommand::ALL.len());
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+}
+
+#[test]
+fn fails_and_redispatches_the_requests_in_flight_by_kind() {
+    // the first request is the build's `list_supported_commands`, the
+    // second the prewarm python is still importing for when it crashes
+    let mut bridge = fake("slow_on = prewarm\nslow_ms = 500\ncrash_after = 3").build().unwrap();
+    bridge.prewarm();
+    std::thread::sleep(Duration::from_millis(50));
+
+    // idempotent, sent again to the new child
+    assert_eq!(bridge.list_supported_commands().unwrap().len(), PythonCommand::ALL.len());
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+
+    // not idempotent, the prewarm failed with the old child without
+    // marking the new one dead. sent again, it would have been the new
+    // child's third request and crashed it
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(bridge.status(), BridgeStatus::Ready);
+    assert!(bridge.list_supported_commands().is_ok());
+    assert_eq!(bridge.get_transport_info().reconnects, 1);
+}
+
+#[test]
+fn prewarm_reports_the_render_backend() {
+    let mut bridge = fake("").build().unwrap();
+    assert_eq!(prewarmed(&bridge), BridgeStatus::Ready);
+    let backend = bridge.get_environment_info(false).unwrap().render_backend.unwrap();
+    assert_eq!(backend.name, "agg");
+    assert!(!backend.interactive);
+
+    // a GUI backend without a display fails prewarm instead of the first render
+    let mut bridge = fake("render_backend = TkAgg").build().unwrap();
+    let error = BridgeError::BadRenderBackend("TkAgg".to_string()).to_string();
+    assert_eq!(prewarmed(&bridge), BridgeStatus::Failed { error });
+    let backend = bridge.get_environment_info(false).unwrap().render_backend.unwrap();
+    assert!(backend.check().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn tracks_the_interpreter_a_launcher_hands_over_to() {
+    let mut bridge = fake("reexec = true").build().unwrap();
+    let info = bridge.get_transport_info();
+    let (python, launcher) = (info.python_pid.unwrap(), info.launcher_pid.unwrap());
+    assert_ne!(python, launcher);
+
+    // the launcher exiting isn't the child dying
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(bridge.list_supported_commands().unwrap().len(), PythonCommand::ALL.len());
+    assert_eq!(bridge.status(), BridgeStatus::Ready);
+    assert_eq!(bridge.get_transport_info().reconnects, 0);
+
+    // nor is the interpreter left running on drop
+    drop(bridge);
+    #[cfg(target_os = "linux")]
+    assert!(!is_running(python));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn spawns_the_child_under_its_resource_limits() {
+    let limit = 1 << 30;
+    let bridge = fake("").memory_limit(limit).build().unwrap();
+    let pid = bridge.get_transport_info().python_pid.unwrap();
+
+    let limits = std::fs::read_to_string(format!("/proc/{}/limits", pid)).unwrap();
+    let address_space = limits.lines().find(|line| line.starts_with("Max address space"));
+    let values: Vec<_> = address_space.unwrap().split_whitespace().skip(3).take(2).collect();
+    assert_eq!(values, [limit.to_string(), limit.to_string()]);
+
+    // too small to even load the child
+    let result = fake("").memory_limit(1 << 20).build();
+    assert!(matches!(result, Err(BridgeError::Spawn(_))), "{:?}", result.map(|_| ()));
+}
+
+#[cfg(unix)]
+#[test]
+fn sends_non_utf8_paths_byte_for_byte() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    let mut bridge = fake("").build().unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let mut name = format!("segments-{}-", std::process::id()).into_bytes();
+    name.extend_from_slice(b"caf\xe9\xff.csv");
+    let path = std::env::temp_dir().join(std::ffi::OsString::from_vec(name));
+
+    let export = bridge.export_segments(&info.overlay_id, &path, TableFormat::Csv);
+    let exported = path.exists();
+    let _ = std::fs::remove_file(&path);
+    assert!(exported, "nothing written to {:?}", path);
+    assert_eq!(export.unwrap().path.as_os_str().as_bytes(), path.as_os_str().as_bytes());
+}
+
+/// whether `pid` is alive, a zombie waiting to be reaped isn't
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> bool {
+    let started = Instant::now();
+    loop {
+        // the state follows the parenthesized command name
+        let state = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+            .ok()
+            .and_then(|stat| stat.rsplit_once(')').map(|(_, rest)| rest.trim_start().to_string()));
+        let running = state.is_some_and(|state| !state.starts_with('Z'));
+        if !running || started.elapsed() > Duration::from_secs(2) {
+            return running;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn removes_the_embedded_script_on_drop() {
+    let bridge = fake("").build().unwrap();
+    let script = bridge.get_bridge_config().script_path;
+    assert!(script.exists());
+
+    drop(bridge);
+    assert!(!script.exists());
+}
+
+#[test]
+fn serves_tiles_during_a_slow_plot() {
+    let mut bridge = fake("slow_on = plot_tissue_overlay\nslow_ms = 1000").build().unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let tiles = bridge.tile_service();
+
+    std::thread::scope(|scope| {
+        let plot = scope.spawn(|| plot(&mut bridge, "gene"));
+        // let the plot reach python first
+        std::thread::sleep(Duration::from_millis(100));
+
+        let started = Instant::now();
+        let mut call = TileCall::default();
+        let tile = tiles.get_tissue_overlay_tile(
+            &info.overlay_id,
+            0,
+            0,
+            0,
+            RenderQuality::default(),
+            &mut call,
+        );
+        assert!(tile.is_ok(), "{:?}", tile);
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert!(!plot.is_finished());
+        assert!(plot.join().unwrap().is_ok());
+    });
+}
+
+#[test]
+fn cancels_the_tiles_of_an_ended_subscription() {
+    let mut bridge = fake("slow_on = get_tissue_overlay_tile\nslow_ms = 300").build().unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let (tiles, canceller) = (bridge.tile_service(), bridge.request_canceller());
+    let tile = |subscription_id: &str, x| {
+        let coord = TileCoord { zoom: 2, x, y: 0 };
+        let mut call = TileCall::default();
+        let quality = RenderQuality::default();
+        tiles.subscription_tile(subscription_id, &info.overlay_id, coord, quality, &mut call)
+    };
+
+    std::thread::scope(|scope| {
+        let ended = scope.spawn(|| (tile("viewport-1", 0), Instant::now()));
+        std::thread::sleep(Duration::from_millis(50));
+        let other = scope.spawn(|| tile("viewport-2", 1));
+        std::thread::sleep(Duration::from_millis(50));
+
+        let cancelled = BridgeError::Cancelled("viewport-1".to_string());
+        let cancelled_at = Instant::now();
+        assert_eq!(canceller.cancel_tagged("viewport-1"), 1);
+        let (ended, done_at) = ended.join().unwrap();
+        assert_eq!(ended.unwrap_err(), cancelled);
+        assert!(done_at - cancelled_at < Duration::from_millis(100));
+
+        // tiles asked for after the end fail without reaching python, the
+        // other subscription's still arrive
+        let late = Instant::now();
+        assert_eq!(tile("viewport-1", 2).unwrap_err(), cancelled);
+        assert!(late.elapsed() < Duration::from_millis(100));
+        assert!(other.join().unwrap().is_ok());
+    });
+}
+
+#[test]
+fn fails_a_tile_in_flight_when_its_dataset_is_unloaded() {
+    let mut bridge = fake("slow_on = get_tissue_overlay_tile\nslow_ms = 2000")
+        .warmup_levels(0)
+        .build()
+        .unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let tiles = bridge.tile_service();
+
+    std::thread::scope(|scope| {
+        let tile = scope.spawn(|| {
+            let mut call = TileCall::default();
+            let quality = RenderQuality::default();
+            let tile = tiles.get_tissue_overlay_tile(&info.overlay_id, 0, 0, 0, quality, &mut call);
+            (tile, Instant::now())
+        });
+        // let the tile reach python first
+        std::thread::sleep(Duration::from_millis(100));
+
+        let unloaded_at = Instant::now();
+        // python only unloads once the tile is done
+        let unload = scope.spawn(|| bridge.unload_dataset("d1"));
+        let (tile, done_at) = tile.join().unwrap();
+        assert_eq!(tile.unwrap_err(), BridgeError::DatasetUnloaded("d1".to_string()));
+        assert!(done_at - unloaded_at < Duration::from_millis(500));
+        assert!(unload.join().unwrap().is_ok());
+    });
+}
+
+#[test]
+fn holds_concurrent_tiles_to_the_limit() {
+    let mut bridge = fake("slow_on = get_tissue_overlay_tile\nslow_ms = 150")
+        .concurrency_limit(PythonCommand::GetTissueOverlayTile, 2)
+        .build()
+        .unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let tiles = bridge.tile_service();
+    let gates = bridge.command_gates();
+    let tile_metrics = || {
+        gates
+            .metrics()
+            .into_iter()
+            .find(|m| m.command == PythonCommand::GetTissueOverlayTile.name())
+            .unwrap()
+    };
+
+    let (mut most_in_flight, mut most_queued) = (0, 0);
+    std::thread::scope(|scope| {
+        let fetches: Vec<_> = (0..5)
+            .map(|i| {
+                let (tiles, overlay_id) = (&tiles, &info.overlay_id);
+                scope.spawn(move || {
+                    let (quality, mut call) = (RenderQuality::default(), TileCall::default());
+                    tiles.get_tissue_overlay_tile(overlay_id, 2, i % 4, i / 4, quality, &mut call)
+                })
+            })
+            .collect();
+        while !fetches.iter().all(|fetch| fetch.is_finished()) {
+            let metrics = tile_metrics();
+            most_in_flight = most_in_flight.max(metrics.in_flight);
+            most_queued = most_queued.max(metrics.queued);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        for fetch in fetches {
+            assert!(fetch.join().unwrap().is_ok());
+        }
+    });
+    assert_eq!(most_in_flight, 2);
+    assert!(most_queued > 0);
+}
+
+#[test]
+fn queues_tile_misses_while_paused() {
+    let mut bridge = fake("slow_on = plot_tissue_overlay\nslow_ms = 1000").build().unwrap();
+    // warmup caches the zoom 0 tile
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let (tiles, gates) = (bridge.tile_service(), bridge.command_gates());
+    let queued = || {
+        gates.lane_metrics().into_iter().find(|m| m.lane == Lane::Tile).unwrap().queued
+    };
+    let fetch = |zoom| {
+        let (quality, mut call) = (RenderQuality::default(), TileCall::default());
+        tiles.get_tissue_overlay_tile(&info.overlay_id, zoom, 0, 0, quality, &mut call)
+    };
+
+    std::thread::scope(|scope| {
+        let plot = scope.spawn(|| plot(&mut bridge, "gene"));
+        std::thread::sleep(Duration::from_millis(100));
+        gates.pause_tiles();
+
+        assert!(fetch(0).is_ok());
+        let miss = scope.spawn(|| fetch(1));
+        let started = Instant::now();
+        while queued() == 0 {
+            assert!(started.elapsed() < Duration::from_secs(1), "the miss never queued");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(!miss.is_finished());
+
+        gates.resume_tiles();
+        assert!(miss.join().unwrap().is_ok());
+        assert!(!plot.is_finished());
+        assert!(plot.join().unwrap().is_ok());
+    });
+
+    // holding the bridge, a miss can't wait
+    gates.pause_tiles();
+    let miss = bridge.get_tissue_overlay_tile(&info.overlay_id, 1, 1, 0, RenderQuality::default());
+    assert!(matches!(miss, Err(BridgeError::TilesPaused)), "{:?}", miss.map(|_| ()));
+}
+
+/// marks the tiles it has seen
+struct Stamp;
+
+impl TileProcessor for Stamp {
+    fn process(&self, tile: &mut TileResponse) {
+        tile.format = "stamped".to_string();
+    }
+}
+
+#[test]
+fn processes_tiles_on_every_path() {
+    let mut bridge = fake("").warmup_levels(0).build().unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    bridge.add_tile_processor(Box::new(Stamp));
+    let id = &info.overlay_id;
+
+    // nothing cached yet, fetched from python
+    let fetched = bridge.get_tile_with_fallback(id, 0, 0, 0).unwrap();
+    assert!(!fetched.provisional);
+    assert_eq!(fetched.tile.format, "stamped");
+    let ancestor = bridge.get_tile_with_fallback(id, 2, 3, 3).unwrap();
+    assert!(ancestor.provisional);
+    assert_eq!(ancestor.tile.format, "stamped");
+
+    // a cache hit and a miss
+    let request = |zoom, x, y| TileRequest { zoom, x, y, priority: None };
+    let batch = bridge.get_tiles_batch(id, &[request(0, 0, 0), request(1, 1, 0)], None).unwrap();
+    for (coord, tile) in batch {
+        assert_eq!(tile.unwrap().format, "stamped", "batch tile {:?}", coord);
+    }
+
+    let (coord, quality) = (TileCoord { zoom: 1, x: 0, y: 1 }, RenderQuality::default());
+    let mut call = TileCall::default();
+    let streamed = bridge.tile_service().subscription_tile("sub-1", id, coord, quality, &mut call);
+    assert_eq!(streamed.unwrap().format, "stamped");
+
+    let other = plot(&mut bridge, "gene").unwrap();
+    for _ in 0..2 {
+        let pair = bridge.get_comparison_tile(id, &other.overlay_id, 0, 0, 0).unwrap();
+        assert_eq!((pair.a.format.as_str(), pair.b.format.as_str()), ("stamped", "stamped"));
+    }
+}
+
+#[test]
+fn returns_the_rest_of_a_batch_around_a_failed_tile() {
+    let mut bridge = fake("fail_tile = 1/1/0").warmup_levels(0).build().unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let request = |zoom, x, y| TileRequest { zoom, x, y, priority: None };
+    let tiles = [request(1, 0, 0), request(1, 1, 0), request(1, 0, 1)];
+    let failed = TileCoord { zoom: 1, x: 1, y: 0 };
+
+    for _ in 0..2 {
+        let batch = bridge.get_tiles_batch(&info.overlay_id, &tiles, None).unwrap();
+        assert_eq!(batch.len(), 3);
+        for (coord, tile) in batch {
+            match tile {
+                Err(BridgeError::Python(e)) if coord == failed => {
+                    assert!(e.contains("1/1/0"), "{}", e)
+                }
+                tile => assert!(tile.is_ok() && coord != failed, "{:?}: {:?}", coord, tile),
+            }
+        }
+    }
+    // the failure isn't cached, the second batch asked python again
+    let stats = bridge.tile_cache_stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (2, 2, 4));
+}
+
+#[test]
+fn counts_one_lookup_per_fallback_request() {
+    let mut bridge = fake("").build().unwrap();
+    // warmup caches the zoom 0 tile
+    let info = plot(&mut bridge, "cluster").unwrap();
+    bridge.reset_tile_cache_stats();
+
+    // served from the zoom 0 ancestor, two levels up
+    let tile = bridge.get_tile_with_fallback(&info.overlay_id, 2, 3, 3).unwrap();
+    assert!(tile.provisional);
+    let stats = bridge.tile_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (0, 1));
+
+    bridge.get_tile_with_fallback(&info.overlay_id, 0, 0, 0).unwrap();
+    let stats = bridge.tile_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+}
+
+#[test]
+fn plots_with_the_call_over_the_context_over_the_defaults() {
+    let mut bridge = fake("").build().unwrap();
+    let extra = |pairs: &[(&str, i32)]| {
+        pairs.iter().map(|&(key, value)| (key.to_string(), value.into())).collect()
+    };
+    let limit = SegmentLimit { max_segments: 100, sampling: Default::default() };
+    bridge
+        .set_render_defaults(RenderDefaults {
+            background: Some([0, 0, 0, 255]),
+            quality: Some(RenderQuality::Fast),
+            orientation: Some(Orientation::Rotate90),
+            origin: Some(Origin::BottomLeft),
+            segment_limit: Some(limit),
+            extra: extra(&[("a", 1), ("b", 1)]),
+            ..RenderDefaults::default()
+        })
+        .unwrap();
+    bridge.set_context("d1", "i1", "s1");
+
+    let options = PlotOptions {
+        background: Some([255, 255, 255, 255]),
+        extra: Some(extra(&[("b", 2)])),
+        ..PlotOptions::default()
+    };
+    let info = bridge.plot_tissue_overlay(Some("d2"), None, None, "cluster", &options).unwrap();
+    let viewport = Viewport { zoom: 0.0, center_x: 0.0, center_y: 0.0 };
+    let params = bridge.save_view_state(&info.overlay_id, viewport).unwrap().params;
+
+    // ids from the call, else the context
+    assert_eq!(params.dataset_id, "d2");
+    assert_eq!((params.img_id.as_deref(), params.seg_id.as_str()), (Some("i1"), "s1"));
+    // options from the call, else the defaults
+    assert_eq!(params.background, Some([255, 255, 255, 255]));
+    assert_eq!(params.quality, RenderQuality::Fast);
+    assert_eq!(params.orientation, Orientation::Rotate90);
+    assert_eq!((params.origin, params.segment_limit), (Origin::BottomLeft, Some(limit)));
+    assert_eq!(params.extra, Some(extra(&[("a", 1), ("b", 2)])));
+}
+
+#[test]
+fn fetches_tiles_again_after_a_replot() {
+    let mut bridge = fake("overlay_id = reused").warmup_levels(0).build().unwrap();
+    let quality = RenderQuality::default();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    bridge.get_tissue_overlay_tile(&info.overlay_id, 0, 0, 0, quality).unwrap();
+    bridge.get_tissue_overlay_tile(&info.overlay_id, 0, 0, 0, quality).unwrap();
+    let stats = bridge.tile_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+
+    let replotted = plot(&mut bridge, "gene").unwrap();
+    assert_eq!(replotted.overlay_id, info.overlay_id);
+    bridge.get_tissue_overlay_tile(&info.overlay_id, 0, 0, 0, quality).unwrap();
+    let stats = bridge.tile_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+}
+
+#[test]
+fn wakes_commands_in_flight_on_shutdown() {
+    let shutdown = CancellationToken::new();
+    let mut bridge = fake("slow_on = barrier\nslow_ms = 5000")
+        .shutdown_token(shutdown.clone())
+        .build()
+        .unwrap();
+
+    let started = Instant::now();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(100));
+            shutdown.cancel();
+        });
+        assert_eq!(bridge.barrier(), Err(BridgeError::ShuttingDown));
+    });
+    assert!(started.elapsed() < Duration::from_secs(2));
+    // and nothing new is sent
+    assert!(matches!(bridge.list_supported_commands(), Err(BridgeError::ShuttingDown)));
+}
+
+#[test]
+fn barrier_waits_for_earlier_work() {
+    let mut bridge = fake("slow_on = get_tissue_overlay_tile\nslow_ms = 300")
+        .warmup_levels(0)
+        .build()
+        .unwrap();
+    let info = plot(&mut bridge, "cluster").unwrap();
+    let tiles = bridge.tile_service();
+
+    std::thread::scope(|scope| {
+        let fetch = scope.spawn(|| {
+            let (quality, mut call) = (RenderQuality::default(), TileCall::default());
+            tiles.get_tissue_overlay_tile(&info.overlay_id, 0, 0, 0, quality, &mut call)
+        });
+        // let the tile reach python first
+        std::thread::sleep(Duration::from_millis(50));
+
+        // held back until python has answered the tile
+        let started = Instant::now();
+        assert_eq!(bridge.barrier(), Ok(()));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(fetch.join().unwrap().is_ok());
+    });
+}